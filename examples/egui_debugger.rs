@@ -0,0 +1,306 @@
+//! Reference debugger frontend built on eframe/egui.
+//!
+//! Run with `cargo run --example egui_debugger --features egui-debugger -- path/to/rom.gb`.
+
+use eframe::egui;
+use rust_gameboycolor::{
+    CpuState, DebugImage, DeviceMode, GameBoyColor, JoypadKey, JoypadKeyState, TileMap,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+const KEY_BINDINGS: [(egui::Key, JoypadKey); 8] = [
+    (egui::Key::ArrowRight, JoypadKey::Right),
+    (egui::Key::ArrowLeft, JoypadKey::Left),
+    (egui::Key::ArrowUp, JoypadKey::Up),
+    (egui::Key::ArrowDown, JoypadKey::Down),
+    (egui::Key::X, JoypadKey::A),
+    (egui::Key::Z, JoypadKey::B),
+    (egui::Key::Space, JoypadKey::Select),
+    (egui::Key::Enter, JoypadKey::Start),
+];
+
+const DISASSEMBLY_LINES: usize = 16;
+const MEMORY_ROWS: u16 = 16;
+
+struct Debugger {
+    gameboy_color: GameBoyColor,
+    device_mode: DeviceMode,
+    screen: Option<egui::TextureHandle>,
+    tiles: Option<egui::TextureHandle>,
+    bg_map: Option<egui::TextureHandle>,
+    running: bool,
+    memory_address: u16,
+    tile_bank: u8,
+    tile_map: TileMap,
+}
+
+impl Debugger {
+    fn new(gameboy_color: GameBoyColor, device_mode: DeviceMode) -> Self {
+        Self {
+            gameboy_color,
+            device_mode,
+            screen: None,
+            tiles: None,
+            bg_map: None,
+            running: true,
+            memory_address: 0xC000,
+            tile_bank: 0,
+            tile_map: TileMap::Low,
+        }
+    }
+
+    fn update_input(&mut self, ctx: &egui::Context) {
+        let mut key_state = JoypadKeyState::new();
+        ctx.input(|input| {
+            for (key, joypad_key) in KEY_BINDINGS {
+                key_state.set_key(joypad_key, input.key_down(key));
+            }
+        });
+        self.gameboy_color.set_key(key_state);
+    }
+
+    fn update_textures(&mut self, ctx: &egui::Context) {
+        let screen = DebugImage {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            pixels: self.gameboy_color.frame_buffer().to_vec(),
+        };
+        upload(ctx, &mut self.screen, "screen", &screen);
+        let tiles = self.gameboy_color.render_tile_data(self.tile_bank);
+        upload(ctx, &mut self.tiles, "tiles", &tiles);
+        let bg_map = self.gameboy_color.render_bg_map(self.tile_map, None);
+        upload(ctx, &mut self.bg_map, "bg_map", &bg_map);
+    }
+
+    fn registers_panel(ui: &mut egui::Ui, state: &CpuState) {
+        ui.heading("Registers");
+        egui::Grid::new("registers").striped(true).show(ui, |ui| {
+            let pairs = [
+                ("AF", (state.a as u16) << 8 | state.f as u16),
+                ("BC", (state.b as u16) << 8 | state.c as u16),
+                ("DE", (state.d as u16) << 8 | state.e as u16),
+                ("HL", (state.h as u16) << 8 | state.l as u16),
+                ("SP", state.sp),
+                ("PC", state.pc),
+            ];
+            for (name, value) in pairs {
+                ui.monospace(name);
+                ui.monospace(format!("{:04X}", value));
+                ui.end_row();
+            }
+        });
+        ui.monospace(format!(
+            "Flags: {}{}{}{}",
+            if state.f & 0x80 != 0 { "Z" } else { "-" },
+            if state.f & 0x40 != 0 { "N" } else { "-" },
+            if state.f & 0x20 != 0 { "H" } else { "-" },
+            if state.f & 0x10 != 0 { "C" } else { "-" },
+        ));
+        ui.monospace(format!("IME: {}  HALT: {}", state.ime, state.halted));
+    }
+
+    fn disassembly_panel(&self, ui: &mut egui::Ui) {
+        ui.heading("Disassembly");
+        let pc = self.gameboy_color.cpu_state().pc;
+        for (address, text) in self.gameboy_color.disassemble_range(pc, DISASSEMBLY_LINES) {
+            let marker = if address == pc { ">" } else { " " };
+            ui.monospace(format!("{} {:04X}  {}", marker, address, text));
+        }
+    }
+
+    fn memory_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.heading("Memory");
+            ui.add(
+                egui::DragValue::new(&mut self.memory_address)
+                    .hexadecimal(4, false, true)
+                    .prefix("$"),
+            );
+        });
+        // Rows start on a multiple of 16 so the columns line up with the low nibble
+        let start = self.memory_address & 0xFFF0;
+        for row in 0..MEMORY_ROWS {
+            let address = start.wrapping_add(row * 16);
+            let bytes: Vec<u8> = (0..16)
+                .map(|i| self.gameboy_color.peek(address.wrapping_add(i)))
+                .collect();
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let text: String = bytes
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7E => byte as char,
+                    _ => '.',
+                })
+                .collect();
+            ui.monospace(format!("{:04X}  {}  {}", address, hex.join(" "), text));
+        }
+    }
+
+    fn tiles_panel(&mut self, ui: &mut egui::Ui) {
+        if self.device_mode == DeviceMode::GameBoyColor {
+            ui.horizontal(|ui| {
+                ui.label("Bank");
+                ui.selectable_value(&mut self.tile_bank, 0, "0");
+                ui.selectable_value(&mut self.tile_bank, 1, "1");
+            });
+        }
+        if let Some(tiles) = &self.tiles {
+            ui.add(egui::Image::new(tiles).fit_to_exact_size(egui::vec2(256.0, 384.0)));
+        }
+    }
+
+    fn bg_map_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.tile_map, TileMap::Low, "9800");
+            ui.selectable_value(&mut self.tile_map, TileMap::High, "9C00");
+        });
+        if let Some(bg_map) = &self.bg_map {
+            ui.add(egui::Image::new(bg_map).fit_to_exact_size(egui::vec2(256.0, 256.0)));
+        }
+    }
+
+    fn oam_panel(&self, ui: &mut egui::Ui) {
+        let cgb = self.device_mode == DeviceMode::GameBoyColor;
+        egui::ScrollArea::vertical()
+            .id_salt("oam")
+            .max_height(240.0)
+            .show(ui, |ui| {
+                egui::Grid::new("oam").striped(true).show(ui, |ui| {
+                    for heading in ["#", "X", "Y", "Tile", "Palette", "Flags"] {
+                        ui.strong(heading);
+                    }
+                    ui.end_row();
+                    for (index, entry) in self.gameboy_color.oam_entries().iter().enumerate() {
+                        let palette = if cgb {
+                            format!("OBJ{} bank {}", entry.cgb_palette, entry.bank)
+                        } else {
+                            format!("OBP{}", entry.dmg_palette)
+                        };
+                        ui.monospace(index.to_string());
+                        ui.monospace(format!("{:3}", entry.x));
+                        ui.monospace(format!("{:3}", entry.y));
+                        ui.monospace(format!("{:02X}", entry.tile));
+                        ui.monospace(palette);
+                        ui.monospace(format!(
+                            "{}{}{}",
+                            if entry.x_flip { "X" } else { "-" },
+                            if entry.y_flip { "Y" } else { "-" },
+                            if entry.behind_bg { "B" } else { "-" },
+                        ));
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    fn palettes_panel(&self, ui: &mut egui::Ui) {
+        for (name, palettes) in [
+            ("BG", self.gameboy_color.bg_palettes()),
+            ("OBJ", self.gameboy_color.obj_palettes()),
+        ] {
+            ui.label(name);
+            ui.horizontal_wrapped(|ui| {
+                for palette in palettes {
+                    for (r, g, b) in palette {
+                        let (rect, _) =
+                            ui.allocate_exact_size(egui::vec2(12.0, 12.0), egui::Sense::hover());
+                        ui.painter()
+                            .rect_filled(rect, 0.0, egui::Color32::from_rgb(r, g, b));
+                    }
+                    ui.add_space(6.0);
+                }
+            });
+        }
+    }
+}
+
+// Creates the texture the first time, then replaces its contents
+fn upload(
+    ctx: &egui::Context,
+    texture: &mut Option<egui::TextureHandle>,
+    name: &str,
+    image: &DebugImage,
+) {
+    let pixels = image
+        .pixels
+        .iter()
+        .flat_map(|&(r, g, b)| [r, g, b])
+        .collect::<Vec<u8>>();
+    let image = egui::ColorImage::from_rgb([image.width, image.height], &pixels);
+    match texture {
+        Some(texture) => texture.set(image, egui::TextureOptions::NEAREST),
+        None => *texture = Some(ctx.load_texture(name, image, egui::TextureOptions::NEAREST)),
+    }
+}
+
+impl eframe::App for Debugger {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.update_input(ctx);
+        if self.running {
+            self.gameboy_color.execute_frame();
+        }
+        self.update_textures(ctx);
+
+        egui::SidePanel::right("debug").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let label = if self.running { "Pause" } else { "Run" };
+                if ui.button(label).clicked() {
+                    self.running = !self.running;
+                }
+                if ui.button("Step frame").clicked() {
+                    self.running = false;
+                    self.gameboy_color.execute_frame();
+                }
+                if ui.button("Step instruction").clicked() {
+                    self.running = false;
+                    self.gameboy_color.execute_instruction();
+                }
+            });
+            ui.separator();
+            Self::registers_panel(ui, &self.gameboy_color.cpu_state());
+            ui.separator();
+            self.disassembly_panel(ui);
+        });
+
+        egui::SidePanel::left("video").show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::CollapsingHeader::new("Tiles")
+                    .default_open(true)
+                    .show(ui, |ui| self.tiles_panel(ui));
+                egui::CollapsingHeader::new("Background map").show(ui, |ui| self.bg_map_panel(ui));
+                egui::CollapsingHeader::new("OAM").show(ui, |ui| self.oam_panel(ui));
+                egui::CollapsingHeader::new("Palettes")
+                    .default_open(true)
+                    .show(ui, |ui| self.palettes_panel(ui));
+            });
+        });
+
+        egui::TopBottomPanel::bottom("memory").show(ctx, |ui| self.memory_panel(ui));
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label(self.gameboy_color.rom_name());
+            if let Some(screen) = &self.screen {
+                let size = egui::vec2(SCREEN_WIDTH as f32 * 3.0, SCREEN_HEIGHT as f32 * 3.0);
+                ui.add(egui::Image::new(screen).fit_to_exact_size(size));
+            }
+        });
+
+        ctx.request_repaint();
+    }
+}
+
+fn main() -> eframe::Result<()> {
+    let file_path = std::env::args()
+        .nth(1)
+        .expect("usage: egui_debugger <path to ROM>");
+    let file = std::fs::read(&file_path).expect("Failed to read ROM");
+    let device_mode = DeviceMode::GameBoyColor;
+    let gameboy_color =
+        GameBoyColor::new(&file, device_mode, None).expect("Failed to create emulator");
+
+    eframe::run_native(
+        "rust-cgb debugger",
+        eframe::NativeOptions::default(),
+        Box::new(|_cc| Ok(Box::new(Debugger::new(gameboy_color, device_mode)))),
+    )
+}
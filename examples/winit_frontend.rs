@@ -0,0 +1,190 @@
+//! Minimal player built on winit, softbuffer and cpal, for systems where SDL2 is hard to
+//! install. Keys follow the default `input::Bindings`.
+//!
+//! Run with `cargo run --example winit_frontend --features winit-frontend -- path/to/rom.gb`.
+
+use rust_gameboycolor::input::{Bindings, Hotkey};
+use rust_gameboycolor::{
+    utils, AudioOut, DeviceMode, GameBoyColor, JoypadKeyState, ResetKind, CLOCK_RATE,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+use std::num::NonZeroU32;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use winit::application::ApplicationHandler;
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, KeyEvent, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{Key, NamedKey};
+use winit::window::{Window, WindowId};
+
+const SCALE: u32 = 3;
+const CYCLES_PER_FRAME: u64 = 70224;
+
+struct Player {
+    gameboy_color: GameBoyColor,
+    bindings: Bindings,
+    key_state: JoypadKeyState,
+    audio: AudioOut,
+    window: Option<Rc<Window>>,
+    surface: Option<softbuffer::Surface<Rc<Window>, Rc<Window>>>,
+    rgba: Vec<u8>,
+    next_frame: Instant,
+    paused: bool,
+    fast_forward: bool,
+}
+
+impl Player {
+    fn new(gameboy_color: GameBoyColor, audio: AudioOut) -> Self {
+        Self {
+            gameboy_color,
+            bindings: Bindings::default(),
+            key_state: JoypadKeyState::new(),
+            audio,
+            window: None,
+            surface: None,
+            rgba: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+            next_frame: Instant::now(),
+            paused: false,
+            fast_forward: false,
+        }
+    }
+
+    fn run_frame(&mut self) {
+        let output = self.gameboy_color.run_frame(self.key_state);
+        if !self.fast_forward {
+            self.audio.push(output.audio);
+        }
+    }
+
+    // Scales the frame to the window with nearest-neighbour sampling
+    fn draw(&mut self) {
+        let (Some(window), Some(surface)) = (&self.window, &mut self.surface) else {
+            return;
+        };
+        let size = window.inner_size();
+        let (Some(width), Some(height)) =
+            (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+        else {
+            return;
+        };
+        surface.resize(width, height).unwrap();
+
+        self.gameboy_color.frame_buffer_rgba(&mut self.rgba);
+        let (width, height) = (width.get() as usize, height.get() as usize);
+        let mut buffer = surface.buffer_mut().unwrap();
+        for y in 0..height {
+            let source_row = y * SCREEN_HEIGHT / height * SCREEN_WIDTH;
+            for x in 0..width {
+                let pixel = &self.rgba[(source_row + x * SCREEN_WIDTH / width) * 4..][..3];
+                buffer[y * width + x] =
+                    (pixel[0] as u32) << 16 | (pixel[1] as u32) << 8 | pixel[2] as u32;
+            }
+        }
+        buffer.present().unwrap();
+    }
+
+    fn handle_key(&mut self, key: &Key, pressed: bool) {
+        let Some(name) = key_name(key) else {
+            return;
+        };
+        match self.bindings.apply(&name, pressed, &mut self.key_state) {
+            Some(Hotkey::Pause) => self.paused = !self.paused,
+            Some(Hotkey::Reset) => self.gameboy_color.reset(ResetKind::Soft),
+            Some(Hotkey::FastForward) => {
+                self.fast_forward = !self.fast_forward;
+                self.audio.clear();
+            }
+            None => {}
+        }
+    }
+}
+
+impl ApplicationHandler for Player {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+        let attributes = Window::default_attributes()
+            .with_title(self.gameboy_color.rom_name())
+            .with_inner_size(LogicalSize::new(
+                SCREEN_WIDTH as u32 * SCALE,
+                SCREEN_HEIGHT as u32 * SCALE,
+            ));
+        let window = Rc::new(event_loop.create_window(attributes).unwrap());
+        let context = softbuffer::Context::new(window.clone()).unwrap();
+        self.surface = Some(softbuffer::Surface::new(&context, window.clone()).unwrap());
+        self.window = Some(window);
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key,
+                        state,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => self.handle_key(&logical_key, state == ElementState::Pressed),
+            WindowEvent::RedrawRequested => self.draw(),
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let now = Instant::now();
+        if !self.paused && (self.fast_forward || now >= self.next_frame) {
+            self.run_frame();
+            let frame_time = Duration::from_secs_f64(CYCLES_PER_FRAME as f64 / CLOCK_RATE as f64);
+            // Catch up after a stall instead of running a burst of frames
+            self.next_frame = (self.next_frame + frame_time).max(now);
+            if let Some(window) = &self.window {
+                window.request_redraw();
+            }
+        }
+        event_loop.set_control_flow(if self.fast_forward && !self.paused {
+            ControlFlow::Poll
+        } else {
+            ControlFlow::WaitUntil(self.next_frame)
+        });
+    }
+}
+
+// Names in the style of SDL, which the default bindings use
+fn key_name(key: &Key) -> Option<String> {
+    let name = match key {
+        Key::Named(NamedKey::ArrowRight) => "Right",
+        Key::Named(NamedKey::ArrowLeft) => "Left",
+        Key::Named(NamedKey::ArrowUp) => "Up",
+        Key::Named(NamedKey::ArrowDown) => "Down",
+        Key::Named(NamedKey::Enter) => "Return",
+        Key::Named(NamedKey::Space) => "Space",
+        Key::Named(NamedKey::Tab) => "Tab",
+        Key::Named(NamedKey::Backspace) => "Backspace",
+        Key::Character(character) => return Some(character.to_uppercase()),
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let file_path = std::env::args()
+        .nth(1)
+        .expect("usage: winit_frontend <path to ROM>");
+    let file = std::fs::read(&file_path)?;
+    let mut gameboy_color = GameBoyColor::new(&file, DeviceMode::GameBoyColor, None)?;
+
+    let audio = AudioOut::new()?;
+    gameboy_color.set_sample_rate(audio.sample_rate());
+    let mut player = Player::new(gameboy_color, audio);
+    EventLoop::new()?.run_app(&mut player)?;
+
+    if let Some(save_data) = player.gameboy_color.save_data() {
+        utils::save_data(player.gameboy_color.rom_name(), &save_data)?;
+    }
+    Ok(())
+}
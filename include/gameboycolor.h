@@ -0,0 +1,86 @@
+/*
+ * C interface to rust-gameboycolor, exported by the shared library built with the
+ * `capi` feature:
+ *
+ *     cargo rustc --release --lib --crate-type cdylib --features capi
+ *
+ * Functions returning int return 0 on success and -1 on failure, with the reason in
+ * gbc_last_error. A handle must only be used by one thread at a time.
+ */
+
+#ifndef GAMEBOYCOLOR_H
+#define GAMEBOYCOLOR_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define GBC_SCREEN_WIDTH 160
+#define GBC_SCREEN_HEIGHT 144
+
+/* Bits for gbc_set_keys */
+#define GBC_KEY_RIGHT (1u << 0)
+#define GBC_KEY_LEFT (1u << 1)
+#define GBC_KEY_UP (1u << 2)
+#define GBC_KEY_DOWN (1u << 3)
+#define GBC_KEY_A (1u << 4)
+#define GBC_KEY_B (1u << 5)
+#define GBC_KEY_SELECT (1u << 6)
+#define GBC_KEY_START (1u << 7)
+
+typedef struct GbcEmulator GbcEmulator;
+
+/* An emulator with no game loaded, freed with gbc_destroy. */
+GbcEmulator *gbc_create(void);
+void gbc_destroy(GbcEmulator *emulator);
+
+/*
+ * Powers on with a copy of `rom`, as a Game Boy Color or, unless `color` is set, a
+ * Game Boy. Starts from the game's save in the application data directory, if any.
+ */
+int gbc_load_rom(GbcEmulator *emulator, const uint8_t *rom, size_t len, bool color);
+
+/*
+ * Runs one frame with the keys last set, queueing its audio. If the core panics, the
+ * game is unloaded and -1 returned; gbc_load_rom loads it again.
+ */
+int gbc_run_frame(GbcEmulator *emulator);
+
+/*
+ * The last frame, GBC_SCREEN_WIDTH * GBC_SCREEN_HEIGHT RGBA8 pixels in row-major
+ * order. The pointer stays valid until the handle is destroyed.
+ */
+const uint8_t *gbc_framebuffer(const GbcEmulator *emulator);
+
+/*
+ * Moves up to `max_samples` queued stereo samples into `out`, interleaved left then
+ * right, and returns how many were moved. `out` needs room for max_samples * 2 values.
+ */
+size_t gbc_audio_pull(GbcEmulator *emulator, int16_t *out, size_t max_samples);
+
+/* Between 8000 and 192000 Hz; the default is 48000. */
+int gbc_set_sample_rate(GbcEmulator *emulator, uint32_t hz);
+
+/* Holds the keys whose GBC_KEY_* bits are set until the next call. */
+void gbc_set_keys(GbcEmulator *emulator, uint8_t keys);
+
+/*
+ * Battery-backed RAM. gbc_save_size is 0 if the game keeps none, gbc_save copies it
+ * into a buffer of exactly that size, and gbc_load_save powers on again from it.
+ */
+size_t gbc_save_size(const GbcEmulator *emulator);
+int gbc_save(GbcEmulator *emulator, uint8_t *out, size_t len);
+int gbc_load_save(GbcEmulator *emulator, const uint8_t *data, size_t len);
+
+/* Why the last failing call failed, valid until the next call. */
+const char *gbc_last_error(const GbcEmulator *emulator);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
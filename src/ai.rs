@@ -0,0 +1,229 @@
+//! An environment for training agents, in the style of OpenAI Gym: [`reset`] with a seed,
+//! [`step`] with the buttons to hold, and get back the screen and a reward computed from
+//! game memory.
+//!
+//! [`reset`]: Environment::reset
+//! [`step`]: Environment::step
+
+use crate::config::{EmulatorConfigBuilder, UnpredictableValues};
+use crate::context::EmulatorError;
+use crate::gameboycolor::GameBoyColor;
+use crate::interface::FixedClock;
+use crate::joypad::JoypadKeyState;
+use crate::rng::Rng;
+use crate::watch::WatchEncoding;
+use crate::DeviceMode;
+
+/// Scores each frame the agent plays. Closures taking the emulator work as rewards.
+pub trait Reward {
+    /// Called after every reset, before the first step.
+    fn reset(&mut self, _gameboy: &GameBoyColor) {}
+
+    fn reward(&mut self, gameboy: &GameBoyColor) -> f64;
+}
+
+impl<F: FnMut(&GameBoyColor) -> f64> Reward for F {
+    fn reward(&mut self, gameboy: &GameBoyColor) -> f64 {
+        self(gameboy)
+    }
+}
+
+/// Rewards the change since the last frame in a value in memory, such as the score or
+/// the player's X position.
+#[derive(Debug, Clone)]
+pub struct RamDelta {
+    address: u16,
+    size: usize,
+    encoding: WatchEncoding,
+    scale: f64,
+    last: u64,
+}
+
+impl RamDelta {
+    /// The `size` bytes from `address`, at most 8, as the CPU sees them.
+    pub fn new(address: u16, size: usize, encoding: WatchEncoding) -> Self {
+        assert!((1..=8).contains(&size), "Invalid size: {}", size);
+        Self {
+            address,
+            size,
+            encoding,
+            scale: 1.0,
+            last: 0,
+        }
+    }
+
+    /// Multiplies the reward, for example by -1 to penalise a value going up.
+    pub fn scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    fn read(&self, gameboy: &GameBoyColor) -> u64 {
+        let bytes: Vec<u8> = (0..self.size)
+            .map(|offset| gameboy.peek(self.address.wrapping_add(offset as u16)))
+            .collect();
+        self.encoding.decode(&bytes)
+    }
+}
+
+impl Reward for RamDelta {
+    fn reset(&mut self, gameboy: &GameBoyColor) {
+        self.last = self.read(gameboy);
+    }
+
+    fn reward(&mut self, gameboy: &GameBoyColor) -> f64 {
+        let value = self.read(gameboy);
+        let delta = value as f64 - self.last as f64;
+        self.last = value;
+        delta * self.scale
+    }
+}
+
+type Termination = Box<dyn FnMut(&GameBoyColor) -> bool>;
+
+/// Result of [`Environment::step`].
+#[derive(Debug)]
+pub struct Step<'a> {
+    /// `SCREEN_WIDTH * SCREEN_HEIGHT` pixels in row-major order.
+    pub screen: &'a [(u8, u8, u8)],
+    /// The sum of every reward over the frames played.
+    pub reward: f64,
+    /// The termination condition was met; reset before stepping again.
+    pub terminated: bool,
+    /// The episode reached its step limit without terminating.
+    pub truncated: bool,
+}
+
+/// A game played one action at a time.
+///
+/// Each reset powers on afresh, with blank cartridge RAM instead of the save on disk and
+/// a cartridge clock that starts at 0 and follows emulated time, so the same seed and
+/// actions always play out the same way. Observations other than the screen can be read
+/// from memory through [`gameboy`](Self::gameboy).
+pub struct Environment {
+    rom: Vec<u8>,
+    device_mode: DeviceMode,
+    gameboy: GameBoyColor,
+    rewards: Vec<Box<dyn Reward>>,
+    termination: Option<Termination>,
+    frame_skip: u32,
+    noop_max: u32,
+    max_steps: Option<u64>,
+    steps: u64,
+}
+
+impl Environment {
+    /// Powers on with seed 0. Call [`reset`](Self::reset) before the first step so the
+    /// rewards start from the console's state.
+    pub fn new(rom: &[u8], device_mode: DeviceMode) -> Result<Self, EmulatorError> {
+        Ok(Self {
+            rom: rom.to_vec(),
+            device_mode,
+            gameboy: Self::power_on(rom, device_mode, 0)?,
+            rewards: Vec::new(),
+            termination: None,
+            frame_skip: 4,
+            noop_max: 0,
+            max_steps: None,
+            steps: 0,
+        })
+    }
+
+    fn power_on(
+        rom: &[u8],
+        device_mode: DeviceMode,
+        seed: u64,
+    ) -> Result<GameBoyColor, EmulatorError> {
+        let config = EmulatorConfigBuilder::default()
+            .device_mode(device_mode)
+            .unpredictable_values(UnpredictableValues::Seeded(seed))
+            .clock_source(Box::new(FixedClock(0)))
+            .emulated_rtc(true)
+            .save_data(Vec::new())
+            .build()?;
+        GameBoyColor::with_config(rom, config)
+    }
+
+    /// Adds a reward to the sum each step returns.
+    pub fn add_reward(&mut self, reward: impl Reward + 'static) {
+        self.rewards.push(Box::new(reward));
+    }
+
+    /// Ends the episode once `condition` holds after a frame, such as when the lives
+    /// counter reaches 0.
+    pub fn set_termination(&mut self, condition: impl FnMut(&GameBoyColor) -> bool + 'static) {
+        self.termination = Some(Box::new(condition));
+    }
+
+    /// Frames each action is held for. Defaults to 4.
+    pub fn set_frame_skip(&mut self, frames: u32) {
+        self.frame_skip = frames.max(1);
+    }
+
+    /// Runs between 0 and `frames` frames without input after each reset, chosen by the
+    /// seed, so episodes don't all start on the same frame. Defaults to 0.
+    pub fn set_noop_max(&mut self, frames: u32) {
+        self.noop_max = frames;
+    }
+
+    /// Marks the episode truncated after `steps` steps.
+    pub fn set_max_steps(&mut self, steps: Option<u64>) {
+        self.max_steps = steps;
+    }
+
+    /// Powers on again from `seed`, which picks the contents of RAM at power-on and the
+    /// number of no-op frames, and returns the first screen.
+    pub fn reset(&mut self, seed: u64) -> &[(u8, u8, u8)] {
+        self.gameboy = Self::power_on(&self.rom, self.device_mode, seed)
+            .expect("The ROM was accepted by Environment::new");
+        let noops = Rng::new(seed).next_u64() % (self.noop_max as u64 + 1);
+        for _ in 0..noops {
+            self.gameboy.run_frame(JoypadKeyState::new());
+        }
+        for reward in &mut self.rewards {
+            reward.reset(&self.gameboy);
+        }
+        self.steps = 0;
+        self.gameboy.frame_buffer()
+    }
+
+    /// Holds `action` for the frame skip, stopping early if the episode terminates.
+    pub fn step(&mut self, action: JoypadKeyState) -> Step<'_> {
+        let mut reward = 0.0;
+        let mut terminated = false;
+        for _ in 0..self.frame_skip {
+            self.gameboy.run_frame(action);
+            for source in &mut self.rewards {
+                reward += source.reward(&self.gameboy);
+            }
+            if let Some(condition) = &mut self.termination {
+                if condition(&self.gameboy) {
+                    terminated = true;
+                    break;
+                }
+            }
+        }
+        self.steps += 1;
+        let truncated = !terminated && self.max_steps.is_some_and(|max| self.steps >= max);
+        Step {
+            screen: self.gameboy.frame_buffer(),
+            reward,
+            terminated,
+            truncated,
+        }
+    }
+
+    /// Steps taken since the last reset.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn gameboy(&self) -> &GameBoyColor {
+        &self.gameboy
+    }
+
+    /// For setting up a scenario, such as writing a level number to RAM, before stepping.
+    pub fn gameboy_mut(&mut self) -> &mut GameBoyColor {
+        &mut self.gameboy
+    }
+}
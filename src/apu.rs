@@ -1,14 +1,29 @@
-use crate::config::Speed;
+use crate::config::{DeviceMode, Model, Speed};
 use crate::context;
+use crate::event::Subsystem;
+use crate::gameboycolor::CLOCK_RATE;
+use crate::state_hash::StateHasher;
 
-use log::warn;
 use modular_bitfield::prelude::*;
 
-const CYCLES_PER_FRAME: u32 = 70224;
-const SAMPLE_PER_FRAME: u32 = 800;
+const DOTS_PER_FRAME: u64 = 70224;
+pub(crate) const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+// Keeps the sum of a sample's dots well within an i32
+pub(crate) const SAMPLE_RATES: std::ops::RangeInclusive<u32> = 8_000..=192_000;
 
-trait Context: context::Config {}
-impl<T> Context for T where T: context::Config {}
+pub(crate) type AudioCallback = Box<dyn FnMut(&[[i16; 2]])>;
+
+trait Context: context::Config + context::Event {}
+impl<T> Context for T where T: context::Config + context::Event {}
+
+/// One of the four sound channels, in NR51 bit order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Wave,
+    Noise,
+}
 
 #[derive(Debug, Default)]
 pub struct Apu {
@@ -23,7 +38,25 @@ pub struct Apu {
     panning: [[bool; 4]; 2],     // 0xFF25
 
     frame_sequencer: FrameSequencer,
+    sample_rate: u32,
+    // Advances by the sample rate for every dot, and a sample is due each time it passes
+    // CLOCK_RATE, so the rate holds exactly over time
     sample_counter: u32,
+    // Sum of the mixed output over the dots since the last sample, averaged into the next
+    // one so tones above the output rate do not alias
+    accumulated: [i32; 2],
+    accumulated_dots: i32,
+
+    // Host-side volume applied after NR50, independent of the emulated hardware
+    output_volume: f32,
+    muted: bool,
+    channel_enabled: [bool; 4],
+
+    // Each channel's own output before panning and NR50, averaged like the mix into
+    // channel_buffer, one entry per sample in audio_buffer, while it is captured
+    capture_channels: bool,
+    channel_buffer: Vec<[i16; 4]>,
+    channel_accumulated: [i32; 4],
 }
 
 impl Apu {
@@ -34,12 +67,83 @@ impl Apu {
             noise: Noise::new(),
 
             frame_sequencer: FrameSequencer::new(), // 512 Hz
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            output_volume: 1.0,
+            channel_enabled: [true; 4],
+            audio_buffer: Vec::with_capacity(buffer_capacity(DEFAULT_SAMPLE_RATE)),
 
             ..Default::default()
         }
     }
 
-    pub fn read(&self, address: u16) -> u8 {
+    // Back to the power-on state, keeping the frontend's settings and the samples
+    // already produced this frame
+    pub fn reset(&mut self) {
+        let audio_buffer = std::mem::take(&mut self.audio_buffer);
+        let channel_buffer = std::mem::take(&mut self.channel_buffer);
+        *self = Self {
+            audio_buffer,
+            sample_rate: self.sample_rate,
+            output_volume: self.output_volume,
+            muted: self.muted,
+            channel_enabled: self.channel_enabled,
+            capture_channels: self.capture_channels,
+            channel_buffer,
+            ..Self::new()
+        };
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        // Listing every field makes a new one fail to build until it is hashed or skipped
+        let Apu {
+            is_on,
+            pulse,
+            wave,
+            noise,
+            master_volume,
+            panning,
+            frame_sequencer: FrameSequencer { counter, step },
+            // Output and frontend settings
+            audio_buffer: _,
+            sample_rate: _,
+            sample_counter: _,
+            accumulated: _,
+            accumulated_dots: _,
+            output_volume: _,
+            muted: _,
+            channel_enabled: _,
+            capture_channels: _,
+            channel_buffer: _,
+            channel_accumulated: _,
+        } = self;
+        let mut hasher = StateHasher::default();
+        hasher
+            .write(is_on)
+            .write(&master_volume.bytes[0])
+            .write(panning)
+            .write(counter)
+            .write(step);
+        for pulse in pulse {
+            pulse.hash_state(&mut hasher);
+        }
+        wave.hash_state(&mut hasher);
+        noise.hash_state(&mut hasher);
+        hasher.finish()
+    }
+
+    // Sound on, with channel 1 set up as the boot chime leaves it, still on but faded to
+    // silence so NR52 reads 0xF1. The SGB boot ROM plays no chime.
+    pub fn skip_boot(&mut self, model: Model) {
+        self.is_on = true;
+        self.master_volume = MasterVolume::from_bytes([0x77]);
+        self.set_panning(0xF3);
+        self.pulse[0].write(0, 0x80, false);
+        self.pulse[0].write(1, 0xBF, false);
+        self.pulse[0].write(2, 0xF3, false);
+        self.pulse[0].is_on = !matches!(model, Model::Sgb | Model::Sgb2);
+    }
+
+    pub fn read(&self, context: &mut impl Context, address: u16) -> u8 {
         match address {
             0xFF10..=0xFF14 => {
                 let offset = address - 0xFF10;
@@ -68,46 +172,122 @@ impl Apu {
                 ret |= (self.wave.is_on as u8) << 2;
                 ret |= (self.noise.is_on as u8) << 3;
                 ret |= (self.is_on as u8) << 7;
-                ret
+                // Bits 4-6 are unused
+                ret | 0x70
             }
 
             0xFF30..=0xFF3F => {
                 let offset = (address - 0xFF30) as usize;
-                self.wave.ram[offset]
+                self.wave.read_ram(offset, context.device_mode())
             }
             _ => {
-                warn!("Apu read not implemented: {:#06X}", address);
+                context.warn(
+                    Subsystem::Apu,
+                    format!("Apu read not implemented: {:#06X}", address),
+                );
                 0x00
             }
         }
     }
 
-    pub fn write(&mut self, address: u16, value: u8) {
+    pub fn write(&mut self, context: &mut impl Context, address: u16, value: u8) {
+        let first_half = self.frame_sequencer.in_first_half();
         match address {
+            // Only NR52 and wave RAM take writes while the APU is off, apart from the
+            // length counters on DMG
+            0xFF10..=0xFF25 if !self.is_on => {
+                if context.device_mode() == DeviceMode::GameBoy {
+                    self.write_length(address, value);
+                }
+            }
             0xFF10..=0xFF14 => {
                 let offset = address - 0xFF10;
-                self.pulse[0].write(offset, value);
+                self.pulse[0].write(offset, value, first_half);
             }
             0xFF16..=0xFF19 => {
                 let offset = address - 0xFF15;
-                self.pulse[1].write(offset, value);
+                self.pulse[1].write(offset, value, first_half);
+            }
+            0xFF1A..=0xFF1E => {
+                if address == 0xFF1E
+                    && value & 0x80 != 0
+                    && context.device_mode() == DeviceMode::GameBoy
+                {
+                    self.wave.corrupt_ram_on_retrigger();
+                }
+                self.wave.write(address, value, first_half)
             }
-            0xFF1A..=0xFF1E => self.wave.write(address, value),
-            0xFF20..=0xFF23 => self.noise.write(address, value),
+            0xFF20..=0xFF23 => self.noise.write(address, value, first_half),
             0xFF24 => self.master_volume = MasterVolume::from_bytes([value]),
-            0xFF25 => {
-                for i in 0..2 {
-                    for j in 0..4 {
-                        self.panning[i][j] = (value >> (i * 4 + j)) & 1 == 1;
-                    }
+            0xFF25 => self.set_panning(value),
+            0xFF26 => {
+                let is_on = (value >> 7) & 1 == 1;
+                if self.is_on && !is_on {
+                    self.power_off(context.device_mode());
+                } else if !self.is_on && is_on {
+                    // The first step after powering on is step 0
+                    self.frame_sequencer = FrameSequencer::new();
                 }
+                self.is_on = is_on;
             }
-            0xFF26 => self.is_on = (value >> 7) & 1 == 1,
             0xFF30..=0xFF3F => {
                 let offset = (address - 0xFF30) as usize;
                 self.wave.ram[offset] = value;
             }
-            _ => warn!("Apu write not implemented: {:#06X}", address),
+            _ => context.warn(
+                Subsystem::Apu,
+                format!("Apu write not implemented: {:#06X}", address),
+            ),
+        }
+    }
+
+    // Powering down clears every sound register, so NR52 reports all channels off.
+    // Wave RAM is left alone, and so are the length counters on DMG
+    fn power_off(&mut self, device_mode: DeviceMode) {
+        let keep_lengths = device_mode == DeviceMode::GameBoy;
+        let pulse = |old: &Pulse| Pulse {
+            length_timer: if keep_lengths { old.length_timer } else { 0 },
+            ..Pulse::new()
+        };
+        self.pulse = [pulse(&self.pulse[0]), pulse(&self.pulse[1])];
+        self.wave = Wave {
+            ram: self.wave.ram,
+            length_timer: if keep_lengths {
+                self.wave.length_timer
+            } else {
+                0
+            },
+            ..Wave::new()
+        };
+        self.noise = Noise {
+            length_timer: if keep_lengths {
+                self.noise.length_timer
+            } else {
+                0
+            },
+            ..Noise::new()
+        };
+        self.master_volume = MasterVolume::default();
+        self.panning = Default::default();
+        self.frame_sequencer = FrameSequencer::new();
+    }
+
+    // The length half of NR11, NR21, NR31 and NR41; other registers are ignored
+    fn write_length(&mut self, address: u16, value: u8) {
+        match address {
+            0xFF11 => self.pulse[0].write_length(value),
+            0xFF16 => self.pulse[1].write_length(value),
+            0xFF1B => self.wave.write(address, value, false),
+            0xFF20 => self.noise.write(address, value, false),
+            _ => {}
+        }
+    }
+
+    fn set_panning(&mut self, value: u8) {
+        for i in 0..2 {
+            for j in 0..4 {
+                self.panning[i][j] = (value >> (i * 4 + j)) & 1 == 1;
+            }
         }
     }
 
@@ -116,9 +296,42 @@ impl Apu {
             Speed::Normal => 4,
             Speed::Double => 2,
         };
+        self.wave.fetched = false;
         for _ in 0..tick_count {
             self.tick_();
         }
+
+        // Mixed once per M-cycle, still far above any output rate
+        if self.is_on && !self.muted {
+            let [left, right] = self.mix();
+            self.accumulated[0] += left * tick_count;
+            self.accumulated[1] += right * tick_count;
+        }
+        if self.is_on && self.capture_channels {
+            let outputs = self.channel_outputs();
+            for (sum, output) in self.channel_accumulated.iter_mut().zip(outputs) {
+                *sum += output as i32 * tick_count;
+            }
+        }
+        self.accumulated_dots += tick_count;
+
+        self.sample_counter += self.sample_rate * tick_count as u32;
+        if self.sample_counter >= CLOCK_RATE as u32 {
+            self.sample_counter -= CLOCK_RATE as u32;
+            let dots = std::mem::take(&mut self.accumulated_dots);
+            let output = self.take_sample(dots);
+            let channels =
+                std::mem::take(&mut self.channel_accumulated).map(|sum| (sum / dots) as i16);
+            // A full buffer would have to grow, so drop the sample instead when allocation is off-limits
+            if cfg!(not(feature = "static-buffers"))
+                || self.audio_buffer.len() < self.audio_buffer.capacity()
+            {
+                self.audio_buffer.push(output);
+                if self.capture_channels {
+                    self.channel_buffer.push(channels);
+                }
+            }
+        }
     }
 
     fn tick_(&mut self) {
@@ -131,31 +344,27 @@ impl Apu {
             self.wave.tick(should_length_tick);
             self.noise.tick(should_length_tick, should_volume_tick);
         }
-
-        self.sample_counter += SAMPLE_PER_FRAME;
-        if self.sample_counter >= CYCLES_PER_FRAME {
-            self.sample_counter -= CYCLES_PER_FRAME;
-            let output = self.mix_output();
-            self.audio_buffer.push(output);
-        }
     }
 
-    fn mix_output(&mut self) -> [i16; 2] {
-        if !self.is_on {
-            return [0, 0];
-        }
+    // The mean of the dots since the last sample, at the host volume
+    fn take_sample(&mut self, dots: i32) -> [i16; 2] {
+        let [left, right] = std::mem::take(&mut self.accumulated).map(|sum| sum / dots);
+        let scale = |out: i32| {
+            (out as f32 * self.output_volume)
+                .round()
+                .clamp(i16::MIN as f32, i16::MAX as f32) as i16
+        };
+        [scale(left), scale(right)]
+    }
 
-        let channel_output = [
-            self.pulse[0].output(),
-            self.pulse[1].output(),
-            self.wave.output(),
-            self.noise.output(),
-        ];
+    // Left and right output for the current dot after NR50 and NR51
+    fn mix(&self) -> [i32; 2] {
+        let channel_output = self.channel_outputs();
         let mut output = [0, 0];
 
         for (i, out) in output.iter_mut().enumerate() {
             for (ch_idx, ch_output) in channel_output.iter().enumerate() {
-                if self.panning[i][ch_idx] {
+                if self.panning[i][ch_idx] && self.channel_enabled[ch_idx] {
                     *out += *ch_output as i32;
                 }
             }
@@ -166,7 +375,47 @@ impl Apu {
             }
         }
 
-        [output[1] as i16, output[0] as i16]
+        [output[1], output[0]]
+    }
+
+    fn channel_outputs(&self) -> [i16; 4] {
+        [
+            self.pulse[0].output(),
+            self.pulse[1].output(),
+            self.wave.output(),
+            self.noise.output(),
+        ]
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        let capacity = buffer_capacity(sample_rate);
+        self.audio_buffer
+            .reserve(capacity.saturating_sub(self.audio_buffer.len()));
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_output_volume(&mut self, volume: f32) {
+        self.output_volume = if volume.is_finite() {
+            volume.max(0.0)
+        } else {
+            0.0
+        };
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.channel_enabled[channel as usize] = enabled;
+    }
+
+    pub fn channel_enabled(&self, channel: Channel) -> bool {
+        self.channel_enabled[channel as usize]
     }
 
     pub fn get_audio_buffer(&self) -> &Vec<[i16; 2]> {
@@ -175,7 +424,32 @@ impl Apu {
 
     pub fn clear_audio_buffer(&mut self) {
         self.audio_buffer.clear();
+        self.channel_buffer.clear();
     }
+
+    // Starts with silence for the samples already in the audio buffer, so the two stay
+    // in step
+    pub fn set_capture_channels(&mut self, capture: bool) {
+        self.capture_channels = capture;
+        self.channel_buffer.clear();
+        self.channel_accumulated = [0; 4];
+        if capture {
+            self.channel_buffer
+                .reserve(buffer_capacity(self.sample_rate));
+            self.channel_buffer.resize(self.audio_buffer.len(), [0; 4]);
+        }
+    }
+
+    // Pulse 1, pulse 2, wave and noise for each sample in the audio buffer, empty
+    // unless they are captured
+    pub fn channel_buffer(&self) -> &[[i16; 4]] {
+        &self.channel_buffer
+    }
+}
+
+// Room for a few frames of samples between clears
+fn buffer_capacity(sample_rate: u32) -> usize {
+    (sample_rate as u64 * DOTS_PER_FRAME * 4 / CLOCK_RATE) as usize + 4
 }
 
 static WAVEFORM: [[u8; 8]; 4] = [
@@ -214,6 +488,44 @@ impl Pulse {
         }
     }
 
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Pulse {
+            is_on,
+            sweep,
+            length_timer,
+            wave_duty,
+            envelope_period,
+            envelope_direction,
+            initial_volume,
+            frequency,
+            length_enable,
+            current_volume,
+            current_frequency,
+            frequency_timer,
+            envelope_timer,
+            sweep_timer,
+            sweep_enable,
+            phase,
+        } = self;
+        hasher
+            .write(is_on)
+            .write(&sweep.bytes[0])
+            .write(length_timer)
+            .write(wave_duty)
+            .write(envelope_period)
+            .write(&(*envelope_direction as u8))
+            .write(initial_volume)
+            .write(frequency)
+            .write(length_enable)
+            .write(current_volume)
+            .write(current_frequency)
+            .write(frequency_timer)
+            .write(envelope_timer)
+            .write(sweep_timer)
+            .write(sweep_enable)
+            .write(phase);
+    }
+
     fn read(&self, offset: u16) -> u8 {
         match offset {
             0 => 0x80 | self.sweep.bytes[0],
@@ -230,24 +542,36 @@ impl Pulse {
         }
     }
 
-    fn write(&mut self, offset: u16, value: u8) {
+    fn write(&mut self, offset: u16, value: u8, first_half: bool) {
         match offset {
             0 => self.sweep = Sweep::from_bytes([value]),
             1 => {
                 self.wave_duty = value >> 6;
-                self.length_timer = 64 - (value & 0x3F);
+                self.write_length(value);
             }
             2 => {
                 self.envelope_period = value & 0x07;
                 self.envelope_direction = EnvelopeDirection::from(value >> 3 & 1);
                 self.initial_volume = value >> 4;
+                if !self.dac_enabled() {
+                    self.is_on = false;
+                }
             }
             3 => self.frequency = (self.frequency & 0x0700) | value as u16,
             4 => {
                 self.frequency = (self.frequency & 0x00FF) | ((value as u16 & 0x07) << 8);
-                self.length_enable = (value >> 6) & 1 == 1;
-                if value >> 7 & 1 == 1 {
-                    self.trigger();
+                let length_enable = (value >> 6) & 1 == 1;
+                let trigger = value >> 7 & 1 == 1;
+                // Enabling length when the next step won't clock it clocks it once now
+                if first_half && !self.length_enable && length_enable && self.length_timer != 0 {
+                    self.length_timer -= 1;
+                    if self.length_timer == 0 && !trigger {
+                        self.is_on = false;
+                    }
+                }
+                self.length_enable = length_enable;
+                if trigger {
+                    self.trigger(first_half);
                 }
             }
 
@@ -255,6 +579,10 @@ impl Pulse {
         }
     }
 
+    fn write_length(&mut self, value: u8) {
+        self.length_timer = 64 - (value & 0x3F);
+    }
+
     fn tick(
         &mut self,
         should_length_tick: bool,
@@ -279,7 +607,11 @@ impl Pulse {
     }
 
     fn length_tick(&mut self) {
-        self.length_timer = self.length_timer.saturating_sub(1);
+        // A counter already at 0 stays frozen there until the next trigger
+        if self.length_timer == 0 {
+            return;
+        }
+        self.length_timer -= 1;
         if self.length_timer == 0 {
             self.is_on = false;
         }
@@ -327,12 +659,21 @@ impl Pulse {
         }
     }
 
-    fn trigger(&mut self) {
-        self.is_on =
-            self.initial_volume != 0 || self.envelope_direction == EnvelopeDirection::Increase;
+    // The DAC is powered by the upper 5 bits of NRx2; with it off the channel can't run
+    fn dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.envelope_direction == EnvelopeDirection::Increase
+    }
+
+    fn trigger(&mut self, first_half: bool) {
+        self.is_on = self.dac_enabled();
 
+        // A reloaded length counter is clocked straight away in the same situation
         if self.length_timer == 0 {
-            self.length_timer = 64;
+            self.length_timer = if self.length_enable && first_half {
+                63
+            } else {
+                64
+            };
         }
         self.frequency_timer = (2048 - self.frequency) * 4;
         self.envelope_timer = if self.envelope_period == 0 {
@@ -379,6 +720,8 @@ struct Wave {
     frequency_timer: u16,
     ram_index: usize,
     current_sample: u8,
+    // A sample was fetched from wave RAM during the current M-cycle
+    fetched: bool,
 }
 
 impl Wave {
@@ -388,6 +731,34 @@ impl Wave {
         }
     }
 
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Wave {
+            is_on,
+            dac_enable,
+            length_timer,
+            output_level,
+            frequency,
+            length_enable,
+            ram,
+            frequency_timer,
+            ram_index,
+            current_sample,
+            fetched,
+        } = self;
+        hasher
+            .write(is_on)
+            .write(dac_enable)
+            .write(length_timer)
+            .write(output_level)
+            .write(frequency)
+            .write(length_enable)
+            .write(ram)
+            .write(frequency_timer)
+            .write(ram_index)
+            .write(current_sample)
+            .write(fetched);
+    }
+
     fn read(&self, address: u16) -> u8 {
         match address {
             0xFF1A => (self.dac_enable as u8) << 7 | 0x7F,
@@ -399,27 +770,72 @@ impl Wave {
         }
     }
 
-    fn write(&mut self, address: u16, value: u8) {
+    fn write(&mut self, address: u16, value: u8, first_half: bool) {
         match address {
-            0xFF1A => self.dac_enable = (value >> 7) & 1 == 1,
+            0xFF1A => {
+                self.dac_enable = (value >> 7) & 1 == 1;
+                if !self.dac_enable {
+                    self.is_on = false;
+                }
+            }
             0xFF1B => self.length_timer = 256 - value as u16,
             0xFF1C => self.output_level = (value >> 5) & 3,
             0xFF1D => self.frequency = (self.frequency & 0x0700) | value as u16,
             0xFF1E => {
                 self.frequency = (self.frequency & 0x00FF) | ((value as u16 & 0x07) << 8);
-                self.length_enable = (value >> 6) & 1 == 1;
-                if value >> 7 & 1 == 1 {
-                    self.trigger();
+                let length_enable = (value >> 6) & 1 == 1;
+                let trigger = value >> 7 & 1 == 1;
+                if first_half && !self.length_enable && length_enable && self.length_timer != 0 {
+                    self.length_timer -= 1;
+                    if self.length_timer == 0 && !trigger {
+                        self.is_on = false;
+                    }
+                }
+                self.length_enable = length_enable;
+                if trigger {
+                    self.trigger(first_half);
                 }
             }
             _ => unreachable!("Wave invalid write address: {:#06X}", address),
         }
     }
 
-    fn trigger(&mut self) {
+    // While the channel plays, the CPU sees the byte it is reading instead of the one
+    // addressed. A DMG only gets that byte in the cycle it was fetched, and 0xFF otherwise
+    fn read_ram(&self, offset: usize, device_mode: DeviceMode) -> u8 {
+        if !self.is_on {
+            self.ram[offset]
+        } else if device_mode == DeviceMode::GameBoy && !self.fetched {
+            0xFF
+        } else {
+            self.ram[self.ram_index / 2]
+        }
+    }
+
+    // On DMG, retriggering just as the channel fetches its next byte overwrites the start
+    // of wave RAM: byte 0 with that byte if it is one of the first four, otherwise the
+    // first four bytes with the aligned four it belongs to
+    fn corrupt_ram_on_retrigger(&mut self) {
+        if !self.is_on || self.frequency_timer > 2 {
+            return;
+        }
+        let offset = (self.ram_index + 1) % 32 / 2;
+        if offset < 4 {
+            self.ram[0] = self.ram[offset];
+        } else {
+            let start = offset & !3;
+            self.ram.copy_within(start..start + 4, 0);
+        }
+    }
+
+    fn trigger(&mut self, first_half: bool) {
         self.is_on = self.dac_enable;
         if self.length_timer == 0 {
-            self.length_timer = 256;
+            self.length_timer = if self.length_enable && first_half {
+                255
+            } else {
+                256
+            };
         }
         self.frequency_timer = (2048 - self.frequency) * 2;
         self.ram_index = 0;
@@ -430,6 +846,7 @@ impl Wave {
         if self.frequency_timer == 0 {
             self.frequency_timer = (2048 - self.frequency) * 2;
             self.ram_index = (self.ram_index + 1) % 32;
+            self.fetched = true;
             if self.ram_index % 2 == 0 {
                 self.current_sample = self.ram[self.ram_index / 2] >> 4;
             } else {
@@ -443,7 +860,10 @@ impl Wave {
     }
 
     fn length_tick(&mut self) {
-        self.length_timer = self.length_timer.saturating_sub(1);
+        if self.length_timer == 0 {
+            return;
+        }
+        self.length_timer -= 1;
         if self.length_timer == 0 {
             self.is_on = false;
         }
@@ -499,6 +919,38 @@ impl Noise {
         }
     }
 
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Noise {
+            is_on,
+            length_timer,
+            initial_volume,
+            envelope_period,
+            envelope_timer,
+            envelope_direction,
+            clock_shift,
+            is_lfsr_width_mode,
+            lsfr,
+            divisor_code,
+            length_enable,
+            current_volume,
+            frequency_timer,
+        } = self;
+        hasher
+            .write(is_on)
+            .write(length_timer)
+            .write(initial_volume)
+            .write(envelope_period)
+            .write(envelope_timer)
+            .write(&(*envelope_direction as u8))
+            .write(clock_shift)
+            .write(is_lfsr_width_mode)
+            .write(lsfr)
+            .write(divisor_code)
+            .write(length_enable)
+            .write(current_volume)
+            .write(frequency_timer);
+    }
+
     fn read(&self, address: u16) -> u8 {
         match address {
             0xFF20 => 0xFF,
@@ -515,13 +967,16 @@ impl Noise {
         }
     }
 
-    fn write(&mut self, address: u16, value: u8) {
+    fn write(&mut self, address: u16, value: u8, first_half: bool) {
         match address {
             0xFF20 => self.length_timer = 64 - (value & 0x3F),
             0xFF21 => {
                 self.envelope_period = value & 0x07;
                 self.envelope_direction = EnvelopeDirection::from(value >> 3 & 1);
                 self.initial_volume = value >> 4;
+                if !self.dac_enabled() {
+                    self.is_on = false;
+                }
             }
             0xFF22 => {
                 self.divisor_code = value & 0x07;
@@ -529,20 +984,36 @@ impl Noise {
                 self.clock_shift = value >> 4;
             }
             0xFF23 => {
-                self.length_enable = (value >> 6) & 1 == 1;
-                if value >> 7 & 1 == 1 {
-                    self.trigger();
+                let length_enable = (value >> 6) & 1 == 1;
+                let trigger = value >> 7 & 1 == 1;
+                if first_half && !self.length_enable && length_enable && self.length_timer != 0 {
+                    self.length_timer -= 1;
+                    if self.length_timer == 0 && !trigger {
+                        self.is_on = false;
+                    }
+                }
+                self.length_enable = length_enable;
+                if trigger {
+                    self.trigger(first_half);
                 }
             }
             _ => unreachable!("Noise invalid write address: {:#06X}", address),
         }
     }
 
-    fn trigger(&mut self) {
-        self.is_on =
-            self.initial_volume != 0 || self.envelope_direction == EnvelopeDirection::Increase;
+    // Same rule as the pulse channels, on NR42
+    fn dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.envelope_direction == EnvelopeDirection::Increase
+    }
+
+    fn trigger(&mut self, first_half: bool) {
+        self.is_on = self.dac_enabled();
         if self.length_timer == 0 {
-            self.length_timer = 64;
+            self.length_timer = if self.length_enable && first_half {
+                63
+            } else {
+                64
+            };
         }
 
         self.envelope_timer = if self.envelope_period == 0 {
@@ -578,7 +1049,7 @@ impl Noise {
         }
     }
 
-    fn output(&mut self) -> i16 {
+    fn output(&self) -> i16 {
         if self.is_on {
             let sample = (self.lsfr & 1) ^ 1;
             (sample as i16 * 2 - 1) * self.current_volume as i16 * 256
@@ -588,7 +1059,10 @@ impl Noise {
     }
 
     fn length_tick(&mut self) {
-        self.length_timer = self.length_timer.saturating_sub(1);
+        if self.length_timer == 0 {
+            return;
+        }
+        self.length_timer -= 1;
         if self.length_timer == 0 {
             self.is_on = false;
         }
@@ -655,6 +1129,7 @@ impl From<u8> for EnvelopeDirection {
 #[derive(Debug, Default)]
 struct FrameSequencer {
     counter: u32,
+    // The next step to run
     step: u8,
 }
 
@@ -673,7 +1148,6 @@ impl FrameSequencer {
         self.counter += 1;
         if self.counter >= 8192 {
             self.counter = 0;
-            self.step = (self.step + 1) % 8;
 
             if self.step % 2 == 0 {
                 should_length_tick = true;
@@ -684,8 +1158,14 @@ impl FrameSequencer {
             if self.step == 2 || self.step == 6 {
                 should_sweep_tick = true;
             }
+            self.step = (self.step + 1) % 8;
         }
 
         (should_length_tick, should_volume_tick, should_sweep_tick)
     }
+
+    // The next step doesn't clock the length counters
+    fn in_first_half(&self) -> bool {
+        self.step % 2 == 1
+    }
 }
@@ -0,0 +1,219 @@
+//! Plays the core's samples on the default output device through cpal.
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample};
+use log::warn;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+// Audio kept queued
+const TARGET_LATENCY_MS: u32 = 50;
+// Older samples are dropped past this many times the target, so a burst of frames never
+// builds up lag
+const MAX_QUEUED_TARGETS: usize = 4;
+// Largest playback speed change used to pull the queue back to its target
+const MAX_DRIFT_CORRECTION: f64 = 0.005;
+
+#[derive(Debug, Error)]
+pub enum AudioOutError {
+    #[error("No audio output device")]
+    NoDevice,
+
+    #[error("Unsupported sample format: {0}")]
+    UnsupportedFormat(SampleFormat),
+
+    #[error(transparent)]
+    Config(#[from] cpal::DefaultStreamConfigError),
+
+    #[error(transparent)]
+    Build(#[from] cpal::BuildStreamError),
+
+    #[error(transparent)]
+    Play(#[from] cpal::PlayStreamError),
+}
+
+/// An output stream fed with samples from [`FrameOutput::audio`](crate::FrameOutput).
+///
+/// Samples are expected at [`sample_rate`](Self::sample_rate), so pass it to
+/// [`GameBoyColor::set_sample_rate`](crate::GameBoyColor::set_sample_rate). Playback
+/// speeds up or slows down slightly to keep about 50 ms queued, so an emulator paced by
+/// its own timer never drifts into underruns or growing latency. The stream stops when
+/// this is dropped.
+pub struct AudioOut {
+    queue: Arc<Mutex<VecDeque<[i16; 2]>>>,
+    sample_rate: u32,
+    max_queued: usize,
+    _stream: cpal::Stream,
+}
+
+impl AudioOut {
+    /// Opens the default output device in its default configuration.
+    pub fn new() -> Result<Self, AudioOutError> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or(AudioOutError::NoDevice)?;
+        let supported = device.default_output_config()?;
+        let sample_format = supported.sample_format();
+        let config = supported.config();
+
+        let target = (config.sample_rate.0 * TARGET_LATENCY_MS / 1000) as usize;
+        let max_queued = target * MAX_QUEUED_TARGETS;
+        let queue = Arc::new(Mutex::new(VecDeque::with_capacity(max_queued)));
+        let stream = match sample_format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, target, queue.clone())?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, target, queue.clone())?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, target, queue.clone())?,
+            format => return Err(AudioOutError::UnsupportedFormat(format)),
+        };
+        stream.play()?;
+
+        Ok(Self {
+            queue,
+            sample_rate: config.sample_rate.0,
+            max_queued,
+            _stream: stream,
+        })
+    }
+
+    /// Queues samples for playback, dropping the oldest if the queue is full.
+    pub fn push(&self, samples: &[[i16; 2]]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples);
+        let excess = queue.len().saturating_sub(self.max_queued);
+        queue.drain(..excess);
+    }
+
+    /// Samples waiting to be played.
+    pub fn queued(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    /// Drops queued samples, for example after pausing or fast-forwarding.
+    pub fn clear(&self) {
+        self.queue.lock().unwrap().clear();
+    }
+
+    /// Rate of the device stream in Hz, which the samples pushed should match.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    target: usize,
+    queue: Arc<Mutex<VecDeque<[i16; 2]>>>,
+) -> Result<cpal::Stream, AudioOutError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let mut resampler = Resampler::new(target);
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let mut queue = queue.lock().unwrap();
+            resampler.adjust(queue.len());
+            for frame in data.chunks_exact_mut(channels) {
+                let [left, right] = resampler.sample(&mut queue);
+                if let [sample] = frame {
+                    *sample = T::from_sample((left + right) / 2.0);
+                    continue;
+                }
+                for (i, sample) in frame.iter_mut().enumerate() {
+                    *sample = T::from_sample(match i {
+                        0 => left,
+                        1 => right,
+                        _ => 0.0,
+                    });
+                }
+            }
+        },
+        |error| warn!("Audio stream error: {}", error),
+        None,
+    )?;
+    Ok(stream)
+}
+
+// Linear interpolation between consecutive samples, stepping through them at a rate
+// close to one
+struct Resampler {
+    target: usize,
+    step: f64,
+    position: f64,
+    previous: [f32; 2],
+    current: [f32; 2],
+}
+
+impl Resampler {
+    fn new(target: usize) -> Self {
+        Self {
+            target,
+            step: 1.0,
+            position: 0.0,
+            previous: [0.0; 2],
+            current: [0.0; 2],
+        }
+    }
+
+    // Consumes slightly faster when the queue is above its target and slower below
+    fn adjust(&mut self, queued: usize) {
+        let error = (queued as f64 - self.target as f64) / self.target as f64;
+        self.step = 1.0 + MAX_DRIFT_CORRECTION * error.clamp(-1.0, 1.0);
+    }
+
+    fn sample(&mut self, queue: &mut VecDeque<[i16; 2]>) -> [f32; 2] {
+        self.position += self.step;
+        while self.position >= 1.0 {
+            self.position -= 1.0;
+            self.previous = self.current;
+            // Silence on underrun
+            let [left, right] = queue.pop_front().unwrap_or_default();
+            self.current = [left as f32 / 32768.0, right as f32 / 32768.0];
+        }
+        let t = self.position as f32;
+        [
+            self.previous[0] + (self.current[0] - self.previous[0]) * t,
+            self.previous[1] + (self.current[1] - self.previous[1]) * t,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Input samples used up for `outputs` output samples with `queued` waiting
+    fn consumed(queued: usize, target: usize, outputs: usize) -> usize {
+        let mut resampler = Resampler::new(target);
+        let mut queue = VecDeque::from(vec![[0; 2]; queued]);
+        resampler.adjust(queue.len());
+        for _ in 0..outputs {
+            resampler.sample(&mut queue);
+        }
+        queued - queue.len()
+    }
+
+    #[test]
+    fn step_follows_the_queue_within_the_drift_correction() {
+        let mut resampler = Resampler::new(1000);
+        resampler.adjust(1000);
+        assert_eq!(resampler.step, 1.0);
+        resampler.adjust(1500);
+        assert_eq!(resampler.step, 1.0 + MAX_DRIFT_CORRECTION / 2.0);
+        resampler.adjust(0);
+        assert_eq!(resampler.step, 1.0 - MAX_DRIFT_CORRECTION);
+        resampler.adjust(10_000);
+        assert_eq!(resampler.step, 1.0 + MAX_DRIFT_CORRECTION);
+    }
+
+    #[test]
+    fn queue_drains_faster_above_its_target_and_slower_below() {
+        assert_eq!(consumed(20_000, 20_000, 10_000), 10_000);
+        // 0.5% faster at twice the target, 0.25% slower at half of it
+        assert!((10_045..=10_055).contains(&consumed(40_000, 20_000, 10_000)));
+        assert!((9_970..=9_980).contains(&consumed(10_000, 20_000, 10_000)));
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// A bounded queue of samples shared between the emulation thread and an audio device
+/// callback. Clones are handles to the same queue.
+///
+/// Feed it from [`GameBoyColor::set_audio_callback`](crate::GameBoyColor::set_audio_callback)
+/// and drain it with [`pop`](Self::pop) from the device callback, so no buffers change
+/// hands each frame. When the consumer falls behind, the oldest samples are dropped.
+#[derive(Debug, Clone)]
+pub struct AudioRing {
+    queue: Arc<Mutex<VecDeque<[i16; 2]>>>,
+    capacity: usize,
+}
+
+impl AudioRing {
+    /// Holds up to `capacity` stereo samples. Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "AudioRing capacity must not be zero");
+        Self {
+            queue: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Appends samples, dropping the oldest ones that no longer fit.
+    pub fn push(&self, samples: &[[i16; 2]]) {
+        let samples = &samples[samples.len().saturating_sub(self.capacity)..];
+        let mut queue = self.queue.lock().unwrap();
+        let excess = (queue.len() + samples.len()).saturating_sub(self.capacity);
+        queue.drain(..excess);
+        queue.extend(samples);
+    }
+
+    /// Moves the oldest samples into `out` and returns how many were written. The rest
+    /// of `out` is left untouched on underrun.
+    pub fn pop(&self, out: &mut [[i16; 2]]) -> usize {
+        let mut queue = self.queue.lock().unwrap();
+        let count = out.len().min(queue.len());
+        for (out, sample) in out.iter_mut().zip(queue.drain(..count)) {
+            *out = sample;
+        }
+        count
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Drops every queued sample, for example after pausing or fast-forwarding.
+    pub fn clear(&self) {
+        self.queue.lock().unwrap().clear();
+    }
+}
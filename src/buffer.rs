@@ -0,0 +1,52 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+#[cfg(feature = "static-buffers")]
+type Storage<T, const N: usize> = [T; N];
+#[cfg(not(feature = "static-buffers"))]
+type Storage<T, const N: usize> = Box<[T; N]>;
+
+/// Fixed-size memory. With the `static-buffers` feature it is stored inline, so the
+/// emulator needs no heap for VRAM, WRAM or the frame buffer.
+pub struct Buffer<T, const N: usize>(Storage<T, N>);
+
+impl<T: Copy, const N: usize> Buffer<T, N> {
+    #[cfg(feature = "static-buffers")]
+    pub fn filled(value: T) -> Self {
+        Self([value; N])
+    }
+
+    #[cfg(not(feature = "static-buffers"))]
+    pub fn filled(value: T) -> Self {
+        match vec![value; N].into_boxed_slice().try_into() {
+            Ok(storage) => Self(storage),
+            Err(_) => unreachable!("Buffer length is always {}", N),
+        }
+    }
+}
+
+impl<T: Copy + Default, const N: usize> Default for Buffer<T, N> {
+    fn default() -> Self {
+        Self::filled(T::default())
+    }
+}
+
+impl<T, const N: usize> Deref for Buffer<T, N> {
+    type Target = [T; N];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T, const N: usize> DerefMut for Buffer<T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T: fmt::Debug, const N: usize> fmt::Debug for Buffer<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0[..].fmt(f)
+    }
+}
@@ -1,10 +1,16 @@
 use log::{debug, warn};
 
-use crate::config::Config;
+use crate::buffer::Buffer;
+use crate::config::{Config, Model, Speed};
+use crate::event::{DmaKind, HardwareEvent, Subsystem};
+use crate::ppu::PpuMode;
+use crate::rng::Rng;
+use crate::state_hash::StateHasher;
 use crate::{context, ppu, DeviceMode};
 
 trait Context:
     context::Cartridge
+    + context::Infrared
     + context::Ppu
     + context::Apu
     + context::Config
@@ -12,10 +18,12 @@ trait Context:
     + context::Joypad
     + context::Timer
     + context::Serial
+    + context::Event
 {
 }
 impl<T> Context for T where
     T: context::Cartridge
+        + context::Infrared
         + context::Ppu
         + context::Apu
         + context::Config
@@ -23,14 +31,26 @@ impl<T> Context for T where
         + context::Joypad
         + context::Timer
         + context::Serial
+        + context::Event
 {
 }
 
+// Bytes HDMA copies per unit of the length in FF55
+const HDMA_BLOCK_SIZE: u8 = 16;
+
 #[derive(Debug)]
 pub struct Bus {
-    wram: Vec<u8>,
+    // Sized for CGB; DMG only uses the first two banks
+    wram: Buffer<u8, 0x8000>,
     wram_bank: u8,
     hram: [u8; 0x7F],
+    // 0xFEA0-0xFEFF, RAM on CGBs before revision E
+    prohibited: [u8; 0x60],
+    model: Model,
+    // Mapped over the cartridge until FF50 is written
+    boot_rom: Option<Vec<u8>>,
+    // Source of the values the hardware leaves unpredictable, 0xFF without one
+    rng: Option<Rng>,
 
     dma: Dma,
     hdma: Hdma,
@@ -43,15 +63,23 @@ pub struct Bus {
 }
 
 impl Bus {
-    pub fn new(device_mode: DeviceMode) -> Self {
-        let wram = match device_mode {
-            DeviceMode::GameBoy => vec![0; 0x2000],
-            DeviceMode::GameBoyColor => vec![0; 0x8000],
-        };
+    pub fn new(model: Model, boot_rom: Option<Vec<u8>>, mut rng: Option<Rng>) -> Self {
+        let mut wram = Buffer::default();
+        let mut hram = [0; 0x7F];
+        let mut prohibited = [0; 0x60];
+        if let Some(rng) = &mut rng {
+            rng.fill(&mut wram[..]);
+            rng.fill(&mut hram);
+            rng.fill(&mut prohibited);
+        }
         Self {
             wram,
             wram_bank: 1,
-            hram: [0; 0x7F],
+            hram,
+            prohibited,
+            model,
+            boot_rom,
+            rng,
 
             dma: Dma::default(),
             hdma: Hdma::default(),
@@ -63,20 +91,34 @@ impl Bus {
         }
     }
 
+    // A read by the CPU, which can't reach the bus OAM DMA is using
     pub fn read(&mut self, context: &mut impl Context, address: u16) -> u8 {
+        match self.dma_conflict(context, address) {
+            Some(DmaConflict::Oam) => 0xFF,
+            Some(DmaConflict::Bus) => self.dma_byte(context),
+            None => self.read_memory(context, address),
+        }
+    }
+
+    // A write by the CPU, lost if it is to the bus OAM DMA is using
+    pub fn write(&mut self, context: &mut impl Context, address: u16, value: u8) {
+        if self.dma_conflict(context, address).is_none() {
+            self.write_memory(context, address, value);
+        }
+    }
+
+    fn read_memory(&mut self, context: &mut impl Context, address: u16) -> u8 {
         let data = match address {
-            0x0000..=0x7FFF => context.cartridge_read(address),
+            0x0000..=0x7FFF => match self.read_boot_rom(address) {
+                Some(data) => data,
+                None => context.cartridge_read(address),
+            },
             0x8000..=0x9FFF => context.ppu_read(address),
             0xA000..=0xBFFF => context.cartridge_read(address),
-            0xC000..=0xFDFF => {
-                let bank = address & 0x1000;
-                self.wram[((address & 0x0FFF) + bank * self.wram_bank as u16) as usize]
-            }
+            0xC000..=0xFDFF => self.wram[self.wram_index(address)],
             0xFE00..=0xFE9F => context.ppu_read(address),
-            0xFEA0..=0xFEFF => {
-                warn!("Invalid Bus Address: {:#06X}", address);
-                0xFF
-            }
+            0xFEA0..=0xFEFF if oam_blocked(context) => 0xFF,
+            0xFEA0..=0xFEFF => self.read_prohibited(address),
             0xFF00 => context.joypad_read(),
             0xFF01..=0xFF02 => context.serial_read(address),
             0xFF04..=0xFF07 => context.timer_read(address),
@@ -88,37 +130,48 @@ impl Bus {
             0xFF4C => 0xFF, // KEY0
             0xFF4D => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Read from FF4D in DMG mode");
+                    context.warn(Subsystem::Bus, "Read from FF4D in DMG mode".to_string());
                     0xFF
                 } else {
                     context.get_speed_switch()
                 }
             }
             0xFF4F => context.ppu_read(address),
-            0xFF50 => {
-                warn!("Boot ROM");
-                0xFF
-            }
+            0xFF50 => 0xFF,
             0xFF51..=0xFF55 => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Read from HDMA register in DMG mode");
+                    context.warn(
+                        Subsystem::Bus,
+                        "Read from HDMA register in DMG mode".to_string(),
+                    );
                     0xFF
                 } else {
                     self.hdma.read(address)
                 }
             }
+            0xFF56 => {
+                if context.device_mode() == DeviceMode::GameBoy {
+                    context.warn(Subsystem::Bus, "Read from FF56 in DMG mode".to_string());
+                    0xFF
+                } else {
+                    context.infrared_read()
+                }
+            }
             0xFF68..=0xFF6B => context.ppu_read(address),
             0xFF70 => {
                 if context.device_mode() == DeviceMode::GameBoyColor {
                     0xF8 | self.wram_bank
                 } else {
-                    warn!("Read from FF70 in DMG mode");
+                    context.warn(Subsystem::Bus, "Read from FF70 in DMG mode".to_string());
                     0xFF
                 }
             }
             0xFF72 => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Read CGB Undocumented Register : FF72");
+                    context.warn(
+                        Subsystem::Bus,
+                        "Read CGB Undocumented Register : FF72".to_string(),
+                    );
                     0xFF
                 } else {
                     self.ff72
@@ -126,7 +179,10 @@ impl Bus {
             }
             0xFF73 => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Read CGB Undocumented Register: FF73");
+                    context.warn(
+                        Subsystem::Bus,
+                        "Read CGB Undocumented Register: FF73".to_string(),
+                    );
                     0xFF
                 } else {
                     self.ff73
@@ -134,7 +190,10 @@ impl Bus {
             }
             0xFF74 => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Read CGB Undocumented Register: FF74");
+                    context.warn(
+                        Subsystem::Bus,
+                        "Read CGB Undocumented Register: FF74".to_string(),
+                    );
                     0xFF
                 } else {
                     self.ff74
@@ -142,7 +201,10 @@ impl Bus {
             }
             0xFF75 => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Read CGB Undocumented Register: FF75");
+                    context.warn(
+                        Subsystem::Bus,
+                        "Read CGB Undocumented Register: FF75".to_string(),
+                    );
                     0xFF
                 } else {
                     self.ff75
@@ -162,22 +224,24 @@ impl Bus {
         data
     }
 
-    pub fn write(&mut self, context: &mut impl Context, address: u16, value: u8) {
+    fn write_memory(&mut self, context: &mut impl Context, address: u16, value: u8) {
         debug!("Bus write: {:#06X} = {:#04X}", address, value);
         match address {
             0x0000..=0x7FFF => context.cartridge_write(address, value),
             0x8000..=0x9FFF => context.ppu_write(address, value),
             0xA000..=0xBFFF => context.cartridge_write(address, value),
             0xC000..=0xFDFF => {
-                let bank = address & 0x1000;
-                let wram_address = (address & 0x0FFF) + bank * self.wram_bank as u16;
-                self.wram[wram_address as usize] = value;
+                let index = self.wram_index(address);
+                self.wram[index] = value;
             }
             0xFE00..=0xFE9F => {
                 context.ppu_write(address, value);
             }
+            0xFEA0..=0xFEFF if oam_blocked(context) => {}
             0xFEA0..=0xFEFF => {
-                warn!("Invalid Bus Address: {:#06X}", address);
+                if let Some(index) = self.prohibited_index(address) {
+                    self.prohibited[index] = value;
+                }
             }
             0xFF00 => context.joypad_write(value),
             0xFF01..=0xFF02 => context.serial_write(address, value),
@@ -185,60 +249,99 @@ impl Bus {
             0xFF0F => context.set_interrupt_flag(value),
             0xFF10..=0xFF3F => context.apu_write(address, value),
             0xFF40..=0xFF45 => context.ppu_write(address, value),
-            0xFF46 => self.dma.write(value),
+            0xFF46 => {
+                self.dma.write(value);
+                context.emit(HardwareEvent::DmaStarted {
+                    kind: DmaKind::Oam,
+                    source: (value as u16) << 8,
+                });
+            }
             0xFF47..=0xFF4B => context.ppu_write(address, value),
             0xFF4D => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Write to FF4D in DMG mode");
+                    context.warn(Subsystem::Bus, "Write to FF4D in DMG mode".to_string());
                 }
                 context.set_speed_switch(value);
             }
             0xFF4F => context.ppu_write(address, value),
-            0xFF50 => warn!("Boot ROM not implemented"),
+            0xFF50 => {
+                if value & 0x01 != 0 {
+                    self.boot_rom = None;
+                }
+            }
             0xFF51..=0xFF55 => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Write to HDMA register in DMG mode");
+                    context.warn(
+                        Subsystem::Bus,
+                        "Write to HDMA register in DMG mode".to_string(),
+                    );
                 } else {
+                    let was_active = self.hdma.enable_gdma || self.hdma.enable_hdma;
                     self.hdma.write(address, value);
+                    let kind = if self.hdma.enable_gdma {
+                        Some(DmaKind::General)
+                    } else if self.hdma.enable_hdma {
+                        Some(DmaKind::HBlank)
+                    } else {
+                        None
+                    };
+                    if let (false, Some(kind)) = (was_active, kind) {
+                        context.emit(HardwareEvent::DmaStarted {
+                            kind,
+                            source: self.hdma.source_address,
+                        });
+                    }
+                }
+            }
+            0xFF56 => {
+                if context.device_mode() == DeviceMode::GameBoy {
+                    context.warn(Subsystem::Bus, "Write to FF56 in DMG mode".to_string());
+                } else {
+                    context.infrared_write(value);
                 }
             }
-            // 0xFF56 => {
-            //     if context.device_mode() == DeviceMode::GameBoy {
-            //         warn!("Write to FF56 in DMG mode");
-            //     } else {
-            //         todo!("Write to FF56 in CGB mode");
-            //     }
-            // }
             0xFF68..=0xFF6C => context.ppu_write(address, value),
             0xFF70 => {
                 if context.device_mode() == DeviceMode::GameBoyColor {
                     self.wram_bank = (value & 0x07).max(1);
                 } else {
-                    warn!("Write to FF70 in DMG mode");
+                    context.warn(Subsystem::Bus, "Write to FF70 in DMG mode".to_string());
                 }
             }
             0xFF72 => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Write CGB Undocumented Register: FF72");
+                    context.warn(
+                        Subsystem::Bus,
+                        "Write CGB Undocumented Register: FF72".to_string(),
+                    );
                 }
                 self.ff72 = value;
             }
             0xFF73 => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Write CGB Undocumented Register: FF73");
+                    context.warn(
+                        Subsystem::Bus,
+                        "Write CGB Undocumented Register: FF73".to_string(),
+                    );
                 }
                 self.ff73 = value;
             }
             0xFF74 => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Write CGB Undocumented Register: FF74");
+                    context.warn(
+                        Subsystem::Bus,
+                        "Write CGB Undocumented Register: FF74".to_string(),
+                    );
                 } else {
                     self.ff74 = value;
                 }
             }
             0xFF75 => {
                 if context.device_mode() == DeviceMode::GameBoy {
-                    warn!("Write CGB Undocumented Register: FF75");
+                    context.warn(
+                        Subsystem::Bus,
+                        "Write CGB Undocumented Register: FF75".to_string(),
+                    );
                 } else {
                     self.ff75 = value & 0x70;
                 }
@@ -253,7 +356,141 @@ impl Bus {
                 );
                 debug!("IE Set After: {:?}", context.interrupt_enable());
             }
-            _ => warn!("Invalid Bus Address: {:#06X}", address),
+            _ => context.warn(
+                Subsystem::Bus,
+                format!("Invalid Bus Address: {:#06X}", address),
+            ),
+        }
+    }
+
+    // Memory held by the bus itself, read without side effects
+    pub fn peek(&self, address: u16) -> Option<u8> {
+        match address {
+            0x0000..=0x7FFF => self.read_boot_rom(address),
+            0xC000..=0xFDFF => Some(self.wram[self.wram_index(address)]),
+            0xFEA0..=0xFEFF => Some(self.read_prohibited(address)),
+            0xFF80..=0xFFFE => Some(self.hram[(address - 0xFF80) as usize]),
+            _ => None,
+        }
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        // Listing every field makes a new one fail to build until it is hashed or skipped
+        let Bus {
+            wram,
+            wram_bank,
+            hram,
+            prohibited,
+            boot_rom,
+            rng,
+            dma:
+                Dma {
+                    register,
+                    source_page,
+                    counter,
+                    active,
+                    start_delay,
+                },
+            hdma:
+                Hdma {
+                    source_address,
+                    destination_address,
+                    length,
+                    enable_gdma,
+                    enable_hdma,
+                    is_prev_hblank,
+                    copied,
+                },
+            ff72,
+            ff73,
+            ff74,
+            ff75,
+            // Fixed at power-on
+            model: _,
+        } = self;
+        let mut hasher = StateHasher::default();
+        hasher
+            .write(&**wram)
+            .write(wram_bank)
+            .write(hram)
+            .write(prohibited)
+            .write(&boot_rom.is_some())
+            .write(&[*ff72, *ff73, *ff74, *ff75])
+            .write(register)
+            .write(source_page)
+            .write(counter)
+            .write(active)
+            .write(start_delay)
+            .write(source_address)
+            .write(destination_address)
+            .write(length)
+            .write(enable_gdma)
+            .write(enable_hdma)
+            .write(is_prev_hblank)
+            .write(copied)
+            .write(rng);
+        hasher.finish()
+    }
+
+    pub fn poke(&mut self, address: u16, value: u8) -> bool {
+        match address {
+            0xC000..=0xFDFF => {
+                let index = self.wram_index(address);
+                self.wram[index] = value;
+            }
+            0xFF80..=0xFFFE => self.hram[(address - 0xFF80) as usize] = value,
+            _ => return false,
+        }
+        true
+    }
+
+    // 0xE000-0xFDFF echoes 0xC000-0xDDFF, and 0xD000-0xDFFF maps the bank selected in FF70
+    fn wram_index(&self, address: u16) -> usize {
+        let address = address & 0x1FFF;
+        let bank = if address < 0x1000 { 0 } else { self.wram_bank };
+        bank as usize * 0x1000 + (address & 0x0FFF) as usize
+    }
+
+    // What 0xFEA0-0xFEFF holds depends on the revision. DMG models read 0x00 and CGB-E
+    // and the AGB repeat the high nibble of the address's low byte, ignoring writes.
+    // Earlier CGBs have RAM there, with some addresses mirroring others.
+    fn read_prohibited(&self, address: u16) -> u8 {
+        if let Some(index) = self.prohibited_index(address) {
+            return self.prohibited[index];
+        }
+        match self.model {
+            Model::Cgb | Model::Agb => (address as u8 & 0xF0) | (address as u8 >> 4),
+            _ => 0x00,
+        }
+    }
+
+    fn prohibited_index(&self, address: u16) -> Option<usize> {
+        let address = match self.model {
+            // Bits 3 and 4 are ignored
+            Model::CgbC => address & !0x18,
+            // The top four rows all map to the last one
+            Model::CgbD if address >= 0xFEC0 => address | 0xF0,
+            Model::CgbD => address,
+            _ => return None,
+        };
+        Some((address - 0xFEA0) as usize)
+    }
+
+    // Back to the power-on state. The generator carries on, so RAM gets fresh values.
+    pub fn reset(&mut self, boot_rom: Option<Vec<u8>>) {
+        *self = Self::new(self.model, boot_rom, self.rng.take());
+    }
+
+    fn unpredictable(&mut self) -> u8 {
+        self.rng.as_mut().map_or(0xFF, Rng::next_u8)
+    }
+
+    // The CGB boot ROM leaves 0x0100-0x01FF unmapped so the cartridge header shows through
+    fn read_boot_rom(&self, address: u16) -> Option<u8> {
+        let boot_rom = self.boot_rom.as_ref()?;
+        match address {
+            0x0100..=0x01FF => None,
+            _ => boot_rom.get(address as usize).copied(),
         }
     }
 
@@ -262,26 +499,75 @@ impl Bus {
         self.process_hdma(context);
     }
 
+    // Which of the CPU's accesses OAM DMA gets in the way of while it copies. OAM is
+    // unreachable, and so is the bus the DMA reads from: VRAM, or the external bus with
+    // the cartridge and WRAM, which the CGB splits in two. HRAM and I/O stay reachable.
+    fn dma_conflict(&self, context: &impl Context, address: u16) -> Option<DmaConflict> {
+        if !self.dma.active {
+            return None;
+        }
+        let bus = |address: u16| match address {
+            0x8000..=0x9FFF => Some(MemoryBus::Video),
+            0xC000..=0xFDFF if context.device_mode() == DeviceMode::GameBoyColor => {
+                Some(MemoryBus::WorkRam)
+            }
+            0x0000..=0xFDFF => Some(MemoryBus::External),
+            _ => None,
+        };
+        match address {
+            0xFE00..=0xFEFF => Some(DmaConflict::Oam),
+            _ if bus(address).is_some() && bus(address) == bus(self.dma.source()) => {
+                Some(DmaConflict::Bus)
+            }
+            _ => None,
+        }
+    }
+
+    // The byte DMA copies this M-cycle
+    fn dma_byte(&mut self, context: &mut impl Context) -> u8 {
+        let source_address = self.dma.source();
+        // Pages 0xFE and 0xFF are past the last one OAM DMA can copy from
+        if source_address >= 0xFE00 {
+            self.unpredictable()
+        } else {
+            self.read_memory(context, source_address)
+        }
+    }
+
     fn process_dma(&mut self, context: &mut impl Context) {
-        if !self.dma.enable {
-            return;
+        if self.dma.active {
+            let data = self.dma_byte(context);
+            let destination_address = 0xFE00 + self.dma.counter as u16;
+            debug!(
+                "DMA Source: {:#04X} -> {:#04X}: {:#04X}",
+                self.dma.source(),
+                destination_address,
+                data
+            );
+            self.write_memory(context, destination_address, data);
+
+            self.dma.counter = self.dma.counter.wrapping_add(1);
+            if self.dma.counter == 0xA0 {
+                self.dma.active = false;
+            }
         }
 
-        let source_address = (self.dma.upper_source_address as u16) << 8 | self.dma.counter as u16;
-        let destination_address = 0xFE00 + self.dma.counter as u16;
-        let data = self.read(context, source_address);
-        debug!(
-            "DMA Source: {:#04X} -> {:#04X}: {:#04X}",
-            source_address, destination_address, data
-        );
-        self.write(context, destination_address, data);
-
-        self.dma.counter = self.dma.counter.wrapping_add(1);
-        if self.dma.counter == 0xA0 {
-            self.dma.enable = false;
+        // A transfer already running carries on until the new one takes over
+        if self.dma.start_delay > 0 {
+            self.dma.start_delay -= 1;
+            if self.dma.start_delay == 0 {
+                self.dma.source_page = self.dma.register;
+                self.dma.counter = 0;
+                self.dma.active = true;
+            }
         }
     }
 
+    /// Whether the CPU is paused while HDMA copies a block.
+    pub fn cpu_stalled(&self) -> bool {
+        self.hdma.copied.is_some()
+    }
+
     fn process_hdma(&mut self, context: &mut impl Context) {
         assert!(!(self.hdma.enable_gdma && self.hdma.enable_hdma));
 
@@ -289,48 +575,97 @@ impl Bus {
         let enter_hblank = is_hblank && !self.hdma.is_prev_hblank;
         self.hdma.is_prev_hblank = is_hblank;
 
-        if self.hdma.enable_gdma || (self.hdma.enable_hdma && enter_hblank) {
-            println!("HDMA: {:#?}", self.hdma);
-            for i in 0..16 {
-                let source_address = self.hdma.source_address + i;
-                let destination_address = 0x8000 | (self.hdma.destination_address + i);
-                let value = self.read(context, source_address);
-                self.write(context, destination_address, value);
+        if let Some(copied) = self.hdma.copied {
+            // A block takes 8 µs at either speed: 2 bytes per M-cycle, or 1 at double speed
+            let count = match context.current_speed() {
+                Speed::Normal => 2,
+                Speed::Double => 1,
+            };
+            for i in copied..copied + count {
+                let source_address = self.hdma.source_address.wrapping_add(i as u16);
+                let destination_address = 0x8000 | (self.hdma.destination_address + i as u16);
+                let value = self.read_memory(context, source_address);
+                self.write_memory(context, destination_address, value);
+            }
+            self.hdma.copied = Some(copied + count);
+            if copied + count == HDMA_BLOCK_SIZE {
+                self.finish_hdma_block();
             }
+        }
 
-            self.hdma.source_address = self.hdma.source_address.wrapping_add(16);
-            self.hdma.destination_address = self.hdma.destination_address.wrapping_add(16);
+        // A GDMA starts its next block as soon as the previous one has finished
+        if self.hdma.copied.is_none()
+            && (self.hdma.enable_gdma || (self.hdma.enable_hdma && enter_hblank))
+        {
+            debug!("HDMA: {:?}", self.hdma);
+            self.hdma.copied = Some(0);
+        }
+    }
 
-            let (length, ovf) = self.hdma.length.overflowing_sub(1);
+    fn finish_hdma_block(&mut self) {
+        self.hdma.copied = None;
+        self.hdma.source_address = self
+            .hdma
+            .source_address
+            .wrapping_add(HDMA_BLOCK_SIZE as u16);
+        self.hdma.destination_address += HDMA_BLOCK_SIZE as u16;
+
+        let (length, ovf) = self.hdma.length.overflowing_sub(1);
+        // Running past the end of VRAM ends the transfer early, and FF55 reads 0xFF either way
+        if ovf || self.hdma.destination_address >= 0x2000 {
+            self.hdma.enable_gdma = false;
+            self.hdma.enable_hdma = false;
+            self.hdma.destination_address &= 0x1FFF;
+            self.hdma.length = 0x7F;
+        } else {
             self.hdma.length = length;
-            if ovf || self.hdma.destination_address >= 0x2000 {
-                self.hdma.enable_gdma = false;
-                self.hdma.enable_hdma = false;
-                self.hdma.destination_address &= 0x1FFF;
-            }
         }
     }
 }
 
+// M-cycles from the write to FF46 until the first byte is copied, counting the write's own
+const DMA_START_DELAY: u8 = 2;
+
 #[derive(Debug, Default)]
 struct Dma {
-    upper_source_address: u8,
+    // Last value written to FF46
+    register: u8,
+    // Page being copied and the next byte in it
+    source_page: u8,
     counter: u8,
-    enable: bool,
+    active: bool,
+    // M-cycles until a transfer requested through FF46 starts
+    start_delay: u8,
 }
 
 impl Dma {
     fn write(&mut self, value: u8) {
-        self.upper_source_address = value;
-        self.counter = 0;
-        self.enable = true;
+        self.register = value;
+        self.start_delay = DMA_START_DELAY;
     }
 
     fn read(&self) -> u8 {
-        self.upper_source_address
+        self.register
+    }
+
+    fn source(&self) -> u16 {
+        (self.source_page as u16) << 8 | self.counter as u16
     }
 }
 
+#[derive(Debug, PartialEq, Eq)]
+enum MemoryBus {
+    External,
+    Video,
+    // Only separate from the external bus on CGB
+    WorkRam,
+}
+
+enum DmaConflict {
+    Oam,
+    Bus,
+}
+
 #[derive(Debug, Default)]
 struct Hdma {
     source_address: u16,
@@ -339,6 +674,8 @@ struct Hdma {
     enable_gdma: bool,
     enable_hdma: bool,
     is_prev_hblank: bool,
+    // Bytes copied so far of the block in progress
+    copied: Option<u8>,
 }
 
 impl Hdma {
@@ -366,7 +703,11 @@ impl Hdma {
                     (self.destination_address & 0xFF00) | (value & 0xF0) as u16
             }
             0xFF55 => {
-                if self.enable_hdma {
+                // The CPU is stalled for the whole GDMA on hardware, so it can't reach FF55 meanwhile
+                if self.enable_gdma {
+                    warn!("HDMA5 write during GDMA: {:#04X}", value);
+                } else if self.enable_hdma && (value >> 7) & 0x01 == 0 {
+                    // No more blocks are copied, and FF55 keeps the number left
                     self.enable_hdma = false;
                 } else if (value >> 7) & 0x01 == 1 {
                     self.enable_hdma = true;
@@ -380,3 +721,11 @@ impl Hdma {
         }
     }
 }
+
+// OAM and the area after it are unreachable while the PPU scans OAM and draws
+fn oam_blocked(context: &impl Context) -> bool {
+    matches!(
+        context.ppu_mode(),
+        PpuMode::OamSearch | PpuMode::DataTransfer
+    )
+}
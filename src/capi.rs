@@ -0,0 +1,330 @@
+//! A C interface for frontends that cannot use the Rust API, declared in
+//! `include/gameboycolor.h`.
+//!
+//! Every function takes a handle from [`gbc_create`]. Those returning `c_int` return 0
+//! on success and -1 on failure, with the reason left in [`gbc_last_error`]. The core
+//! has no save states, so the save functions move the cartridge's battery-backed RAM.
+
+use crate::apu;
+use crate::config::{DeviceMode, EmulatorConfigBuilder};
+use crate::gameboycolor::{GameBoyColor, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::joypad::{JoypadKey, JoypadKeyState};
+
+use std::collections::VecDeque;
+use std::ffi::{c_char, c_int, CString};
+use std::fmt::Display;
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+// Keys in the order of their bits in gbc_set_keys
+const KEYS: [JoypadKey; 8] = [
+    JoypadKey::Right,
+    JoypadKey::Left,
+    JoypadKey::Up,
+    JoypadKey::Down,
+    JoypadKey::A,
+    JoypadKey::B,
+    JoypadKey::Select,
+    JoypadKey::Start,
+];
+
+// A second at the highest sample rate. Older samples are dropped if the frontend stops
+// pulling
+const MAX_QUEUED_SAMPLES: usize = 192_000;
+
+/// The handle behind `GbcEmulator *`.
+pub struct GbcEmulator {
+    gameboy: Option<GameBoyColor>,
+    // Kept to power on again with a loaded save
+    rom: Vec<u8>,
+    device_mode: DeviceMode,
+    sample_rate: Option<u32>,
+    keys: JoypadKeyState,
+    frame: Vec<u8>,
+    audio: VecDeque<[i16; 2]>,
+    error: CString,
+}
+
+impl GbcEmulator {
+    fn fail(&mut self, error: impl Display) -> c_int {
+        self.error = CString::new(error.to_string()).unwrap_or_default();
+        -1
+    }
+
+    fn power_on(&mut self, save: Option<Vec<u8>>) -> c_int {
+        let mut builder = EmulatorConfigBuilder::default().device_mode(self.device_mode);
+        if let Some(save) = save {
+            builder = builder.save_data(save);
+        }
+        let config = match builder.build() {
+            Ok(config) => config,
+            Err(error) => return self.fail(error),
+        };
+        // Panics in the core must not unwind into C
+        let rom = &self.rom;
+        let gameboy = match panic::catch_unwind(AssertUnwindSafe(|| {
+            GameBoyColor::with_config(rom, config)
+        })) {
+            Ok(Ok(gameboy)) => gameboy,
+            Ok(Err(error)) => return self.fail(error),
+            Err(_) => return self.fail("Invalid ROM"),
+        };
+        self.gameboy = Some(gameboy);
+        if let (Some(gameboy), Some(hz)) = (&mut self.gameboy, self.sample_rate) {
+            gameboy.set_sample_rate(hz);
+        }
+        self.frame.fill(0);
+        self.audio.clear();
+        0
+    }
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+/// Creates an emulator with no game loaded. Free it with [`gbc_destroy`].
+#[no_mangle]
+pub extern "C" fn gbc_create() -> *mut GbcEmulator {
+    Box::into_raw(Box::new(GbcEmulator {
+        gameboy: None,
+        rom: Vec::new(),
+        device_mode: DeviceMode::GameBoyColor,
+        sample_rate: None,
+        keys: JoypadKeyState::new(),
+        frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+        audio: VecDeque::new(),
+        error: CString::default(),
+    }))
+}
+
+/// # Safety
+///
+/// `emulator` must come from [`gbc_create`] and not be used again. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_destroy(emulator: *mut GbcEmulator) {
+    if !emulator.is_null() {
+        drop(Box::from_raw(emulator));
+    }
+}
+
+/// Powers on with `rom`, copied, as a Game Boy Color, or as a Game Boy unless `color`
+/// is set. Like `GameBoyColor::new` it starts from the game's save in the application
+/// data directory, if there is one.
+///
+/// # Safety
+///
+/// `emulator` must be a live handle and `rom` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_load_rom(
+    emulator: *mut GbcEmulator,
+    rom: *const u8,
+    len: usize,
+    color: bool,
+) -> c_int {
+    let Some(emulator) = emulator.as_mut() else {
+        return -1;
+    };
+    if rom.is_null() {
+        return emulator.fail("ROM is null");
+    }
+    emulator.rom = bytes(rom, len).to_vec();
+    emulator.device_mode = if color {
+        DeviceMode::GameBoyColor
+    } else {
+        DeviceMode::GameBoy
+    };
+    emulator.gameboy = None;
+    emulator.power_on(None)
+}
+
+/// Runs one frame with the keys from [`gbc_set_keys`], queueing its audio. If the core
+/// panics, the game is unloaded and -1 returned.
+///
+/// # Safety
+///
+/// `emulator` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_run_frame(emulator: *mut GbcEmulator) -> c_int {
+    let Some(emulator) = emulator.as_mut() else {
+        return -1;
+    };
+    let Some(gameboy) = &mut emulator.gameboy else {
+        return emulator.fail("No ROM loaded");
+    };
+    let (keys, audio, frame) = (emulator.keys, &mut emulator.audio, &mut emulator.frame);
+    let ran = panic::catch_unwind(AssertUnwindSafe(|| {
+        let output = gameboy.run_frame(keys);
+        audio.extend(output.audio);
+        gameboy.frame_buffer_rgba(frame);
+    }));
+    if ran.is_err() {
+        // The core may have stopped halfway through an update, so it is not run again
+        emulator.gameboy = None;
+        return emulator.fail("The emulator panicked; load the ROM again");
+    }
+    let excess = emulator.audio.len().saturating_sub(MAX_QUEUED_SAMPLES);
+    emulator.audio.drain(..excess);
+    0
+}
+
+/// The last frame as `GBC_SCREEN_WIDTH * GBC_SCREEN_HEIGHT` RGBA8 pixels, black before
+/// the first. The pointer stays valid until the handle is destroyed.
+///
+/// # Safety
+///
+/// `emulator` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_framebuffer(emulator: *const GbcEmulator) -> *const u8 {
+    emulator
+        .as_ref()
+        .map_or(ptr::null(), |emulator| emulator.frame.as_ptr())
+}
+
+/// Moves up to `max_samples` queued stereo samples into `out` as interleaved left and
+/// right `int16_t`s, and returns how many were moved.
+///
+/// # Safety
+///
+/// `emulator` must be a live handle and `out` must have room for `max_samples * 2`
+/// values.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_audio_pull(
+    emulator: *mut GbcEmulator,
+    out: *mut i16,
+    max_samples: usize,
+) -> usize {
+    let Some(emulator) = emulator.as_mut() else {
+        return 0;
+    };
+    if out.is_null() {
+        return 0;
+    }
+    let count = max_samples.min(emulator.audio.len());
+    let out = slice::from_raw_parts_mut(out, count * 2);
+    for (pair, sample) in out.chunks_exact_mut(2).zip(emulator.audio.drain(..count)) {
+        pair.copy_from_slice(&sample);
+    }
+    count
+}
+
+/// Sets the rate of the samples from [`gbc_audio_pull`], between 8000 and 192000 Hz.
+/// The default is 48000.
+///
+/// # Safety
+///
+/// `emulator` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_set_sample_rate(emulator: *mut GbcEmulator, hz: u32) -> c_int {
+    let Some(emulator) = emulator.as_mut() else {
+        return -1;
+    };
+    if !apu::SAMPLE_RATES.contains(&hz) {
+        return emulator.fail(format!("Unsupported sample rate: {} Hz", hz));
+    }
+    emulator.sample_rate = Some(hz);
+    if let Some(gameboy) = &mut emulator.gameboy {
+        gameboy.set_sample_rate(hz);
+    }
+    0
+}
+
+/// Holds the keys whose `GBC_KEY_*` bits are set until the next call.
+///
+/// # Safety
+///
+/// `emulator` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_set_keys(emulator: *mut GbcEmulator, keys: u8) {
+    let Some(emulator) = emulator.as_mut() else {
+        return;
+    };
+    for (bit, key) in KEYS.into_iter().enumerate() {
+        emulator.keys.set_key(key, keys >> bit & 1 == 1);
+    }
+}
+
+/// Size of the battery-backed RAM in bytes, or 0 without a game that keeps any.
+///
+/// # Safety
+///
+/// `emulator` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_save_size(emulator: *const GbcEmulator) -> usize {
+    emulator
+        .as_ref()
+        .and_then(|emulator| emulator.gameboy.as_ref()?.save_data())
+        .map_or(0, |save| save.len())
+}
+
+/// Copies the battery-backed RAM into `out`, which must hold [`gbc_save_size`] bytes.
+///
+/// # Safety
+///
+/// `emulator` must be a live handle and `out` must point to `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_save(emulator: *mut GbcEmulator, out: *mut u8, len: usize) -> c_int {
+    let Some(emulator) = emulator.as_mut() else {
+        return -1;
+    };
+    let Some(save) = emulator
+        .gameboy
+        .as_ref()
+        .and_then(|gameboy| gameboy.save_data())
+    else {
+        return emulator.fail("No battery-backed RAM");
+    };
+    if out.is_null() || len != save.len() {
+        return emulator.fail(format!(
+            "Save is {} bytes, got room for {}",
+            save.len(),
+            len
+        ));
+    }
+    slice::from_raw_parts_mut(out, len).copy_from_slice(&save);
+    0
+}
+
+/// Powers on again from a save made by [`gbc_save`] for the same game.
+///
+/// # Safety
+///
+/// `emulator` must be a live handle and `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_load_save(
+    emulator: *mut GbcEmulator,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    let Some(emulator) = emulator.as_mut() else {
+        return -1;
+    };
+    let size = gbc_save_size(emulator);
+    if size == 0 {
+        return emulator.fail("No battery-backed RAM");
+    }
+    if data.is_null() || len != size {
+        return emulator.fail(format!(
+            "Save is {} bytes but the cartridge keeps {}",
+            len, size
+        ));
+    }
+    emulator.power_on(Some(bytes(data, len).to_vec()))
+}
+
+/// Why the last call that returned -1 failed, as a NUL-terminated string owned by the
+/// handle and valid until the next call.
+///
+/// # Safety
+///
+/// `emulator` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn gbc_last_error(emulator: *const GbcEmulator) -> *const c_char {
+    emulator
+        .as_ref()
+        .map_or(ptr::null(), |emulator| emulator.error.as_ptr())
+}
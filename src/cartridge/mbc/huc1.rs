@@ -1,25 +1,130 @@
 use crate::cartridge::{rom, Mbc};
+use crate::state_hash::StateHasher;
 
 pub struct Huc1 {
-    rom: Vec<u8>,
+    rom: rom::Rom,
+    ram: Vec<u8>,
+    rom_bank: u8,
+    ram_bank: u8,
+    rom_bank_mask: u8,
+    ram_bank_mask: u8,
+    // 0x0E in 0x0000-0x1FFF maps the IR port over 0xA000-0xBFFF, any other value maps RAM
+    ir_mode: bool,
+    ir_led: bool,
 }
 
 impl Mbc for Huc1 {
     fn read(&self, address: u16) -> u8 {
-        todo!()
+        match address {
+            0x0000..=0x3FFF => self.rom.data()[address as usize],
+            0x4000..=0x7FFF => {
+                let bank = (self.rom_bank & self.rom_bank_mask) as usize * 0x4000;
+                self.rom.data()[bank + (address & 0x3FFF) as usize]
+            }
+            0xA000..=0xBFFF => {
+                if self.ir_mode {
+                    // Our own LED lights the sensor, as can an attached InfraredTransceiver
+                    0xC0 | self.ir_led as u8
+                } else {
+                    match self.ram_address(address) {
+                        Some(index) => self.ram[index],
+                        None => 0xFF,
+                    }
+                }
+            }
+            _ => unreachable!("Unreachable HuC1 read address: {:#06X}", address),
+        }
     }
 
-    fn write(&mut self, _address: u16, _value: u8) {
-        todo!()
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ir_mode = value & 0x0F == 0x0E,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x3F).max(1),
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF => {
+                if self.ir_mode {
+                    self.ir_led = value & 0x01 == 0x01;
+                } else if let Some(index) = self.ram_address(address) {
+                    self.ram[index] = value;
+                }
+            }
+            _ => unreachable!("Unreachable HuC1 write address: {:#06X}", address),
+        }
     }
 
-    fn save_data(&self) -> Option<Vec<u8>> {
-        todo!()
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.rom.have_ram().then_some(&self.ram[..])
+    }
+
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Huc1 {
+            ram,
+            rom_bank,
+            ram_bank,
+            ir_mode,
+            ir_led,
+            // Fixed by the cartridge
+            rom: _,
+            rom_bank_mask: _,
+            ram_bank_mask: _,
+        } = self;
+        hasher
+            .write(ram)
+            .write(rom_bank)
+            .write(ram_bank)
+            .write(ir_mode)
+            .write(ir_led);
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => (self.rom_bank & self.rom_bank_mask) as usize,
+        }
+    }
+
+    fn infrared_mapped(&self) -> bool {
+        self.ir_mode
+    }
+
+    fn drives_peripherals(&self, address: u16) -> bool {
+        self.infrared_mapped() && (0xA000..=0xBFFF).contains(&address)
+    }
+
+    fn infrared_led(&self) -> bool {
+        self.ir_led
     }
 }
 
 impl Huc1 {
     pub fn new(rom: rom::Rom, backup: Option<Vec<u8>>) -> Self {
-        todo!()
+        let ram = match backup {
+            Some(data) => data,
+            None => vec![0; rom.ram_size()],
+        };
+
+        let rom_bank_mask = (rom.rom_size() / 0x4000).saturating_sub(1) as u8;
+        let ram_bank_mask = (rom.ram_size() / 0x2000).saturating_sub(1) as u8;
+
+        Self {
+            rom,
+            ram,
+            rom_bank: 1,
+            ram_bank: 0,
+            rom_bank_mask,
+            ram_bank_mask,
+            ir_mode: false,
+            ir_led: false,
+        }
+    }
+
+    // RAM has no enable register on HuC1; it is accessible whenever IR mode is off
+    fn ram_address(&self, address: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let bank = (self.ram_bank & self.ram_bank_mask) as usize;
+        Some((bank * 0x2000 + (address & 0x1FFF) as usize) % self.ram.len())
     }
 }
@@ -0,0 +1,245 @@
+use crate::cartridge::{rom, Mbc};
+use crate::interface::ClockSource;
+use crate::state_hash::StateHasher;
+use log::warn;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+// The clock offset is saved after RAM as a little-endian i64
+const RTC_FOOTER_SIZE: usize = 8;
+
+pub struct Huc3 {
+    rom: rom::Rom,
+    ram: Vec<u8>,
+    rom_bank: u8,
+    ram_bank: u8,
+    rom_bank_mask: u8,
+    ram_bank_mask: u8,
+    // Written to 0x0000-0x1FFF, decides what 0xA000-0xBFFF maps
+    mode: u8,
+    ir_led: bool,
+
+    // The RTC chip is driven through commands written in mode 0xB. It has 256 nibbles
+    // of memory, the time being copied to and from 0x00-0x06 by extended commands.
+    memory: Box<[u8; 0x100]>,
+    address: u8,
+    last_command: u8,
+    result: u8,
+    // Minutes from the clock source to the cartridge clock
+    offset_minutes: i64,
+    clock: Option<Box<dyn ClockSource>>,
+}
+
+impl Mbc for Huc3 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom.data()[address as usize],
+            0x4000..=0x7FFF => {
+                let bank = (self.rom_bank & self.rom_bank_mask) as usize * 0x4000;
+                self.rom.data()[bank + (address & 0x3FFF) as usize]
+            }
+            0xA000..=0xBFFF => match self.mode {
+                0x00 | 0x0A => match self.ram_address(address) {
+                    Some(index) => self.ram[index],
+                    None => 0xFF,
+                },
+                0x0C => 0x80 | (self.last_command << 4) | self.result,
+                // The RTC is always ready for the next command
+                0x0D => 0x01,
+                // Our own LED lights the sensor, as can an attached InfraredTransceiver
+                0x0E => 0xC0 | self.ir_led as u8,
+                _ => 0xFF,
+            },
+            _ => unreachable!("Unreachable HuC3 read address: {:#06X}", address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.mode = value & 0x0F,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF => match self.mode {
+                // Mode 0 maps RAM read-only
+                0x0A => {
+                    if let Some(index) = self.ram_address(address) {
+                        self.ram[index] = value;
+                    }
+                }
+                0x0B => self.rtc_command(value),
+                0x0E => self.ir_led = value & 0x01 == 0x01,
+                _ => {}
+            },
+            _ => unreachable!("Unreachable HuC3 write address: {:#06X}", address),
+        }
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        Some(&self.ram)
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        let mut data = self.ram.clone();
+        data.extend(self.offset_minutes.to_le_bytes());
+        Some(data)
+    }
+
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Huc3 {
+            ram,
+            rom_bank,
+            ram_bank,
+            mode,
+            ir_led,
+            memory,
+            address,
+            last_command,
+            result,
+            offset_minutes,
+            // Fixed by the cartridge, and the host clock
+            rom: _,
+            rom_bank_mask: _,
+            ram_bank_mask: _,
+            clock: _,
+        } = self;
+        hasher
+            .write(ram)
+            .write(rom_bank)
+            .write(ram_bank)
+            .write(mode)
+            .write(ir_led)
+            .write(&**memory)
+            .write(address)
+            .write(last_command)
+            .write(result)
+            .write(offset_minutes);
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => (self.rom_bank & self.rom_bank_mask) as usize,
+        }
+    }
+
+    fn rtc_supported(&self) -> bool {
+        self.clock.is_some()
+    }
+
+    fn infrared_mapped(&self) -> bool {
+        self.mode == 0x0E
+    }
+
+    fn drives_peripherals(&self, address: u16) -> bool {
+        self.infrared_mapped() && (0xA000..=0xBFFF).contains(&address)
+    }
+
+    fn infrared_led(&self) -> bool {
+        self.ir_led
+    }
+}
+
+impl Huc3 {
+    pub fn new(
+        rom: rom::Rom,
+        backup: Option<Vec<u8>>,
+        clock: Option<Box<dyn ClockSource>>,
+    ) -> Self {
+        let ram_size = rom.ram_size();
+        let (ram, offset_minutes) = match backup {
+            Some(data) if data.len() == ram_size + RTC_FOOTER_SIZE => {
+                let footer = data[ram_size..].try_into().unwrap();
+                (data[..ram_size].to_vec(), i64::from_le_bytes(footer))
+            }
+            Some(data) => (data, 0),
+            None => (vec![0; ram_size], 0),
+        };
+
+        let rom_bank_mask = (rom.rom_size() / 0x4000).saturating_sub(1) as u8;
+        let ram_bank_mask = (rom.ram_size() / 0x2000).saturating_sub(1) as u8;
+
+        Self {
+            rom,
+            ram,
+            rom_bank: 1,
+            ram_bank: 0,
+            rom_bank_mask,
+            ram_bank_mask,
+            mode: 0,
+            ir_led: false,
+            memory: Box::new([0; 0x100]),
+            address: 0,
+            last_command: 0,
+            result: 0,
+            offset_minutes,
+            clock,
+        }
+    }
+
+    fn ram_address(&self, address: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let bank = (self.ram_bank & self.ram_bank_mask) as usize;
+        Some((bank * 0x2000 + (address & 0x1FFF) as usize) % self.ram.len())
+    }
+
+    // The upper nibble is the command and the lower its argument
+    fn rtc_command(&mut self, value: u8) {
+        let (command, argument) = ((value >> 4) & 0x07, value & 0x0F);
+        match command {
+            0x1 => {
+                self.result = self.memory[self.address as usize];
+                self.address = self.address.wrapping_add(1);
+            }
+            0x3 => {
+                self.memory[self.address as usize] = argument;
+                self.address = self.address.wrapping_add(1);
+            }
+            0x4 => self.address = (self.address & 0xF0) | argument,
+            0x5 => self.address = (self.address & 0x0F) | (argument << 4),
+            0x6 => match argument {
+                0x0 => self.latch_clock(),
+                0x1 => self.set_clock(),
+                // Status: reports the clock as running
+                0x2 => self.result = 0x01,
+                _ => warn!("Unsupported HuC3 RTC extended command: {:#03X}", argument),
+            },
+            _ => warn!("Unsupported HuC3 RTC command: {:#04X}", value),
+        }
+        self.last_command = command;
+    }
+
+    fn clock_minutes(&self) -> i64 {
+        let seconds = self.clock.as_ref().map_or(0, |clock| clock.now());
+        seconds.div_euclid(60)
+    }
+
+    fn minutes(&self) -> i64 {
+        self.clock_minutes() + self.offset_minutes
+    }
+
+    // Minute of the day in nibbles 0x00-0x02 and the day count in 0x03-0x06
+    fn latch_clock(&mut self) {
+        let minutes = self.minutes();
+        let minute_of_day = minutes.rem_euclid(MINUTES_PER_DAY) as u16;
+        let days = minutes.div_euclid(MINUTES_PER_DAY) as u16;
+        for i in 0..3 {
+            self.memory[i] = (minute_of_day >> (i * 4)) as u8 & 0x0F;
+        }
+        for i in 0..4 {
+            self.memory[3 + i] = (days >> (i * 4)) as u8 & 0x0F;
+        }
+    }
+
+    fn set_clock(&mut self) {
+        let nibbles = |range: std::ops::Range<usize>| {
+            self.memory[range]
+                .iter()
+                .rev()
+                .fold(0i64, |value, &nibble| (value << 4) | nibble as i64)
+        };
+        let minutes = nibbles(3..7) * MINUTES_PER_DAY + nibbles(0..3);
+        self.offset_minutes = minutes - self.clock_minutes();
+    }
+}
@@ -1,4 +1,11 @@
 use crate::cartridge::{rom, Mbc};
+use crate::state_hash::StateHasher;
+
+const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
 
 pub struct Mbc1 {
     rom: rom::Rom,
@@ -9,36 +16,20 @@ pub struct Mbc1 {
     rom_bank_mask: u8,
     ram_bank_mask: u8,
     banking_mode: bool,
+    // MBC1M wires the upper bank bits to ROM A18-A19 instead of A19-A20
+    multicart: bool,
 }
 
 impl Mbc for Mbc1 {
     fn read(&self, address: u16) -> u8 {
         match address {
-            0x0000..=0x3FFF => {
-                let rom_bank = if self.banking_mode {
-                    ((self.ram_bank_or_upper_rom_bank << 5) & self.rom_bank_mask) as usize
-                } else {
-                    0
-                };
-                self.rom.data()[rom_bank * 0x4000 + address as usize]
-            }
-            0x4000..=0x7FFF => {
-                let rom_bank = ((self.ram_bank_or_upper_rom_bank << 5 | self.rom_bank)
-                    & self.rom_bank_mask) as usize;
-                self.rom.data()[rom_bank * 0x4000 + (address & 0x3FFF) as usize]
-            }
-            0xA000..=0xBFFF => {
-                if self.ram_enable {
-                    let ram_bank = if self.banking_mode {
-                        (self.ram_bank_or_upper_rom_bank & self.ram_bank_mask) as usize
-                    } else {
-                        0
-                    };
-                    self.ram[ram_bank * 0x2000 + (address & 0x1FFF) as usize]
-                } else {
-                    0xFF
-                }
+            0x0000..=0x7FFF => {
+                self.rom.data()[self.rom_bank(address) * 0x4000 + (address & 0x3FFF) as usize]
             }
+            0xA000..=0xBFFF => match self.ram_address(address) {
+                Some(index) => self.ram[index],
+                None => 0xFF,
+            },
             _ => unreachable!("Unreachable MBC1 read address: {:#06X}", address),
         }
     }
@@ -46,28 +37,49 @@ impl Mbc for Mbc1 {
     fn write(&mut self, address: u16, value: u8) {
         match address {
             0x0000..=0x1FFF => self.ram_enable = (value & 0x0F) == 0x0A,
+            // Only the 5-bit register is checked for zero, so banks 0x20/0x40/0x60 alias to the next bank
             0x2000..=0x3FFF => self.rom_bank = (value & 0x1F).max(1),
             0x4000..=0x5FFF => self.ram_bank_or_upper_rom_bank = value & 0x03,
             0x6000..=0x7FFF => self.banking_mode = value & 0x01 == 0x01,
             0xA000..=0xBFFF => {
-                if self.ram_enable {
-                    let ram_bank = if self.banking_mode {
-                        (self.ram_bank_or_upper_rom_bank & self.ram_bank_mask) as usize
-                    } else {
-                        0
-                    };
-                    self.ram[ram_bank * 0x2000 + (address & 0x1FFF) as usize] = value;
+                if let Some(index) = self.ram_address(address) {
+                    self.ram[index] = value;
                 }
             }
             _ => unreachable!("Unreachable MBC1 write address: {:#06X}", address),
         }
     }
 
-    fn save_data(&self) -> Option<Vec<u8>> {
-        if self.rom.have_ram() {
-            Some(self.ram.clone())
-        } else {
-            None
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.rom.have_ram().then_some(&self.ram[..])
+    }
+
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Mbc1 {
+            ram,
+            ram_enable,
+            rom_bank,
+            ram_bank_or_upper_rom_bank,
+            banking_mode,
+            // Fixed by the cartridge
+            rom: _,
+            rom_bank_mask: _,
+            ram_bank_mask: _,
+            multicart: _,
+        } = self;
+        hasher
+            .write(ram)
+            .write(ram_enable)
+            .write(rom_bank)
+            .write(ram_bank_or_upper_rom_bank)
+            .write(banking_mode);
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF if self.banking_mode => self.upper_rom_bank(),
+            0x0000..=0x3FFF => 0,
+            _ => self.upper_rom_bank() | self.lower_rom_bank(),
         }
     }
 }
@@ -81,29 +93,50 @@ impl Mbc1 {
 
         let rom_bank_mask = (rom.rom_size() / 0x4000).saturating_sub(1) as u8;
         let ram_bank_mask = (rom.ram_size() / 0x2000).saturating_sub(1) as u8;
-
-        // println!(
-        //     "ROM: size: {}, banks: {}, mask: {:b}",
-        //     rom.rom_size(),
-        //     rom.rom_size() / 0x4000,
-        //     rom_bank_mask
-        // );
-        // println!(
-        //     "RAM: size: {}, banks: {}, mask: {:b}",
-        //     rom.ram_size(),
-        //     rom.ram_size() / 0x2000,
-        //     ram_bank_mask
-        // );
+        let multicart = is_multicart(rom.data());
 
         Self {
             rom,
             ram,
             ram_enable: false,
             rom_bank: 1,
-            ram_bank_or_upper_rom_bank: 1,
+            ram_bank_or_upper_rom_bank: 0,
             rom_bank_mask,
             ram_bank_mask,
             banking_mode: false,
+            multicart,
+        }
+    }
+
+    fn upper_rom_bank(&self) -> usize {
+        let shift = if self.multicart { 4 } else { 5 };
+        ((self.ram_bank_or_upper_rom_bank << shift) & self.rom_bank_mask) as usize
+    }
+
+    fn lower_rom_bank(&self) -> usize {
+        let mask = if self.multicart { 0x0F } else { 0x1F };
+        (self.rom_bank & mask & self.rom_bank_mask) as usize
+    }
+
+    fn ram_address(&self, address: u16) -> Option<usize> {
+        if !self.ram_enable || self.ram.is_empty() {
+            return None;
         }
+        // The upper bank bits only reach the RAM chip in mode 1
+        let ram_bank = if self.banking_mode {
+            (self.ram_bank_or_upper_rom_bank & self.ram_bank_mask) as usize
+        } else {
+            0
+        };
+        Some((ram_bank * 0x2000 + (address & 0x1FFF) as usize) % self.ram.len())
     }
 }
+
+// 8 Mbit multicarts repeat the boot logo in the header of each 256 KiB game
+fn is_multicart(data: &[u8]) -> bool {
+    data.len() == 0x100000
+        && (1..4).any(|game| {
+            let header = game * 0x40000 + 0x0104;
+            data[header..header + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+        })
+}
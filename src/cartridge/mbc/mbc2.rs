@@ -1,4 +1,7 @@
 use crate::cartridge::{rom, Mbc};
+use crate::state_hash::StateHasher;
+
+const RAM_SIZE: usize = 512;
 
 pub struct Mbc2 {
     rom: rom::Rom,
@@ -17,15 +20,10 @@ impl Mbc for Mbc2 {
                 let offset = (address - 0x4000) as usize;
                 self.rom.data()[bank + offset]
             }
-            0xA000..=0xA1FF => {
+            // 512 half-byte cells, echoed through 0xA000-0xBFFF
+            0xA000..=0xBFFF => {
                 if self.ram_enable {
-                    let address = (address & 0x1FF) as usize / 2;
-                    let data = self.ram[address];
-                    if address % 2 == 0 {
-                        data & 0x0F
-                    } else {
-                        data >> 4
-                    }
+                    self.ram[(address & 0x1FF) as usize] | 0xF0
                 } else {
                     0xFF
                 }
@@ -36,6 +34,7 @@ impl Mbc for Mbc2 {
 
     fn write(&mut self, address: u16, value: u8) {
         match address {
+            // A8 selects between RAMG and ROMB
             0x0000..=0x3FFF => {
                 if address & 0x100 == 0 {
                     self.ram_enable = value & 0x0F == 0x0A;
@@ -43,25 +42,37 @@ impl Mbc for Mbc2 {
                     self.rom_bank = (value & 0x0F).max(1);
                 }
             }
+            0x4000..=0x7FFF => {}
             0xA000..=0xBFFF => {
                 if self.ram_enable {
-                    let address = (address & 0x1FF) as usize / 2;
-                    let data = self.ram[address];
-                    if address % 2 == 0 {
-                        self.ram[address] = (data & 0xF0) | (value & 0x0F);
-                    } else {
-                        self.ram[address] = (data & 0x0F) | (value << 4);
-                    }
+                    self.ram[(address & 0x1FF) as usize] = value & 0x0F;
                 }
             }
             _ => unreachable!("Unreachable MBC2 write address: {:#06X}", address),
         }
     }
-    fn save_data(&self) -> Option<Vec<u8>> {
-        if self.rom.have_ram() {
-            Some(self.ram.clone())
-        } else {
-            None
+
+    // The RAM is built into the MBC, so only the battery decides whether it is kept
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.rom.have_battery().then_some(&self.ram[..])
+    }
+
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Mbc2 {
+            ram,
+            ram_enable,
+            rom_bank,
+            // Fixed by the cartridge
+            rom: _,
+            rom_bank_mask: _,
+        } = self;
+        hasher.write(ram).write(ram_enable).write(rom_bank);
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => (self.rom_bank & self.rom_bank_mask) as usize,
         }
     }
 }
@@ -70,10 +81,8 @@ impl Mbc2 {
     pub fn new(rom: rom::Rom, backup: Option<Vec<u8>>) -> Self {
         let rom_bank_num = rom.rom_size() / 0x4000;
         let rom_bank_mask = rom_bank_num.saturating_sub(1) as u8;
-        let ram = match backup {
-            Some(data) => data,
-            None => vec![0; 512],
-        };
+        let mut ram = backup.map(upgrade_save).unwrap_or_default();
+        ram.resize(RAM_SIZE, 0);
 
         Self {
             rom,
@@ -84,3 +93,20 @@ impl Mbc2 {
         }
     }
 }
+
+// Saves used to pack the cells into nibbles, putting cells 2n and 2n+1 both in byte n:
+// in its low nibble for even n and its high nibble for odd n. Each cell now has a byte
+// of its own with the upper nibble clear, so a set upper nibble marks an old save. An
+// old save with every odd byte zero cannot be told apart and loads as it is.
+fn upgrade_save(save: Vec<u8>) -> Vec<u8> {
+    if save.iter().all(|&byte| byte & 0xF0 == 0) {
+        return save;
+    }
+    (0..RAM_SIZE)
+        .map(|cell| {
+            let byte = save.get(cell / 2).copied().unwrap_or(0);
+            let shift = if cell / 2 % 2 == 0 { 0 } else { 4 };
+            byte >> shift & 0x0F
+        })
+        .collect()
+}
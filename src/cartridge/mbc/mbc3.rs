@@ -1,7 +1,15 @@
 use crate::cartridge::{rom, Mbc};
-use chrono::{DateTime, Datelike, Timelike, Utc};
+use crate::interface::ClockSource;
+use crate::state_hash::StateHasher;
+use crate::support::{self, RtcFooter, RtcFooterFormat, RtcRegisters, SaveFile};
 use log::warn;
 
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+// Bits of register 0Ch
+const DAY_HIGH: u8 = 0x01;
+const HALT: u8 = 0x40;
+const DAY_CARRY: u8 = 0x80;
+
 pub struct Mbc3 {
     rom: rom::Rom,
     rom_bank: u8,
@@ -11,8 +19,8 @@ pub struct Mbc3 {
     ram_rtc_enable: bool,
     rtc_register_select: RegisterSelect,
     prev_latch_data: u8,
-    clock: DateTime<Utc>,
-    carry_day: bool,
+    rtc: Rtc,
+    clock: Option<Box<dyn ClockSource>>,
 }
 
 impl Mbc for Mbc3 {
@@ -32,18 +40,7 @@ impl Mbc for Mbc3 {
                             let offset = (address - 0xA000) as usize;
                             self.ram[bank + offset]
                         }
-                        RegisterSelect::Rtc(reg) => match reg {
-                            0x08 => self.clock.second() as u8,
-                            0x09 => self.clock.minute() as u8,
-                            0x0A => self.clock.hour() as u8,
-                            0x0B => self.clock.day() as u8,
-                            0x0C => {
-                                let day = (self.clock.day() >> 8) as u8;
-                                let carry_day = self.carry_day as u8;
-                                carry_day << 7 | day
-                            }
-                            _ => 0,
-                        },
+                        RegisterSelect::Rtc(reg) => self.rtc.read(reg),
                     }
                 } else {
                     0xFF
@@ -71,10 +68,7 @@ impl Mbc for Mbc3 {
             },
             0x6000..=0x7FFF => {
                 if self.prev_latch_data == 0x00 && value == 0x01 {
-                    self.clock = Utc::now();
-                    let prev_day = self.clock.day() & 0x1FF;
-                    let now_day = self.clock.day() & 0x1FF;
-                    self.carry_day = prev_day > now_day;
+                    self.rtc.latch(self.now());
                 }
                 self.prev_latch_data = value;
             }
@@ -86,9 +80,7 @@ impl Mbc for Mbc3 {
                             let offset = (address - 0xA000) as usize;
                             self.ram[bank + offset] = value;
                         }
-                        RegisterSelect::Rtc(_) => {
-                            warn!("Invalid RTC write address: {:#06X}", address)
-                        }
+                        RegisterSelect::Rtc(reg) => self.rtc.write(reg, value, self.now()),
                     }
                 }
             }
@@ -97,25 +89,114 @@ impl Mbc for Mbc3 {
         }
     }
 
+    fn battery_ram(&self) -> Option<&[u8]> {
+        (self.rom.have_timer() || self.rom.have_ram()).then_some(&self.ram[..])
+    }
+
     fn save_data(&self) -> Option<Vec<u8>> {
-        if self.rom.have_ram() {
+        if self.rom.have_timer() {
+            // The clock goes after RAM in the layout BGB and VBA-M use
+            let now = self.now();
+            let current = self.rtc.at(now);
+            let save = SaveFile {
+                sram: self.ram.clone(),
+                rtc: Some(RtcFooter {
+                    current,
+                    latched: self.rtc.latched,
+                    timestamp: now as u64,
+                    battery_low: self.rtc.battery_low,
+                }),
+            };
+            Some(support::export_save(&save, RtcFooterFormat::Long))
+        } else if self.rom.have_ram() {
             Some(self.ram.clone())
         } else {
             None
         }
     }
+
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Mbc3 {
+            ram,
+            rom_bank,
+            ram_rtc_enable,
+            rtc_register_select,
+            prev_latch_data,
+            rtc:
+                Rtc {
+                    current,
+                    latched,
+                    last_update,
+                    battery_low,
+                },
+            // Fixed by the cartridge, and the host clock
+            rom: _,
+            rom_bank_mask: _,
+            ram_bank_mask: _,
+            clock: _,
+        } = self;
+        let register_select = match *rtc_register_select {
+            RegisterSelect::RamBank(bank) => (0u8, bank),
+            RegisterSelect::Rtc(register) => (1, register),
+        };
+        hasher
+            .write(ram)
+            .write(rom_bank)
+            .write(ram_rtc_enable)
+            .write(&register_select.0)
+            .write(&register_select.1)
+            .write(prev_latch_data);
+        for registers in [current, latched] {
+            hasher.write(&[
+                registers.seconds,
+                registers.minutes,
+                registers.hours,
+                registers.days_low,
+                registers.days_high,
+            ]);
+        }
+        hasher.write(last_update).write(battery_low);
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => (self.rom_bank & self.rom_bank_mask) as usize,
+        }
+    }
+
+    fn rtc_supported(&self) -> bool {
+        self.rom.have_timer() && self.clock.is_some()
+    }
+
+    fn rtc_battery_low(&self) -> bool {
+        self.rtc.battery_low
+    }
+
+    fn set_rtc_battery_low(&mut self, low: bool) {
+        self.rtc.update(self.now());
+        self.rtc.battery_low = low;
+    }
 }
 
 impl Mbc3 {
-    pub fn new(rom: rom::Rom, backup: Option<Vec<u8>>) -> Self {
+    pub fn new(
+        rom: rom::Rom,
+        backup: Option<Vec<u8>>,
+        clock: Option<Box<dyn ClockSource>>,
+    ) -> Self {
         let rom_bank_num = rom.rom_size() / 0x4000;
         let ram_bank_num = rom.ram_size() / 0x2000;
         let rom_bank_mask = rom_bank_num.saturating_sub(1) as u8;
         let ram_bank_mask = ram_bank_num.saturating_sub(1) as u8;
 
-        let ram = match backup {
-            Some(data) => data,
-            None => vec![0; rom.ram_size()],
+        let (ram, rtc) = match backup.map(|data| support::import_save(&data, rom.ram_size())) {
+            Some(Ok(save)) => (save.sram, save.rtc.map(Rtc::from_footer)),
+            Some(Err(e)) => {
+                warn!("Ignoring the MBC3 save: {}", e);
+                (vec![0; rom.ram_size()], None)
+            }
+            None => (vec![0; rom.ram_size()], None),
         };
 
         Self {
@@ -127,11 +208,15 @@ impl Mbc3 {
             ram_rtc_enable: false,
             rtc_register_select: RegisterSelect::RamBank(0),
             prev_latch_data: 0,
-            clock: Utc::now(),
-            carry_day: false,
+            rtc: rtc.unwrap_or_else(|| Rtc::new(clock.as_ref().map_or(0, |clock| clock.now()))),
+            clock,
         }
     }
 
+    fn now(&self) -> i64 {
+        self.clock.as_ref().map_or(0, |clock| clock.now())
+    }
+
     fn is_mbc30(&self) -> bool {
         self.rom.rom_size() > 2 * 1024 * 1024 || self.rom.ram_size() > 32 * 1024
     }
@@ -142,3 +227,137 @@ enum RegisterSelect {
     RamBank(u8),
     Rtc(u8),
 }
+
+// The clock keeps the registers it had at `last_update` and catches up with the clock
+// source whenever the game touches it. Reads see the latched copy. With a low battery
+// the oscillator stops, and the time until it is replaced is lost.
+#[derive(Debug, Clone, Copy)]
+struct Rtc {
+    current: RtcRegisters,
+    latched: RtcRegisters,
+    last_update: i64,
+    battery_low: bool,
+}
+
+impl Rtc {
+    fn new(now: i64) -> Self {
+        Self {
+            current: RtcRegisters::default(),
+            latched: RtcRegisters::default(),
+            last_update: now,
+            battery_low: false,
+        }
+    }
+
+    fn from_footer(footer: RtcFooter) -> Self {
+        Self {
+            current: footer.current,
+            latched: footer.latched,
+            last_update: footer.timestamp as i64,
+            battery_low: footer.battery_low,
+        }
+    }
+
+    fn read(&self, reg: u8) -> u8 {
+        let registers = self.latched;
+        match reg {
+            0x08 => registers.seconds,
+            0x09 => registers.minutes,
+            0x0A => registers.hours,
+            0x0B => registers.days_low,
+            0x0C => registers.days_high,
+            _ => unreachable!("Invalid RTC register: {:#04X}", reg),
+        }
+    }
+
+    fn write(&mut self, reg: u8, value: u8, now: i64) {
+        self.update(now);
+        let registers = &mut self.current;
+        match reg {
+            0x08 => registers.seconds = value & 0x3F,
+            0x09 => registers.minutes = value & 0x3F,
+            0x0A => registers.hours = value & 0x1F,
+            0x0B => registers.days_low = value,
+            0x0C => registers.days_high = value & (DAY_CARRY | HALT | DAY_HIGH),
+            _ => unreachable!("Invalid RTC register: {:#04X}", reg),
+        }
+    }
+
+    fn latch(&mut self, now: i64) {
+        self.update(now);
+        self.latched = self.current;
+    }
+
+    fn update(&mut self, now: i64) {
+        self.current = self.at(now);
+        self.last_update = now;
+    }
+
+    // The registers as of `now`
+    fn at(&self, now: i64) -> RtcRegisters {
+        let mut registers = self.current;
+        let elapsed = now - self.last_update;
+        if !self.battery_low && registers.days_high & HALT == 0 && elapsed > 0 {
+            advance(&mut registers, elapsed as u64);
+        }
+        registers
+    }
+}
+
+fn advance(registers: &mut RtcRegisters, mut seconds: u64) {
+    // Values written out of range only wrap at their bit width, which needs stepping
+    while seconds > 0 && !in_range(registers) {
+        tick(registers);
+        seconds -= 1;
+    }
+    if seconds == 0 {
+        return;
+    }
+
+    let days = ((registers.days_high & DAY_HIGH) as u64) << 8 | registers.days_low as u64;
+    let total = days * SECONDS_PER_DAY
+        + registers.hours as u64 * 3600
+        + registers.minutes as u64 * 60
+        + registers.seconds as u64
+        + seconds;
+    let days = total / SECONDS_PER_DAY;
+    registers.seconds = (total % 60) as u8;
+    registers.minutes = (total / 60 % 60) as u8;
+    registers.hours = (total / 3600 % 24) as u8;
+    registers.days_low = days as u8;
+    registers.days_high = (registers.days_high & !DAY_HIGH) | ((days >> 8) as u8 & DAY_HIGH);
+    if days > 0x1FF {
+        registers.days_high |= DAY_CARRY;
+    }
+}
+
+fn in_range(registers: &RtcRegisters) -> bool {
+    registers.seconds < 60 && registers.minutes < 60 && registers.hours < 24
+}
+
+fn tick(registers: &mut RtcRegisters) {
+    registers.seconds = (registers.seconds + 1) & 0x3F;
+    if registers.seconds != 60 {
+        return;
+    }
+    registers.seconds = 0;
+    registers.minutes = (registers.minutes + 1) & 0x3F;
+    if registers.minutes != 60 {
+        return;
+    }
+    registers.minutes = 0;
+    registers.hours = (registers.hours + 1) & 0x1F;
+    if registers.hours != 24 {
+        return;
+    }
+    registers.hours = 0;
+    let (days_low, overflow) = registers.days_low.overflowing_add(1);
+    registers.days_low = days_low;
+    if overflow {
+        if registers.days_high & DAY_HIGH == 0 {
+            registers.days_high |= DAY_HIGH;
+        } else {
+            registers.days_high = (registers.days_high & !DAY_HIGH) | DAY_CARRY;
+        }
+    }
+}
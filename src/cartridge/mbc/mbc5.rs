@@ -1,4 +1,5 @@
 use crate::cartridge::{rom, Mbc};
+use crate::state_hash::StateHasher;
 
 pub struct Mbc5 {
     rom: rom::Rom,
@@ -8,6 +9,9 @@ pub struct Mbc5 {
     rom_bank_mask: u16,
     ram_bank: u8,
     ram_bank_mask: u8,
+    // Rumble cartridges drive the motor with bit 3 of the RAM bank
+    has_rumble: bool,
+    rumble: bool,
 }
 
 impl Mbc for Mbc5 {
@@ -20,7 +24,7 @@ impl Mbc for Mbc5 {
                 self.rom.data()[bank + offset]
             }
             0xA000..=0xBFFF => {
-                if self.ram_enable {
+                if self.ram_enable && !self.ram.is_empty() {
                     let bank = (self.ram_bank & self.ram_bank_mask) as usize * 0x2000;
                     let offset = (address - 0xA000) as usize;
                     self.ram[bank + offset]
@@ -35,29 +39,70 @@ impl Mbc for Mbc5 {
     fn write(&mut self, address: u16, value: u8) {
         match address {
             0x0000..=0x1FFF => self.ram_enable = value & 0x0F == 0x0A,
+            // 9-bit bank number; unlike MBC1, bank 0 can be mapped to 0x4000-0x7FFF
             0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
             0x3000..=0x3FFF => {
                 self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8)
             }
-            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumble = value & 0x08 != 0;
+                    self.ram_bank = value & 0x07;
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            }
+            0x6000..=0x7FFF => {}
             0xA000..=0xBFFF => {
-                if self.ram_enable {
+                if self.ram_enable && !self.ram.is_empty() {
                     let bank = (self.ram_bank & self.ram_bank_mask) as usize * 0x2000;
                     let offset = (address - 0xA000) as usize;
-                    self.ram[bank as usize + offset] = value;
+                    self.ram[bank + offset] = value;
                 }
             }
             _ => unreachable!("Unreachable MBC5 write address: {:#06X}", address),
         }
     }
 
-    fn save_data(&self) -> Option<Vec<u8>> {
-        if self.rom.have_ram() {
-            Some(self.ram.clone())
-        } else {
-            None
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.rom.have_ram().then_some(&self.ram[..])
+    }
+
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Mbc5 {
+            ram,
+            ram_enable,
+            rom_bank,
+            ram_bank,
+            rumble,
+            // Fixed by the cartridge
+            rom: _,
+            rom_bank_mask: _,
+            ram_bank_mask: _,
+            has_rumble: _,
+        } = self;
+        hasher
+            .write(ram)
+            .write(ram_enable)
+            .write(rom_bank)
+            .write(ram_bank)
+            .write(rumble);
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => (self.rom_bank & self.rom_bank_mask) as usize,
         }
     }
+
+    fn drives_peripherals(&self, address: u16) -> bool {
+        self.has_rumble && (0x4000..=0x5FFF).contains(&address)
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
 }
 
 impl Mbc5 {
@@ -72,6 +117,7 @@ impl Mbc5 {
 
         let rom_bank_mask = rom_bank_num.saturating_sub(1) as u16;
         let ram_bank_mask = ram_bank_num.saturating_sub(1) as u8;
+        let has_rumble = rom.have_rumble();
 
         Self {
             rom,
@@ -81,6 +127,8 @@ impl Mbc5 {
             ram_bank: 0,
             rom_bank_mask,
             ram_bank_mask,
+            has_rumble,
+            rumble: false,
         }
     }
 }
@@ -1,25 +1,134 @@
 use crate::cartridge::{rom, Mbc};
+use crate::state_hash::StateHasher;
 
+// Both the ROM and RAM are switched in two halves of their areas, with 8 KiB ROM banks
+// and 4 KiB RAM banks
+const ROM_BANK_SIZE: usize = 0x2000;
+const RAM_BANK_SIZE: usize = 0x1000;
+
+// The flash chip a half of the ROM area can be switched to is not emulated: it reads
+// 0xFF and ignores writes
 pub struct Mbc6 {
-    rom: Vec<u8>,
+    rom: rom::Rom,
+    ram: Vec<u8>,
+    ram_enable: bool,
+    // 0x4000-0x5FFF and 0x6000-0x7FFF
+    rom_banks: [u8; 2],
+    flash_selected: [bool; 2],
+    rom_bank_mask: u8,
+    // 0xA000-0xAFFF and 0xB000-0xBFFF
+    ram_banks: [u8; 2],
+    ram_bank_mask: u8,
 }
 
 impl Mbc for Mbc6 {
     fn read(&self, address: u16) -> u8 {
-        todo!()
+        match address {
+            0x0000..=0x3FFF => self.rom.data()[address as usize],
+            0x4000..=0x7FFF => {
+                let half = (address >= 0x6000) as usize;
+                if self.flash_selected[half] {
+                    return 0xFF;
+                }
+                let bank = (self.rom_banks[half] & self.rom_bank_mask) as usize * ROM_BANK_SIZE;
+                let offset = (address & 0x1FFF) as usize;
+                self.rom.data()[bank + offset]
+            }
+            0xA000..=0xBFFF => match self.ram_index(address) {
+                Some(index) => self.ram[index],
+                None => 0xFF,
+            },
+            _ => unreachable!("Unreachable MBC6 read address: {:#06X}", address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x03FF => self.ram_enable = value & 0x0F == 0x0A,
+            0x0400..=0x07FF => self.ram_banks[0] = value & 0x07,
+            0x0800..=0x0BFF => self.ram_banks[1] = value & 0x07,
+            // Flash enable and write enable
+            0x0C00..=0x1FFF => {}
+            0x2000..=0x27FF => self.rom_banks[0] = value & 0x7F,
+            0x2800..=0x2FFF => self.flash_selected[0] = value == 0x08,
+            0x3000..=0x37FF => self.rom_banks[1] = value & 0x7F,
+            0x3800..=0x3FFF => self.flash_selected[1] = value == 0x08,
+            0x4000..=0x7FFF => {}
+            0xA000..=0xBFFF => {
+                if let Some(index) = self.ram_index(address) {
+                    self.ram[index] = value;
+                }
+            }
+            _ => unreachable!("Unreachable MBC6 write address: {:#06X}", address),
+        }
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.rom.have_ram().then_some(&self.ram[..])
     }
 
-    fn write(&mut self, _address: u16, _value: u8) {
-        todo!()
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Mbc6 {
+            ram,
+            ram_enable,
+            rom_banks,
+            flash_selected,
+            ram_banks,
+            // Fixed by the cartridge
+            rom: _,
+            rom_bank_mask: _,
+            ram_bank_mask: _,
+        } = self;
+        hasher
+            .write(ram)
+            .write(ram_enable)
+            .write(rom_banks)
+            .write(flash_selected)
+            .write(ram_banks);
     }
 
-    fn save_data(&self) -> Option<Vec<u8>> {
-        todo!()
+    // In 8 KiB banks, 0x0000-0x3FFF holding banks 0 and 1
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => (address >= 0x2000) as usize,
+            0x4000..=0x5FFF => (self.rom_banks[0] & self.rom_bank_mask) as usize,
+            _ => (self.rom_banks[1] & self.rom_bank_mask) as usize,
+        }
     }
 }
 
 impl Mbc6 {
     pub fn new(rom: rom::Rom, backup: Option<Vec<u8>>) -> Self {
-        todo!()
+        let ram = match backup {
+            Some(data) => data,
+            None => vec![0; rom.ram_size()],
+        };
+
+        let rom_bank_num = rom.rom_size() / ROM_BANK_SIZE;
+        let ram_bank_num = rom.ram_size() / RAM_BANK_SIZE;
+
+        let rom_bank_mask = rom_bank_num.saturating_sub(1) as u8;
+        let ram_bank_mask = ram_bank_num.saturating_sub(1) as u8;
+
+        Self {
+            rom,
+            ram,
+            ram_enable: false,
+            // Mapped as bank 1 of a 16 KiB banked cartridge
+            rom_banks: [2, 3],
+            flash_selected: [false; 2],
+            rom_bank_mask,
+            ram_banks: [0; 2],
+            ram_bank_mask,
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> Option<usize> {
+        if !self.ram_enable || self.ram.is_empty() {
+            return None;
+        }
+        let half = (address >= 0xB000) as usize;
+        let bank = (self.ram_banks[half] & self.ram_bank_mask) as usize * RAM_BANK_SIZE;
+        Some(bank + (address & 0x0FFF) as usize)
     }
 }
@@ -1,7 +1,9 @@
 pub mod huc1;
+pub mod huc3;
 pub mod mbc1;
 pub mod mbc2;
 pub mod mbc3;
 pub mod mbc5;
 pub mod mbc6;
+pub mod pocket_camera;
 pub mod rom_only;
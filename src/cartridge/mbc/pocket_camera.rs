@@ -0,0 +1,169 @@
+use crate::cartridge::{rom, Mbc};
+use crate::peripherals::{CAMERA_HEIGHT, CAMERA_WIDTH};
+use crate::state_hash::StateHasher;
+
+// Setting bit 4 of the RAM bank maps the camera registers over 0xA000-0xBFFF
+const REGISTER_BANK: u8 = 0x10;
+// A000 holds the capture control, A001-A005 the exposure settings and A006-A035 the
+// 4x4 dither matrix of three thresholds each
+const REGISTER_COUNT: usize = 0x36;
+const MATRIX_START: usize = 0x06;
+// The picture is written to RAM bank 0 as 16x14 tiles
+const PICTURE_ADDRESS: usize = 0x0100;
+
+// The Game Boy Camera. Captures complete at once, and the picture is only dithered
+// through the game's threshold matrix, without the sensor's edge and exposure processing
+pub struct PocketCamera {
+    rom: rom::Rom,
+    ram: Vec<u8>,
+    ram_enable: bool,
+    rom_bank: u8,
+    rom_bank_mask: u8,
+    ram_bank: u8,
+    ram_bank_mask: u8,
+    registers: [u8; REGISTER_COUNT],
+}
+
+impl Mbc for PocketCamera {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom.data()[address as usize],
+            0x4000..=0x7FFF => {
+                let bank = (self.rom_bank & self.rom_bank_mask) as usize * 0x4000;
+                self.rom.data()[bank + (address & 0x3FFF) as usize]
+            }
+            0xA000..=0xBFFF => {
+                if self.ram_bank & REGISTER_BANK != 0 {
+                    // Only the capture control reads back
+                    match address & 0x7F {
+                        0x00 => self.registers[0] & 0x07,
+                        _ => 0x00,
+                    }
+                } else {
+                    match self.ram_address(address) {
+                        Some(index) => self.ram[index],
+                        None => 0xFF,
+                    }
+                }
+            }
+            _ => unreachable!("Unreachable Pocket Camera read address: {:#06X}", address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enable = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = value & 0x1F,
+            0x6000..=0x7FFF => {}
+            0xA000..=0xBFFF => {
+                if self.ram_bank & REGISTER_BANK != 0 {
+                    let register = (address & 0x7F) as usize;
+                    if register < REGISTER_COUNT {
+                        self.registers[register] = value;
+                    }
+                } else if self.ram_enable {
+                    if let Some(index) = self.ram_address(address) {
+                        self.ram[index] = value;
+                    }
+                }
+            }
+            _ => unreachable!("Unreachable Pocket Camera write address: {:#06X}", address),
+        }
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        self.rom.have_ram().then_some(&self.ram[..])
+    }
+
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let PocketCamera {
+            ram,
+            ram_enable,
+            rom_bank,
+            ram_bank,
+            registers,
+            // Fixed by the cartridge
+            rom: _,
+            rom_bank_mask: _,
+            ram_bank_mask: _,
+        } = self;
+        hasher
+            .write(ram)
+            .write(ram_enable)
+            .write(rom_bank)
+            .write(ram_bank)
+            .write(registers);
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        match address {
+            0x0000..=0x3FFF => 0,
+            _ => (self.rom_bank & self.rom_bank_mask) as usize,
+        }
+    }
+
+    fn drives_peripherals(&self, address: u16) -> bool {
+        self.ram_bank & REGISTER_BANK != 0 && (0xA000..=0xBFFF).contains(&address)
+    }
+
+    fn capture_requested(&self) -> bool {
+        self.registers[0] & 0x01 != 0
+    }
+
+    fn finish_capture(&mut self, picture: &[u8]) {
+        self.registers[0] &= !0x01;
+        if self.ram.len() < PICTURE_ADDRESS + CAMERA_WIDTH * CAMERA_HEIGHT / 4 {
+            return;
+        }
+        for (y, row) in picture.chunks_exact(CAMERA_WIDTH).enumerate() {
+            for (x, &brightness) in row.iter().enumerate() {
+                let cell = MATRIX_START + ((y & 3) * 4 + (x & 3)) * 3;
+                let thresholds = &self.registers[cell..cell + 3];
+                let color = 3 - thresholds.iter().filter(|&&t| brightness >= t).count() as u8;
+
+                let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+                let index = PICTURE_ADDRESS + tile * 16 + (y % 8) * 2;
+                let mask = 0x80 >> (x % 8);
+                for (plane, byte) in self.ram[index..index + 2].iter_mut().enumerate() {
+                    if color >> plane & 1 != 0 {
+                        *byte |= mask;
+                    } else {
+                        *byte &= !mask;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl PocketCamera {
+    pub fn new(rom: rom::Rom, backup: Option<Vec<u8>>) -> Self {
+        let ram = match backup {
+            Some(data) => data,
+            None => vec![0; rom.ram_size()],
+        };
+
+        let rom_bank_mask = (rom.rom_size() / 0x4000).saturating_sub(1) as u8;
+        let ram_bank_mask = (rom.ram_size() / 0x2000).saturating_sub(1) as u8;
+
+        Self {
+            rom,
+            ram,
+            ram_enable: false,
+            rom_bank: 1,
+            rom_bank_mask,
+            ram_bank: 0,
+            ram_bank_mask,
+            registers: [0; REGISTER_COUNT],
+        }
+    }
+
+    fn ram_address(&self, address: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let bank = (self.ram_bank & self.ram_bank_mask) as usize;
+        Some((bank * 0x2000 + (address & 0x1FFF) as usize) % self.ram.len())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::cartridge::{rom, Mbc};
+use crate::state_hash::StateHasher;
 
 pub struct RomOnly {
     rom: Vec<u8>,
@@ -6,16 +7,23 @@ pub struct RomOnly {
 
 impl Mbc for RomOnly {
     fn read(&self, address: u16) -> u8 {
-        self.rom[address as usize]
+        match address {
+            0x0000..=0x7FFF => self.rom.get(address as usize).copied().unwrap_or(0xFF),
+            // No external RAM is wired up
+            0xA000..=0xBFFF => 0xFF,
+            _ => unreachable!("Unreachable ROM only read address: {:#06X}", address),
+        }
     }
 
     fn write(&mut self, _address: u16, _value: u8) {
         // Do nothing
     }
 
-    fn save_data(&self) -> Option<Vec<u8>> {
+    fn battery_ram(&self) -> Option<&[u8]> {
         None
     }
+
+    fn hash_state(&self, _hasher: &mut StateHasher) {}
 }
 
 impl RomOnly {
@@ -1,14 +1,68 @@
 mod mbc;
 pub mod rom;
 
-use mbc::{huc1, mbc1, mbc2, mbc3, mbc5, mbc6, rom_only};
+use crate::context::EmulatorError;
+use crate::interface::ClockSource;
+#[cfg(feature = "rtc")]
+use crate::interface::SystemClock;
+use crate::state_hash::StateHasher;
+use mbc::{huc1, huc3, mbc1, mbc2, mbc3, mbc5, mbc6, pocket_camera, rom_only};
 use std::{default, fmt};
 
 pub trait Mbc {
     fn read(&self, address: u16) -> u8;
     fn write(&mut self, address: u16, value: u8);
 
-    fn save_data(&self) -> Option<Vec<u8>>;
+    // The cartridge RAM a battery keeps, which the save data starts with
+    fn battery_ram(&self) -> Option<&[u8]>;
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery_ram().map(<[u8]>::to_vec)
+    }
+
+    // Everything but the ROM, for `Cartridge::state_hash`
+    fn hash_state(&self, hasher: &mut StateHasher);
+
+    // ROM bank mapped at `address`, which is in 0x0000-0x7FFF
+    fn rom_bank(&self, address: u16) -> usize {
+        (address >= 0x4000) as usize
+    }
+
+    fn rtc_supported(&self) -> bool {
+        false
+    }
+
+    fn rtc_battery_low(&self) -> bool {
+        false
+    }
+
+    fn set_rtc_battery_low(&mut self, _low: bool) {}
+
+    // The IR port is mapped over 0xA000-0xBFFF, with bit 0 reading the sensor
+    fn infrared_mapped(&self) -> bool {
+        false
+    }
+
+    // Whether the write just made to `address` can have moved the IR LED, the rumble
+    // motor or the camera, so the rest of the system only checks them after those
+    fn drives_peripherals(&self, _address: u16) -> bool {
+        false
+    }
+
+    fn infrared_led(&self) -> bool {
+        false
+    }
+
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    // The game started a capture, which `finish_capture` completes with the picture
+    fn capture_requested(&self) -> bool {
+        false
+    }
+
+    fn finish_capture(&mut self, _picture: &[u8]) {}
 }
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -22,6 +76,7 @@ enum MbcType {
     Mbc6,
     Mbc7,
     Mmm01,
+    PocketCamera,
     Huc1,
     Huc3,
 }
@@ -37,6 +92,7 @@ impl fmt::Display for MbcType {
             MbcType::Mbc6 => "Mbc6",
             MbcType::Mbc7 => "Mbc7",
             MbcType::Mmm01 => "Mmm01",
+            MbcType::PocketCamera => "PocketCamera",
             MbcType::Huc1 => "Huc1",
             MbcType::Huc3 => "Huc3",
         };
@@ -51,21 +107,32 @@ pub enum Cartridge {
     Mbc3(mbc3::Mbc3),
     Mbc5(mbc5::Mbc5),
     Mbc6(mbc6::Mbc6),
+    PocketCamera(pocket_camera::PocketCamera),
     Huc1(huc1::Huc1),
+    Huc3(huc3::Huc3),
 }
 
 impl Cartridge {
-    pub fn new(rom: rom::Rom, backup: Option<Vec<u8>>) -> Self {
-        match rom.mbc_type() {
+    pub fn new(
+        rom: rom::Rom,
+        backup: Option<Vec<u8>>,
+        clock: Option<Box<dyn ClockSource>>,
+    ) -> Result<Self, EmulatorError> {
+        let cartridge = match rom.mbc_type() {
             MbcType::RomOnly => Cartridge::RomOnly(rom_only::RomOnly::new(rom)),
             MbcType::Mbc1 => Cartridge::Mbc1(mbc1::Mbc1::new(rom, backup)),
             MbcType::Mbc2 => Cartridge::Mbc2(mbc2::Mbc2::new(rom, backup)),
-            MbcType::Mbc3 => Cartridge::Mbc3(mbc3::Mbc3::new(rom, backup)),
+            MbcType::Mbc3 => Cartridge::Mbc3(mbc3::Mbc3::new(rom, backup, clock)),
             MbcType::Mbc5 => Cartridge::Mbc5(mbc5::Mbc5::new(rom, backup)),
             MbcType::Mbc6 => Cartridge::Mbc6(mbc6::Mbc6::new(rom, backup)),
+            MbcType::PocketCamera => {
+                Cartridge::PocketCamera(pocket_camera::PocketCamera::new(rom, backup))
+            }
             MbcType::Huc1 => Cartridge::Huc1(huc1::Huc1::new(rom, backup)),
-            _ => unimplemented!(),
-        }
+            MbcType::Huc3 => Cartridge::Huc3(huc3::Huc3::new(rom, backup, clock)),
+            mbc_type => return Err(rom::RomError::UnsupportedCartridgeType(mbc_type).into()),
+        };
+        Ok(cartridge)
     }
 
     pub fn read(&self, address: u16) -> u8 {
@@ -76,7 +143,9 @@ impl Cartridge {
             Cartridge::Mbc3(mbc) => mbc.read(address),
             Cartridge::Mbc5(mbc) => mbc.read(address),
             Cartridge::Mbc6(mbc) => mbc.read(address),
+            Cartridge::PocketCamera(mbc) => mbc.read(address),
             Cartridge::Huc1(mbc) => mbc.read(address),
+            Cartridge::Huc3(mbc) => mbc.read(address),
         }
     }
 
@@ -88,7 +157,9 @@ impl Cartridge {
             Cartridge::Mbc3(mbc) => mbc.write(address, value),
             Cartridge::Mbc5(mbc) => mbc.write(address, value),
             Cartridge::Mbc6(mbc) => mbc.write(address, value),
+            Cartridge::PocketCamera(mbc) => mbc.write(address, value),
             Cartridge::Huc1(mbc) => mbc.write(address, value),
+            Cartridge::Huc3(mbc) => mbc.write(address, value),
         }
     }
 
@@ -100,7 +171,204 @@ impl Cartridge {
             Cartridge::Mbc3(mbc) => mbc.save_data(),
             Cartridge::Mbc5(mbc) => mbc.save_data(),
             Cartridge::Mbc6(mbc) => mbc.save_data(),
+            Cartridge::PocketCamera(mbc) => mbc.save_data(),
             Cartridge::Huc1(mbc) => mbc.save_data(),
+            Cartridge::Huc3(mbc) => mbc.save_data(),
+        }
+    }
+
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        match self {
+            Cartridge::RomOnly(rom) => rom.battery_ram(),
+            Cartridge::Mbc1(mbc) => mbc.battery_ram(),
+            Cartridge::Mbc2(mbc) => mbc.battery_ram(),
+            Cartridge::Mbc3(mbc) => mbc.battery_ram(),
+            Cartridge::Mbc5(mbc) => mbc.battery_ram(),
+            Cartridge::Mbc6(mbc) => mbc.battery_ram(),
+            Cartridge::PocketCamera(mbc) => mbc.battery_ram(),
+            Cartridge::Huc1(mbc) => mbc.battery_ram(),
+            Cartridge::Huc3(mbc) => mbc.battery_ram(),
+        }
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+        match self {
+            Cartridge::RomOnly(rom) => rom.hash_state(&mut hasher),
+            Cartridge::Mbc1(mbc) => mbc.hash_state(&mut hasher),
+            Cartridge::Mbc2(mbc) => mbc.hash_state(&mut hasher),
+            Cartridge::Mbc3(mbc) => mbc.hash_state(&mut hasher),
+            Cartridge::Mbc5(mbc) => mbc.hash_state(&mut hasher),
+            Cartridge::Mbc6(mbc) => mbc.hash_state(&mut hasher),
+            Cartridge::PocketCamera(mbc) => mbc.hash_state(&mut hasher),
+            Cartridge::Huc1(mbc) => mbc.hash_state(&mut hasher),
+            Cartridge::Huc3(mbc) => mbc.hash_state(&mut hasher),
+        }
+        hasher.finish()
+    }
+
+    pub fn rom_bank(&self, address: u16) -> usize {
+        match self {
+            Cartridge::RomOnly(rom) => rom.rom_bank(address),
+            Cartridge::Mbc1(mbc) => mbc.rom_bank(address),
+            Cartridge::Mbc2(mbc) => mbc.rom_bank(address),
+            Cartridge::Mbc3(mbc) => mbc.rom_bank(address),
+            Cartridge::Mbc5(mbc) => mbc.rom_bank(address),
+            Cartridge::Mbc6(mbc) => mbc.rom_bank(address),
+            Cartridge::PocketCamera(mbc) => mbc.rom_bank(address),
+            Cartridge::Huc1(mbc) => mbc.rom_bank(address),
+            Cartridge::Huc3(mbc) => mbc.rom_bank(address),
         }
     }
+
+    pub fn mapper(&self) -> &'static str {
+        match self {
+            Cartridge::RomOnly(_) => "RomOnly",
+            Cartridge::Mbc1(_) => "Mbc1",
+            Cartridge::Mbc2(_) => "Mbc2",
+            Cartridge::Mbc3(_) => "Mbc3",
+            Cartridge::Mbc5(_) => "Mbc5",
+            Cartridge::Mbc6(_) => "Mbc6",
+            Cartridge::PocketCamera(_) => "PocketCamera",
+            Cartridge::Huc1(_) => "Huc1",
+            Cartridge::Huc3(_) => "Huc3",
+        }
+    }
+
+    pub fn rtc_supported(&self) -> bool {
+        match self {
+            Cartridge::RomOnly(rom) => rom.rtc_supported(),
+            Cartridge::Mbc1(mbc) => mbc.rtc_supported(),
+            Cartridge::Mbc2(mbc) => mbc.rtc_supported(),
+            Cartridge::Mbc3(mbc) => mbc.rtc_supported(),
+            Cartridge::Mbc5(mbc) => mbc.rtc_supported(),
+            Cartridge::Mbc6(mbc) => mbc.rtc_supported(),
+            Cartridge::PocketCamera(mbc) => mbc.rtc_supported(),
+            Cartridge::Huc1(mbc) => mbc.rtc_supported(),
+            Cartridge::Huc3(mbc) => mbc.rtc_supported(),
+        }
+    }
+
+    pub fn rtc_battery_low(&self) -> bool {
+        match self {
+            Cartridge::RomOnly(rom) => rom.rtc_battery_low(),
+            Cartridge::Mbc1(mbc) => mbc.rtc_battery_low(),
+            Cartridge::Mbc2(mbc) => mbc.rtc_battery_low(),
+            Cartridge::Mbc3(mbc) => mbc.rtc_battery_low(),
+            Cartridge::Mbc5(mbc) => mbc.rtc_battery_low(),
+            Cartridge::Mbc6(mbc) => mbc.rtc_battery_low(),
+            Cartridge::PocketCamera(mbc) => mbc.rtc_battery_low(),
+            Cartridge::Huc1(mbc) => mbc.rtc_battery_low(),
+            Cartridge::Huc3(mbc) => mbc.rtc_battery_low(),
+        }
+    }
+
+    pub fn set_rtc_battery_low(&mut self, low: bool) {
+        match self {
+            Cartridge::RomOnly(rom) => rom.set_rtc_battery_low(low),
+            Cartridge::Mbc1(mbc) => mbc.set_rtc_battery_low(low),
+            Cartridge::Mbc2(mbc) => mbc.set_rtc_battery_low(low),
+            Cartridge::Mbc3(mbc) => mbc.set_rtc_battery_low(low),
+            Cartridge::Mbc5(mbc) => mbc.set_rtc_battery_low(low),
+            Cartridge::Mbc6(mbc) => mbc.set_rtc_battery_low(low),
+            Cartridge::PocketCamera(mbc) => mbc.set_rtc_battery_low(low),
+            Cartridge::Huc1(mbc) => mbc.set_rtc_battery_low(low),
+            Cartridge::Huc3(mbc) => mbc.set_rtc_battery_low(low),
+        }
+    }
+
+    pub fn infrared_mapped(&self) -> bool {
+        match self {
+            Cartridge::RomOnly(rom) => rom.infrared_mapped(),
+            Cartridge::Mbc1(mbc) => mbc.infrared_mapped(),
+            Cartridge::Mbc2(mbc) => mbc.infrared_mapped(),
+            Cartridge::Mbc3(mbc) => mbc.infrared_mapped(),
+            Cartridge::Mbc5(mbc) => mbc.infrared_mapped(),
+            Cartridge::Mbc6(mbc) => mbc.infrared_mapped(),
+            Cartridge::PocketCamera(mbc) => mbc.infrared_mapped(),
+            Cartridge::Huc1(mbc) => mbc.infrared_mapped(),
+            Cartridge::Huc3(mbc) => mbc.infrared_mapped(),
+        }
+    }
+
+    pub fn drives_peripherals(&self, address: u16) -> bool {
+        match self {
+            Cartridge::RomOnly(rom) => rom.drives_peripherals(address),
+            Cartridge::Mbc1(mbc) => mbc.drives_peripherals(address),
+            Cartridge::Mbc2(mbc) => mbc.drives_peripherals(address),
+            Cartridge::Mbc3(mbc) => mbc.drives_peripherals(address),
+            Cartridge::Mbc5(mbc) => mbc.drives_peripherals(address),
+            Cartridge::Mbc6(mbc) => mbc.drives_peripherals(address),
+            Cartridge::PocketCamera(mbc) => mbc.drives_peripherals(address),
+            Cartridge::Huc1(mbc) => mbc.drives_peripherals(address),
+            Cartridge::Huc3(mbc) => mbc.drives_peripherals(address),
+        }
+    }
+
+    pub fn infrared_led(&self) -> bool {
+        match self {
+            Cartridge::RomOnly(rom) => rom.infrared_led(),
+            Cartridge::Mbc1(mbc) => mbc.infrared_led(),
+            Cartridge::Mbc2(mbc) => mbc.infrared_led(),
+            Cartridge::Mbc3(mbc) => mbc.infrared_led(),
+            Cartridge::Mbc5(mbc) => mbc.infrared_led(),
+            Cartridge::Mbc6(mbc) => mbc.infrared_led(),
+            Cartridge::PocketCamera(mbc) => mbc.infrared_led(),
+            Cartridge::Huc1(mbc) => mbc.infrared_led(),
+            Cartridge::Huc3(mbc) => mbc.infrared_led(),
+        }
+    }
+
+    pub fn rumble(&self) -> bool {
+        match self {
+            Cartridge::RomOnly(rom) => rom.rumble(),
+            Cartridge::Mbc1(mbc) => mbc.rumble(),
+            Cartridge::Mbc2(mbc) => mbc.rumble(),
+            Cartridge::Mbc3(mbc) => mbc.rumble(),
+            Cartridge::Mbc5(mbc) => mbc.rumble(),
+            Cartridge::Mbc6(mbc) => mbc.rumble(),
+            Cartridge::PocketCamera(mbc) => mbc.rumble(),
+            Cartridge::Huc1(mbc) => mbc.rumble(),
+            Cartridge::Huc3(mbc) => mbc.rumble(),
+        }
+    }
+
+    pub fn capture_requested(&self) -> bool {
+        match self {
+            Cartridge::RomOnly(rom) => rom.capture_requested(),
+            Cartridge::Mbc1(mbc) => mbc.capture_requested(),
+            Cartridge::Mbc2(mbc) => mbc.capture_requested(),
+            Cartridge::Mbc3(mbc) => mbc.capture_requested(),
+            Cartridge::Mbc5(mbc) => mbc.capture_requested(),
+            Cartridge::Mbc6(mbc) => mbc.capture_requested(),
+            Cartridge::PocketCamera(mbc) => mbc.capture_requested(),
+            Cartridge::Huc1(mbc) => mbc.capture_requested(),
+            Cartridge::Huc3(mbc) => mbc.capture_requested(),
+        }
+    }
+
+    pub fn finish_capture(&mut self, picture: &[u8]) {
+        match self {
+            Cartridge::RomOnly(rom) => rom.finish_capture(picture),
+            Cartridge::Mbc1(mbc) => mbc.finish_capture(picture),
+            Cartridge::Mbc2(mbc) => mbc.finish_capture(picture),
+            Cartridge::Mbc3(mbc) => mbc.finish_capture(picture),
+            Cartridge::Mbc5(mbc) => mbc.finish_capture(picture),
+            Cartridge::Mbc6(mbc) => mbc.finish_capture(picture),
+            Cartridge::PocketCamera(mbc) => mbc.finish_capture(picture),
+            Cartridge::Huc1(mbc) => mbc.finish_capture(picture),
+            Cartridge::Huc3(mbc) => mbc.finish_capture(picture),
+        }
+    }
+}
+
+#[cfg(feature = "rtc")]
+pub fn host_clock() -> Option<Box<dyn ClockSource>> {
+    Some(Box::new(SystemClock))
+}
+
+// Without a time source cartridge clocks only change when the game sets them
+#[cfg(not(feature = "rtc"))]
+pub fn host_clock() -> Option<Box<dyn ClockSource>> {
+    None
 }
@@ -1,9 +1,13 @@
 use derive_builder::Builder;
-use log::{info, warn};
+use log::info;
 use std::fmt::Display;
 use thiserror::Error;
 
 use crate::cartridge::MbcType;
+use crate::context::EmulatorError;
+
+// The header ends at 0x014F
+const HEADER_END: usize = 0x0150;
 
 pub struct Rom {
     data: Vec<u8>,
@@ -13,7 +17,10 @@ pub struct Rom {
     new_licensee_code: [u8; 2],
     sgb_flag: bool,
     cartridge_type: CartridgeType,
+    // At least the declared size, and larger when the image holds more banks
     rom_size: usize,
+    declared_rom_size: usize,
+    image_size: usize,
     ram_size: usize,
     destination_code: String,
     old_licensee_code: u8,
@@ -23,7 +30,10 @@ pub struct Rom {
 }
 
 impl Rom {
-    pub fn new(data: &[u8]) -> Result<Self, RomError> {
+    pub fn new(data: &[u8]) -> Result<Self, EmulatorError> {
+        if data.len() < HEADER_END {
+            return Err(RomError::TooShort(data.len()).into());
+        }
         let title = data[0x0134..=0x0143]
             .iter()
             .copied()
@@ -41,7 +51,7 @@ impl Rom {
         let new_licensee_code = data[0x0144..=0x0145].try_into().unwrap();
         let sgb_flag = data[0x0146] == 0x03;
         let cartridge_type = CartridgeType::new(data[0x0147])?;
-        let rom_size = match data[0x0148] {
+        let declared_rom_size = match data[0x0148] {
             0x00 => 32 * 1024,
             0x01 => 64 * 1024,
             0x02 => 128 * 1024,
@@ -51,7 +61,7 @@ impl Rom {
             0x06 => 2 * 1024 * 1024,
             0x07 => 4 * 1024 * 1024,
             0x08 => 8 * 1024 * 1024,
-            _ => return Err(RomError::InvalidRomSize(data[0x0148])),
+            _ => return Err(RomError::InvalidRomSize(data[0x0148]).into()),
         };
 
         let ram_size = match data[0x0149] {
@@ -61,7 +71,7 @@ impl Rom {
             0x03 => 32 * 1024,
             0x04 => 128 * 1024,
             0x05 => 64 * 1024,
-            _ => return Err(RomError::InvalidRamSize(data[0x0149])),
+            _ => return Err(RomError::InvalidRamSize(data[0x0149]).into()),
         };
 
         let destination_code = match data[0x014A] {
@@ -75,9 +85,6 @@ impl Rom {
         for &byte in &data[0x0134..=0x014C] {
             header_checksum = header_checksum.wrapping_sub(byte).wrapping_sub(1);
         }
-        if header_checksum != data[0x014D] {
-            warn!("Invalid header checksum");
-        }
 
         let mut global_checksum: u16 = 0;
         for (i, byte) in data.iter().enumerate() {
@@ -86,9 +93,12 @@ impl Rom {
             }
         }
 
-        if global_checksum != u16::from_be_bytes(data[0x014E..=0x014F].try_into().unwrap()) {
-            warn!("Invalid global checksum");
-        }
+        // Some homebrew and bootlegs carry more banks than the header declares, and
+        // truncated dumps fewer. The image is padded with open bus to a power-of-two
+        // number of banks so the mappers can mask bank numbers with it.
+        let rom_size = declared_rom_size.max(data.len().next_power_of_two());
+        let mut padded = data.to_vec();
+        padded.resize(rom_size, 0xFF);
 
         info!("Title: {}", title);
         info!("Manufacturer Code: {:?}", manufacturer_code);
@@ -96,7 +106,7 @@ impl Rom {
         info!("New Licensee Code: {:?}", new_licensee_code);
         info!("SGB Flag: {}", sgb_flag);
         info!("Cartridge Type: {}", cartridge_type);
-        info!("ROM Size: {} bytes", rom_size);
+        info!("ROM Size: {} bytes", declared_rom_size);
         info!("RAM Size: {} bytes", ram_size);
         info!("Destination Code: {}", destination_code);
         info!("Old Licensee Code: {}", old_licensee_code);
@@ -105,7 +115,7 @@ impl Rom {
         info!("Global Checksum: {}", global_checksum);
 
         Ok(Self {
-            data: data.to_vec(),
+            data: padded,
             title,
             manufacturer_code,
             cgb_flag,
@@ -113,6 +123,8 @@ impl Rom {
             sgb_flag,
             cartridge_type,
             rom_size,
+            declared_rom_size,
+            image_size: data.len(),
             ram_size,
             destination_code: destination_code.to_string(),
             old_licensee_code,
@@ -130,6 +142,11 @@ impl Rom {
         self.cgb_flag
     }
 
+    // The SGB only listens to packets when the old licensee code also points at the new one
+    pub fn sgb_supported(&self) -> bool {
+        self.sgb_flag && self.old_licensee_code == 0x33
+    }
+
     pub fn data(&self) -> &[u8] {
         &self.data
     }
@@ -138,6 +155,14 @@ impl Rom {
         self.rom_size
     }
 
+    pub fn declared_rom_size(&self) -> usize {
+        self.declared_rom_size
+    }
+
+    pub fn image_size(&self) -> usize {
+        self.image_size
+    }
+
     pub fn ram_size(&self) -> usize {
         self.ram_size
     }
@@ -146,9 +171,29 @@ impl Rom {
         self.cartridge_type.has_ram
     }
 
+    pub fn have_battery(&self) -> bool {
+        self.cartridge_type.has_battery
+    }
+
+    pub fn have_timer(&self) -> bool {
+        self.cartridge_type.has_timer
+    }
+
+    pub fn have_rumble(&self) -> bool {
+        self.cartridge_type.has_rumble
+    }
+
     pub fn title(&self) -> &str {
         &self.title
     }
+
+    pub fn header_checksum_valid(&self) -> bool {
+        self.header_checksum == self.data[0x014D]
+    }
+
+    pub fn global_checksum_valid(&self) -> bool {
+        self.global_checksum == u16::from_be_bytes([self.data[0x014E], self.data[0x014F]])
+    }
 }
 
 #[derive(Error, Debug)]
@@ -161,6 +206,16 @@ pub enum RomError {
     InvalidRomSize(u8),
     #[error("Invalid RAM size: {0}")]
     InvalidRamSize(u8),
+    #[error("ROM is {0} bytes, too short to hold a header")]
+    TooShort(usize),
+    #[error("Unsupported cartridge type: {0}")]
+    UnsupportedCartridgeType(MbcType),
+}
+
+impl From<RomError> for EmulatorError {
+    fn from(e: RomError) -> Self {
+        EmulatorError::RomError(e.to_string())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -227,7 +282,15 @@ impl CartridgeType {
                     .has_battery(true),
                 0x20 => builder.mbc(MbcType::Mbc6),
                 0x22 => builder.mbc(MbcType::Mbc7).has_sensor(true),
-                0xFE => builder.mbc(MbcType::Huc3),
+                0xFC => builder
+                    .mbc(MbcType::PocketCamera)
+                    .has_ram(true)
+                    .has_battery(true),
+                0xFE => builder
+                    .mbc(MbcType::Huc3)
+                    .has_ram(true)
+                    .has_battery(true)
+                    .has_timer(true),
                 0xFF => builder.mbc(MbcType::Huc1).has_ram(true).has_battery(true),
                 _ => return Err(RomError::InvalidCartridgeType(code)),
             }
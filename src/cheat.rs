@@ -0,0 +1,165 @@
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CheatError {
+    #[error("Invalid Game Genie code: {0}")]
+    InvalidGameGenie(String),
+}
+
+/// Replaces a byte of ROM as the CPU, and anything else reading the cartridge, sees it.
+/// The ROM itself is left untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomPatch {
+    pub address: u16,
+    pub value: u8,
+    /// Only patch while the ROM holds this byte, as Game Genie codes with a compare
+    /// value do. It keeps a patch from hitting the other banks mapped at the address.
+    pub compare: Option<u8>,
+    /// Only patch while this ROM bank is mapped at the address. Banks are the 16 KiB
+    /// ones the mapper switches, or 8 KiB on MBC6, which switches two halves.
+    pub bank: Option<usize>,
+}
+
+impl RomPatch {
+    /// Patches `address` in whichever bank is mapped there. Panics unless `address`
+    /// is in ROM, 0x0000-0x7FFF.
+    pub fn new(address: u16, value: u8) -> Self {
+        assert!(address < 0x8000, "Not a ROM address: {:#06X}", address);
+        Self {
+            address,
+            value,
+            compare: None,
+            bank: None,
+        }
+    }
+
+    pub fn with_compare(self, compare: u8) -> Self {
+        Self {
+            compare: Some(compare),
+            ..self
+        }
+    }
+
+    pub fn in_bank(self, bank: usize) -> Self {
+        Self {
+            bank: Some(bank),
+            ..self
+        }
+    }
+
+    /// Decodes a Game Genie code, `ABC-DEF` or `ABC-DEF-GHI` with the compare value.
+    pub fn from_game_genie(code: &str) -> Result<Self, CheatError> {
+        let invalid = || CheatError::InvalidGameGenie(code.to_string());
+        let groups: Vec<&str> = code.trim().split('-').collect();
+        if !matches!(groups.len(), 2 | 3) || groups.iter().any(|group| group.len() != 3) {
+            return Err(invalid());
+        }
+        let digits = groups
+            .concat()
+            .chars()
+            .map(|c| c.to_digit(16).map(|digit| digit as u16))
+            .collect::<Option<Vec<u16>>>()
+            .ok_or_else(invalid)?;
+
+        let value = (digits[0] << 4 | digits[1]) as u8;
+        // The top nibble is stored inverted, after the other three
+        let address = (digits[5] ^ 0xF) << 12 | digits[2] << 8 | digits[3] << 4 | digits[4];
+        if address >= 0x8000 {
+            return Err(invalid());
+        }
+        let mut patch = Self::new(address, value);
+        if let [g, _, i] = digits[6..] {
+            // The middle digit is unused, and the other two hold the value scrambled
+            let encoded = (g << 4 | i) as u8;
+            patch.compare = Some(encoded.rotate_right(2) ^ 0xBA);
+        }
+        Ok(patch)
+    }
+
+    fn applies_to(&self, address: u16, bank: usize, value: u8) -> bool {
+        self.address == address
+            && self.bank.is_none_or(|patch_bank| patch_bank == bank)
+            && self.compare.is_none_or(|compare| compare == value)
+    }
+}
+
+impl FromStr for RomPatch {
+    type Err = CheatError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        Self::from_game_genie(code)
+    }
+}
+
+/// Returned by [`GameBoyColor::add_rom_patch`](crate::GameBoyColor::add_rom_patch) to
+/// enable, disable or remove the patch later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PatchId(u64);
+
+struct Entry {
+    id: PatchId,
+    patch: RomPatch,
+    enabled: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct RomPatches {
+    entries: Vec<Entry>,
+    next_id: u64,
+    // Any entry is enabled, so ROM reads without one skip the scan
+    active: bool,
+}
+
+impl RomPatches {
+    pub fn add(&mut self, patch: RomPatch) -> PatchId {
+        let id = PatchId(self.next_id);
+        self.next_id += 1;
+        self.entries.push(Entry {
+            id,
+            patch,
+            enabled: true,
+        });
+        self.active = true;
+        id
+    }
+
+    pub fn set_enabled(&mut self, id: PatchId, enabled: bool) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                self.update_active();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, id: PatchId) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.update_active();
+        self.entries.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.active = false;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn update_active(&mut self) {
+        self.active = self.entries.iter().any(|entry| entry.enabled);
+    }
+
+    // The first enabled patch that matches wins
+    pub fn apply(&self, address: u16, bank: usize, value: u8) -> u8 {
+        self.entries
+            .iter()
+            .find(|entry| entry.enabled && entry.patch.applies_to(address, bank, value))
+            .map_or(value, |entry| entry.patch.value)
+    }
+}
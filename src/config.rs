@@ -1,17 +1,90 @@
+use derive_builder::Builder;
 use modular_bitfield::bitfield;
 use modular_bitfield::prelude::*;
 
+use crate::interface::{ClockSource, LinkCable};
+use crate::peripherals::{CameraSource, InfraredTransceiver, PrinterSink, RumbleSink};
+use crate::rng::Rng;
+use crate::watchdog;
+
+use std::io::Write;
+
+/// Options used to construct a [`GameBoyColor`](crate::GameBoyColor).
+#[derive(Builder)]
+#[builder(pattern = "owned")]
+pub struct EmulatorConfig {
+    #[builder(default = "DeviceMode::GameBoyColor")]
+    pub(crate) device_mode: DeviceMode,
+    /// Defaults to DMG for `DeviceMode::GameBoy` and CGB for `DeviceMode::GameBoyColor`.
+    #[builder(default, setter(strip_option))]
+    pub(crate) model: Option<Model>,
+    #[builder(default, setter(strip_option))]
+    pub(crate) link_cable: Option<Box<dyn LinkCable>>,
+    /// M-cycles a frame may take before `EmulationStalled` is raised and control returns.
+    #[builder(default = "watchdog::DEFAULT_CYCLE_LIMIT")]
+    pub(crate) watchdog_cycle_limit: u64,
+    /// Boot ROM image to run before the cartridge: 256 bytes for DMG models, 2304 for CGB.
+    /// Without one the CPU starts at 0x0100 with the registers the boot ROM would leave.
+    #[builder(default, setter(strip_option))]
+    pub(crate) boot_rom: Option<Vec<u8>>,
+    /// Receives a Gameboy Doctor line before each instruction. LY reads as 0x90 while
+    /// it is set, as the Gameboy Doctor logs expect.
+    #[builder(default, setter(strip_option))]
+    pub(crate) doctor_log: Option<Box<dyn Write>>,
+    /// Performs a [`ResetKind::Soft`] reset when A, B, Start and Select are pressed
+    /// together. [`EmulatorEvent::SoftResetCombo`](crate::EmulatorEvent::SoftResetCombo)
+    /// is raised either way.
+    #[builder(default)]
+    pub(crate) soft_reset_combo: bool,
+    /// Time source for cartridge clocks. Defaults to the host clock with the `rtc`
+    /// feature; otherwise the clocks only change when the game sets them.
+    #[builder(default, setter(strip_option))]
+    pub(crate) clock_source: Option<Box<dyn ClockSource>>,
+    /// Advances cartridge clocks with emulated time instead, starting from the clock
+    /// source's time at power-on. Fast-forward then speeds up the in-game clock and
+    /// pausing stops it. Combined with a [`FixedClock`](crate::FixedClock) the clock
+    /// is reproducible.
+    #[builder(default)]
+    pub(crate) emulated_rtc: bool,
+    /// How CGB palette colours are turned into RGB. Defaults to the raw colours.
+    #[builder(default)]
+    pub(crate) color_correction: ColorCorrection,
+    /// What to use where the hardware leaves a value unpredictable. Defaults to fixed values.
+    #[builder(default)]
+    pub(crate) unpredictable_values: UnpredictableValues,
+    /// Lit by the CGB's infrared port and by HuC1 and HuC3 cartridges.
+    #[builder(default, setter(strip_option))]
+    pub(crate) infrared: Option<Box<dyn InfraredTransceiver>>,
+    /// Driven by MBC5 rumble cartridges.
+    #[builder(default, setter(strip_option))]
+    pub(crate) rumble: Option<Box<dyn RumbleSink>>,
+    /// Supplies the Game Boy Camera's pictures. Without one the camera sees white.
+    #[builder(default, setter(strip_option))]
+    pub(crate) camera: Option<Box<dyn CameraSource>>,
+    /// Plugs a [`GameBoyPrinter`](crate::GameBoyPrinter) into the link port, in place
+    /// of `link_cable`, that prints to this sink.
+    #[builder(default, setter(strip_option))]
+    pub(crate) printer: Option<Box<dyn PrinterSink>>,
+    /// Battery-backed RAM to start with, in the layout [`save_data`](crate::GameBoyColor::save_data)
+    /// returns, instead of the save in the application data directory. An empty save
+    /// starts with blank RAM.
+    #[builder(default, setter(strip_option))]
+    pub(crate) save_data: Option<Vec<u8>>,
+}
+
 pub struct Config {
     device_mode: DeviceMode,
     speed_switch: PrepareSpeedSwitch,
+    gameboy_doctor: bool,
 }
 
 impl Config {
-    pub fn new(device_mode: DeviceMode) -> Self {
+    pub fn new(device_mode: DeviceMode, gameboy_doctor: bool) -> Self {
         let speed_switch = PrepareSpeedSwitch::default();
         Self {
             device_mode,
             speed_switch,
+            gameboy_doctor,
         }
     }
 
@@ -19,8 +92,12 @@ impl Config {
         self.device_mode
     }
 
+    pub fn gameboy_doctor(&self) -> bool {
+        self.gameboy_doctor
+    }
+
     pub fn set_speed_switch(&mut self, value: u8) {
-        self.speed_switch = PrepareSpeedSwitch::from(value & 0x01);
+        self.speed_switch.set_armed(value & 0x01 != 0);
     }
 
     pub fn get_speed_switch(&self) -> u8 {
@@ -32,6 +109,18 @@ impl Config {
     pub fn current_speed(&self) -> Speed {
         self.speed_switch.speed()
     }
+
+    /// Performs an armed speed switch and disarms it.
+    pub fn switch_speed(&mut self) {
+        if !self.speed_switch.armed() {
+            return;
+        }
+        let speed = match self.speed_switch.speed() {
+            Speed::Normal => Speed::Double,
+            Speed::Double => Speed::Normal,
+        };
+        self.speed_switch = PrepareSpeedSwitch::new().with_speed(speed);
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -40,6 +129,90 @@ pub enum DeviceMode {
     GameBoyColor,
 }
 
+/// Adjusts CGB colours to look as they did on a handheld's screen. DMG shades are
+/// not affected.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ColorCorrection {
+    /// Each 5-bit channel scaled to 8 bits, which looks oversaturated next to the hardware.
+    #[default]
+    Off,
+    /// The washed-out, slightly blended colours of the CGB LCD.
+    CgbLcd,
+    /// The darker GBA LCD, for games that brighten their palettes when run on a GBA.
+    GbaLcd,
+}
+
+/// Values for state the hardware leaves unpredictable: WRAM, HRAM and the RAM early CGBs
+/// have at 0xFEA0-0xFEFF at power-on, and OAM DMA from pages 0xFE and 0xFF.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum UnpredictableValues {
+    /// RAM starts cleared and the other reads give 0xFF.
+    #[default]
+    Fixed,
+    /// Pseudo-random values from a generator with this seed, so runs with the same seed
+    /// are still identical.
+    Seeded(u64),
+}
+
+impl UnpredictableValues {
+    pub(crate) fn rng(self) -> Option<Rng> {
+        match self {
+            UnpredictableValues::Fixed => None,
+            UnpredictableValues::Seeded(seed) => Some(Rng::new(seed)),
+        }
+    }
+}
+
+/// How [`GameBoyColor::reset`](crate::GameBoyColor::reset) restarts the console.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ResetKind {
+    /// Runs the boot sequence again as after power-on. The cartridge keeps its RAM,
+    /// clock and bank registers, and held buttons stay held.
+    Soft,
+}
+
+/// The console hardware revision, which decides the power-on state left by its boot ROM.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Model {
+    Dmg0,
+    Dmg,
+    Mgb,
+    Sgb,
+    Sgb2,
+    /// CGB revisions 0 to C, which have RAM at 0xFEA0-0xFEFF.
+    CgbC,
+    /// CGB revision D, which mirrors part of the RAM at 0xFEA0-0xFEFF differently.
+    CgbD,
+    /// CGB revision E, the last one.
+    Cgb,
+    Agb,
+}
+
+impl Model {
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::CgbC | Model::CgbD | Model::Cgb | Model::Agb)
+    }
+
+    pub fn boot_rom_size(self) -> usize {
+        if self.is_cgb() {
+            0x900
+        } else {
+            0x100
+        }
+    }
+}
+
+impl From<DeviceMode> for Model {
+    fn from(device_mode: DeviceMode) -> Self {
+        match device_mode {
+            DeviceMode::GameBoy => Model::Dmg,
+            DeviceMode::GameBoyColor => Model::Cgb,
+        }
+    }
+}
+
 #[bitfield(bits = 8)]
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Default)]
@@ -1,12 +1,36 @@
 use core::error;
 
 use crate::cartridge::rom::{self, CgbFlag};
-use crate::config::DeviceMode;
-use crate::interface::LinkCable;
+use crate::cheat::{PatchId, RomPatch, RomPatches};
+use crate::compat_palette::CompatPalettes;
+use crate::config::{
+    ColorCorrection, DeviceMode, EmulatorConfig, EmulatorConfigBuilderError, Model, ResetKind,
+};
+use crate::event::{
+    self, DebugEvent, EmulatorEvent, EventBus, HardwareEvent, InterruptKind, StallReason, Subsystem,
+};
+use crate::gameboycolor::Capabilities;
+use crate::interface::{EmulatedClock, LinkCable};
 use crate::joypad::JoypadKeyState;
-use crate::utils;
-use crate::{apu, bus, cartridge, config, cpu, interrupt, joypad, ppu, serial, timer};
-
+use crate::link_tap::SerialExchange;
+use crate::peripherals::{CameraSource, InfraredTransceiver, Peripherals, PrinterSink, RumbleSink};
+use crate::printer::printer_cable;
+use crate::snoop::{
+    AccessKind, BusAccess, BusObservers, ObserverId, WatchpointCondition, WatchpointId, Watchpoints,
+};
+use crate::state_hash::{StateHasher, StateHashes};
+use crate::support;
+use crate::tile_capture::CapturedTile;
+use crate::watchdog::Watchdog;
+use crate::wav::AudioRecorder;
+use crate::{apu, bus, cartridge, config, cpu, interrupt, joypad, ppu, serial, sgb, timer};
+
+use std::cell::Cell;
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::rc::Rc;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -16,321 +40,1087 @@ pub enum EmulatorError {
 
     #[error("Error loading save data: {0}")]
     SaveDataError(#[from] std::io::Error),
+
+    #[error("Invalid configuration: {0}")]
+    ConfigError(#[from] EmulatorConfigBuilderError),
+
+    #[error("Invalid boot ROM: {0}")]
+    BootRomError(String),
+
+    #[error("Invalid ROM: {0}")]
+    RomError(String),
 }
 
+// Every feature in Cargo.toml, which public_api_test checks
+const COMPILED_FEATURES: [(&str, bool); 14] = [
+    ("rtc", cfg!(feature = "rtc")),
+    ("static-buffers", cfg!(feature = "static-buffers")),
+    ("indexed-output", cfg!(feature = "indexed-output")),
+    ("pixel-source", cfg!(feature = "pixel-source")),
+    ("texture-pack", cfg!(feature = "texture-pack")),
+    ("std-runtime", cfg!(feature = "std-runtime")),
+    ("egui-debugger", cfg!(feature = "egui-debugger")),
+    ("audio-out", cfg!(feature = "audio-out")),
+    ("winit-frontend", cfg!(feature = "winit-frontend")),
+    ("capi", cfg!(feature = "capi")),
+    ("wasm", cfg!(feature = "wasm")),
+    ("python", cfg!(feature = "python")),
+    ("video-recording", cfg!(feature = "video-recording")),
+    ("serde", cfg!(feature = "serde")),
+];
+
 pub struct Context {
     cpu: cpu::Cpu,
     inner1: Inner1,
+    watchdog: Watchdog,
+    doctor_log: Option<Box<dyn Write>>,
+    trace_hook: Option<cpu::TraceHook>,
+    audio_callback: Option<apu::AudioCallback>,
+    audio_recorder: Option<AudioRecorder>,
+    // Samples at the start of the audio buffer already passed to the callback and the
+    // recorder
+    audio_delivered: usize,
+    breakpoints: BTreeSet<u16>,
+    // Advanced after every instruction when cartridge clocks follow emulated time
+    emulated_time: Option<Rc<Cell<u64>>>,
+
+    model: Model,
+    // Kept to map it again on reset
+    boot_rom: Option<Vec<u8>>,
+    soft_reset_combo: bool,
+    compat_palettes: Option<CompatPalettes>,
 
     rom_name: String,
 }
 
 impl Context {
-    pub fn new(
-        data: &[u8],
-        device_mode: DeviceMode,
-        link_cable: Option<Box<dyn LinkCable>>,
-    ) -> Result<Self, EmulatorError> {
-        let rom = rom::Rom::new(data).unwrap();
+    pub fn new(data: &[u8], config: EmulatorConfig) -> Result<Self, EmulatorError> {
+        let EmulatorConfig {
+            device_mode,
+            model,
+            link_cable,
+            watchdog_cycle_limit,
+            boot_rom,
+            doctor_log,
+            soft_reset_combo,
+            clock_source,
+            emulated_rtc,
+            color_correction,
+            unpredictable_values,
+            infrared,
+            rumble,
+            camera,
+            printer,
+            save_data,
+        } = config;
+        let model = model.unwrap_or_else(|| device_mode.into());
+        if device_mode == DeviceMode::GameBoyColor && !model.is_cgb() {
+            return Err(EmulatorError::UnsupportedMode(format!(
+                "{:?} cannot run in GameBoyColor mode",
+                model
+            )));
+        }
+        if let Some(boot_rom) = &boot_rom {
+            if boot_rom.len() != model.boot_rom_size() {
+                return Err(EmulatorError::BootRomError(format!(
+                    "{:?} boot ROM must be {} bytes, got {}",
+                    model,
+                    model.boot_rom_size(),
+                    boot_rom.len()
+                )));
+            }
+        }
+        let rom = rom::Rom::new(data)?;
         if rom.cgb_flag() == CgbFlag::CgbOnly && device_mode == DeviceMode::GameBoy {
             return Err(EmulatorError::UnsupportedMode(
                 "GameBoy Color only game cannot be run in GameBoy mode".to_string(),
             ));
         }
 
-        let rom_name = rom.title().to_string();
-        let backup = utils::load_save_data(&rom_name)?;
+        let mut warnings = Vec::new();
+        if !rom.header_checksum_valid() {
+            warnings.push("Invalid header checksum".to_string());
+        }
+        if !rom.global_checksum_valid() {
+            warnings.push("Invalid global checksum".to_string());
+        }
+        if rom.image_size() != rom.declared_rom_size() {
+            warnings.push(format!(
+                "ROM image is {} bytes but the header declares {}",
+                rom.image_size(),
+                rom.declared_rom_size()
+            ));
+        }
+
+        // Without its own boot ROM a DMG game on a CGB starts in compatibility mode, in the
+        // colours the boot ROM would pick for it
+        let mut device_mode = device_mode;
+        let mut compat_palettes = None;
+        if device_mode == DeviceMode::GameBoyColor
+            && rom.cgb_flag() == CgbFlag::DMGOnly
+            && boot_rom.is_none()
+        {
+            device_mode = DeviceMode::GameBoy;
+            compat_palettes = Some(CompatPalettes::for_header(data));
+        }
 
-        let cartridge = cartridge::Cartridge::new(rom, backup);
-        Ok(Self {
-            cpu: cpu::Cpu::new(device_mode),
+        let sgb =
+            (matches!(model, Model::Sgb | Model::Sgb2) && rom.sgb_supported()).then(sgb::Sgb::new);
+
+        let rom_name = rom.title().to_string();
+        let backup = match save_data {
+            Some(save_data) if save_data.is_empty() => None,
+            Some(save_data) => Some(save_data),
+            // A browser has no data directory to look in
+            None if cfg!(target_arch = "wasm32") => None,
+            None => support::load_save_data(&rom_name)?,
+        };
+
+        let mut clock_source = clock_source.or_else(cartridge::host_clock);
+        let mut emulated_time = None;
+        if emulated_rtc {
+            let clock = EmulatedClock::new(clock_source.map_or(0, |clock| clock.now()));
+            emulated_time = Some(clock.dots());
+            clock_source = Some(Box::new(clock));
+        }
+        let cartridge = cartridge::Cartridge::new(rom, backup, clock_source)?;
+        let link_cable = printer.map(printer_cable).or(link_cable);
+        let skip_boot = boot_rom.is_none();
+        let cpu = if skip_boot {
+            cpu::Cpu::new(model, device_mode)
+        } else {
+            cpu::Cpu::power_on()
+        };
+        let mut context = Self {
+            cpu,
             inner1: Inner1 {
-                bus: bus::Bus::new(device_mode),
+                bus: bus::Bus::new(model, boot_rom.clone(), unpredictable_values.rng()),
+                observers: BusObservers::default(),
+                watchpoints: Watchpoints::default(),
+                overclock: 0,
+                overclock_left: 0,
                 inner2: Inner2 {
                     cartridge,
-                    ppu: ppu::Ppu::new(device_mode),
+                    rom_patches: RomPatches::default(),
+                    ppu: ppu::Ppu::new(),
                     apu: apu::Apu::new(),
                     joypad: joypad::Joypad::new(),
                     timer: timer::Timer::new(),
                     serial: serial::Serial::new(link_cable),
+                    sgb,
+                    peripherals: Peripherals::new(infrared, rumble, camera),
                     inner3: Inner3 {
                         interrupt: interrupt::Interrupt::new(),
-                        config: config::Config::new(device_mode),
+                        config: config::Config::new(device_mode, doctor_log.is_some()),
+                        events: Vec::new(),
+                        event_bus: EventBus::default(),
                     },
                 },
             },
+            watchdog: Watchdog::new(watchdog_cycle_limit),
+            doctor_log,
+            trace_hook: None,
+            audio_callback: None,
+            audio_recorder: None,
+            audio_delivered: 0,
+            breakpoints: BTreeSet::new(),
+            emulated_time,
+            model,
+            boot_rom,
+            soft_reset_combo,
+            compat_palettes,
             rom_name,
+        };
+        context.set_color_correction(color_correction);
+        context
+            .inner1
+            .inner2
+            .ppu
+            .set_compatibility(compat_palettes.is_some());
+        let sgb = context.inner1.inner2.sgb.is_some();
+        context.inner1.inner2.ppu.set_shade_output(sgb);
+        if skip_boot {
+            context.skip_boot();
+        }
+        for warning in warnings {
+            context.inner1.warn(Subsystem::Cartridge, warning);
+        }
+        Ok(context)
+    }
+
+    // Leaves IO registers as the boot ROM would
+    fn skip_boot(&mut self) {
+        let device_mode = self.inner1.device_mode();
+        let inner2 = &mut self.inner1.inner2;
+        inner2.timer.skip_boot(self.model);
+        inner2.ppu.skip_boot(device_mode);
+        if let Some(palettes) = &self.compat_palettes {
+            inner2.ppu.load_compat_palettes(palettes);
+        }
+        inner2.apu.skip_boot(self.model);
+    }
+
+    pub fn reset(&mut self, kind: ResetKind) {
+        match kind {
+            ResetKind::Soft => {
+                let device_mode = self.inner1.device_mode();
+                let gameboy_doctor = self.inner1.gameboy_doctor();
+                self.cpu = match self.boot_rom {
+                    None => cpu::Cpu::new(self.model, device_mode),
+                    Some(_) => cpu::Cpu::power_on(),
+                };
+                self.inner1.bus.reset(self.boot_rom.clone());
+                self.inner1.overclock_left = 0;
+                let inner2 = &mut self.inner1.inner2;
+                inner2.ppu.reset();
+                inner2.apu.reset();
+                inner2.timer = timer::Timer::new();
+                inner2.serial.reset();
+                inner2.peripherals.reset();
+                if let Some(sgb) = &mut inner2.sgb {
+                    *sgb = sgb::Sgb::new();
+                }
+                inner2.inner3.interrupt = interrupt::Interrupt::new();
+                inner2.inner3.config = config::Config::new(device_mode, gameboy_doctor);
+                if self.boot_rom.is_none() {
+                    self.skip_boot();
+                }
+            }
+        }
+    }
+
+    // Returns the watchpoint the instruction met, if any
+    pub fn execute_instruction(&mut self) -> Option<DebugEvent> {
+        let pc = self.cpu.state().pc;
+        let dots = self.dots();
+        self.execute_logged();
+        if let Some(emulated_time) = &self.emulated_time {
+            emulated_time.set(emulated_time.get() + (self.dots() - dots));
+        }
+        let (address, old, new) = self.inner1.watchpoints.take_hit()?;
+        Some(DebugEvent::WatchpointHit {
+            address,
+            old,
+            new,
+            pc,
         })
     }
 
-    pub fn execute_instruction(&mut self) {
+    fn execute_logged(&mut self) {
+        if self.doctor_log.is_none() && self.trace_hook.is_none() {
+            self.cpu.execute_instruction(&mut self.inner1);
+            return;
+        }
+
+        let state = self.cpu.state();
+        // Straight from the bus, as bus observers only see the CPU
+        let inner1 = &mut self.inner1;
+        let pcmem: [u8; 4] = std::array::from_fn(|i| {
+            let address = state.pc.wrapping_add(i as u16);
+            inner1.bus.read(&mut inner1.inner2, address)
+        });
+        let count = self.cpu.instruction_count();
         self.cpu.execute_instruction(&mut self.inner1);
+        // Nothing is traced for interrupt dispatch, HALT or STOP
+        if self.cpu.instruction_count() == count {
+            return;
+        }
+        if let Some(trace_hook) = &mut self.trace_hook {
+            trace_hook(&state, pcmem);
+        }
+        if let Some(doctor_log) = &mut self.doctor_log {
+            if writeln!(doctor_log, "{}", state.doctor_line(pcmem)).is_err() {
+                self.doctor_log = None;
+            }
+        }
     }
 
-    pub fn execute_frame(&mut self) {
+    pub fn set_trace_hook(&mut self, trace_hook: Option<cpu::TraceHook>) {
+        self.trace_hook = trace_hook;
+    }
+
+    // Stops early when a watchpoint is met, leaving the rest of the frame for the next call
+    pub fn execute_frame(&mut self) -> Option<DebugEvent> {
         let frame = self.inner1.frame();
+        let start = self.cpu.clock();
+        let budget = self.watchdog.frame_budget();
         while self.inner1.frame() == frame {
-            self.execute_instruction();
+            if let Some(hit) = self.execute_instruction() {
+                return Some(hit);
+            }
+            // The system clock is halted until a button is pressed
+            if self.cpu.is_stopped() {
+                return None;
+            }
+            let cycles = self.cpu.clock() - start;
+            if cycles >= budget {
+                self.report_stall(Some(StallReason::NoFrameProgress { cycles }));
+                return None;
+            }
+        }
+        self.report_stall(self.cpu_stall());
+        None
+    }
+
+    pub fn run_cycles(&mut self, cycles: u64) -> (u64, Option<DebugEvent>) {
+        let start = self.cpu.clock();
+        while self.cpu.clock() - start < cycles {
+            if let Some(hit) = self.execute_instruction() {
+                return (self.cpu.clock() - start, Some(hit));
+            }
+            // The system clock is halted until a button is pressed
+            if self.cpu.is_stopped() {
+                break;
+            }
         }
+        (self.cpu.clock() - start, None)
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.cpu.clock()
+    }
+
+    // Frames the PPU has completed, however they were run
+    pub fn frame(&self) -> u64 {
+        self.inner1.frame()
+    }
+
+    pub fn halted_cycles(&self) -> u64 {
+        self.cpu.halted_clock()
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    fn at_breakpoint(&self) -> Option<u16> {
+        self.cpu
+            .next_fetch()
+            .filter(|pc| self.breakpoints.contains(pc))
+    }
+
+    pub fn step(&mut self) -> DebugEvent {
+        if let Some(hit) = self.execute_instruction() {
+            return hit;
+        }
+        match self.at_breakpoint() {
+            Some(pc) => DebugEvent::Breakpoint { pc },
+            None => DebugEvent::Stepped {
+                pc: self.cpu.state().pc,
+            },
+        }
+    }
+
+    pub fn run_until_break(&mut self) -> DebugEvent {
+        let frame = self.inner1.frame();
+        // Always execute at least once, so a breakpoint at the current PC is stepped over
+        loop {
+            if let Some(hit) = self.execute_instruction() {
+                return hit;
+            }
+            if self.inner1.frame() != frame || self.cpu.is_stopped() {
+                return DebugEvent::FrameEnded;
+            }
+            if let Some(pc) = self.at_breakpoint() {
+                return DebugEvent::Breakpoint { pc };
+            }
+        }
+    }
+
+    fn cpu_stall(&self) -> Option<StallReason> {
+        let pc = self.cpu.state().pc;
+        if let Some(opcode) = self.cpu.lockup() {
+            Some(StallReason::InvalidOpcode { opcode, pc })
+        } else if self.cpu.is_halted() && self.inner1.interrupt_enable().into_bytes()[0] == 0 {
+            Some(StallReason::HaltWithoutWakeSource { pc })
+        } else {
+            None
+        }
+    }
+
+    fn report_stall(&mut self, stall: Option<StallReason>) {
+        if let Some(reason) = self.watchdog.update(stall) {
+            self.push_event(EmulatorEvent::EmulationStalled(reason));
+        }
+    }
+
+    pub fn push_event(&mut self, event: EmulatorEvent) {
+        self.inner1.inner2.inner3.events.push(event);
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        self.inner1.peek(address)
+    }
+
+    pub fn poke(&mut self, address: u16, value: u8) {
+        if self.inner1.bus.poke(address, value) {
+            return;
+        }
+        let inner2 = &mut self.inner1.inner2;
+        match address {
+            0xA000..=0xBFFF => inner2.cartridge.write(address, value),
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => inner2.ppu.poke(address, value),
+            _ => {}
+        }
+    }
+
+    pub fn add_bus_observer(
+        &mut self,
+        range: RangeInclusive<u16>,
+        callback: Box<dyn FnMut(&BusAccess)>,
+    ) -> ObserverId {
+        self.inner1.observers.add(range, callback)
+    }
+
+    pub fn remove_bus_observer(&mut self, id: ObserverId) -> bool {
+        self.inner1.observers.remove(id)
+    }
+
+    pub fn add_watchpoint(
+        &mut self,
+        range: RangeInclusive<u16>,
+        condition: WatchpointCondition,
+    ) -> WatchpointId {
+        self.inner1.watchpoints.add(range, condition)
+    }
+
+    pub fn remove_watchpoint(&mut self, id: WatchpointId) -> bool {
+        self.inner1.watchpoints.remove(id)
+    }
+
+    pub fn add_rom_patch(&mut self, patch: RomPatch) -> PatchId {
+        self.inner1.inner2.rom_patches.add(patch)
+    }
+
+    pub fn set_rom_patch_enabled(&mut self, id: PatchId, enabled: bool) -> bool {
+        self.inner1.inner2.rom_patches.set_enabled(id, enabled)
+    }
+
+    pub fn remove_rom_patch(&mut self, id: PatchId) -> bool {
+        self.inner1.inner2.rom_patches.remove(id)
+    }
+
+    pub fn clear_rom_patches(&mut self) {
+        self.inner1.inner2.rom_patches.clear();
+    }
+
+    pub fn subscribe(&mut self, subscriber: event::Subscriber) {
+        self.inner1.inner2.inner3.event_bus.subscribe(subscriber);
+    }
+
+    pub fn take_events(&mut self) -> Vec<EmulatorEvent> {
+        std::mem::take(&mut self.inner1.inner2.inner3.events)
     }
 
     pub fn set_key(&mut self, key_state: JoypadKeyState) {
+        let combo_held = self.inner1.inner2.joypad.key_state().is_soft_reset_combo();
         self.inner1.inner2.set_key(key_state);
+        if key_state.is_soft_reset_combo() && !combo_held {
+            self.push_event(EmulatorEvent::SoftResetCombo);
+            if self.soft_reset_combo {
+                self.reset(ResetKind::Soft);
+            }
+        }
     }
 
     pub fn frame_buffer(&self) -> &[(u8, u8, u8)] {
         self.inner1.frame_buffer()
     }
 
+    pub fn sgb_frame_buffer(&self) -> Option<&[(u8, u8, u8)]> {
+        self.inner1
+            .inner2
+            .sgb
+            .as_ref()
+            .map(|sgb| sgb.frame_buffer())
+    }
+
+    pub fn dirty_lines(&self) -> ppu::DirtyLines {
+        self.inner1.inner2.ppu.dirty_lines()
+    }
+
+    #[cfg(feature = "pixel-source")]
+    pub fn pixel_sources(&self) -> &[ppu::PixelSource] {
+        self.inner1.inner2.ppu.pixel_sources()
+    }
+
+    #[cfg(feature = "pixel-source")]
+    pub fn tile_data(&self, tile_id: u16) -> Option<[u8; 16]> {
+        self.inner1.inner2.ppu.tile_data(tile_id)
+    }
+
+    pub fn set_tile_capture(&mut self, enabled: bool) {
+        self.inner1
+            .inner2
+            .ppu
+            .set_tile_capture(enabled, self.inner1.device_mode());
+    }
+
+    pub fn captured_tiles(&self) -> &[CapturedTile] {
+        self.inner1.inner2.ppu.captured_tiles()
+    }
+
+    pub fn render_tile_data(&self, bank: u8) -> ppu::DebugImage {
+        self.inner1
+            .inner2
+            .ppu
+            .render_tile_data(bank, self.inner1.device_mode())
+    }
+
+    pub fn render_bg_map(&self, map: ppu::TileMap, bank: Option<u8>) -> ppu::DebugImage {
+        self.inner1
+            .inner2
+            .ppu
+            .render_bg_map(map, bank, self.inner1.device_mode())
+    }
+
+    pub fn oam_entries(&self) -> Vec<ppu::OamEntry> {
+        self.inner1.inner2.ppu.oam_entries()
+    }
+
+    pub fn ppu_latches(&self) -> ppu::PpuLatches {
+        self.inner1.inner2.ppu.latches()
+    }
+
+    pub fn set_ppu_latches(&mut self, latches: ppu::PpuLatches) {
+        self.inner1.inner2.ppu.set_latches(latches);
+    }
+
+    pub fn bg_palettes(&self) -> [[(u8, u8, u8); 4]; 8] {
+        self.inner1
+            .inner2
+            .ppu
+            .bg_palettes(self.inner1.device_mode())
+    }
+
+    pub fn obj_palettes(&self) -> [[(u8, u8, u8); 4]; 8] {
+        self.inner1
+            .inner2
+            .ppu
+            .obj_palettes(self.inner1.device_mode())
+    }
+
+    #[cfg(feature = "indexed-output")]
+    pub fn indexed_frame_buffer(&self) -> &[u8] {
+        self.inner1.inner2.ppu.indexed_frame_buffer()
+    }
+
+    #[cfg(feature = "indexed-output")]
+    pub fn palette_table(&self) -> [(u8, u8, u8); 64] {
+        self.inner1
+            .inner2
+            .ppu
+            .palette_table(self.inner1.device_mode())
+    }
+
     pub fn save_data(&self) -> Option<Vec<u8>> {
         self.inner1.save_data()
     }
 
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        self.inner1.inner2.cartridge.battery_ram()
+    }
+
     pub fn rom_name(&self) -> &str {
         &self.rom_name
     }
 
+    pub fn rtc_supported(&self) -> bool {
+        self.inner1.inner2.cartridge.rtc_supported()
+    }
+
+    pub fn rtc_battery_low(&self) -> bool {
+        self.inner1.inner2.cartridge.rtc_battery_low()
+    }
+
+    pub fn set_rtc_battery_low(&mut self, low: bool) {
+        self.inner1.inner2.cartridge.set_rtc_battery_low(low);
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        let inner2 = &self.inner1.inner2;
+        Capabilities {
+            model: self.model,
+            device_mode: self.inner1.device_mode(),
+            boot_rom: self.boot_rom.is_some(),
+            mapper: inner2.cartridge.mapper(),
+            rtc: inner2.cartridge.rtc_supported(),
+            link_cable: inner2.serial.link_attached(),
+            sprite_limit: inner2.ppu.sprite_limit(),
+            super_game_boy: inner2.sgb.is_some(),
+            features: COMPILED_FEATURES
+                .iter()
+                .filter(|&&(_, enabled)| enabled)
+                .map(|&(name, _)| name)
+                .collect(),
+        }
+    }
+
     pub fn get_audio_buffer(&self) -> &Vec<[i16; 2]> {
         self.inner1.inner2.apu.get_audio_buffer()
     }
 
     pub fn clear_audio_buffer(&mut self) {
         self.inner1.inner2.apu.clear_audio_buffer();
+        self.audio_delivered = 0;
     }
-}
 
-pub trait Bus {
-    fn read(&mut self, address: u16) -> u8;
-    fn write(&mut self, address: u16, value: u8);
+    pub fn set_audio_callback(&mut self, audio_callback: Option<apu::AudioCallback>) {
+        self.audio_callback = audio_callback;
+        self.audio_delivered = self.get_audio_buffer().len();
+    }
 
-    fn tick(&mut self);
-}
+    // Recording finishes any recording in progress
+    pub fn start_audio_recording(&mut self, path: &Path, channels: bool) -> io::Result<()> {
+        self.stop_audio_recording()?;
+        let apu = &mut self.inner1.inner2.apu;
+        let recorder = AudioRecorder::create(path, apu.sample_rate(), channels)?;
+        apu.set_capture_channels(recorder.records_channels());
+        self.audio_recorder = Some(recorder);
+        self.audio_delivered = self.get_audio_buffer().len();
+        Ok(())
+    }
 
-pub trait Cartridge {
-    fn cartridge_read(&self, address: u16) -> u8;
-    fn cartridge_write(&mut self, address: u16, value: u8);
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        let Some(recorder) = self.audio_recorder.take() else {
+            return Ok(());
+        };
+        self.inner1.inner2.apu.set_capture_channels(false);
+        recorder.finish()
+    }
 
-    fn save_data(&self) -> Option<Vec<u8>>;
-}
+    // Passes the samples produced since the last call to the audio callback and recorder
+    pub fn deliver_audio(&mut self) {
+        let apu = &self.inner1.inner2.apu;
+        let samples = &apu.get_audio_buffer()[self.audio_delivered..];
+        if samples.is_empty() {
+            return;
+        }
+        if let Some(audio_callback) = &mut self.audio_callback {
+            audio_callback(samples);
+        }
+        if let Some(recorder) = &mut self.audio_recorder {
+            let channels = apu
+                .channel_buffer()
+                .get(self.audio_delivered..)
+                .unwrap_or(&[]);
+            recorder.write(samples, channels);
+        }
+        self.audio_delivered += samples.len();
+    }
 
-pub trait Ppu {
-    fn ppu_read(&mut self, address: u16) -> u8;
-    fn ppu_write(&mut self, address: u16, value: u8);
+    pub fn polled_keys(&self) -> JoypadKeyState {
+        self.inner1.inner2.joypad.polled_keys()
+    }
 
-    fn ppu_tick(&mut self);
-    fn frame_buffer(&self) -> &[(u8, u8, u8)];
-    fn frame(&self) -> u64;
-    fn ppu_mode(&self) -> ppu::PpuMode;
-}
+    pub fn read_pressed_keys(&self) -> JoypadKeyState {
+        self.inner1.inner2.joypad.read_pressed_keys()
+    }
 
-pub trait Apu {
-    fn apu_read(&mut self, address: u16) -> u8;
-    fn apu_write(&mut self, address: u16, value: u8);
+    pub fn clear_polled_keys(&mut self) {
+        self.inner1.inner2.joypad.clear_polled_keys();
+    }
 
-    fn apu_tick(&mut self);
-    fn audio_buffer(&self) -> &Vec<[i16; 2]>;
-    fn clear_audio_buffer(&mut self);
-}
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.inner1.inner2.apu.set_output_volume(volume);
+    }
 
-pub trait Timer {
-    fn timer_read(&self, address: u16) -> u8;
-    fn timer_write(&mut self, address: u16, value: u8);
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.inner1.inner2.apu.set_sample_rate(sample_rate);
+    }
 
-    fn timer_tick(&mut self);
-}
+    pub fn sample_rate(&self) -> u32 {
+        self.inner1.inner2.apu.sample_rate()
+    }
 
-pub trait Joypad {
-    fn joypad_read(&self) -> u8;
-    fn joypad_write(&mut self, value: u8);
-    fn set_key(&mut self, key_state: JoypadKeyState);
-}
+    pub fn set_muted(&mut self, muted: bool) {
+        self.inner1.inner2.apu.set_muted(muted);
+    }
 
-pub trait Serial {
-    fn serial_read(&self, address: u16) -> u8;
-    fn serial_write(&mut self, address: u16, value: u8);
-    fn serial_tick(&mut self);
-}
+    pub fn set_channel_enabled(&mut self, channel: apu::Channel, enabled: bool) {
+        self.inner1.inner2.apu.set_channel_enabled(channel, enabled);
+    }
 
-pub trait Interrupt {
-    fn interrupt_enable(&self) -> interrupt::InterruptEnable;
-    fn interrupt_flag(&self) -> interrupt::InterruptFlag;
+    pub fn channel_enabled(&self, channel: apu::Channel) -> bool {
+        self.inner1.inner2.apu.channel_enabled(channel)
+    }
 
-    fn set_interrupt_enable(&mut self, value: u8);
-    fn set_interrupt_flag(&mut self, value: u8);
+    pub fn cpu_state(&self) -> cpu::CpuState {
+        self.cpu.state()
+    }
 
-    fn set_interrupt_vblank(&mut self, value: bool);
-    fn set_interrupt_lcd(&mut self, value: bool);
-    fn set_interrupt_timer(&mut self, value: bool);
-    fn set_interrupt_serial(&mut self, value: bool);
-    fn set_interrupt_joypad(&mut self, value: bool);
-}
+    pub fn state_hashes(&self) -> StateHashes {
+        let inner2 = &self.inner1.inner2;
+        let mut io = StateHasher::default();
+        io.write(&inner2.timer)
+            .write(&inner2.inner3.interrupt)
+            .write(&inner2.joypad)
+            .write(&inner2.serial)
+            .write(&inner2.peripherals)
+            .write(&inner2.inner3.config.get_speed_switch())
+            .write(&inner2.sgb);
+        let mut cpu = StateHasher::default();
+        cpu.write(&self.cpu.state_hash())
+            .write(&self.inner1.overclock_left);
+        StateHashes {
+            cpu: cpu.finish(),
+            ppu: inner2.ppu.state_hash(),
+            apu: inner2.apu.state_hash(),
+            ram: self.inner1.bus.state_hash(),
+            cartridge: inner2.cartridge.state_hash(),
+            io: io.finish(),
+        }
+    }
 
-pub trait Config {
-    fn device_mode(&self) -> DeviceMode;
+    pub fn set_mode2_interrupt_offset(&mut self, dots: u16) {
+        self.inner1.inner2.ppu.set_mode2_interrupt_offset(dots);
+    }
 
-    fn set_speed_switch(&mut self, value: u8);
-    fn get_speed_switch(&self) -> u8;
-    fn current_speed(&self) -> config::Speed;
-}
+    pub fn dots(&self) -> u64 {
+        self.inner1.inner2.ppu.dots()
+    }
 
-struct Inner1 {
-    bus: bus::Bus,
-    inner2: Inner2,
-}
+    pub fn frame_dots(&self) -> u64 {
+        self.inner1.inner2.ppu.frame_dots()
+    }
 
-impl Bus for Inner1 {
-    fn read(&mut self, address: u16) -> u8 {
-        self.bus.read(&mut self.inner2, address)
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.inner1.inner2.ppu.set_sprite_limit(enabled);
     }
 
-    fn write(&mut self, address: u16, value: u8) {
-        self.bus.write(&mut self.inner2, address, value);
+    pub fn set_overclock(&mut self, cycles: u32) {
+        self.inner1.overclock = cycles;
     }
 
-    fn tick(&mut self) {
-        self.bus.tick(&mut self.inner2);
-        self.inner2.ppu_tick();
-        self.inner2.apu_tick();
-        self.inner2.timer_tick();
-        self.inner2.serial_tick();
+    pub fn overclock(&self) -> u32 {
+        self.inner1.overclock
     }
-}
 
-impl Cartridge for Inner1 {
-    fn cartridge_read(&self, address: u16) -> u8 {
-        self.inner2.cartridge_read(address)
+    pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+        self.inner1.inner2.ppu.set_color_correction(correction);
     }
 
-    fn cartridge_write(&mut self, address: u16, value: u8) {
-        self.inner2.cartridge_write(address, value);
+    pub fn serial_clock_in(&mut self, value: u8) -> Option<u8> {
+        let inner2 = &mut self.inner1.inner2;
+        inner2.serial.clock_in(value, &mut inner2.inner3)
     }
 
-    fn save_data(&self) -> Option<Vec<u8>> {
-        self.inner2.save_data()
+    pub fn set_link_cable(&mut self, link_cable: Option<Box<dyn LinkCable>>) {
+        self.inner1.inner2.serial.set_link_cable(link_cable);
     }
-}
 
-impl Ppu for Inner1 {
-    fn ppu_read(&mut self, address: u16) -> u8 {
-        self.inner2.ppu_read(address)
+    pub fn set_printer(&mut self, printer: Option<Box<dyn PrinterSink>>) {
+        self.set_link_cable(printer.map(printer_cable));
     }
 
-    fn ppu_write(&mut self, address: u16, value: u8) {
-        self.inner2.ppu_write(address, value);
+    pub fn set_infrared(&mut self, device: Option<Box<dyn InfraredTransceiver>>) {
+        self.inner1.inner2.peripherals.set_infrared(device);
     }
 
-    fn ppu_tick(&mut self) {
-        self.inner2.ppu_tick();
+    pub fn set_rumble(&mut self, device: Option<Box<dyn RumbleSink>>) {
+        self.inner1.inner2.peripherals.set_rumble(device);
     }
 
-    fn frame_buffer(&self) -> &[(u8, u8, u8)] {
-        self.inner2.frame_buffer()
+    pub fn set_camera(&mut self, device: Option<Box<dyn CameraSource>>) {
+        self.inner1.inner2.peripherals.set_camera(device);
     }
 
-    fn frame(&self) -> u64 {
-        self.inner2.frame()
+    pub fn set_link_tap(&mut self, enabled: bool) {
+        self.inner1.inner2.serial.set_tap(enabled);
     }
 
-    fn ppu_mode(&self) -> ppu::PpuMode {
-        self.inner2.ppu_mode()
+    pub fn link_log(&self) -> &[SerialExchange] {
+        self.inner1.inner2.serial.tap_log()
     }
 }
 
-impl Apu for Inner1 {
-    fn apu_read(&mut self, address: u16) -> u8 {
-        self.inner2.apu_read(address)
-    }
+// Declares a context trait, along with impls for the layers that only pass it inward.
+// `#[forward(Inner1 => inner2)]` implements it for Inner1 by calling the same method on
+// `self.inner2`, so a new method or trait needs no hand-written forwarding.
+macro_rules! context_trait {
+    (
+        $(#[forward($($layer:ident => $field:ident),*)])?
+        pub trait $trait:ident $methods:tt
+    ) => {
+        pub trait $trait $methods
+        $($(context_trait!(@forward $trait, $layer, $field, $methods);)*)?
+    };
+    (@forward $trait:ident, $layer:ident, $field:ident, { $($methods:tt)* }) => {
+        context_trait!(@methods $trait, $layer, $field, [] $($methods)*);
+    };
+    (
+        @methods $trait:ident, $layer:ident, $field:ident, [$($impls:tt)*]
+        fn $name:ident(&self $(, $arg:ident: $ty:ty)*) $(-> $ret:ty)?;
+        $($rest:tt)*
+    ) => {
+        context_trait!(
+            @methods $trait, $layer, $field,
+            [
+                $($impls)*
+                fn $name(&self $(, $arg: $ty)*) $(-> $ret)? {
+                    self.$field.$name($($arg),*)
+                }
+            ]
+            $($rest)*
+        );
+    };
+    (
+        @methods $trait:ident, $layer:ident, $field:ident, [$($impls:tt)*]
+        fn $name:ident(&mut self $(, $arg:ident: $ty:ty)*) $(-> $ret:ty)?;
+        $($rest:tt)*
+    ) => {
+        context_trait!(
+            @methods $trait, $layer, $field,
+            [
+                $($impls)*
+                fn $name(&mut self $(, $arg: $ty)*) $(-> $ret)? {
+                    self.$field.$name($($arg),*)
+                }
+            ]
+            $($rest)*
+        );
+    };
+    (@methods $trait:ident, $layer:ident, $field:ident, [$($impls:tt)*]) => {
+        impl $trait for $layer {
+            $($impls)*
+        }
+    };
+}
 
-    fn apu_write(&mut self, address: u16, value: u8) {
-        self.inner2.apu_write(address, value);
-    }
+context_trait! {
+    pub trait Bus {
+        fn read(&mut self, address: u16) -> u8;
+        fn write(&mut self, address: u16, value: u8);
+        fn cpu_stalled(&self) -> bool;
 
-    fn apu_tick(&mut self) {
-        self.inner2.apu_tick();
+        fn tick(&mut self);
     }
+}
 
-    fn audio_buffer(&self) -> &Vec<[i16; 2]> {
-        self.inner2.audio_buffer()
+context_trait! {
+    #[forward(Inner1 => inner2)]
+    pub trait Cartridge {
+        fn cartridge_read(&self, address: u16) -> u8;
+        fn cartridge_write(&mut self, address: u16, value: u8);
+
+        fn save_data(&self) -> Option<Vec<u8>>;
     }
+}
 
-    fn clear_audio_buffer(&mut self) {
-        self.inner2.clear_audio_buffer();
+context_trait! {
+    #[forward(Inner1 => inner2)]
+    pub trait Infrared {
+        fn infrared_read(&self) -> u8;
+        fn infrared_write(&mut self, value: u8);
     }
 }
 
-impl Interrupt for Inner1 {
-    fn interrupt_flag(&self) -> interrupt::InterruptFlag {
-        self.inner2.interrupt_flag()
+context_trait! {
+    #[forward(Inner1 => inner2)]
+    pub trait Ppu {
+        fn ppu_read(&mut self, address: u16) -> u8;
+        fn ppu_write(&mut self, address: u16, value: u8);
+
+        fn ppu_tick(&mut self);
+        fn frame_buffer(&self) -> &[(u8, u8, u8)];
+        fn frame(&self) -> u64;
+        fn ppu_mode(&self) -> ppu::PpuMode;
     }
+}
 
-    fn interrupt_enable(&self) -> interrupt::InterruptEnable {
-        self.inner2.interrupt_enable()
+context_trait! {
+    #[forward(Inner1 => inner2)]
+    pub trait Apu {
+        fn apu_read(&mut self, address: u16) -> u8;
+        fn apu_write(&mut self, address: u16, value: u8);
+
+        fn apu_tick(&mut self);
+        fn audio_buffer(&self) -> &Vec<[i16; 2]>;
+        fn clear_audio_buffer(&mut self);
     }
+}
 
-    fn set_interrupt_enable(&mut self, value: u8) {
-        self.inner2.set_interrupt_enable(value);
+context_trait! {
+    #[forward(Inner1 => inner2)]
+    pub trait Timer {
+        fn timer_read(&self, address: u16) -> u8;
+        fn timer_write(&mut self, address: u16, value: u8);
+        fn reset_div(&mut self);
+
+        fn timer_tick(&mut self);
     }
+}
 
-    fn set_interrupt_flag(&mut self, value: u8) {
-        self.inner2.set_interrupt_flag(value);
+context_trait! {
+    #[forward(Inner1 => inner2)]
+    pub trait Joypad {
+        fn joypad_read(&mut self) -> u8;
+        fn joypad_write(&mut self, value: u8);
+        fn selected_key_held(&self) -> bool;
+        fn set_key(&mut self, key_state: JoypadKeyState);
     }
+}
 
-    fn set_interrupt_vblank(&mut self, value: bool) {
-        self.inner2.set_interrupt_vblank(value);
+context_trait! {
+    pub trait Serial {
+        fn serial_read(&self, address: u16) -> u8;
+        fn serial_write(&mut self, address: u16, value: u8);
+        fn serial_tick(&mut self);
     }
+}
 
-    fn set_interrupt_lcd(&mut self, value: bool) {
-        self.inner2.set_interrupt_lcd(value);
+context_trait! {
+    #[forward(Inner1 => inner2, Inner2 => inner3)]
+    pub trait Interrupt {
+        fn interrupt_enable(&self) -> interrupt::InterruptEnable;
+        fn interrupt_flag(&self) -> interrupt::InterruptFlag;
+
+        fn set_interrupt_enable(&mut self, value: u8);
+        fn set_interrupt_flag(&mut self, value: u8);
+
+        fn set_interrupt_vblank(&mut self, value: bool);
+        fn set_interrupt_lcd(&mut self, value: bool);
+        fn set_interrupt_timer(&mut self, value: bool);
+        fn set_interrupt_serial(&mut self, value: bool);
+        fn set_interrupt_joypad(&mut self, value: bool);
     }
+}
 
-    fn set_interrupt_timer(&mut self, value: bool) {
-        self.inner2.set_interrupt_timer(value);
+context_trait! {
+    #[forward(Inner1 => inner2, Inner2 => inner3)]
+    pub trait Config {
+        fn device_mode(&self) -> DeviceMode;
+        fn gameboy_doctor(&self) -> bool;
+
+        fn set_speed_switch(&mut self, value: u8);
+        fn get_speed_switch(&self) -> u8;
+        fn current_speed(&self) -> config::Speed;
+        fn switch_speed(&mut self);
     }
+}
 
-    fn set_interrupt_serial(&mut self, value: bool) {
-        self.inner2.set_interrupt_serial(value);
+context_trait! {
+    #[forward(Inner1 => inner2, Inner2 => inner3)]
+    pub trait Event {
+        fn warn(&mut self, subsystem: Subsystem, detail: String);
+        fn emit(&mut self, event: HardwareEvent);
     }
+}
 
-    fn set_interrupt_joypad(&mut self, value: bool) {
-        self.inner2.set_interrupt_joypad(value);
+struct Inner1 {
+    bus: bus::Bus,
+    // Outside the bus so they survive a reset
+    observers: BusObservers,
+    watchpoints: Watchpoints,
+    // Extra M-cycles the CPU gets at the start of each VBlank, and those still left
+    overclock: u32,
+    overclock_left: u32,
+    inner2: Inner2,
+}
+
+impl Inner1 {
+    // Memory as the CPU sees it, without ticking or side effects. IO registers read as 0xFF.
+    fn peek(&self, address: u16) -> u8 {
+        if let Some(data) = self.bus.peek(address) {
+            return data;
+        }
+        match address {
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.inner2.cartridge_read(address),
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.inner2.ppu.peek(address),
+            _ => 0xFF,
+        }
     }
 }
 
-impl Config for Inner1 {
-    fn device_mode(&self) -> DeviceMode {
-        self.inner2.device_mode()
+impl Bus for Inner1 {
+    fn read(&mut self, address: u16) -> u8 {
+        let value = self.bus.read(&mut self.inner2, address);
+        self.observers.notify(AccessKind::Read, address, value);
+        self.watchpoints
+            .check(AccessKind::Read, address, value, value);
+        value
     }
 
-    fn set_speed_switch(&mut self, value: u8) {
-        self.inner2.set_speed_switch(value);
+    fn write(&mut self, address: u16, value: u8) {
+        // Read as the CPU would, so I/O registers give their real value
+        let old = self
+            .watchpoints
+            .watches(AccessKind::Write, address)
+            .then(|| self.bus.read(&mut self.inner2, address));
+        self.bus.write(&mut self.inner2, address, value);
+        self.observers.notify(AccessKind::Write, address, value);
+        if let Some(old) = old {
+            self.watchpoints
+                .check(AccessKind::Write, address, old, value);
+        }
     }
 
-    fn get_speed_switch(&self) -> u8 {
-        self.inner2.get_speed_switch()
+    fn cpu_stalled(&self) -> bool {
+        self.bus.cpu_stalled()
     }
 
-    fn current_speed(&self) -> config::Speed {
-        self.inner2.current_speed()
+    fn tick(&mut self) {
+        // Overclocked cycles run the CPU alone, so everything else keeps its timing
+        if self.overclock_left > 0 {
+            self.overclock_left -= 1;
+            return;
+        }
+        let mode = self.inner2.ppu.ppu_mode();
+        self.bus.tick(&mut self.inner2);
+        self.inner2.ppu_tick();
+        self.inner2.apu_tick();
+        self.inner2.timer_tick();
+        self.inner2.serial_tick();
+        if mode != ppu::PpuMode::VBlank && self.inner2.ppu.ppu_mode() == ppu::PpuMode::VBlank {
+            self.overclock_left = self.overclock;
+        }
     }
 }
 
 struct Inner2 {
     cartridge: cartridge::Cartridge,
+    rom_patches: RomPatches,
     ppu: ppu::Ppu,
     apu: apu::Apu,
     joypad: joypad::Joypad,
     timer: timer::Timer,
     serial: serial::Serial,
+    // Only for an SGB running a game that supports it
+    sgb: Option<sgb::Sgb>,
+    peripherals: Peripherals,
     inner3: Inner3,
 }
 
 impl Cartridge for Inner2 {
     fn cartridge_read(&self, address: u16) -> u8 {
-        self.cartridge.read(address)
+        let value = self.cartridge.read(address);
+        match address {
+            0x0000..=0x7FFF if self.rom_patches.is_active() => {
+                let bank = self.cartridge.rom_bank(address);
+                self.rom_patches.apply(address, bank, value)
+            }
+            0x0000..=0x7FFF => value,
+            _ if self.cartridge.infrared_mapped() && self.peripherals.receiving() => value | 0x01,
+            _ => value,
+        }
     }
 
     fn cartridge_write(&mut self, address: u16, value: u8) {
         self.cartridge.write(address, value);
+        if !self.cartridge.drives_peripherals(address) {
+            return;
+        }
+        self.peripherals
+            .set_cartridge_led(self.cartridge.infrared_led());
+        self.peripherals.set_rumbling(self.cartridge.rumble());
+        if self.cartridge.capture_requested() {
+            let picture = self.peripherals.capture();
+            self.cartridge.finish_capture(&picture);
+        }
     }
 
     fn save_data(&self) -> Option<Vec<u8>> {
@@ -338,6 +1128,16 @@ impl Cartridge for Inner2 {
     }
 }
 
+impl Infrared for Inner2 {
+    fn infrared_read(&self) -> u8 {
+        self.peripherals.read_rp()
+    }
+
+    fn infrared_write(&mut self, value: u8) {
+        self.peripherals.write_rp(value);
+    }
+}
+
 impl Ppu for Inner2 {
     fn ppu_read(&mut self, address: u16) -> u8 {
         self.ppu.read(&mut self.inner3, address)
@@ -348,7 +1148,13 @@ impl Ppu for Inner2 {
     }
 
     fn ppu_tick(&mut self) {
+        let frame = self.ppu.frame();
         self.ppu.tick(&mut self.inner3);
+        if let Some(sgb) = &mut self.sgb {
+            if self.ppu.frame() != frame {
+                sgb.finish_frame(self.ppu.shades().unwrap());
+            }
+        }
     }
 
     fn frame_buffer(&self) -> &[(u8, u8, u8)] {
@@ -366,11 +1172,11 @@ impl Ppu for Inner2 {
 
 impl Apu for Inner2 {
     fn apu_read(&mut self, address: u16) -> u8 {
-        self.apu.read(address)
+        self.apu.read(&mut self.inner3, address)
     }
 
     fn apu_write(&mut self, address: u16, value: u8) {
-        self.apu.write(address, value);
+        self.apu.write(&mut self.inner3, address, value);
     }
 
     fn apu_tick(&mut self) {
@@ -387,14 +1193,25 @@ impl Apu for Inner2 {
 }
 
 impl Joypad for Inner2 {
-    fn joypad_read(&self) -> u8 {
-        self.joypad.read()
+    fn joypad_read(&mut self) -> u8 {
+        let value = self.joypad.read();
+        match &self.sgb {
+            Some(sgb) => sgb.joypad_read(value),
+            None => value,
+        }
     }
 
     fn joypad_write(&mut self, value: u8) {
+        if let Some(sgb) = &mut self.sgb {
+            sgb.joypad_write(value);
+        }
         self.joypad.write(value);
     }
 
+    fn selected_key_held(&self) -> bool {
+        self.joypad.selected_key_held()
+    }
+
     fn set_key(&mut self, key_state: JoypadKeyState) {
         self.joypad.set_key(&mut self.inner3, key_state);
     }
@@ -409,6 +1226,10 @@ impl Timer for Inner2 {
         self.timer.write(address, value);
     }
 
+    fn reset_div(&mut self) {
+        self.timer.reset_div();
+    }
+
     fn timer_tick(&mut self) {
         self.timer.tick(&mut self.inner3);
     }
@@ -428,65 +1249,17 @@ impl Serial for Inner2 {
     }
 }
 
-impl Interrupt for Inner2 {
-    fn interrupt_flag(&self) -> interrupt::InterruptFlag {
-        self.inner3.interrupt_flag()
-    }
-
-    fn interrupt_enable(&self) -> interrupt::InterruptEnable {
-        self.inner3.interrupt_enable()
-    }
-
-    fn set_interrupt_enable(&mut self, value: u8) {
-        self.inner3.set_interrupt_enable(value);
-    }
-
-    fn set_interrupt_flag(&mut self, value: u8) {
-        self.inner3.set_interrupt_flag(value);
-    }
-
-    fn set_interrupt_vblank(&mut self, value: bool) {
-        self.inner3.set_interrupt_vblank(value);
-    }
-
-    fn set_interrupt_lcd(&mut self, value: bool) {
-        self.inner3.set_interrupt_lcd(value);
-    }
-
-    fn set_interrupt_timer(&mut self, value: bool) {
-        self.inner3.set_interrupt_timer(value);
-    }
-
-    fn set_interrupt_serial(&mut self, value: bool) {
-        self.inner3.set_interrupt_serial(value);
-    }
-
-    fn set_interrupt_joypad(&mut self, value: bool) {
-        self.inner3.set_interrupt_joypad(value);
-    }
-}
-
-impl Config for Inner2 {
-    fn device_mode(&self) -> DeviceMode {
-        self.inner3.device_mode()
-    }
-
-    fn set_speed_switch(&mut self, value: u8) {
-        self.inner3.set_speed_switch(value);
-    }
-
-    fn get_speed_switch(&self) -> u8 {
-        self.inner3.get_speed_switch()
-    }
-
-    fn current_speed(&self) -> config::Speed {
-        self.inner3.current_speed()
-    }
-}
-
 struct Inner3 {
     interrupt: interrupt::Interrupt,
     config: config::Config,
+    events: Vec<EmulatorEvent>,
+    event_bus: EventBus,
+}
+
+impl Inner3 {
+    fn raise_interrupt(&mut self, kind: InterruptKind) {
+        self.event_bus.emit(HardwareEvent::InterruptRaised(kind));
+    }
 }
 
 impl Interrupt for Inner3 {
@@ -508,22 +1281,51 @@ impl Interrupt for Inner3 {
 
     fn set_interrupt_vblank(&mut self, value: bool) {
         self.interrupt.set_intterupt_vblank(value);
+        if value {
+            self.raise_interrupt(InterruptKind::VBlank);
+        }
     }
 
     fn set_interrupt_lcd(&mut self, value: bool) {
         self.interrupt.set_interrupt_lcd(value);
+        if value {
+            self.raise_interrupt(InterruptKind::Lcd);
+        }
     }
 
     fn set_interrupt_timer(&mut self, value: bool) {
         self.interrupt.set_interrupt_timer(value);
+        if value {
+            self.raise_interrupt(InterruptKind::Timer);
+        }
     }
 
     fn set_interrupt_serial(&mut self, value: bool) {
         self.interrupt.set_interrupt_serial(value);
+        if value {
+            self.raise_interrupt(InterruptKind::Serial);
+        }
     }
 
     fn set_interrupt_joypad(&mut self, value: bool) {
         self.interrupt.set_interrupt_joypad(value);
+        if value {
+            self.raise_interrupt(InterruptKind::Joypad);
+        }
+    }
+}
+
+impl Event for Inner3 {
+    fn warn(&mut self, subsystem: Subsystem, detail: String) {
+        log::warn!("{:?}: {}", subsystem, detail);
+        let warning = EmulatorEvent::Warning { subsystem, detail };
+        if self.events.len() < event::MAX_PENDING_EVENTS && !self.events.contains(&warning) {
+            self.events.push(warning);
+        }
+    }
+
+    fn emit(&mut self, event: HardwareEvent) {
+        self.event_bus.emit(event);
     }
 }
 
@@ -532,6 +1334,10 @@ impl Config for Inner3 {
         self.config.device_mode()
     }
 
+    fn gameboy_doctor(&self) -> bool {
+        self.config.gameboy_doctor()
+    }
+
     fn set_speed_switch(&mut self, value: u8) {
         self.config.set_speed_switch(value);
     }
@@ -543,4 +1349,8 @@ impl Config for Inner3 {
     fn current_speed(&self) -> config::Speed {
         self.config.current_speed()
     }
+
+    fn switch_speed(&mut self) {
+        self.config.switch_speed();
+    }
 }
@@ -1,50 +1,248 @@
-use crate::config::DeviceMode;
+use crate::config::{DeviceMode, Model};
 use crate::context;
+use crate::event::Subsystem;
+use crate::state_hash::StateHasher;
 use modular_bitfield::prelude::*;
 
-use log::debug;
+use log::{debug, warn};
 
-trait Context: context::Bus + context::Interrupt {}
-impl<T: context::Bus + context::Interrupt> Context for T {}
+trait Context:
+    context::Bus
+    + context::Interrupt
+    + context::Config
+    + context::Event
+    + context::Joypad
+    + context::Timer
+{
+}
+impl<T> Context for T where
+    T: context::Bus
+        + context::Interrupt
+        + context::Config
+        + context::Event
+        + context::Joypad
+        + context::Timer
+{
+}
+
+// M-cycles the CPU is paused for while the clock speed changes
+const SPEED_SWITCH_CYCLES: u16 = 2050;
 
 #[derive(Debug)]
 pub struct Cpu {
     registers: Registers,
     ime: bool,
     halt: bool,
+    // Set when HALT is skipped with IME=0, so the next opcode fetch repeats its byte
+    halt_bug: bool,
+    stop: bool,
+    speed_switch_delay: u16,
+    // Opcode that hung the CPU
+    lockup: Option<u8>,
 
     clock: u64,
+    // M-cycles spent in HALT
+    halted_clock: u64,
 
-    // for debugging
+    // Instructions executed
     counter: u64,
 }
 
 impl Cpu {
-    pub fn new(device_mode: DeviceMode) -> Self {
+    pub fn new(model: Model, device_mode: DeviceMode) -> Self {
         Self {
-            registers: Registers::new(device_mode),
+            registers: Registers::new(model, device_mode),
             ime: false,
             halt: false,
+            halt_bug: false,
+            stop: false,
+            speed_switch_delay: 0,
+            lockup: None,
             clock: 0,
+            halted_clock: 0,
 
             counter: 0,
         }
     }
 
+    /// Starts from reset at 0x0000, for running a boot ROM.
+    pub fn power_on() -> Self {
+        Self {
+            registers: Registers::default(),
+            ..Self::new(Model::Dmg, DeviceMode::GameBoy)
+        }
+    }
+
+    // At `pc` with the stack at `sp`, the flags in `f` and the other registers as the DMG
+    // boot ROM leaves them, for running an instruction on its own
+    pub(crate) fn at(pc: u16, sp: u16, f: u8) -> Self {
+        let mut cpu = Self::new(Model::Dmg, DeviceMode::GameBoy);
+        cpu.registers.pc = pc;
+        cpu.registers.sp = sp;
+        cpu.registers.f.bytes[0] = f & 0xF0;
+        cpu
+    }
+
+    pub fn instruction_count(&self) -> u64 {
+        self.counter
+    }
+
     fn tick(&mut self, context: &mut impl Context) {
         self.clock = self.clock.wrapping_add(1);
         context.tick();
     }
+
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    pub fn halted_clock(&self) -> u64 {
+        self.halted_clock
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        // Listing every field makes a new one fail to build until it is hashed or skipped
+        let Cpu {
+            registers,
+            ime,
+            halt,
+            halt_bug,
+            stop,
+            speed_switch_delay,
+            lockup,
+            clock,
+            // Statistics
+            halted_clock: _,
+            counter: _,
+        } = self;
+        let Registers {
+            a,
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+            f,
+            pc,
+            sp,
+        } = registers;
+        let mut hasher = StateHasher::default();
+        hasher
+            .write(&[*a, f.bytes[0], *b, *c, *d, *e, *h, *l])
+            .write(sp)
+            .write(pc)
+            .write(ime)
+            .write(halt)
+            .write(halt_bug)
+            .write(stop)
+            .write(speed_switch_delay)
+            .write(lockup)
+            .write(clock);
+        hasher.finish()
+    }
+
+    pub fn lockup(&self) -> Option<u8> {
+        self.lockup
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halt
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop
+    }
+
+    // Where the next opcode fetch reads from, unless the CPU is halted, stopped or hung
+    pub fn next_fetch(&self) -> Option<u16> {
+        let fetching = !self.halt && !self.stop && self.lockup.is_none();
+        fetching.then_some(self.registers.pc)
+    }
+
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            a: self.registers.a,
+            f: self.registers.f.bytes[0],
+            b: self.registers.b,
+            c: self.registers.c,
+            d: self.registers.d,
+            e: self.registers.e,
+            h: self.registers.h,
+            l: self.registers.l,
+            sp: self.registers.sp,
+            pc: self.registers.pc,
+            ime: self.ime,
+            halted: self.halt,
+        }
+    }
+}
+
+/// Snapshot of the CPU registers, for debuggers and tracing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuState {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+    pub halted: bool,
+}
+
+impl CpuState {
+    /// Formats the state as a Gameboy Doctor log line, given the four bytes at PC.
+    pub fn doctor_line(&self, pcmem: [u8; 4]) -> String {
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.sp, self.pc,
+            pcmem[0], pcmem[1], pcmem[2], pcmem[3]
+        )
+    }
 }
 
+// Called after each instruction with the registers and the four bytes at PC from before it
+pub(crate) type TraceHook = Box<dyn FnMut(&CpuState, [u8; 4])>;
+
 impl Cpu {
     pub fn execute_instruction(&mut self, context: &mut impl Context) {
+        if self.lockup.is_some() {
+            self.tick(context);
+            return;
+        }
+
+        if self.stop {
+            // Only a button press restarts the system clock
+            if context.selected_key_held() {
+                self.stop = false;
+            }
+            return;
+        }
+
+        if self.speed_switch_delay > 0 {
+            self.speed_switch_delay -= 1;
+            self.clock = self.clock.wrapping_add(1);
+            return;
+        }
+
+        if context.cpu_stalled() {
+            self.tick(context);
+            return;
+        }
+
         if self.halt {
             let interrupt_flag = context.interrupt_flag().into_bytes()[0];
             let interrupt_enable = context.interrupt_enable().into_bytes()[0];
-            if interrupt_flag & interrupt_enable != 0 {
+            // With IME=0 the CPU just resumes after HALT; the request stays in IF
+            if interrupt_flag & interrupt_enable & 0x1F != 0 {
                 self.halt = false;
             }
+            self.halted_clock += 1;
             self.tick(context);
             return;
         }
@@ -75,7 +273,7 @@ impl Cpu {
             0x0E => self.ld_r8_imm8(context, opcode),
             0x0F => self.rrca(),
 
-            0x10 => self.stop(),
+            0x10 => self.stop(context),
             0x11 => self.ld_r16_imm16(context, opcode),
             0x12 => self.ld_r16mem_a(context, opcode),
             0x13 => self.inc_r16(context, opcode),
@@ -212,20 +410,9 @@ impl Cpu {
             0xFE => self.cp_a_imm8(context),
             0xFF => self.rst_tgt3(context, opcode),
 
-            _ => unreachable!("Invalid opcode: {:#04x}", opcode),
+            _ => self.lock_up(opcode),
         }
 
-        // debug!("Count: {:4}, Cycle: {}, IME: {}, PC: {:#06X}, opcode: {:#04X}, sp: {:#06X}, a: {:#04X}, b: {:#04X}, c: {:#04X}, d: {:#04X}, e: {:#04X}, h: {:#04X}, l: {:#04X}, {}{}{}{}", self.counter, self.clock, self.ime, self.registers.pc, opcode, self.registers.sp, self.registers.a, self.registers.b, self.registers.c, self.registers.d, self.registers.e, self.registers.h, self.registers.l,
-        // if self.registers.f.zero() { "Z" } else { "z" },
-        // if self.registers.f.subtract() { "N" } else { "n" },
-        // if self.registers.f.half_carry() { "H" } else { "h" },
-        // if self.registers.f.carry() { "C" } else { "c" });
-        // println!("Count: {:4}, Cycle: {}, IME: {}, PC: {:#06X}, opcode: {:#04X}, sp: {:#06X}, a: {:#04X}, b: {:#04X}, c: {:#04X}, d: {:#04X}, e: {:#04X}, h: {:#04X}, l: {:#04X}, {}{}{}{}", self.counter, self.clock, self.ime, self.registers.pc, opcode, self.registers.sp, self.registers.a, self.registers.b, self.registers.c, self.registers.d, self.registers.e, self.registers.h, self.registers.l,
-        // println!("Count: {:4}, IME: {}, PC: {:#06X}, opcode: {:#04X}, sp: {:#06X}, a: {:#04X}, b: {:#04X}, c: {:#04X}, d: {:#04X}, e: {:#04X}, h: {:#04X}, l: {:#04X}, {}{}{}{}", self.counter, self.ime, self.registers.pc, opcode, self.registers.sp, self.registers.a, self.registers.b, self.registers.c, self.registers.d, self.registers.e, self.registers.h, self.registers.l,
-        // if self.registers.f.zero() { "Z" } else { "z" },
-        // if self.registers.f.subtract() { "N" } else { "n" },
-        // if self.registers.f.half_carry() { "H" } else { "h" },
-        // if self.registers.f.carry() { "C" } else { "c" });
         self.counter += 1;
     }
 
@@ -461,8 +648,63 @@ impl Cpu {
         }
     }
 
-    fn stop(&mut self) {
-        // self.halt = true;
+    // Invalid opcodes hang the CPU until the console is reset
+    fn lock_up(&mut self, opcode: u8) {
+        self.registers.pc = self.registers.pc.wrapping_sub(1);
+        self.lockup = Some(opcode);
+        warn!(
+            "CPU locked up on invalid opcode {:#04x} at {:#06x}",
+            opcode, self.registers.pc
+        );
+    }
+
+    // STOP behaves differently depending on held buttons, pending interrupts and
+    // whether a speed switch is armed; in several cases it also swallows the next byte.
+    fn stop(&mut self, context: &mut impl Context) {
+        let button_held = context.selected_key_held();
+        let interrupt_flag = context.interrupt_flag().into_bytes()[0];
+        let interrupt_enable = context.interrupt_enable().into_bytes()[0];
+        let interrupt_pending = interrupt_flag & interrupt_enable & 0x1F != 0;
+        let speed_switch = context.device_mode() == DeviceMode::GameBoyColor
+            && context.get_speed_switch() & 0x01 != 0;
+
+        if button_held {
+            if !interrupt_pending {
+                self.skip_byte();
+                self.halt = true;
+            }
+            debug!("Stop ignored while a button is held");
+            return;
+        }
+
+        context.reset_div();
+
+        if speed_switch {
+            if interrupt_pending && self.ime {
+                let detail = format!(
+                    "STOP with a pending interrupt and IME set at {:#06x} is unreliable on hardware",
+                    self.registers.pc.wrapping_sub(1)
+                );
+                context.warn(Subsystem::Cpu, detail);
+            }
+            // Without a pending interrupt the CPU sits in HALT until the switch completes
+            if !interrupt_pending {
+                self.skip_byte();
+            }
+            context.switch_speed();
+            self.speed_switch_delay = SPEED_SWITCH_CYCLES;
+            debug!("Speed switch to {:?}", context.current_speed());
+        } else {
+            if !interrupt_pending {
+                self.skip_byte();
+            }
+            self.stop = true;
+            debug!("Stop");
+        }
+    }
+
+    fn skip_byte(&mut self) {
+        self.registers.pc = self.registers.pc.wrapping_add(1);
     }
 
     fn ld_r8_r8(&mut self, context: &mut impl Context, opcode: u8) {
@@ -470,7 +712,7 @@ impl Cpu {
         let src_register = Register8::from(opcode & 0b111);
 
         match (dest_register, src_register) {
-            (Register8::HLIndirect, Register8::HLIndirect) => self.halt(),
+            (Register8::HLIndirect, Register8::HLIndirect) => self.halt(context),
             _ => {
                 let value = self.get_register8(context, src_register);
                 self.set_register8(context, dest_register, value);
@@ -478,7 +720,15 @@ impl Cpu {
         }
     }
 
-    fn halt(&mut self) {
+    fn halt(&mut self, context: &mut impl Context) {
+        let interrupt_flag = context.interrupt_flag().into_bytes()[0];
+        let interrupt_enable = context.interrupt_enable().into_bytes()[0];
+        if !self.ime && interrupt_flag & interrupt_enable & 0x1F != 0 {
+            // HALT bug: the CPU doesn't halt and fails to increment PC once
+            self.halt_bug = true;
+            debug!("Halt bug");
+            return;
+        }
         self.halt = true;
         debug!("Halt");
     }
@@ -1102,7 +1352,7 @@ impl Cpu {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct Registers {
     a: u8,
     b: u8,
@@ -1117,33 +1367,39 @@ struct Registers {
 }
 
 impl Registers {
-    fn new(device_mode: DeviceMode) -> Self {
-        match device_mode {
-            DeviceMode::GameBoy => Self {
-                a: 0x11,
-                b: 0x01,
-                c: 0x00,
-                d: 0x00,
-                e: 0x08,
-                h: 0x00,
-                l: 0x7C,
-                f: Flags::new().with_zero(true),
-                pc: 0x100,
-                sp: 0xFFFE,
-            },
-
-            DeviceMode::GameBoyColor => Self {
-                a: 0x11,
-                b: 0x00,
-                c: 0x00,
-                d: 0xFF,
-                e: 0x56,
-                h: 0x00,
-                l: 0x0D,
-                f: Flags::new().with_zero(true),
-                pc: 0x100,
-                sp: 0xFFFE,
-            },
+    // Values left behind by each model's boot ROM. The header-dependent bits (DMG H/C flags,
+    // CGB B in compatibility mode) assume a non-zero header checksum and an unlisted title.
+    fn new(model: Model, device_mode: DeviceMode) -> Self {
+        let (a, f, b, c, d, e, h, l) = match (model, device_mode) {
+            (Model::Dmg0, _) => (0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03),
+            (Model::Dmg, _) => (0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+            (Model::Mgb, _) => (0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+            (Model::Sgb, _) => (0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+            (Model::Sgb2, _) => (0xFF, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+            (Model::CgbC | Model::CgbD | Model::Cgb, DeviceMode::GameBoyColor) => {
+                (0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D)
+            }
+            (Model::CgbC | Model::CgbD | Model::Cgb, DeviceMode::GameBoy) => {
+                (0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C)
+            }
+            // AGB sets bit 0 of B, which games use to detect a GBA
+            (Model::Agb, DeviceMode::GameBoyColor) => {
+                (0x11, 0x00, 0x01, 0x00, 0xFF, 0x56, 0x00, 0x0D)
+            }
+            (Model::Agb, DeviceMode::GameBoy) => (0x11, 0x00, 0x01, 0x00, 0x00, 0x08, 0x00, 0x7C),
+        };
+
+        Self {
+            a,
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+            f: Flags::from_bytes([f]),
+            pc: 0x100,
+            sp: 0xFFFE,
         }
     }
 }
@@ -1186,7 +1442,11 @@ impl Cpu {
 
     fn fetch_8(&mut self, context: &mut impl Context) -> u8 {
         let data = self.read_8(self.registers.pc, context);
-        self.registers.pc += 1;
+        if self.halt_bug {
+            self.halt_bug = false;
+        } else {
+            self.registers.pc += 1;
+        }
         data
     }
 
@@ -0,0 +1,127 @@
+//! SM83 disassembler, for code views in debuggers.
+
+const R8: [&str; 8] = ["b", "c", "d", "e", "h", "l", "(hl)", "a"];
+const R16: [&str; 4] = ["bc", "de", "hl", "sp"];
+// PUSH and POP take AF in place of SP
+const R16_STACK: [&str; 4] = ["bc", "de", "hl", "af"];
+const R16_MEMORY: [&str; 4] = ["(bc)", "(de)", "(hl+)", "(hl-)"];
+const CONDITION: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const ROTATE: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+/// Decodes the instruction at the start of `bytes`, which was read from `address`, and
+/// returns it with its length in bytes. Relative jumps show their target address.
+/// Opcodes the CPU does not implement, and instructions cut off by the end of `bytes`,
+/// decode as a one-byte `db`. An empty slice decodes as an empty string of length 0.
+pub fn disassemble(address: u16, bytes: &[u8]) -> (String, usize) {
+    let Some(&opcode) = bytes.first() else {
+        return (String::new(), 0);
+    };
+    let length = instruction_length(opcode);
+    if length > bytes.len() {
+        return (db(opcode), 1);
+    }
+
+    let n = bytes.get(1).copied().unwrap_or_default();
+    let nn = u16::from_le_bytes([n, bytes.get(2).copied().unwrap_or_default()]);
+    let e = n as i8;
+
+    let x = opcode >> 6;
+    let y = (opcode >> 3 & 0x07) as usize;
+    let z = opcode & 0x07;
+    let p = y >> 1;
+    let q = y & 1;
+
+    let text = match (x, z) {
+        (0, 0) => match y {
+            0 => "nop".to_string(),
+            1 => format!("ld (${:04X}), sp", nn),
+            2 => "stop".to_string(),
+            3 => format!("jr ${:04X}", relative_target(address, e)),
+            _ => format!(
+                "jr {}, ${:04X}",
+                CONDITION[y - 4],
+                relative_target(address, e)
+            ),
+        },
+        (0, 1) if q == 0 => format!("ld {}, ${:04X}", R16[p], nn),
+        (0, 1) => format!("add hl, {}", R16[p]),
+        (0, 2) if q == 0 => format!("ld {}, a", R16_MEMORY[p]),
+        (0, 2) => format!("ld a, {}", R16_MEMORY[p]),
+        (0, 3) if q == 0 => format!("inc {}", R16[p]),
+        (0, 3) => format!("dec {}", R16[p]),
+        (0, 4) => format!("inc {}", R8[y]),
+        (0, 5) => format!("dec {}", R8[y]),
+        (0, 6) => format!("ld {}, ${:02X}", R8[y], n),
+        (0, _) => ["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"][y].to_string(),
+
+        (1, 6) if y == 6 => "halt".to_string(),
+        (1, _) => format!("ld {}, {}", R8[y], R8[z as usize]),
+
+        (2, _) => format!("{} a, {}", ALU[y], R8[z as usize]),
+
+        (_, 0) => match y {
+            0..=3 => format!("ret {}", CONDITION[y]),
+            4 => format!("ldh (${:04X}), a", 0xFF00 | n as u16),
+            5 => format!("add sp, {}", e),
+            6 => format!("ldh a, (${:04X})", 0xFF00 | n as u16),
+            _ => format!("ld hl, sp{:+}", e),
+        },
+        (_, 1) if q == 0 => format!("pop {}", R16_STACK[p]),
+        (_, 1) => ["ret", "reti", "jp hl", "ld sp, hl"][p].to_string(),
+        (_, 2) => match y {
+            0..=3 => format!("jp {}, ${:04X}", CONDITION[y], nn),
+            4 => "ldh (c), a".to_string(),
+            5 => format!("ld (${:04X}), a", nn),
+            6 => "ldh a, (c)".to_string(),
+            _ => format!("ld a, (${:04X})", nn),
+        },
+        (_, 3) => match y {
+            0 => format!("jp ${:04X}", nn),
+            1 => prefix_cb(n),
+            6 => "di".to_string(),
+            7 => "ei".to_string(),
+            _ => db(opcode),
+        },
+        (_, 4) if y < 4 => format!("call {}, ${:04X}", CONDITION[y], nn),
+        (_, 5) if q == 0 => format!("push {}", R16_STACK[p]),
+        (_, 5) if p == 0 => format!("call ${:04X}", nn),
+        (_, 6) => format!("{} a, ${:02X}", ALU[y], n),
+        (_, 7) => format!("rst ${:02X}", y * 8),
+        _ => db(opcode),
+    };
+    (text, length)
+}
+
+fn prefix_cb(opcode: u8) -> String {
+    let y = (opcode >> 3 & 0x07) as usize;
+    let r8 = R8[(opcode & 0x07) as usize];
+    match opcode >> 6 {
+        0 => format!("{} {}", ROTATE[y], r8),
+        1 => format!("bit {}, {}", y, r8),
+        2 => format!("res {}, {}", y, r8),
+        _ => format!("set {}, {}", y, r8),
+    }
+}
+
+fn db(opcode: u8) -> String {
+    format!("db ${:02X}", opcode)
+}
+
+fn relative_target(address: u16, offset: i8) -> u16 {
+    address.wrapping_add(2).wrapping_add(offset as u16)
+}
+
+// Unused opcodes count as one byte
+fn instruction_length(opcode: u8) -> usize {
+    match opcode {
+        0x01 | 0x08 | 0x11 | 0x21 | 0x31 => 3,
+        0xC2 | 0xC3 | 0xC4 | 0xCA | 0xCC | 0xCD | 0xD2 | 0xD4 | 0xDA | 0xDC => 3,
+        0xEA | 0xFA => 3,
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E => 2,
+        0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => 2,
+        0xC6 | 0xCB | 0xCE | 0xD6 | 0xDE | 0xE0 | 0xE6 | 0xE8 | 0xEE => 2,
+        0xF0 | 0xF6 | 0xF8 | 0xFE => 2,
+        _ => 1,
+    }
+}
@@ -0,0 +1,121 @@
+use crate::ppu::PpuMode;
+
+/// Notifications for the frontend, drained with [`GameBoyColor::take_events`](crate::GameBoyColor::take_events).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorEvent {
+    /// The game can no longer make progress on its own.
+    EmulationStalled(StallReason),
+    /// Something the game did, or the ROM itself, is not what real hardware expects.
+    /// Identical warnings are reported once until the events are taken.
+    Warning {
+        subsystem: Subsystem,
+        detail: String,
+    },
+    /// A, B, Start and Select were pressed together, which most games take as a
+    /// request to restart.
+    SoftResetCombo,
+    /// The condition of a [`Split`](crate::Split) started to hold at the end of `frame`,
+    /// counted as in [`GameBoyColor::frame_count`](crate::GameBoyColor::frame_count).
+    Split { name: String, frame: u64 },
+}
+
+// Warnings beyond this many pending events are only logged
+pub(crate) const MAX_PENDING_EVENTS: usize = 64;
+
+/// The part of the console that raised a [`EmulatorEvent::Warning`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Cpu,
+    Bus,
+    Ppu,
+    Apu,
+    Cartridge,
+}
+
+/// Why [`GameBoyColor::step`](crate::GameBoyColor::step),
+/// [`GameBoyColor::run_until_break`](crate::GameBoyColor::run_until_break) or
+/// [`GameBoyColor::execute_frame`](crate::GameBoyColor::execute_frame) returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugEvent {
+    /// The CPU is about to fetch the instruction at a breakpoint.
+    Breakpoint { pc: u16 },
+    /// The step finished, and the next instruction is at `pc`.
+    Stepped { pc: u16 },
+    /// The instruction at `pc` met a watchpoint by accessing `address`. Reads have the
+    /// byte read as both `old` and `new`.
+    WatchpointHit {
+        address: u16,
+        old: u8,
+        new: u8,
+        pc: u16,
+    },
+    /// The frame ended, or the CPU entered STOP, before any breakpoint was reached.
+    FrameEnded,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StallReason {
+    /// The CPU executed an invalid opcode and hung.
+    InvalidOpcode { opcode: u8, pc: u16 },
+    /// HALT was entered with IE = 0, so no interrupt can ever wake the CPU.
+    HaltWithoutWakeSource { pc: u16 },
+    /// No frame was completed within the configured number of M-cycles.
+    NoFrameProgress { cycles: u64 },
+}
+
+/// Hardware activity published to the subscribers added with
+/// [`GameBoyColor::subscribe`](crate::GameBoyColor::subscribe), as it happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HardwareEvent {
+    InterruptRaised(InterruptKind),
+    PpuModeChanged(PpuMode),
+    DmaStarted {
+        kind: DmaKind,
+        source: u16,
+    },
+    /// A serial transfer completed, `sent` going out as `received` came in.
+    SerialExchanged {
+        sent: u8,
+        received: u8,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptKind {
+    VBlank,
+    Lcd,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaKind {
+    /// OAM DMA through FF46.
+    Oam,
+    /// CGB general-purpose DMA, copying everything at once.
+    General,
+    /// CGB HBlank DMA, copying 16 bytes each HBlank.
+    HBlank,
+}
+
+pub(crate) type Subscriber = Box<dyn FnMut(&HardwareEvent)>;
+
+// Subsystems publish through `context::Event::emit` and never see who is listening
+#[derive(Default)]
+pub(crate) struct EventBus {
+    subscribers: Vec<Subscriber>,
+}
+
+impl EventBus {
+    pub fn subscribe(&mut self, subscriber: Subscriber) {
+        self.subscribers.push(subscriber);
+    }
+
+    pub fn emit(&mut self, event: HardwareEvent) {
+        for subscriber in &mut self.subscribers {
+            subscriber(&event);
+        }
+    }
+}
@@ -1,14 +1,58 @@
+use crate::apu::{self, Channel};
+use crate::cheat::{PatchId, RomPatch};
+use crate::config::{ColorCorrection, EmulatorConfig, EmulatorConfigBuilder, ResetKind};
 use crate::context;
 use crate::context::EmulatorError;
+use crate::cpu::CpuState;
+use crate::disasm;
+use crate::event::{DebugEvent, EmulatorEvent, HardwareEvent};
 use crate::interface::LinkCable;
 use crate::joypad::JoypadKeyState;
-use crate::utils;
-use crate::DeviceMode;
+use crate::link_tap::SerialExchange;
+use crate::peripherals::{CameraSource, InfraredTransceiver, PrinterSink, RumbleSink};
+use crate::power::FrameActivity;
+#[cfg(feature = "pixel-source")]
+use crate::ppu::PixelSource;
+use crate::ppu::{DebugImage, DirtyLines, OamEntry, PpuLatches, TileMap};
+use crate::snoop::{BusAccess, ObserverId, WatchpointCondition, WatchpointId};
+use crate::split::{Split, Splits};
+use crate::state_hash::StateHashes;
+use crate::support::{self, Fnv1aHasher};
+use crate::tile_capture::CapturedTile;
+#[cfg(feature = "video-recording")]
+use crate::video::VideoRecorder;
+use crate::{DeviceMode, Model};
+
+use log::info;
+use std::hash::Hasher;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+/// Size of the Super Game Boy picture, with the border around the screen.
+pub const SGB_SCREEN_WIDTH: usize = 256;
+pub const SGB_SCREEN_HEIGHT: usize = 224;
+/// Rate of the clock used for timestamps, the same in both speed modes.
+pub const CLOCK_RATE: u64 = 4_194_304;
 
 pub struct GameBoyColor {
-    context: context::Context,
+    // Boxed so moving the emulator stays cheap when `static-buffers` stores memory inline
+    context: Box<context::Context>,
 
-    frame_counter: usize,
+    frame_counter: u64,
+    lag_frames: u64,
+    splits: Splits,
+    // A watchpoint stopped the last frame partway through
+    frame_interrupted: bool,
+    // Timestamp of the first sample in the audio buffer
+    audio_start: u64,
+    // Total and halted M-cycles when the last frame was completed
+    frame_start_cycles: (u64, u64),
+    activity: FrameActivity,
+    #[cfg(feature = "video-recording")]
+    video_recorder: Option<VideoRecorder>,
 }
 
 impl GameBoyColor {
@@ -17,39 +61,769 @@ impl GameBoyColor {
         device_mode: DeviceMode,
         link_cable: Option<Box<dyn LinkCable>>,
     ) -> Result<Self, EmulatorError> {
-        let context = context::Context::new(data, device_mode, link_cable)?;
+        let mut builder = EmulatorConfigBuilder::default().device_mode(device_mode);
+        if let Some(link_cable) = link_cable {
+            builder = builder.link_cable(link_cable);
+        }
+        Self::with_config(data, builder.build()?)
+    }
+
+    pub fn with_config(data: &[u8], config: EmulatorConfig) -> Result<Self, EmulatorError> {
+        let context = Box::new(context::Context::new(data, config)?);
+        info!("{:?}", context.capabilities());
         Ok(Self {
             context,
             frame_counter: 0,
+            lag_frames: 0,
+            splits: Splits::default(),
+            frame_interrupted: false,
+            audio_start: 0,
+            frame_start_cycles: (0, 0),
+            activity: FrameActivity::default(),
+            #[cfg(feature = "video-recording")]
+            video_recorder: None,
         })
     }
 
     pub fn execute_instruction(&mut self) {
+        // Watchpoints only stop the calls that return a DebugEvent
         self.context.execute_instruction();
+        self.context.deliver_audio();
+    }
+
+    /// Stops [`run_until_break`](Self::run_until_break) before the CPU fetches the
+    /// instruction at `address`. Breakpoints are kept across [`reset`](Self::reset).
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.context.add_breakpoint(address);
+    }
+
+    /// Returns whether a breakpoint was set at `address`.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.context.remove_breakpoint(address)
+    }
+
+    /// Executes one instruction, or one M-cycle while the CPU is halted.
+    pub fn step(&mut self) -> DebugEvent {
+        let frame = self.context.frame();
+        let event = self.context.step();
+        self.context.deliver_audio();
+        self.end_interrupted_frame(frame);
+        event
+    }
+
+    /// Executes instructions until the CPU reaches a breakpoint or the frame ends.
+    /// A breakpoint at the current PC is stepped over, so calling this again resumes.
+    /// Like [`execute_instruction`](Self::execute_instruction), this does not count
+    /// frames; [`execute_frame`](Self::execute_frame) ignores breakpoints.
+    pub fn run_until_break(&mut self) -> DebugEvent {
+        let frame = self.context.frame();
+        let event = self.context.run_until_break();
+        self.context.deliver_audio();
+        self.end_interrupted_frame(frame);
+        event
     }
 
-    pub fn execute_frame(&mut self) {
+    // A frame a watchpoint interrupted may be finished by stepping instead of by
+    // execute_frame, which then starts the next one afresh
+    fn end_interrupted_frame(&mut self, frame: u64) {
+        if self.context.frame() != frame {
+            self.frame_interrupted = false;
+        }
+    }
+
+    fn clear_audio_buffer(&mut self) {
         self.context.clear_audio_buffer();
-        self.context.execute_frame();
+        self.audio_start = self.timestamp();
+    }
+
+    /// Restarts the console without reloading the cartridge. Settings made through
+    /// this API, such as the volume and sprite limit, are kept.
+    pub fn reset(&mut self, kind: ResetKind) {
+        self.context.reset(kind);
+        self.frame_interrupted = false;
+    }
+
+    /// Runs until the end of the frame. When a watchpoint is met it returns the hit
+    /// straight away instead, and the next call finishes the same frame, so the audio
+    /// buffer ends up holding the whole frame's samples. If [`step`](Self::step) or
+    /// [`run_until_break`](Self::run_until_break) finish the frame instead, the next call
+    /// starts a new one.
+    pub fn execute_frame(&mut self) -> Option<DebugEvent> {
+        if !self.frame_interrupted {
+            self.clear_audio_buffer();
+            self.context.clear_polled_keys();
+        }
+        let hit = self.context.execute_frame();
+        self.context.deliver_audio();
+        self.frame_interrupted = hit.is_some();
+        if hit.is_some() {
+            return hit;
+        }
+        self.frame_counter += 1;
+        if self.is_lag_frame() {
+            self.lag_frames += 1;
+        }
+        self.splits.check(&mut self.context, self.frame_counter);
+        self.record_activity();
+        #[cfg(feature = "video-recording")]
+        if let Some(recorder) = &mut self.video_recorder {
+            recorder.add_frame(self.context.frame_buffer(), self.context.dots());
+        }
+        None
+    }
+
+    /// Runs whole instructions until at least `cycles` M-cycles have passed and returns
+    /// how many did, which overshoots by up to one instruction. The audio buffer is
+    /// cleared first, so it then holds the samples for those cycles. Frames completed
+    /// this way are not counted, and it returns early while the CPU is in STOP.
+    ///
+    /// Breakpoints don't stop it, as they don't stop [`execute_frame`](Self::execute_frame).
+    /// A watchpoint does: it returns right after the instruction that met it, with the
+    /// cycles run so far and the [`DebugEvent::WatchpointHit`], and the next call carries
+    /// on from there.
+    pub fn run_cycles(&mut self, cycles: u64) -> (u64, Option<DebugEvent>) {
+        let frame = self.context.frame();
+        self.clear_audio_buffer();
+        let ran = self.context.run_cycles(cycles);
+        self.context.deliver_audio();
+        self.end_interrupted_frame(frame);
+        ran
+    }
+
+    /// M-cycles the CPU has run since power-on or the last [`reset`](Self::reset). They
+    /// pass twice as fast in double speed mode; [`timestamp`](Self::timestamp) does not.
+    pub fn total_cycles(&self) -> u64 {
+        self.context.total_cycles()
+    }
+
+    /// Frames run through [`execute_frame`](Self::execute_frame). It keeps counting
+    /// across [`reset`](Self::reset).
+    pub fn frame_count(&self) -> u64 {
+        self.frame_counter
+    }
+
+    /// Whether the game did not read the joypad during the last frame.
+    pub fn is_lag_frame(&self) -> bool {
+        self.polled_keys().is_empty()
+    }
+
+    /// Frames counted by [`frame_count`](Self::frame_count) that were lag frames.
+    pub fn lag_frames(&self) -> u64 {
+        self.lag_frames
+    }
+
+    pub fn add_split(&mut self, split: Split) {
+        self.splits.add(split);
+    }
+
+    pub fn clear_splits(&mut self) {
+        self.splits.clear();
+    }
+
+    /// Applies `inputs`, runs one frame and returns everything a frontend presents for it.
+    /// A watchpoint stops the frame early, as in [`execute_frame`](Self::execute_frame),
+    /// and is returned in [`FrameOutput::debug_event`].
+    pub fn run_frame(&mut self, inputs: JoypadKeyState) -> FrameOutput<'_> {
+        self.set_key(inputs);
+        let debug_event = self.execute_frame();
+        let events = self.take_events();
+        FrameOutput {
+            video: self.frame_buffer(),
+            audio: self.audio_buffer(),
+            events,
+            timestamp: self.timestamp(),
+            audio_timestamp: self.audio_start,
+            overshoot: self.frame_overshoot(),
+            debug_event,
+        }
+    }
+
+    /// Clock cycles at [`CLOCK_RATE`] since power-on. Only advances while the system
+    /// clock runs, so it stands still during STOP.
+    pub fn timestamp(&self) -> u64 {
+        self.context.dots()
+    }
+
+    /// Dots at [`CLOCK_RATE`] run since the current frame began. Frames end as LY wraps
+    /// to 0, or when the LCD is switched on, and [`execute_frame`](Self::execute_frame)
+    /// can only stop between instructions, so afterwards this is how far the last one
+    /// ran past the boundary. Those dots count toward the next frame, which ends on
+    /// schedule and so runs that much shorter; lockstep and AV sync code can carry them
+    /// instead of assuming every call ran exactly 70224 dots.
+    pub fn frame_overshoot(&self) -> u64 {
+        self.context.frame_dots()
     }
 
     pub fn frame_buffer(&self) -> &[(u8, u8, u8)] {
         self.context.frame_buffer()
     }
 
+    /// The picture a Super Game Boy sends to the TV: the screen coloured by the game's SGB
+    /// palettes inside its border, `SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT` pixels updated as
+    /// each frame ends. `None` unless the model is an SGB and the game supports it.
+    pub fn sgb_frame_buffer(&self) -> Option<&[(u8, u8, u8)]> {
+        self.context.sgb_frame_buffer()
+    }
+
+    /// Writes the frame as RGBA8, four bytes per pixel with alpha 0xFF, ready for a
+    /// streaming texture. Panics unless `output` holds exactly
+    /// `SCREEN_WIDTH * SCREEN_HEIGHT * 4` bytes.
+    pub fn frame_buffer_rgba(&self, output: &mut [u8]) {
+        assert_eq!(output.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+        for (pixel, &(r, g, b)) in output.chunks_exact_mut(4).zip(self.frame_buffer()) {
+            pixel.copy_from_slice(&[r, g, b, 0xFF]);
+        }
+    }
+
+    /// Writes the frame as RGB565, red in the top five bits. Panics unless `output`
+    /// holds exactly `SCREEN_WIDTH * SCREEN_HEIGHT` pixels.
+    pub fn frame_buffer_rgb565(&self, output: &mut [u16]) {
+        assert_eq!(output.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+        for (pixel, &(r, g, b)) in output.iter_mut().zip(self.frame_buffer()) {
+            *pixel = (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3;
+        }
+    }
+
+    /// Where each pixel of [`frame_buffer`](Self::frame_buffer) came from, in the same order.
+    #[cfg(feature = "pixel-source")]
+    pub fn pixel_sources(&self) -> &[PixelSource] {
+        self.context.pixel_sources()
+    }
+
+    /// The 16 bytes of tile data for a [`PixelSource::tile_id`] as VRAM holds them now, or
+    /// `None` outside 0-383 and 512-895.
+    #[cfg(feature = "pixel-source")]
+    pub fn tile_data(&self, tile_id: u16) -> Option<[u8; 16]> {
+        self.context.tile_data(tile_id)
+    }
+
+    /// Starts recording every distinct tile the game writes to VRAM tile data from now
+    /// on, leaving out the tiles already there. Disabling discards what was recorded; the recording is kept
+    /// across [`reset`](Self::reset).
+    pub fn set_tile_capture(&mut self, enabled: bool) {
+        self.context.set_tile_capture(enabled);
+    }
+
+    /// Tiles recorded since [`set_tile_capture`](Self::set_tile_capture) was enabled, in
+    /// the order they were first seen. Tiles are recorded as each frame ends.
+    pub fn captured_tiles(&self) -> &[CapturedTile] {
+        self.context.captured_tiles()
+    }
+
+    /// The 384 tiles in VRAM bank 0 or 1, 16 to a row in a 128x192 image, coloured with
+    /// BG palette 0. Only bit 0 of `bank` is used, as with VBK.
+    pub fn render_tile_data(&self, bank: u8) -> DebugImage {
+        self.context.render_tile_data(bank)
+    }
+
+    /// A whole tile map as a 256x256 image, with the tile data area LCDC selects for the
+    /// background. In CGB mode each tile's attributes pick its palette, flips and bank;
+    /// `bank` overrides the bank for every tile instead, with only its bit 0 used.
+    pub fn render_bg_map(&self, map: TileMap, bank: Option<u8>) -> DebugImage {
+        self.context.render_bg_map(map, bank)
+    }
+
+    /// The 40 objects in OAM, in order.
+    pub fn oam_entries(&self) -> Vec<OamEntry> {
+        self.context.oam_entries()
+    }
+
+    pub fn ppu_latches(&self) -> PpuLatches {
+        self.context.ppu_latches()
+    }
+
+    /// Overwrites the latches, taking effect from the next line drawn. Get the current
+    /// ones with [`ppu_latches`](Self::ppu_latches) and change the fields needed.
+    pub fn set_ppu_latches(&mut self, latches: PpuLatches) {
+        self.context.set_ppu_latches(latches);
+    }
+
+    /// The eight BG palettes as RGB, with colour correction applied. In DMG mode palette 0
+    /// is BGP and the others are white.
+    pub fn bg_palettes(&self) -> [[(u8, u8, u8); 4]; 8] {
+        self.context.bg_palettes()
+    }
+
+    /// The eight OBJ palettes as RGB, with colour correction applied. In DMG mode palettes
+    /// 0 and 1 are OBP0 and OBP1 and the others are white.
+    pub fn obj_palettes(&self) -> [[(u8, u8, u8); 4]; 8] {
+        self.context.obj_palettes()
+    }
+
+    /// The frame as palette indices: entries 0-31 are the eight BG palettes and 32-63 the
+    /// eight OBJ palettes, four colours each. In DMG mode BGP is entry 0 and OBP0/OBP1 are 8 and 9.
+    #[cfg(feature = "indexed-output")]
+    pub fn indexed_frame_buffer(&self) -> &[u8] {
+        self.context.indexed_frame_buffer()
+    }
+
+    /// Colours for [`indexed_frame_buffer`](Self::indexed_frame_buffer) from the palettes as
+    /// they are now, so palette changes made mid-frame are not reflected.
+    #[cfg(feature = "indexed-output")]
+    pub fn palette_table(&self) -> [(u8, u8, u8); 64] {
+        self.context.palette_table()
+    }
+
+    /// Scanlines that differ from the previous frame, so only those need redrawing.
+    pub fn dirty_lines(&self) -> DirtyLines {
+        self.context.dirty_lines()
+    }
+
     pub fn audio_buffer(&self) -> &Vec<[i16; 2]> {
         self.context.get_audio_buffer()
     }
 
+    /// Scales the mixed output; 1.0 is unchanged and louder values clip at the i16 range.
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.context.set_master_volume(volume);
+    }
+
+    /// Silences the audio buffer without affecting emulation.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.context.set_muted(muted);
+    }
+
+    /// Leaves `channel` out of the mix without affecting emulation, so NR52 and the
+    /// game see it running as before. The setting is kept across [`reset`](Self::reset).
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.context.set_channel_enabled(channel, enabled);
+    }
+
+    pub fn channel_enabled(&self, channel: Channel) -> bool {
+        self.context.channel_enabled(channel)
+    }
+
+    /// Enables `channel` and disables the other three.
+    pub fn solo_channel(&mut self, channel: Channel) {
+        for other in [
+            Channel::Pulse1,
+            Channel::Pulse2,
+            Channel::Wave,
+            Channel::Noise,
+        ] {
+            self.set_channel_enabled(other, other == channel);
+        }
+    }
+
+    /// Sets the rate of the samples in [`audio_buffer`](Self::audio_buffer), 48 kHz by
+    /// default. Each sample is the mean of the output since the one before, so any
+    /// rate keeps the pitch and filters out tones it cannot represent. The setting is
+    /// kept across [`reset`](Self::reset). Panics unless `hz` is between 8 and 192 kHz.
+    pub fn set_sample_rate(&mut self, hz: u32) {
+        assert!(
+            apu::SAMPLE_RATES.contains(&hz),
+            "Unsupported sample rate: {} Hz",
+            hz
+        );
+        self.context.set_sample_rate(hz);
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.context.sample_rate()
+    }
+
+    /// Calls `callback` with the new samples whenever [`execute_frame`](Self::execute_frame),
+    /// [`run_cycles`](Self::run_cycles) or another call that runs the CPU returns, so an
+    /// audio device can be fed without copying [`audio_buffer`](Self::audio_buffer). The
+    /// buffer is still filled as before. An [`AudioRing`](crate::AudioRing) makes the
+    /// samples available to another thread.
+    pub fn set_audio_callback(&mut self, callback: impl FnMut(&[[i16; 2]]) + 'static) {
+        self.context.set_audio_callback(Some(Box::new(callback)));
+    }
+
+    fn record_activity(&mut self) {
+        let (cycles, halted_cycles) = (self.total_cycles(), self.context.halted_cycles());
+        let (start, halted_start) = self.frame_start_cycles;
+        self.activity = FrameActivity {
+            // Both restart from 0 on reset
+            cycles: cycles.saturating_sub(start),
+            halted_cycles: halted_cycles.saturating_sub(halted_start),
+            screen_changed: !self.dirty_lines().is_empty(),
+            audio_silent: self
+                .audio_buffer()
+                .windows(2)
+                .all(|pair| pair[0] == pair[1]),
+            input: !self.context.read_pressed_keys().is_empty(),
+        };
+        self.frame_start_cycles = (cycles, halted_cycles);
+    }
+
+    /// What the game did during the last frame [`execute_frame`](Self::execute_frame)
+    /// completed, for deciding with a [`PowerAdvisor`](crate::PowerAdvisor) when the
+    /// frontend can save power.
+    pub fn frame_activity(&self) -> FrameActivity {
+        self.activity
+    }
+
+    pub fn clear_audio_callback(&mut self) {
+        self.context.set_audio_callback(None);
+    }
+
+    /// Writes the samples in [`audio_buffer`](Self::audio_buffer) to a 16-bit stereo WAV
+    /// file at `path` as they are produced, at the current sample rate, which should not
+    /// change until the recording stops. With `channels`, each channel is also recorded
+    /// before panning and NR50 to a mono file beside it: `<stem>_pulse1.wav`,
+    /// `_pulse2.wav`, `_wave.wav` and `_noise.wav`. A recording already in progress is
+    /// stopped first. Recording stops when a file reaches the 4 GiB a WAV file can hold,
+    /// which [`stop_audio_recording`](Self::stop_audio_recording) then reports.
+    pub fn start_audio_recording(
+        &mut self,
+        path: impl AsRef<Path>,
+        channels: bool,
+    ) -> io::Result<()> {
+        self.context.start_audio_recording(path.as_ref(), channels)
+    }
+
+    /// Finishes the files, reporting the first error met while writing them. Does
+    /// nothing without a recording in progress.
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        self.context.stop_audio_recording()
+    }
+
+    /// Records each frame [`execute_frame`](Self::execute_frame) completes to an
+    /// animated GIF if `path` ends in `.gif`, or an APNG otherwise. Frames are timed
+    /// by emulated time, so the clip plays at 59.73 Hz whatever the emulation speed.
+    /// GIF delays are in hundredths of a second and viewers slow down frames shorter
+    /// than two, so a GIF drops about one frame in three to keep time; APNGs keep them
+    /// all. A recording already in progress is stopped first.
+    #[cfg(feature = "video-recording")]
+    pub fn start_video_recording(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.stop_video_recording()?;
+        self.video_recorder = Some(VideoRecorder::create(path.as_ref())?);
+        Ok(())
+    }
+
+    /// Finishes the file, reporting the first error met while writing it. Does nothing
+    /// without a recording in progress.
+    #[cfg(feature = "video-recording")]
+    pub fn stop_video_recording(&mut self) -> io::Result<()> {
+        match self.video_recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
     pub fn set_key(&mut self, key_state: JoypadKeyState) {
         self.context.set_key(key_state);
     }
 
+    /// Keys on the joypad lines the game selected when it read P1 during the last frame.
+    /// Empty on a lag frame, where input made no difference.
+    pub fn polled_keys(&self) -> JoypadKeyState {
+        self.context.polled_keys()
+    }
+
     pub fn save_data(&self) -> Option<Vec<u8>> {
         self.context.save_data()
     }
 
+    // The start of the save data, borrowed
+    pub(crate) fn battery_ram(&self) -> Option<&[u8]> {
+        self.context.battery_ram()
+    }
+
     pub fn rom_name(&self) -> &str {
         self.context.rom_name()
     }
+
+    /// Whether the cartridge has a real-time clock that this build can drive.
+    /// Without the `rtc` feature the clock registers read back as frozen values.
+    pub fn rtc_supported(&self) -> bool {
+        self.context.rtc_supported()
+    }
+
+    /// Whether the MBC3 clock battery is flagged as run down. The flag is saved with the
+    /// clock in [`save_data`](Self::save_data).
+    pub fn rtc_battery_low(&self) -> bool {
+        self.context.rtc_battery_low()
+    }
+
+    /// Runs the MBC3 clock battery down, or replaces it, to test how a game handles a
+    /// clock that lost time. While the battery is low the clock stops counting, and the
+    /// time that passes is not made up when it is replaced. Other mappers ignore this.
+    pub fn set_rtc_battery_low(&mut self, low: bool) {
+        self.context.set_rtc_battery_low(low);
+    }
+
+    /// What this build and this instance support, for frontends that adapt their UI.
+    pub fn capabilities(&self) -> Capabilities {
+        self.context.capabilities()
+    }
+
+    pub fn screenshot(&self) -> Screenshot {
+        let pixels = self.frame_buffer().to_vec();
+        let mut hasher = Fnv1aHasher::default();
+        for &(r, g, b) in &pixels {
+            hasher.write(&[r, g, b]);
+        }
+        Screenshot {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            pixels,
+            frame: self.frame_counter,
+            rom_title: self.rom_name().to_string(),
+            hash: hasher.finish(),
+        }
+    }
+
+    /// Writes the screen to `path` as a PNG.
+    pub fn screenshot_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        support::save_png(path, SCREEN_WIDTH, SCREEN_HEIGHT, self.frame_buffer())
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        self.context.cpu_state()
+    }
+
+    /// Hashes of the CPU, PPU, APU, RAM, cartridge and I/O state, to compare with another
+    /// instance running the same game.
+    pub fn state_hashes(&self) -> StateHashes {
+        self.context.state_hashes()
+    }
+
+    /// One hash of everything in [`state_hashes`](Self::state_hashes).
+    pub fn state_hash(&self) -> u64 {
+        self.state_hashes().combined()
+    }
+
+    /// Calls `hook` after every instruction with the registers and the four bytes at PC
+    /// from before it ran. Interrupt dispatch, HALT and STOP are not traced.
+    /// [`CpuState::doctor_line`] turns the arguments into a Gameboy Doctor log line.
+    pub fn set_trace_hook(&mut self, hook: impl FnMut(&CpuState, [u8; 4]) + 'static) {
+        self.context.set_trace_hook(Some(Box::new(hook)));
+    }
+
+    pub fn clear_trace_hook(&mut self) {
+        self.context.set_trace_hook(None);
+    }
+
+    /// Reads memory as the CPU sees it, without advancing the clock or any read side
+    /// effects. I/O registers read as 0xFF.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.context.peek(address)
+    }
+
+    /// Writes WRAM, HRAM, VRAM, OAM or cartridge RAM without advancing the clock. Writes
+    /// to ROM and I/O registers are ignored, and cartridge RAM only takes the write while
+    /// the game has it enabled.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.context.poke(address, value);
+    }
+
+    /// Disassembles `count` instructions from `start` as the CPU sees memory, reading it
+    /// like [`peek`](Self::peek). Returns each instruction with its address.
+    pub fn disassemble_range(&self, start: u16, count: usize) -> Vec<(u16, String)> {
+        let mut address = start;
+        (0..count)
+            .map(|_| {
+                let bytes: [u8; 3] =
+                    std::array::from_fn(|i| self.peek(address.wrapping_add(i as u16)));
+                let (text, length) = disasm::disassemble(address, &bytes);
+                let instruction = (address, text);
+                address = address.wrapping_add(length as u16);
+                instruction
+            })
+            .collect()
+    }
+
+    /// Calls `callback` with every CPU read and write in `range`, after it happens.
+    /// Observers cannot change what the game sees.
+    pub fn add_bus_observer(
+        &mut self,
+        range: RangeInclusive<u16>,
+        callback: impl FnMut(&BusAccess) + 'static,
+    ) -> ObserverId {
+        self.context.add_bus_observer(range, Box::new(callback))
+    }
+
+    /// Returns whether the observer was still registered.
+    pub fn remove_bus_observer(&mut self, id: ObserverId) -> bool {
+        self.context.remove_bus_observer(id)
+    }
+
+    /// Stops [`execute_frame`](Self::execute_frame), [`step`](Self::step) and
+    /// [`run_until_break`](Self::run_until_break) after a CPU access in `range` that
+    /// meets `condition`, returning [`DebugEvent::WatchpointHit`].
+    pub fn add_watchpoint(
+        &mut self,
+        range: RangeInclusive<u16>,
+        condition: WatchpointCondition,
+    ) -> WatchpointId {
+        self.context.add_watchpoint(range, condition)
+    }
+
+    /// Returns whether the watchpoint was still registered.
+    pub fn remove_watchpoint(&mut self, id: WatchpointId) -> bool {
+        self.context.remove_watchpoint(id)
+    }
+
+    /// Applies `patch` to every cartridge read from now on, including the one fetching
+    /// the next instruction. Patches are kept across [`reset`](Self::reset).
+    pub fn add_rom_patch(&mut self, patch: RomPatch) -> PatchId {
+        self.context.add_rom_patch(patch)
+    }
+
+    /// Turns a patch off or back on without removing it. Returns whether the patch
+    /// is still registered.
+    pub fn set_rom_patch_enabled(&mut self, id: PatchId, enabled: bool) -> bool {
+        self.context.set_rom_patch_enabled(id, enabled)
+    }
+
+    /// Returns whether the patch was still registered.
+    pub fn remove_rom_patch(&mut self, id: PatchId) -> bool {
+        self.context.remove_rom_patch(id)
+    }
+
+    pub fn clear_rom_patches(&mut self) {
+        self.context.clear_rom_patches();
+    }
+
+    /// Calls `subscriber` with every [`HardwareEvent`] from now on, while the instruction
+    /// that caused it runs.
+    pub fn subscribe(&mut self, subscriber: impl FnMut(&HardwareEvent) + 'static) {
+        self.context.subscribe(Box::new(subscriber));
+    }
+
+    /// Returns the events raised since the last call.
+    pub fn take_events(&mut self) -> Vec<EmulatorEvent> {
+        self.context.take_events()
+    }
+
+    /// Sets how many dots before LY increments the mode 2 STAT interrupt is raised.
+    /// Defaults to 4 (one M-cycle); 0 raises it on the first dot of the line.
+    pub fn set_mode2_interrupt_offset(&mut self, dots: u16) {
+        self.context.set_mode2_interrupt_offset(dots);
+    }
+
+    /// Enforces the hardware limit of 10 objects per scanline, which is the default.
+    /// Lifting it removes the flicker games use to show more, at the cost of accuracy.
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.context.set_sprite_limit(enabled);
+    }
+
+    /// Gives the CPU `cycles` extra M-cycles as each VBlank begins, during which the PPU,
+    /// APU, timer and DMA stand still. Games that slow down or flicker because their
+    /// logic overruns a frame then keep up, while the frame rate and audio pitch stay
+    /// the same. 0, the default, is the hardware's speed. Kept across [`reset`](Self::reset).
+    pub fn set_overclock(&mut self, cycles: u32) {
+        self.context.set_overclock(cycles);
+    }
+
+    pub fn overclock(&self) -> u32 {
+        self.context.overclock()
+    }
+
+    /// Changes the [`ColorCorrection`] chosen in the config. Takes effect from the next
+    /// pixel drawn.
+    pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+        self.context.set_color_correction(correction);
+    }
+
+    /// Acts as the link master and clocks one byte into the serial port immediately.
+    /// Returns the byte the Game Boy shifted out, or `None` if it has not started a
+    /// transfer on the external clock (SC = 0x80).
+    pub fn serial_clock_in(&mut self, value: u8) -> Option<u8> {
+        self.context.serial_clock_in(value)
+    }
+
+    /// Starts recording every byte exchanged over the serial port, whichever side drives
+    /// the clock. Disabling discards the log; it is kept across [`reset`](Self::reset).
+    /// Pass the log to [`analyze_link`](crate::analyze_link) to pick out known protocols.
+    pub fn set_link_tap(&mut self, enabled: bool) {
+        self.context.set_link_tap(enabled);
+    }
+
+    /// Exchanges recorded since [`set_link_tap`](Self::set_link_tap) was enabled, oldest first.
+    pub fn link_log(&self) -> &[SerialExchange] {
+        self.context.link_log()
+    }
+
+    /// Plugs a cable into the link port, or unplugs it with `None`. Like the other
+    /// devices below, it can be swapped at any time and stays attached across
+    /// [`reset`](Self::reset).
+    pub fn set_link_cable(&mut self, link_cable: Option<Box<dyn LinkCable>>) {
+        self.context.set_link_cable(link_cable);
+    }
+
+    /// Plugs a [`GameBoyPrinter`](crate::GameBoyPrinter) that prints to `sink` into the
+    /// link port, replacing any cable.
+    pub fn set_printer(&mut self, sink: Option<Box<dyn PrinterSink>>) {
+        self.context.set_printer(sink);
+    }
+
+    /// A new transceiver is told straight away whether the LED is lit.
+    pub fn set_infrared(&mut self, device: Option<Box<dyn InfraredTransceiver>>) {
+        self.context.set_infrared(device);
+    }
+
+    /// A new sink is told straight away whether the motor is running.
+    pub fn set_rumble(&mut self, device: Option<Box<dyn RumbleSink>>) {
+        self.context.set_rumble(device);
+    }
+
+    pub fn set_camera(&mut self, device: Option<Box<dyn CameraSource>>) {
+        self.context.set_camera(device);
+    }
+}
+
+/// Returned by [`GameBoyColor::capabilities`]. It is also logged at `info` level when
+/// the emulator is created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    pub model: Model,
+    pub device_mode: DeviceMode,
+    /// A boot ROM runs at power-on and on reset.
+    pub boot_rom: bool,
+    /// The cartridge's memory bank controller, such as `"Mbc3"`.
+    pub mapper: &'static str,
+    /// Same as [`GameBoyColor::rtc_supported`].
+    pub rtc: bool,
+    pub link_cable: bool,
+    /// The 10 objects per scanline limit is enforced.
+    pub sprite_limit: bool,
+    /// Super Game Boy borders, palettes and multiplayer, for an SGB model running a game
+    /// that supports them.
+    pub super_game_boy: bool,
+    /// The optional Cargo features compiled in, such as `"rtc"`.
+    pub features: Vec<&'static str>,
+}
+
+/// Output of [`GameBoyColor::run_frame`].
+#[derive(Debug)]
+pub struct FrameOutput<'a> {
+    /// `SCREEN_WIDTH * SCREEN_HEIGHT` pixels in row-major order.
+    pub video: &'a [(u8, u8, u8)],
+    /// Stereo samples produced during the frame.
+    pub audio: &'a [[i16; 2]],
+    pub events: Vec<EmulatorEvent>,
+    /// [`GameBoyColor::timestamp`] when the frame was completed.
+    pub timestamp: u64,
+    /// [`GameBoyColor::timestamp`] of the first audio sample.
+    pub audio_timestamp: u64,
+    /// [`GameBoyColor::frame_overshoot`] when the frame was completed.
+    pub overshoot: u64,
+    /// The watchpoint hit that stopped the frame early. `video` is then only drawn down
+    /// to the current line, and the next call finishes the frame.
+    pub debug_event: Option<DebugEvent>,
+}
+
+/// A captured frame together with the context needed to reproduce it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<(u8, u8, u8)>,
+    /// Number of frames executed through [`GameBoyColor::execute_frame`].
+    pub frame: u64,
+    pub rom_title: String,
+    /// Stable FNV-1a hash of `pixels`.
+    pub hash: u64,
+}
+
+impl Screenshot {
+    /// The pixels as an 8-bit RGB PNG.
+    pub fn to_png(&self) -> Vec<u8> {
+        support::encode_png(self.width, self.height, &self.pixels)
+    }
 }
@@ -0,0 +1,157 @@
+//! Key bindings shared by frontends, so SDL, egui and web builds read one config format.
+//!
+//! Host keys are plain strings named by the frontend. The defaults use SDL key names.
+
+use crate::joypad::{JoypadKey, JoypadKeyState};
+
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+const BUTTONS: [JoypadKey; 8] = [
+    JoypadKey::Right,
+    JoypadKey::Left,
+    JoypadKey::Up,
+    JoypadKey::Down,
+    JoypadKey::A,
+    JoypadKey::B,
+    JoypadKey::Select,
+    JoypadKey::Start,
+];
+
+/// Frontend commands a key can trigger besides the joypad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Hotkey {
+    Pause,
+    Reset,
+    FastForward,
+}
+
+/// What a bound key does. Serialized as the bare button or hotkey name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum Action {
+    Joypad(JoypadKey),
+    Hotkey(Hotkey),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum BindingsError {
+    #[error("Empty key name")]
+    EmptyKey,
+
+    #[error("No key bound to {0:?}")]
+    Unbound(JoypadKey),
+}
+
+/// Host keys mapped to joypad buttons and hotkeys. Each key does one thing, while an
+/// action can have several keys. Deserializing validates the result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        try_from = "BTreeMap<String, Action>",
+        into = "BTreeMap<String, Action>"
+    )
+)]
+pub struct Bindings {
+    keys: BTreeMap<String, Action>,
+}
+
+impl Bindings {
+    /// No keys bound.
+    pub fn empty() -> Self {
+        Self {
+            keys: BTreeMap::new(),
+        }
+    }
+
+    /// Binds `key`, returning what it did before.
+    pub fn bind(&mut self, key: impl Into<String>, action: Action) -> Option<Action> {
+        self.keys.insert(key.into(), action)
+    }
+
+    pub fn unbind(&mut self, key: &str) -> Option<Action> {
+        self.keys.remove(key)
+    }
+
+    pub fn action(&self, key: &str) -> Option<Action> {
+        self.keys.get(key).copied()
+    }
+
+    /// Keys bound to `action`, in name order.
+    pub fn keys_for(&self, action: Action) -> impl Iterator<Item = &str> {
+        self.keys
+            .iter()
+            .filter(move |&(_, &bound)| bound == action)
+            .map(|(key, _)| key.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Action)> {
+        self.keys
+            .iter()
+            .map(|(key, &action)| (key.as_str(), action))
+    }
+
+    /// Checks that no key name is empty and every joypad button has a key.
+    pub fn validate(&self) -> Result<(), BindingsError> {
+        if self.keys.contains_key("") {
+            return Err(BindingsError::EmptyKey);
+        }
+        for button in BUTTONS {
+            if self.keys_for(Action::Joypad(button)).next().is_none() {
+                return Err(BindingsError::Unbound(button));
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a key press or release. Joypad buttons update `state`, and a pressed
+    /// hotkey is returned for the frontend to act on.
+    pub fn apply(&self, key: &str, pressed: bool, state: &mut JoypadKeyState) -> Option<Hotkey> {
+        match self.action(key)? {
+            Action::Joypad(button) => {
+                state.set_key(button, pressed);
+                None
+            }
+            Action::Hotkey(hotkey) => pressed.then_some(hotkey),
+        }
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut bindings = Self::empty();
+        for (key, button) in ["Right", "Left", "Up", "Down", "X", "Z", "Space", "Return"]
+            .into_iter()
+            .zip(BUTTONS)
+        {
+            bindings.bind(key, Action::Joypad(button));
+        }
+        bindings.bind("P", Action::Hotkey(Hotkey::Pause));
+        bindings.bind("R", Action::Hotkey(Hotkey::Reset));
+        bindings.bind("Tab", Action::Hotkey(Hotkey::FastForward));
+        bindings
+    }
+}
+
+impl TryFrom<BTreeMap<String, Action>> for Bindings {
+    type Error = BindingsError;
+
+    fn try_from(keys: BTreeMap<String, Action>) -> Result<Self, Self::Error> {
+        let bindings = Self { keys };
+        bindings.validate()?;
+        Ok(bindings)
+    }
+}
+
+impl From<Bindings> for BTreeMap<String, Action> {
+    fn from(bindings: Bindings) -> Self {
+        bindings.keys
+    }
+}
@@ -1,12 +1,105 @@
+use crate::gameboycolor::CLOCK_RATE;
+
+use std::cell::Cell;
 use std::io::{Read, Write};
 use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
 use std::sync::mpsc::{channel, Receiver, Sender};
 
+/// The other end of the serial port. `try_recv` is polled while a transfer is in flight,
+/// so emulation is only reproducible if the cable answers independently of host timing.
 pub trait LinkCable {
     fn send(&mut self, data: u8);
     fn try_recv(&mut self) -> Option<u8>;
 }
 
+/// A peripheral on the link port, such as a printer or storage accessory, that answers
+/// each byte as the Game Boy clocks it out. Plug it in with [`DeviceCable`].
+pub trait SerialDevice {
+    /// Takes the byte the Game Boy shifted out and returns the byte shifted back in.
+    fn exchange(&mut self, sent: u8) -> u8;
+}
+
+/// Connects a [`SerialDevice`] to the serial port. The reply is ready as soon as the
+/// byte is sent, so the exchange completes in the time the Game Boy's clock takes.
+pub struct DeviceCable<D: SerialDevice> {
+    device: D,
+    reply: Option<u8>,
+}
+
+impl<D: SerialDevice> DeviceCable<D> {
+    pub fn new(device: D) -> Self {
+        Self {
+            device,
+            reply: None,
+        }
+    }
+}
+
+impl<D: SerialDevice> LinkCable for DeviceCable<D> {
+    fn send(&mut self, data: u8) {
+        self.reply = Some(self.device.exchange(data));
+    }
+
+    fn try_recv(&mut self) -> Option<u8> {
+        self.reply.take()
+    }
+}
+
+/// Wall-clock time for cartridge clocks such as the MBC3 RTC, in seconds since the Unix
+/// epoch. A fixed or scaled source makes clock-based events reproducible.
+pub trait ClockSource {
+    fn now(&self) -> i64;
+}
+
+/// The host clock.
+#[cfg(feature = "rtc")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "rtc")]
+impl ClockSource for SystemClock {
+    fn now(&self) -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+}
+
+/// A clock that stays at the given time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedClock(pub i64);
+
+impl ClockSource for FixedClock {
+    fn now(&self) -> i64 {
+        self.0
+    }
+}
+
+// Counts emulated time from `start`, so it stands still while the emulator is paused
+pub(crate) struct EmulatedClock {
+    start: i64,
+    dots: Rc<Cell<u64>>,
+}
+
+impl EmulatedClock {
+    pub fn new(start: i64) -> Self {
+        Self {
+            start,
+            dots: Rc::default(),
+        }
+    }
+
+    // Dots at CLOCK_RATE the owner adds as emulation runs
+    pub fn dots(&self) -> Rc<Cell<u64>> {
+        self.dots.clone()
+    }
+}
+
+impl ClockSource for EmulatedClock {
+    fn now(&self) -> i64 {
+        self.start + (self.dots.get() / CLOCK_RATE) as i64
+    }
+}
+
 pub struct NetworkCable {
     client_tx: Sender<u8>,
     server_rx: Receiver<u8>,
@@ -2,6 +2,8 @@ use log::warn;
 use modular_bitfield::bitfield;
 use modular_bitfield::prelude::*;
 
+use crate::state_hash::{StateHash, StateHasher};
+
 pub struct Interrupt {
     interrupt_flag: InterruptFlag,
     interrupt_enable: InterruptEnable,
@@ -52,6 +54,19 @@ impl Interrupt {
     }
 }
 
+impl StateHash for Interrupt {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Interrupt {
+            interrupt_flag,
+            interrupt_enable,
+        } = self;
+        hasher.write(&[
+            interrupt_flag.into_bytes()[0],
+            interrupt_enable.into_bytes()[0],
+        ]);
+    }
+}
+
 #[bitfield(bits = 8)]
 #[derive(Debug, Clone, Copy)]
 pub struct InterruptFlag {
@@ -1,4 +1,5 @@
 use crate::context;
+use crate::state_hash::{StateHash, StateHasher};
 use bitflags::bitflags;
 
 trait Context: context::Interrupt {}
@@ -22,6 +23,8 @@ pub struct Joypad {
     key_state: JoypadKeyState,
     direction_selected: bool,
     action_selected: bool,
+    // Keys on the lines selected at each P1 read since the last clear
+    polled_keys: Keys,
 }
 
 impl Joypad {
@@ -30,17 +33,20 @@ impl Joypad {
             key_state: JoypadKeyState::new(),
             direction_selected: false,
             action_selected: false,
+            polled_keys: Keys::empty(),
         }
     }
 
-    pub fn read(&self) -> u8 {
+    pub fn read(&mut self) -> u8 {
         let mut ret = 0xCF;
 
         if self.direction_selected {
+            self.polled_keys |= Keys::RIGHT | Keys::LEFT | Keys::UP | Keys::DOWN;
             ret &= !0x10; // ビット4を0に設定（P14選択）
             ret = (ret & 0xF0) | self.key_state.get_direction();
         }
         if self.action_selected {
+            self.polled_keys |= Keys::A | Keys::B | Keys::SELECT | Keys::START;
             ret &= !0x20; // ビット5を0に設定（P15選択）
             ret = (ret & 0xF0) | self.key_state.get_action();
         }
@@ -48,17 +54,40 @@ impl Joypad {
         ret
     }
 
+    // Whether a key on a selected line is held, without counting as a poll
+    pub fn selected_key_held(&self) -> bool {
+        (self.direction_selected && self.key_state.get_direction() != 0x0F)
+            || (self.action_selected && self.key_state.get_action() != 0x0F)
+    }
+
     pub fn write(&mut self, value: u8) {
         self.direction_selected = value & 0x10 == 0;
         self.action_selected = value & 0x20 == 0;
     }
 
+    pub fn polled_keys(&self) -> JoypadKeyState {
+        JoypadKeyState(self.polled_keys)
+    }
+
+    // Pressed keys the game saw
+    pub fn read_pressed_keys(&self) -> JoypadKeyState {
+        JoypadKeyState(self.polled_keys & self.key_state.0)
+    }
+
+    pub fn clear_polled_keys(&mut self) {
+        self.polled_keys = Keys::empty();
+    }
+
+    pub fn key_state(&self) -> JoypadKeyState {
+        self.key_state
+    }
+
     pub fn set_key(&mut self, context: &mut impl Context, key_state: JoypadKeyState) {
         let prev_key = self.key_state.0.bits();
         let cur_key = key_state.0.bits();
 
         let changed_keys = prev_key ^ cur_key;
-        let pressed_keys = changed_keys & !cur_key;
+        let pressed_keys = changed_keys & cur_key;
 
         if pressed_keys != 0 {
             context.set_interrupt_joypad(true);
@@ -68,6 +97,24 @@ impl Joypad {
     }
 }
 
+impl StateHash for Joypad {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Joypad {
+            key_state,
+            direction_selected,
+            action_selected,
+            // What the frontend has seen the game read
+            polled_keys: _,
+        } = self;
+        hasher
+            .write(&key_state.0.bits())
+            .write(direction_selected)
+            .write(action_selected);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum JoypadKey {
     Right,
     Left,
@@ -88,7 +135,24 @@ impl JoypadKeyState {
     }
 
     pub fn set_key(&mut self, key: JoypadKey, pressed: bool) {
-        let key_flag = match key {
+        let key_flag = Self::key_flag(key);
+        if pressed {
+            self.0.insert(key_flag);
+        } else {
+            self.0.remove(key_flag);
+        }
+    }
+
+    pub fn is_pressed(&self, key: JoypadKey) -> bool {
+        self.0.contains(Self::key_flag(key))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn key_flag(key: JoypadKey) -> Keys {
+        match key {
             JoypadKey::Right => Keys::RIGHT,
             JoypadKey::Left => Keys::LEFT,
             JoypadKey::Up => Keys::UP,
@@ -97,15 +161,14 @@ impl JoypadKeyState {
             JoypadKey::B => Keys::B,
             JoypadKey::Select => Keys::SELECT,
             JoypadKey::Start => Keys::START,
-        };
-
-        if pressed {
-            self.0.insert(key_flag);
-        } else {
-            self.0.remove(key_flag);
         }
     }
 
+    pub(crate) fn is_soft_reset_combo(&self) -> bool {
+        self.0
+            .contains(Keys::A | Keys::B | Keys::SELECT | Keys::START)
+    }
+
     fn get_direction(&self) -> u8 {
         (!self.0.bits()) & 0x0F
     }
@@ -1,19 +1,143 @@
+//! A Game Boy and Game Boy Color emulator core.
+//!
+//! The items re-exported at the crate root form the stable API and follow semver.
+
+pub mod ai;
 mod apu;
+#[cfg(feature = "audio-out")]
+mod audio_out;
+mod audio_ring;
+mod buffer;
 mod bus;
+#[cfg(feature = "capi")]
+pub mod capi;
 mod cartridge;
+mod cheat;
+mod compat_palette;
 mod config;
 mod context;
 mod cpu;
-pub mod gameboycolor;
+pub mod disasm;
+mod event;
+mod gameboycolor;
+pub mod input;
 mod interface;
 mod interrupt;
 mod joypad;
+mod link_tap;
+pub mod opcodes;
+mod peripherals;
+mod power;
 mod ppu;
+mod printer;
+#[cfg(feature = "python")]
+mod python;
+mod rng;
+#[cfg(feature = "std-runtime")]
+mod runtime;
 mod serial;
+mod sgb;
+mod snoop;
+mod split;
+mod state_hash;
+mod support;
+mod sync;
+#[cfg(feature = "texture-pack")]
+mod texture_pack;
+mod tile_capture;
 mod timer;
-pub mod utils;
+mod turbo_file;
+#[cfg(feature = "pixel-source")]
+mod upscale;
+#[cfg(feature = "video-recording")]
+mod video;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod watch;
+mod watchdog;
+mod wav;
 
-pub use crate::config::DeviceMode;
-pub use crate::gameboycolor::GameBoyColor;
-pub use crate::interface::{LinkCable, NetworkCable};
+pub use crate::apu::Channel;
+#[cfg(feature = "audio-out")]
+pub use crate::audio_out::{AudioOut, AudioOutError};
+pub use crate::audio_ring::AudioRing;
+pub use crate::cheat::{CheatError, PatchId, RomPatch};
+pub use crate::config::{
+    ColorCorrection, DeviceMode, EmulatorConfig, EmulatorConfigBuilder, EmulatorConfigBuilderError,
+    Model, ResetKind, UnpredictableValues,
+};
+pub use crate::context::EmulatorError;
+pub use crate::cpu::CpuState;
+pub use crate::event::{
+    DebugEvent, DmaKind, EmulatorEvent, HardwareEvent, InterruptKind, StallReason, Subsystem,
+};
+pub use crate::gameboycolor::{
+    Capabilities, FrameOutput, GameBoyColor, Screenshot, CLOCK_RATE, SCREEN_HEIGHT, SCREEN_WIDTH,
+    SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH,
+};
+#[cfg(feature = "rtc")]
+pub use crate::interface::SystemClock;
+pub use crate::interface::{
+    ClockSource, DeviceCable, FixedClock, LinkCable, NetworkCable, SerialDevice,
+};
 pub use crate::joypad::{JoypadKey, JoypadKeyState};
+pub use crate::link_tap::{analyze_link, LinkAnnotation, LinkProtocol, SerialExchange};
+pub use crate::peripherals::{
+    CameraSource, InfraredTransceiver, PrintedImage, PrinterSink, RumbleSink, CAMERA_HEIGHT,
+    CAMERA_WIDTH,
+};
+pub use crate::power::{FrameActivity, PowerAdvice, PowerAdvisor};
+pub use crate::ppu::{DebugImage, DirtyLines, OamEntry, PpuLatches, PpuMode, TileMap};
+#[cfg(feature = "pixel-source")]
+pub use crate::ppu::{PixelSource, SourceLayer};
+pub use crate::printer::GameBoyPrinter;
+#[cfg(feature = "python")]
+pub use crate::python::PyGameBoy;
+#[cfg(feature = "std-runtime")]
+pub use crate::runtime::{Command, EmulatorThread, Frame};
+pub use crate::snoop::{AccessKind, BusAccess, ObserverId, WatchpointCondition, WatchpointId};
+pub use crate::split::{Split, SplitCondition};
+pub use crate::state_hash::StateHashes;
+pub use crate::sync::{SyncAdvice, SyncAdvisor};
+#[cfg(feature = "texture-pack")]
+pub use crate::texture_pack::{TexturePack, TexturePackError};
+pub use crate::tile_capture::CapturedTile;
+pub use crate::turbo_file::TurboFile;
+#[cfg(feature = "pixel-source")]
+pub use crate::upscale::scale2x;
+#[cfg(feature = "wasm")]
+pub use crate::wasm::{WasmGameBoy, WasmKey};
+pub use crate::watch::{SramWatcher, Watch, WatchChange, WatchEncoding};
+
+/// Save files, PNG screenshots and frame comparison for frontends.
+pub mod utils {
+    pub use crate::support::{
+        diff_frames, encode_png, export_save, import_save, load_save_data, save_data, save_png,
+        DiffReport, RtcFooter, RtcFooterFormat, RtcRegisters, SaveFile, SaveFormatError, SaveSlots,
+        DEFAULT_SLOT,
+    };
+}
+
+/// Internal types must stay unreachable from the public API.
+///
+/// ```compile_fail
+/// use rust_gameboycolor::gameboycolor::GameBoyColor;
+/// ```
+///
+/// ```compile_fail
+/// use rust_gameboycolor::gameboycolor::Screenshot;
+/// ```
+///
+/// ```compile_fail
+/// use rust_gameboycolor::support::SaveSlots;
+/// ```
+///
+/// ```compile_fail
+/// use rust_gameboycolor::utils::Fnv1aHasher;
+/// ```
+///
+/// ```compile_fail
+/// use rust_gameboycolor::utils::write_png_chunk;
+/// ```
+#[cfg(doctest)]
+pub struct InternalApiDenyList;
@@ -0,0 +1,129 @@
+use std::ops::Range;
+
+// Printer packets open with these two bytes
+pub(crate) const PRINTER_MAGIC: [u8; 2] = [0x88, 0x33];
+// Magic, command, compression flag and data length come before the data
+const PRINTER_HEADER_SIZE: usize = 6;
+// Checksum, then the keep-alive and status bytes the printer answers with
+const PRINTER_TRAILER_SIZE: usize = 4;
+// Pokemon games send a run of these before every block of trade data
+const TRADE_PREAMBLE: u8 = 0xFD;
+const MIN_TRADE_PREAMBLE: usize = 3;
+
+/// A byte exchanged over the link cable, recorded while
+/// [`GameBoyColor::set_link_tap`](crate::GameBoyColor::set_link_tap) is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialExchange {
+    /// [`GameBoyColor::timestamp`](crate::GameBoyColor::timestamp) when the byte arrived.
+    pub timestamp: u64,
+    pub sent: u8,
+    pub received: u8,
+    /// SC during the transfer.
+    pub control: u8,
+}
+
+impl SerialExchange {
+    /// This Game Boy drove the clock, as the link master.
+    pub fn is_master(&self) -> bool {
+        self.control & 0x01 != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkProtocol {
+    /// Game Boy Printer packets sent by the game.
+    Printer,
+    /// The preamble-framed data blocks Pokemon games exchange in the trade center.
+    PokemonTrade,
+}
+
+/// A stretch of a link log that [`analyze_link`] recognised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkAnnotation {
+    /// Indices of the exchanges it covers.
+    pub range: Range<usize>,
+    pub protocol: LinkProtocol,
+    pub description: String,
+}
+
+/// Annotates the parts of a link log that follow a known protocol, in log order.
+/// Exchanges that match nothing are left out.
+pub fn analyze_link(log: &[SerialExchange]) -> Vec<LinkAnnotation> {
+    let sent: Vec<u8> = log.iter().map(|exchange| exchange.sent).collect();
+    let mut annotations = Vec::new();
+    let mut i = 0;
+    while i < sent.len() {
+        let annotation = printer_packet(log, &sent, i).or_else(|| trade_block(&sent, i));
+        match annotation {
+            Some(annotation) => {
+                i = annotation.range.end;
+                annotations.push(annotation);
+            }
+            None => i += 1,
+        }
+    }
+    annotations
+}
+
+fn printer_packet(log: &[SerialExchange], sent: &[u8], start: usize) -> Option<LinkAnnotation> {
+    let header = sent.get(start..start + PRINTER_HEADER_SIZE)?;
+    if header[..2] != PRINTER_MAGIC {
+        return None;
+    }
+    let (command, compressed) = (header[2], header[3] & 0x01 != 0);
+    let length = u16::from_le_bytes([header[4], header[5]]) as usize;
+    let data_end = start + PRINTER_HEADER_SIZE + length;
+    let end = data_end + PRINTER_TRAILER_SIZE;
+    if end > sent.len() {
+        return None;
+    }
+
+    let expected = sent[start + 2..data_end]
+        .iter()
+        .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    let checksum = u16::from_le_bytes([sent[data_end], sent[data_end + 1]]);
+    let name = match command {
+        0x01 => "Init".to_string(),
+        0x02 => "Print".to_string(),
+        0x04 => "Data".to_string(),
+        0x08 => "Break".to_string(),
+        0x0F => "Status".to_string(),
+        _ => format!("Command ${:02X}", command),
+    };
+    let mut description = format!("{} packet, {} bytes of data", name, length);
+    if compressed {
+        description.push_str(", compressed");
+    }
+    if checksum != expected {
+        description.push_str(", bad checksum");
+    }
+    description.push_str(&format!(", status ${:02X}", log[end - 1].received));
+    Some(LinkAnnotation {
+        range: start..end,
+        protocol: LinkProtocol::Printer,
+        description,
+    })
+}
+
+// A block runs from its preamble up to the next preamble or the end of the log
+fn trade_block(sent: &[u8], start: usize) -> Option<LinkAnnotation> {
+    let preamble = sent[start..]
+        .iter()
+        .take_while(|&&byte| byte == TRADE_PREAMBLE)
+        .count();
+    if preamble < MIN_TRADE_PREAMBLE {
+        return None;
+    }
+    let data_start = start + preamble;
+    let data_end = (data_start..sent.len())
+        .find(|&i| sent[i..].starts_with(&[TRADE_PREAMBLE; MIN_TRADE_PREAMBLE]))
+        .unwrap_or(sent.len());
+    Some(LinkAnnotation {
+        range: start..data_end,
+        protocol: LinkProtocol::PokemonTrade,
+        description: format!(
+            "Data block, {} bytes after the preamble",
+            data_end - data_start
+        ),
+    })
+}
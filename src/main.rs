@@ -1,13 +1,14 @@
 use anyhow::{Context, Result};
 use clap::Parser;
 use log::{debug, info};
+use rust_gameboycolor::input::{Bindings, Hotkey};
 use rust_gameboycolor::utils;
 use rust_gameboycolor::{
-    gameboycolor, DeviceMode, JoypadKey, JoypadKeyState, LinkCable, NetworkCable,
+    DeviceMode, EmulatorConfigBuilder, GameBoyColor, JoypadKeyState, LinkCable, NetworkCable,
+    ResetKind,
 };
 use sdl2::audio;
 use sdl2::event::{self, Event};
-use sdl2::keyboard::Keycode;
 use sdl2::libc::kevent;
 use sdl2::pixels::Color;
 use std::env;
@@ -41,6 +42,8 @@ struct Args {
     file_path: String,
     #[clap(short, long)]
     gb: bool,
+    #[clap(short, long)]
+    boot_rom: Option<String>,
 }
 
 fn main() -> Result<()> {
@@ -63,8 +66,15 @@ fn main() -> Result<()> {
     let network_cable = NetworkCable::new(listen_port, send_port);
 
     info!("DeviceMode: {:?}", device_mode);
-    let mut gameboy_color =
-        gameboycolor::GameBoyColor::new(&file, device_mode, Some(Box::new(network_cable)))?;
+    let mut builder = EmulatorConfigBuilder::default()
+        .device_mode(device_mode)
+        .link_cable(Box::new(network_cable));
+    if let Some(boot_rom_path) = args.boot_rom {
+        let boot_rom = std::fs::read(&boot_rom_path)
+            .with_context(|| format!("Failed to read boot ROM: {}", boot_rom_path))?;
+        builder = builder.boot_rom(boot_rom);
+    }
+    let mut gameboy_color = GameBoyColor::with_config(&file, builder.build()?)?;
 
     let sdl2_context = sdl2::init()
         .map_err(|e| anyhow::anyhow!(e))
@@ -116,7 +126,10 @@ fn main() -> Result<()> {
         .map_err(|e| anyhow::anyhow!(e))
         .context("Failed to get event pump")?;
 
+    let bindings = Bindings::default();
     let mut key_state = JoypadKeyState::new();
+    let mut paused = false;
+    let mut fast_forward = false;
 
     let mut reverb = Reverb::new(48_000, 400, 0.2);
     'running: loop {
@@ -127,36 +140,27 @@ fn main() -> Result<()> {
                 Event::KeyDown {
                     keycode: Some(keycode),
                     ..
-                } => match keycode {
-                    Keycode::Right => key_state.set_key(JoypadKey::Right, true),
-                    Keycode::Left => key_state.set_key(JoypadKey::Left, true),
-                    Keycode::Up => key_state.set_key(JoypadKey::Up, true),
-                    Keycode::Down => key_state.set_key(JoypadKey::Down, true),
-                    Keycode::X => key_state.set_key(JoypadKey::A, true),
-                    Keycode::Z => key_state.set_key(JoypadKey::B, true),
-                    Keycode::Space => key_state.set_key(JoypadKey::Select, true),
-                    Keycode::Return => key_state.set_key(JoypadKey::Start, true),
-                    _ => {}
+                } => match bindings.apply(&keycode.name(), true, &mut key_state) {
+                    Some(Hotkey::Pause) => paused = !paused,
+                    Some(Hotkey::Reset) => gameboy_color.reset(ResetKind::Soft),
+                    Some(Hotkey::FastForward) => fast_forward = !fast_forward,
+                    None => {}
                 },
                 Event::KeyUp {
                     keycode: Some(keycode),
                     ..
-                } => match keycode {
-                    Keycode::Right => key_state.set_key(JoypadKey::Right, false),
-                    Keycode::Left => key_state.set_key(JoypadKey::Left, false),
-                    Keycode::Up => key_state.set_key(JoypadKey::Up, false),
-                    Keycode::Down => key_state.set_key(JoypadKey::Down, false),
-                    Keycode::X => key_state.set_key(JoypadKey::A, false),
-                    Keycode::Z => key_state.set_key(JoypadKey::B, false),
-                    Keycode::Space => key_state.set_key(JoypadKey::Select, false),
-                    Keycode::Return => key_state.set_key(JoypadKey::Start, false),
-
-                    _ => {}
-                },
+                } => {
+                    bindings.apply(&keycode.name(), false, &mut key_state);
+                }
                 _ => {}
             }
         }
 
+        if paused {
+            std::thread::sleep(time::Duration::from_millis(16));
+            continue;
+        }
+
         // let start_time = time::Instant::now();
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
@@ -177,6 +181,10 @@ fn main() -> Result<()> {
         canvas.present();
 
         let audio_buffer = gameboy_color.audio_buffer();
+        // Fast forward runs unthrottled and drops the sound
+        if fast_forward {
+            continue;
+        }
         while audio_queue.size() > 1600 {
             std::thread::sleep(time::Duration::from_micros(1));
         }
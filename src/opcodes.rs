@@ -0,0 +1,223 @@
+//! Length and timing of every SM83 opcode, measured by running each one on the emulated
+//! CPU, so the table always matches what the emulator does.
+
+use crate::config::{self, DeviceMode, Speed};
+use crate::context;
+use crate::cpu::Cpu;
+use crate::event::{HardwareEvent, Subsystem};
+use crate::interrupt::{self, InterruptEnable, InterruptFlag};
+use crate::joypad::JoypadKeyState;
+
+// Where the instruction and the stack are placed, away from the addresses the other
+// registers point to
+const START: u16 = 0xC000;
+const STACK: u16 = 0xD000;
+
+/// One opcode's entry in [`table`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    /// The opcode byte, after the 0xCB for prefixed opcodes.
+    pub opcode: u8,
+    /// Follows a 0xCB prefix.
+    pub prefixed: bool,
+    /// Bytes, counting the prefix and the operands.
+    pub length: u8,
+    /// M-cycles taken. For conditional jumps, calls and returns, when not taken.
+    pub cycles: u8,
+    /// M-cycles a conditional jump, call or return takes when taken.
+    pub branch_cycles: Option<u8>,
+}
+
+/// Every unprefixed opcode the CPU implements, in order, then every opcode prefixed by
+/// 0xCB. The 11 opcodes that lock up the CPU, such as 0xD3, are left out.
+pub fn table() -> Vec<OpcodeInfo> {
+    let unprefixed = (0..=0xFF)
+        .filter(|&opcode| opcode != 0xCB)
+        .filter_map(|opcode| measure(opcode, false));
+    let prefixed = (0..=0xFF).filter_map(|opcode| measure(opcode, true));
+    unprefixed.chain(prefixed).collect()
+}
+
+/// [`table`] as CSV, one opcode per line after an `opcode,length,cycles,branch_cycles`
+/// header. Opcodes are written in hex, such as `C4` or `CB 7E`, and `branch_cycles` is
+/// empty for instructions that don't branch.
+pub fn table_csv() -> String {
+    let mut csv = "opcode,length,cycles,branch_cycles\n".to_string();
+    for info in table() {
+        let prefix = if info.prefixed { "CB " } else { "" };
+        let branch_cycles = info.branch_cycles.map_or(String::new(), |c| c.to_string());
+        csv += &format!(
+            "{}{:02X},{},{},{}\n",
+            prefix, info.opcode, info.length, info.cycles, branch_cycles
+        );
+    }
+    csv
+}
+
+// Runs the opcode with every flag clear and then every flag set, so conditional
+// instructions branch one way and then the other
+fn measure(opcode: u8, prefixed: bool) -> Option<OpcodeInfo> {
+    let code = if prefixed {
+        [0xCB, opcode, 0x00]
+    } else {
+        [opcode, 0x00, 0x00]
+    };
+    let (clear_cycles, length) = run(code, 0x00)?;
+    let (set_cycles, _) = run(code, 0xF0)?;
+    let cycles = clear_cycles.min(set_cycles);
+    let branch_cycles = clear_cycles.max(set_cycles);
+    Some(OpcodeInfo {
+        opcode,
+        prefixed,
+        length,
+        cycles,
+        branch_cycles: (branch_cycles != cycles).then_some(branch_cycles),
+    })
+}
+
+// M-cycles and bytes the instruction took, or None if it locked up the CPU
+fn run(code: [u8; 3], f: u8) -> Option<(u8, u8)> {
+    let mut probe = Probe::new(code);
+    let mut cpu = Cpu::at(START, STACK, f);
+    cpu.execute_instruction(&mut probe);
+    if cpu.lockup().is_some() {
+        return None;
+    }
+    // Jumps leave PC elsewhere, but still fetch all their operands
+    let advanced = cpu.state().pc.wrapping_sub(START);
+    let length = if advanced <= code.len() as u16 {
+        probe.fetched.max(advanced as u8)
+    } else {
+        probe.fetched
+    };
+    Some((cpu.clock() as u8, length))
+}
+
+// Flat RAM with nothing else attached
+struct Probe {
+    memory: Box<[u8; 0x10000]>,
+    interrupt: interrupt::Interrupt,
+    config: config::Config,
+    // Bytes of the instruction read so far
+    fetched: u8,
+}
+
+impl Probe {
+    fn new(code: [u8; 3]) -> Self {
+        let mut memory = Box::new([0; 0x10000]);
+        memory[START as usize..START as usize + code.len()].copy_from_slice(&code);
+        Self {
+            memory,
+            interrupt: interrupt::Interrupt::new(),
+            config: config::Config::new(DeviceMode::GameBoy, false),
+            fetched: 0,
+        }
+    }
+}
+
+impl context::Bus for Probe {
+    fn read(&mut self, address: u16) -> u8 {
+        let offset = address.wrapping_sub(START);
+        if offset < 3 {
+            self.fetched = self.fetched.max(offset as u8 + 1);
+        }
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+    }
+
+    fn cpu_stalled(&self) -> bool {
+        false
+    }
+
+    fn tick(&mut self) {}
+}
+
+impl context::Interrupt for Probe {
+    fn interrupt_enable(&self) -> InterruptEnable {
+        self.interrupt.interrupt_enable()
+    }
+
+    fn interrupt_flag(&self) -> InterruptFlag {
+        self.interrupt.interrupt_flag()
+    }
+
+    fn set_interrupt_enable(&mut self, value: u8) {
+        self.interrupt.set_interrupt_enable(value);
+    }
+
+    fn set_interrupt_flag(&mut self, value: u8) {
+        self.interrupt.set_interrupt_flag(value);
+    }
+
+    fn set_interrupt_vblank(&mut self, _value: bool) {}
+
+    fn set_interrupt_lcd(&mut self, _value: bool) {}
+
+    fn set_interrupt_timer(&mut self, _value: bool) {}
+
+    fn set_interrupt_serial(&mut self, _value: bool) {}
+
+    fn set_interrupt_joypad(&mut self, _value: bool) {}
+}
+
+impl context::Config for Probe {
+    fn device_mode(&self) -> DeviceMode {
+        self.config.device_mode()
+    }
+
+    fn gameboy_doctor(&self) -> bool {
+        false
+    }
+
+    fn set_speed_switch(&mut self, value: u8) {
+        self.config.set_speed_switch(value);
+    }
+
+    fn get_speed_switch(&self) -> u8 {
+        self.config.get_speed_switch()
+    }
+
+    fn current_speed(&self) -> Speed {
+        self.config.current_speed()
+    }
+
+    fn switch_speed(&mut self) {
+        self.config.switch_speed();
+    }
+}
+
+impl context::Event for Probe {
+    fn warn(&mut self, _subsystem: Subsystem, _detail: String) {}
+
+    fn emit(&mut self, _event: HardwareEvent) {}
+}
+
+impl context::Joypad for Probe {
+    fn joypad_read(&mut self) -> u8 {
+        0xFF
+    }
+
+    fn joypad_write(&mut self, _value: u8) {}
+
+    // No buttons held, so STOP stops
+    fn selected_key_held(&self) -> bool {
+        false
+    }
+
+    fn set_key(&mut self, _key_state: JoypadKeyState) {}
+}
+
+impl context::Timer for Probe {
+    fn timer_read(&self, _address: u16) -> u8 {
+        0
+    }
+
+    fn timer_write(&mut self, _address: u16, _value: u8) {}
+
+    fn reset_div(&mut self) {}
+
+    fn timer_tick(&mut self) {}
+}
@@ -0,0 +1,168 @@
+//! Devices outside the console that the emulated hardware drives or reads from, apart
+//! from whatever is plugged into the link port.
+
+use crate::state_hash::{StateHash, StateHasher};
+
+/// Width of the Game Boy Camera's picture.
+pub const CAMERA_WIDTH: usize = 128;
+/// Height of the Game Boy Camera's picture.
+pub const CAMERA_HEIGHT: usize = 112;
+
+/// The other side of an infrared link, seen by the CGB's port at 0xFF56 and by the
+/// HuC1 and HuC3 cartridges.
+pub trait InfraredTransceiver {
+    /// The Game Boy switched its LED on or off.
+    fn set_led(&mut self, on: bool);
+    /// Whether light from the other side reaches the Game Boy's sensor.
+    fn receiving(&self) -> bool;
+}
+
+/// The motor in an MBC5 rumble cartridge.
+pub trait RumbleSink {
+    fn set_rumble(&mut self, on: bool);
+}
+
+/// Pictures for the Game Boy Camera's sensor.
+pub trait CameraSource {
+    /// Fills `picture` with `CAMERA_WIDTH * CAMERA_HEIGHT` greyscale pixels in row-major
+    /// order, from 0 for black to 255 for white.
+    fn capture(&mut self, picture: &mut [u8]);
+}
+
+// Devices attached to the console, and the CGB infrared register that drives one of them
+#[derive(Default)]
+pub(crate) struct Peripherals {
+    infrared: Option<Box<dyn InfraredTransceiver>>,
+    rumble: Option<Box<dyn RumbleSink>>,
+    camera: Option<Box<dyn CameraSource>>,
+    // RP, 0xFF56: the LED in bit 0 and read enable in bits 6-7
+    rp: u8,
+    // Either LED lights the transceiver
+    cartridge_led: bool,
+    rumbling: bool,
+}
+
+impl Peripherals {
+    pub fn new(
+        infrared: Option<Box<dyn InfraredTransceiver>>,
+        rumble: Option<Box<dyn RumbleSink>>,
+        camera: Option<Box<dyn CameraSource>>,
+    ) -> Self {
+        Self {
+            infrared,
+            rumble,
+            camera,
+            ..Default::default()
+        }
+    }
+
+    // Devices stay attached, and the cartridge keeps driving its LED and motor
+    pub fn reset(&mut self) {
+        self.write_rp(0);
+    }
+
+    // A newly attached device starts from the current state
+    pub fn set_infrared(&mut self, mut device: Option<Box<dyn InfraredTransceiver>>) {
+        if let Some(device) = &mut device {
+            device.set_led(self.led());
+        }
+        self.infrared = device;
+    }
+
+    pub fn set_rumble(&mut self, mut device: Option<Box<dyn RumbleSink>>) {
+        if let Some(device) = &mut device {
+            device.set_rumble(self.rumbling);
+        }
+        self.rumble = device;
+    }
+
+    pub fn set_camera(&mut self, device: Option<Box<dyn CameraSource>>) {
+        self.camera = device;
+    }
+
+    pub fn read_rp(&self) -> u8 {
+        // Bit 1 reads 0 while light is received, and only with reading enabled
+        let dark = self.rp & 0xC0 != 0xC0 || !self.receiving();
+        0x3C | (self.rp & 0xC1) | (dark as u8) << 1
+    }
+
+    pub fn write_rp(&mut self, value: u8) {
+        let led = self.led();
+        self.rp = value & 0xC1;
+        self.led_changed(led);
+    }
+
+    pub fn set_cartridge_led(&mut self, on: bool) {
+        let led = self.led();
+        self.cartridge_led = on;
+        self.led_changed(led);
+    }
+
+    pub fn receiving(&self) -> bool {
+        self.infrared
+            .as_ref()
+            .is_some_and(|device| device.receiving())
+    }
+
+    pub fn set_rumbling(&mut self, on: bool) {
+        if self.rumbling == on {
+            return;
+        }
+        self.rumbling = on;
+        if let Some(device) = &mut self.rumble {
+            device.set_rumble(on);
+        }
+    }
+
+    // White without a camera attached
+    pub fn capture(&mut self) -> Vec<u8> {
+        let mut picture = vec![0xFF; CAMERA_WIDTH * CAMERA_HEIGHT];
+        if let Some(camera) = &mut self.camera {
+            camera.capture(&mut picture);
+        }
+        picture
+    }
+
+    fn led(&self) -> bool {
+        self.rp & 0x01 != 0 || self.cartridge_led
+    }
+
+    fn led_changed(&mut self, old: bool) {
+        let led = self.led();
+        if led == old {
+            return;
+        }
+        if let Some(device) = &mut self.infrared {
+            device.set_led(led);
+        }
+    }
+}
+
+impl StateHash for Peripherals {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Peripherals {
+            rp,
+            cartridge_led,
+            // Host devices and what they were last told
+            infrared: _,
+            rumble: _,
+            camera: _,
+            rumbling: _,
+        } = self;
+        hasher.write(rp).write(cartridge_led);
+    }
+}
+
+/// A picture printed by a [`GameBoyPrinter`](crate::GameBoyPrinter).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintedImage {
+    pub width: usize,
+    pub height: usize,
+    /// `width * height` shades in row-major order, from 0 for white to 3 for black.
+    pub pixels: Vec<u8>,
+}
+
+/// Receives what a [`GameBoyPrinter`](crate::GameBoyPrinter) prints.
+pub trait PrinterSink {
+    fn print(&mut self, image: &PrintedImage);
+}
@@ -0,0 +1,108 @@
+//! Tells a frontend when the game has gone idle, from what each frame did, so it can
+//! stop presenting unchanged frames and let the host sleep.
+
+// Frames in a row that must be idle before the advice changes, about a second
+const DEFAULT_IDLE_FRAMES: u32 = 60;
+
+/// What the game did during a frame, from
+/// [`GameBoyColor::frame_activity`](crate::GameBoyColor::frame_activity).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameActivity {
+    /// M-cycles the frame took.
+    pub cycles: u64,
+    /// Of those, M-cycles the CPU spent in HALT waiting for an interrupt.
+    pub halted_cycles: u64,
+    /// Some scanline differs from the frame before.
+    pub screen_changed: bool,
+    /// Every audio sample was the same.
+    pub audio_silent: bool,
+    /// The game read a pressed button.
+    pub input: bool,
+}
+
+impl FrameActivity {
+    /// The share of the frame the CPU was halted, from 0 to 1.
+    pub fn halted_fraction(&self) -> f64 {
+        if self.cycles == 0 {
+            return 0.0;
+        }
+        self.halted_cycles as f64 / self.cycles as f64
+    }
+
+    /// Nothing could be seen or heard and the CPU spent most of the frame halted, as on a
+    /// pause menu or a title screen waiting for a button.
+    pub fn is_idle(&self) -> bool {
+        !self.screen_changed && self.audio_silent && !self.input && self.halted_fraction() >= 0.5
+    }
+}
+
+/// Whether a frontend can save power, from [`PowerAdvisor::advice`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerAdvice {
+    /// Present every frame and hold any wake lock.
+    Active,
+    /// Nothing has changed for a while. Frames still have to run on time, since the
+    /// game's timers keep counting, but the frontend can skip presenting them, sleep
+    /// until the next one is due instead of spinning, and let the display dim and the
+    /// host sleep on their usual timeouts.
+    Idle,
+}
+
+/// Watches [`FrameActivity`] for frontends on battery-powered devices, advising when
+/// the game has sat idle long enough to stop inhibiting sleep.
+///
+/// Call [`record_frame`](Self::record_frame) after each frame. The advice returns to
+/// [`PowerAdvice::Active`] on the first frame that isn't idle.
+#[derive(Debug, Clone)]
+pub struct PowerAdvisor {
+    idle_after: u32,
+    idle_frames: u32,
+}
+
+impl Default for PowerAdvisor {
+    fn default() -> Self {
+        Self {
+            idle_after: DEFAULT_IDLE_FRAMES,
+            idle_frames: 0,
+        }
+    }
+}
+
+impl PowerAdvisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Idle frames in a row before the advice becomes [`PowerAdvice::Idle`]. Defaults
+    /// to 60, about a second.
+    pub fn set_idle_after(&mut self, frames: u32) {
+        self.idle_after = frames.max(1);
+    }
+
+    pub fn record_frame(&mut self, activity: &FrameActivity) {
+        if activity.is_idle() {
+            self.idle_frames = self.idle_frames.saturating_add(1);
+        } else {
+            self.idle_frames = 0;
+        }
+    }
+
+    /// Forgets the idle frames, for when the frontend wakes up for its own reasons, such
+    /// as the window regaining focus.
+    pub fn wake(&mut self) {
+        self.idle_frames = 0;
+    }
+
+    /// Idle frames in a row so far.
+    pub fn idle_frames(&self) -> u32 {
+        self.idle_frames
+    }
+
+    pub fn advice(&self) -> PowerAdvice {
+        if self.idle_frames >= self.idle_after {
+            PowerAdvice::Idle
+        } else {
+            PowerAdvice::Active
+        }
+    }
+}
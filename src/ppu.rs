@@ -1,25 +1,46 @@
-use crate::config::Speed;
+use crate::buffer::Buffer;
+use crate::compat_palette::CompatPalettes;
+use crate::config::{ColorCorrection, Speed};
 use crate::context;
+use crate::event::{HardwareEvent, Subsystem};
+use crate::state_hash::{StateHash, StateHasher};
+use crate::tile_capture::{CapturedTile, TileCapture};
 use crate::DeviceMode;
-use log::{debug, warn};
+use log::debug;
 
 use modular_bitfield::bitfield;
 use modular_bitfield::prelude::*;
 
-trait Context: context::Interrupt + context::Config {}
-impl<T> Context for T where T: context::Interrupt + context::Config {}
+trait Context: context::Interrupt + context::Config + context::Event {}
+impl<T> Context for T where T: context::Interrupt + context::Config + context::Event {}
+
+// The mode 2 STAT interrupt source goes high one M-cycle before LY increments.
+const DEFAULT_MODE2_INTERRUPT_OFFSET: u16 = 4;
+const MAX_OBJS_PER_LINE: usize = 10;
 
 #[derive(Default)]
 pub struct Ppu {
-    vram: Vec<u8>,
+    // Sized for CGB; DMG only uses bank 0
+    vram: Buffer<u8, 0x4000>,
     vram_bank: u8,
-    oam: Vec<u8>,
-    frame_buffer: Vec<(u8, u8, u8)>,
-    line_info: Vec<Option<PixelInfo>>,
+    oam: Buffer<u8, 0xA0>,
+    frame_buffer: Buffer<(u8, u8, u8), { 160 * 144 }>,
+    #[cfg(feature = "indexed-output")]
+    indexed_frame_buffer: Buffer<u8, { 160 * 144 }>,
+    // Always on the heap: inline it would overflow the stack with `static-buffers`
+    #[cfg(feature = "pixel-source")]
+    pixel_sources: Box<[PixelSource]>,
+    // The DMG shade of every pixel, kept for the Super Game Boy
+    shades: Option<Box<[u8]>>,
+    line_info: Buffer<Option<PixelInfo>, 160>,
 
     lx: u16,
     mode: PpuMode,
     prev_interrupt: bool,
+    mode2_interrupt_offset: u16,
+    sprite_limit: bool,
+    // A CGB running a DMG game, where the DMG shades pick colours from the CGB palettes
+    compatibility: bool,
 
     lcdc: Lcdc,                          // FF40
     stat: Stat,                          // FF41
@@ -32,33 +53,174 @@ pub struct Ppu {
     window_y: u8,                        // FF4A
     window_x: u8,                        // FF4B
     window_line_counter: u8,
+    // LY has matched WY this frame, so the window may be drawn
+    window_y_triggered: bool,
 
     bg_color_palette: ColorPalette,
     obj_color_palette: ColorPalette,
 
-    scan_line_obj_x: Vec<u8>,
+    scan_line_obj_x: Buffer<u8, 160>,
 
     frame: u64,
+    // Dots since power-on, which run at the same rate in both speed modes
+    dots: u64,
+    // Dots when the current frame began
+    frame_start: u64,
+    // Lines changed in the frame being drawn, and in the last completed frame
+    pending_dirty_lines: DirtyLines,
+    dirty_lines: DirtyLines,
+    tile_capture: Option<TileCapture>,
 }
 
 impl Ppu {
-    pub fn new(device_mode: DeviceMode) -> Self {
-        let vram = match device_mode {
-            DeviceMode::GameBoy => vec![0; 0x2000],
-            DeviceMode::GameBoyColor => vec![0; 0x4000],
-        };
-        let oam = vec![0; 0xA0];
-        let frame_buffer = vec![(0, 0, 0); 160 * 144];
-        let line_info = vec![None; 160];
+    pub fn new() -> Self {
         Self {
+            scan_line_obj_x: Buffer::filled(u8::MAX),
+            mode2_interrupt_offset: DEFAULT_MODE2_INTERRUPT_OFFSET,
+            sprite_limit: true,
+            #[cfg(feature = "pixel-source")]
+            pixel_sources: vec![PixelSource::default(); 160 * 144].into_boxed_slice(),
+
+            ..Default::default()
+        }
+    }
+
+    // Back to the power-on state, keeping the frontend's settings
+    pub fn reset(&mut self) {
+        let correction = self.color_correction();
+        *self = Self {
+            mode2_interrupt_offset: self.mode2_interrupt_offset,
+            sprite_limit: self.sprite_limit,
+            compatibility: self.compatibility,
+            shades: self.shades.take(),
+            tile_capture: self.tile_capture.take(),
+            ..Self::new()
+        };
+        self.set_color_correction(correction);
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        // Listing every field makes a new one fail to build until it is hashed or skipped
+        let Ppu {
             vram,
+            vram_bank,
             oam,
-            frame_buffer,
-            line_info,
+            lx,
+            mode,
+            prev_interrupt,
+            compatibility,
+            lcdc,
+            stat,
+            scy,
+            scx,
+            ly,
+            lyc,
+            bg_palette,
+            obj_palette,
+            window_y,
+            window_x,
+            window_line_counter,
+            window_y_triggered,
+            bg_color_palette,
+            obj_color_palette,
+            scan_line_obj_x,
+            frame,
+            dots,
+            // Outputs and frontend settings
+            frame_buffer: _,
+            #[cfg(feature = "indexed-output")]
+                indexed_frame_buffer: _,
+            #[cfg(feature = "pixel-source")]
+                pixel_sources: _,
+            shades: _,
+            line_info: _,
+            mode2_interrupt_offset: _,
+            sprite_limit: _,
+            frame_start: _,
+            pending_dirty_lines: _,
+            dirty_lines: _,
+            tile_capture: _,
+        } = self;
+        let mut hasher = StateHasher::default();
+        hasher
+            .write(&**vram)
+            .write(vram_bank)
+            .write(&**oam)
+            .write(lx)
+            .write(&(*mode as u8))
+            .write(prev_interrupt)
+            .write(compatibility)
+            .write(&[
+                (*lcdc).into(),
+                (*stat).into(),
+                *scy,
+                *scx,
+                *ly,
+                *lyc,
+                bg_palette.into_bytes()[0],
+                obj_palette[0].into_bytes()[0],
+                obj_palette[1].into_bytes()[0],
+                *window_y,
+                *window_x,
+            ])
+            .write(window_line_counter)
+            .write(window_y_triggered)
+            .write(&**scan_line_obj_x)
+            .write(frame)
+            .write(dots)
+            .write(bg_color_palette)
+            .write(obj_color_palette);
+        hasher.finish()
+    }
 
-            scan_line_obj_x: vec![u8::MAX; 160],
+    pub fn set_shade_output(&mut self, enabled: bool) {
+        self.shades = enabled.then(|| vec![0; 160 * 144].into_boxed_slice());
+    }
 
-            ..Default::default()
+    pub fn shades(&self) -> Option<&[u8]> {
+        self.shades.as_deref()
+    }
+
+    pub fn set_compatibility(&mut self, enabled: bool) {
+        self.compatibility = enabled;
+    }
+
+    // What the CGB boot ROM leaves in the CGB palettes for a DMG game
+    pub fn load_compat_palettes(&mut self, palettes: &CompatPalettes) {
+        self.bg_color_palette.set_palette(0, palettes.bg);
+        for (palette, colors) in palettes.obj.into_iter().enumerate() {
+            self.obj_color_palette.set_palette(palette, colors);
+        }
+    }
+
+    // LCD on with the BG tiles at 0x8000, as the boot ROM leaves it. The CGB boot ROM
+    // also clears the BG palettes to white.
+    pub fn skip_boot(&mut self, device_mode: DeviceMode) {
+        self.lcdc = Lcdc::from(0x91);
+        self.bg_palette = MonochromePalette::from_bytes([0xFC]);
+        if device_mode == DeviceMode::GameBoyColor {
+            self.bg_color_palette.fill(0xFF);
+        }
+    }
+
+    // VRAM in the selected bank and OAM, read without side effects
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0x9FFF => {
+                self.vram[self.vram_bank as usize * 0x2000 + (address - 0x8000) as usize]
+            }
+            0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    pub fn poke(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF => {
+                self.vram[self.vram_bank as usize * 0x2000 + (address - 0x8000) as usize] = value
+            }
+            0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize] = value,
+            _ => {}
         }
     }
 
@@ -73,22 +235,31 @@ impl Ppu {
             0xFF40 => self.lcdc.into(),
             0xFF41 => {
                 self.stat.set_lyc_ly_coincidence(self.ly == self.lyc);
-                self.stat.into()
+                // Bit 7 is unused and always reads as 1
+                u8::from(self.stat) | 0x80
             }
             0xFF42 => self.scy,
             0xFF43 => self.scx,
+            // Gameboy Doctor logs are recorded with LY stubbed
+            0xFF44 if context.gameboy_doctor() => 0x90,
             0xFF44 => self.ly,
             0xFF45 => self.lyc,
             // FF46 DMA transfer
             0xFF47 => {
                 if context.device_mode() == DeviceMode::GameBoyColor {
-                    warn!("Attempted to read from FF47 in CGB mode");
+                    context.warn(
+                        Subsystem::Ppu,
+                        "Attempted to read from FF47 in CGB mode".to_string(),
+                    );
                 }
                 self.bg_palette.bytes[0]
             }
             0xFF48 | 0xFF49 => {
                 if context.device_mode() == DeviceMode::GameBoyColor {
-                    warn!("Attempted to read from FF48 or FF49 in CGB mode");
+                    context.warn(
+                        Subsystem::Ppu,
+                        "Attempted to read from FF48 or FF49 in CGB mode".to_string(),
+                    );
                 }
                 self.obj_palette[(address - 0xFF48) as usize].bytes[0]
             }
@@ -98,7 +269,10 @@ impl Ppu {
                 if context.device_mode() == DeviceMode::GameBoyColor {
                     0b1111_1110 | self.vram_bank
                 } else {
-                    warn!("Attempted to read from FF4F in DMG mode");
+                    context.warn(
+                        Subsystem::Ppu,
+                        "Attempted to read from FF4F in DMG mode".to_string(),
+                    );
                     0xFF
                 }
             }
@@ -107,7 +281,10 @@ impl Ppu {
                 if context.device_mode() == DeviceMode::GameBoyColor {
                     self.bg_color_palette.read(address - 0xFF68)
                 } else {
-                    warn!("Attempted to read from FF68 or FF69 in DMG mode");
+                    context.warn(
+                        Subsystem::Ppu,
+                        "Attempted to read from FF68 or FF69 in DMG mode".to_string(),
+                    );
                     0xFF
                 }
             }
@@ -117,7 +294,10 @@ impl Ppu {
                 if context.device_mode() == DeviceMode::GameBoyColor {
                     self.obj_color_palette.read(address - 0xFF6A)
                 } else {
-                    warn!("Attempted to read from FF6A or FF6B in DMG mode");
+                    context.warn(
+                        Subsystem::Ppu,
+                        "Attempted to read from FF6A or FF6B in DMG mode".to_string(),
+                    );
                     0xFF
                 }
             }
@@ -132,6 +312,9 @@ impl Ppu {
                 let offset = (address - 0x8000) as usize;
                 let vram_addr = self.vram_bank as usize * 0x2000 + offset;
                 self.vram[vram_addr] = value;
+                if let Some(tile_capture) = &mut self.tile_capture {
+                    tile_capture.mark(vram_addr);
+                }
             }
             0xFE00..=0xFE9F => self.oam[(address - 0xFE00) as usize] = value,
             0xFF40 => {
@@ -139,7 +322,7 @@ impl Ppu {
                 if !self.lcdc.lcd_enable() && new_lcdc.lcd_enable() {
                     self.lx = 0;
                     self.ly = 0;
-                    self.frame += 1;
+                    self.finish_frame();
                 }
                 self.lcdc = new_lcdc;
             }
@@ -151,13 +334,19 @@ impl Ppu {
             // FF46 DMA transfer
             0xFF47 => {
                 if context.device_mode() == DeviceMode::GameBoyColor {
-                    warn!("Attempted to write to FF47 in CGB mode");
+                    context.warn(
+                        Subsystem::Ppu,
+                        "Attempted to write to FF47 in CGB mode".to_string(),
+                    );
                 }
                 self.bg_palette = MonochromePalette::from_bytes([value]);
             }
             0xFF48 | 0xFF49 => {
                 if context.device_mode() == DeviceMode::GameBoyColor {
-                    warn!("Attempted to write to FF48 or FF49 in CGB mode");
+                    context.warn(
+                        Subsystem::Ppu,
+                        "Attempted to write to FF48 or FF49 in CGB mode".to_string(),
+                    );
                 }
                 self.obj_palette[(address - 0xFF48) as usize] =
                     MonochromePalette::from_bytes([value]);
@@ -168,7 +357,10 @@ impl Ppu {
                 if context.device_mode() == DeviceMode::GameBoyColor {
                     self.vram_bank = value & 0x01;
                 } else {
-                    warn!("Attempted to write to FF4F in DMG mode");
+                    context.warn(
+                        Subsystem::Ppu,
+                        "Attempted to write to FF4F in DMG mode".to_string(),
+                    );
                 }
             }
             // BG Color Palette
@@ -179,7 +371,10 @@ impl Ppu {
             0xFF6A | 0xFF6B => {
                 self.obj_color_palette.write(address - 0xFF6A, value);
             }
-            _ => warn!("Invalid PPU write address: {:#06X}", address),
+            _ => context.warn(
+                Subsystem::Ppu,
+                format!("Invalid PPU write address: {:#06X}", address),
+            ),
         }
     }
 
@@ -199,10 +394,11 @@ impl Ppu {
             self.frame, self.lx, self.ly, self.mode
         );
 
+        self.dots += 1;
         self.update_lx_ly();
 
         if !self.lcdc.lcd_enable() {
-            self.mode = PpuMode::HBlank;
+            self.set_mode(PpuMode::HBlank, context);
             return;
         }
 
@@ -221,7 +417,7 @@ impl Ppu {
             self.ly += 1;
             if self.ly == 154 {
                 self.ly = 0;
-                self.frame += 1;
+                self.finish_frame();
             }
         }
     }
@@ -242,6 +438,7 @@ impl Ppu {
 
     fn set_mode(&mut self, mode: PpuMode, context: &mut impl Context) {
         if self.mode != mode {
+            context.emit(HardwareEvent::PpuModeChanged(mode));
             if mode == PpuMode::VBlank {
                 context.set_interrupt_vblank(true);
             } else if mode == PpuMode::DataTransfer {
@@ -258,36 +455,149 @@ impl Ppu {
             self.render_obj(context);
         }
 
+        let mut changed = false;
         for x in 0..160 {
-            let pixel_index = (self.ly as usize) * 160 + x as usize;
-            if self.line_info[x as usize].is_none() {
-                self.frame_buffer[pixel_index] = (0xFF, 0xFF, 0xFF);
-                continue;
+            let pixel_index = (self.ly as usize) * 160 + x;
+            let color = self.pixel_color(x);
+            changed |= self.frame_buffer[pixel_index] != color;
+            self.frame_buffer[pixel_index] = color;
+            #[cfg(feature = "indexed-output")]
+            {
+                self.indexed_frame_buffer[pixel_index] = self.pixel_palette_index(x);
             }
+            #[cfg(feature = "pixel-source")]
+            {
+                self.pixel_sources[pixel_index] = self.pixel_source(x);
+            }
+            let shade = self.pixel_shade(x);
+            if let Some(shades) = &mut self.shades {
+                shades[pixel_index] = shade;
+            }
+        }
+        if changed {
+            self.pending_dirty_lines.set(self.ly as usize);
+        }
+    }
 
-            let pixel_info = self.line_info[x as usize].unwrap();
-
-            let color = match pixel_info.layer {
-                Layer::Monochrome_Bg_Win => self.bg_palette.get_color(pixel_info.color_id),
-                Layer::Monochrome_Obj_0 => self.obj_palette[0].get_color(pixel_info.color_id),
-                Layer::Monochrome_Obj_1 => self.obj_palette[1].get_color(pixel_info.color_id),
+    fn pixel_color(&self, x: usize) -> (u8, u8, u8) {
+        match self.line_info[x] {
+            None => (0xFF, 0xFF, 0xFF),
+            Some(pixel_info) => match pixel_info.layer {
+                Layer::Monochrome_Bg_Win => self.bg_shade_color(pixel_info.color_id),
+                Layer::Monochrome_Obj_0 => self.obj_shade_color(0, pixel_info.color_id),
+                Layer::Monochrome_Obj_1 => self.obj_shade_color(1, pixel_info.color_id),
                 Layer::Color_Bg_Win => self
                     .bg_color_palette
                     .get_color(pixel_info.palette_number.unwrap(), pixel_info.color_id),
                 Layer::Color_Obj => self
                     .obj_color_palette
                     .get_color(pixel_info.palette_number.unwrap(), pixel_info.color_id),
-            };
+            },
+        }
+    }
 
-            self.frame_buffer[pixel_index] = color;
+    fn pixel_shade(&self, x: usize) -> u8 {
+        match self.line_info[x] {
+            None => 0,
+            Some(pixel_info) => match pixel_info.layer {
+                Layer::Monochrome_Obj_0 => self.obj_palette[0].shade(pixel_info.color_id),
+                Layer::Monochrome_Obj_1 => self.obj_palette[1].shade(pixel_info.color_id),
+                _ => self.bg_palette.shade(pixel_info.color_id),
+            },
+        }
+    }
+
+    fn bg_shade_color(&self, color_id: u8) -> (u8, u8, u8) {
+        let shade = self.bg_palette.shade(color_id);
+        if self.compatibility {
+            self.bg_color_palette.get_color(0, shade)
+        } else {
+            MonochromePalette::to_rgb256(shade)
+        }
+    }
+
+    fn obj_shade_color(&self, palette: usize, color_id: u8) -> (u8, u8, u8) {
+        let shade = self.obj_palette[palette].shade(color_id);
+        if self.compatibility {
+            self.obj_color_palette.get_color(palette as u8, shade)
+        } else {
+            MonochromePalette::to_rgb256(shade)
+        }
+    }
+
+    // Colour 0 never covers an object. On CGB, LCDC bit 0 clear puts every object on top,
+    // otherwise either the BG attribute or the OAM priority bit lets the background win.
+    fn bg_covers_obj(
+        &self,
+        context: &impl Context,
+        pixel_info: PixelInfo,
+        obj_attr: &ObjAttr,
+    ) -> bool {
+        if pixel_info.color_id == 0 {
+            return false;
+        }
+        match context.device_mode() {
+            DeviceMode::GameBoy => obj_attr.bg_window_priority_is_high(),
+            DeviceMode::GameBoyColor => {
+                self.lcdc.bg_and_window_enable()
+                    && (pixel_info.bg_priority || obj_attr.bg_window_priority_is_high())
+            }
+        }
+    }
+
+    // BG palettes take entries 0-31 and OBJ palettes 32-63, four colours each
+    #[cfg(feature = "indexed-output")]
+    fn pixel_palette_index(&self, x: usize) -> u8 {
+        match self.line_info[x] {
+            None => 0,
+            Some(pixel_info) => {
+                let palette = match pixel_info.layer {
+                    Layer::Monochrome_Bg_Win => 0,
+                    Layer::Monochrome_Obj_0 => 8,
+                    Layer::Monochrome_Obj_1 => 9,
+                    Layer::Color_Bg_Win => pixel_info.palette_number.unwrap(),
+                    Layer::Color_Obj => 8 + pixel_info.palette_number.unwrap(),
+                };
+                palette * 4 + pixel_info.color_id
+            }
+        }
+    }
+
+    #[cfg(feature = "pixel-source")]
+    fn pixel_source(&self, x: usize) -> PixelSource {
+        let Some(pixel_info) = self.line_info[x] else {
+            return PixelSource::default();
+        };
+        let bg_layer = if pixel_info.window {
+            SourceLayer::Window
+        } else {
+            SourceLayer::Background
+        };
+        let (layer, palette) = match pixel_info.layer {
+            Layer::Monochrome_Bg_Win => (bg_layer, 0),
+            Layer::Monochrome_Obj_0 => (SourceLayer::Object, 8),
+            Layer::Monochrome_Obj_1 => (SourceLayer::Object, 9),
+            Layer::Color_Bg_Win => (bg_layer, pixel_info.palette_number.unwrap()),
+            Layer::Color_Obj => (SourceLayer::Object, 8 + pixel_info.palette_number.unwrap()),
+        };
+        PixelSource {
+            layer,
+            tile_id: pixel_info.tile_id,
+            palette,
+            color_id: pixel_info.color_id,
+            tile_x: pixel_info.tile_x,
+            tile_y: pixel_info.tile_y,
+            x_flip: pixel_info.x_flip,
+            y_flip: pixel_info.y_flip,
         }
     }
 
     fn render_background(&mut self, context: &impl Context) {
-        let is_in_window_y = self.window_y <= self.ly;
+        // Moving WY afterwards neither hides the window again nor shows it early
         if self.ly == self.window_y {
-            self.window_line_counter = 0;
+            self.window_y_triggered = true;
         }
+        let is_in_window_y = self.window_y_triggered;
         let mut increment_window_line_counter = false;
         for x in 0..160 {
             // if !self.lcdc.bg_and_window_enable() {
@@ -359,6 +669,13 @@ impl Ppu {
                         layer: Layer::Monochrome_Bg_Win,
                         palette_number: None,
                         color_id: pixel_data_id,
+                        bg_priority: false,
+                        window: render_window,
+                        tile_id: (tile_address / 16) as u16,
+                        tile_x: pixel_x as u8,
+                        tile_y: pixel_y as u8,
+                        x_flip: cgb_map_attributes.is_x_flip(),
+                        y_flip: cgb_map_attributes.is_y_flip(),
                     });
                 }
                 DeviceMode::GameBoyColor => {
@@ -366,6 +683,13 @@ impl Ppu {
                         layer: Layer::Color_Bg_Win,
                         palette_number: Some(cgb_map_attributes.palette_number()),
                         color_id: pixel_data_id,
+                        bg_priority: cgb_map_attributes.priority(),
+                        window: render_window,
+                        tile_id: (tile_address / 16) as u16,
+                        tile_x: pixel_x as u8,
+                        tile_y: pixel_y as u8,
+                        x_flip: cgb_map_attributes.is_x_flip(),
+                        y_flip: cgb_map_attributes.is_y_flip(),
                     });
                 }
             }
@@ -375,9 +699,21 @@ impl Ppu {
         }
     }
 
-    fn render_obj(&mut self, context: &impl Context) {
-        let mut scanline_obj_count = 0;
+    // Selects the objects overlapping the current line in OAM order, as mode 2 does.
+    fn oam_scan(&self) -> ([ObjAttr; 40], usize) {
+        let obj_y_length = self.obj_y_length();
+        let limit = if self.sprite_limit {
+            MAX_OBJS_PER_LINE
+        } else {
+            40
+        };
+
+        let mut objs = [ObjAttr::new(); 40];
+        let mut count = 0;
         for i in 0..40 {
+            if count == limit {
+                break;
+            }
             let obj_attr_address = i * 4;
             let obj_attr = ObjAttr::from_bytes(
                 self.oam[obj_attr_address..obj_attr_address + 4]
@@ -385,22 +721,35 @@ impl Ppu {
                     .unwrap(),
             );
 
-            let obj_y_length = if self.lcdc.obj_size() == ObjSize::EightBySixteen {
-                16
-            } else {
-                8
-            };
-
             let upper_y = obj_attr.y().wrapping_sub(16);
             if !(upper_y..(upper_y.wrapping_add(obj_y_length))).contains(&self.ly) {
                 continue;
             }
 
-            scanline_obj_count += 1;
-            if scanline_obj_count > 10 {
-                break;
-            }
+            objs[count] = obj_attr;
+            count += 1;
+        }
+        (objs, count)
+    }
 
+    fn obj_y_length(&self) -> u8 {
+        if self.lcdc.obj_size() == ObjSize::EightBySixteen {
+            16
+        } else {
+            8
+        }
+    }
+
+    // Objects are resolved against each other before the background: the winning
+    // object pixel claims its position even when the background then covers it.
+    // On CGB the first object in OAM wins; on DMG the smallest X wins, then OAM order.
+    fn render_obj(&mut self, context: &impl Context) {
+        let obj_y_length = self.obj_y_length();
+        let (objs, count) = self.oam_scan();
+        self.scan_line_obj_x.fill(u8::MAX);
+        let mut bg_line = [None; 160];
+        bg_line.copy_from_slice(&self.line_info[..]);
+        for obj_attr in &objs[..count] {
             let offset_y = self.ly.wrapping_sub(obj_attr.y().wrapping_sub(16));
             for offset_x in 0..8 {
                 let screen_x = obj_attr.x().wrapping_sub(8).wrapping_add(offset_x);
@@ -409,13 +758,12 @@ impl Ppu {
                     continue;
                 }
 
-                if let Some(pixel_info) = self.line_info[screen_x as usize] {
-                    if obj_attr.bg_window_priority_is_high() && pixel_info.color_id != 0 {
-                        continue;
-                    }
-                }
-
-                if obj_attr.x() >= self.scan_line_obj_x[screen_x as usize] {
+                let claimed_x = self.scan_line_obj_x[screen_x as usize];
+                let outranked = match context.device_mode() {
+                    DeviceMode::GameBoy => obj_attr.x() >= claimed_x,
+                    DeviceMode::GameBoyColor => claimed_x != u8::MAX,
+                };
+                if outranked {
                     continue;
                 }
 
@@ -431,17 +779,23 @@ impl Ppu {
                     offset_y
                 };
 
-                let mut tile_address = if self.lcdc.obj_size() == ObjSize::EightBySixteen {
-                    (obj_attr.tile_number() & 0xFE) as usize * 16
+                // 8x16 objects are a pair of tiles: the even one on top, the odd one below
+                let (tile_number, tile_row) = if self.lcdc.obj_size() == ObjSize::EightBySixteen {
+                    if pixel_y < 8 {
+                        (obj_attr.tile_number() & 0xFE, pixel_y)
+                    } else {
+                        (obj_attr.tile_number() | 0x01, pixel_y - 8)
+                    }
                 } else {
-                    obj_attr.tile_number() as usize * 16
+                    (obj_attr.tile_number(), pixel_y)
                 };
 
+                let mut tile_address = tile_number as usize * 16;
                 if context.device_mode() == DeviceMode::GameBoyColor {
                     tile_address += obj_attr.cgb_bank() as usize * 0x2000;
                 }
 
-                let pixel_address = tile_address + pixel_y as usize * 2;
+                let pixel_address = tile_address + tile_row as usize * 2;
                 let pixel_data_low = (self.vram[pixel_address] >> (7 - pixel_x)) & 1;
                 let pixel_data_high = (self.vram[pixel_address + 1] >> (7 - pixel_x)) & 1;
                 let pixel_data_id = (pixel_data_high << 1) | pixel_data_low;
@@ -450,6 +804,15 @@ impl Ppu {
                     continue;
                 }
 
+                self.scan_line_obj_x[screen_x as usize] = obj_attr.x();
+                if let Some(pixel_info) = bg_line[screen_x as usize] {
+                    if self.bg_covers_obj(context, pixel_info, obj_attr) {
+                        // Undo any object this one outranked
+                        self.line_info[screen_x as usize] = Some(pixel_info);
+                        continue;
+                    }
+                }
+
                 match context.device_mode() {
                     DeviceMode::GameBoy => {
                         let layer = match obj_attr.dmg_palette_number() {
@@ -464,6 +827,13 @@ impl Ppu {
                             layer,
                             palette_number: None,
                             color_id: pixel_data_id,
+                            bg_priority: false,
+                            window: false,
+                            tile_id: (tile_address / 16) as u16,
+                            tile_x: pixel_x,
+                            tile_y: tile_row,
+                            x_flip: obj_attr.x_flip(),
+                            y_flip: obj_attr.y_flip(),
                         });
                     }
                     DeviceMode::GameBoyColor => {
@@ -471,6 +841,13 @@ impl Ppu {
                             layer: Layer::Color_Obj,
                             palette_number: Some(obj_attr.cgb_palette_number()),
                             color_id: pixel_data_id,
+                            bg_priority: false,
+                            window: false,
+                            tile_id: (tile_address / 16) as u16,
+                            tile_x: pixel_x,
+                            tile_y: tile_row,
+                            x_flip: obj_attr.x_flip(),
+                            y_flip: obj_attr.y_flip(),
                         });
                     }
                 }
@@ -485,6 +862,7 @@ impl Ppu {
             PpuMode::OamSearch => self.stat.oam_interrupt(),
             PpuMode::DataTransfer => false,
         };
+        cur_interrupt |= self.stat.oam_interrupt() && self.is_early_mode2_interrupt();
         cur_interrupt |= self.stat.lyc_ly_coincidence_interrupt() && (self.ly == self.lyc);
 
         if !self.prev_interrupt && cur_interrupt {
@@ -494,13 +872,357 @@ impl Ppu {
         self.prev_interrupt = cur_interrupt;
     }
 
+    // Whether the current dot is within the offset before the next visible line begins.
+    fn is_early_mode2_interrupt(&self) -> bool {
+        let next_line_is_visible = self.ly < 143 || self.ly == 153;
+        next_line_is_visible && self.lx + self.mode2_interrupt_offset >= 456
+    }
+
+    pub fn set_mode2_interrupt_offset(&mut self, dots: u16) {
+        self.mode2_interrupt_offset = dots.min(80);
+    }
+
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprite_limit = enabled;
+    }
+
+    pub fn sprite_limit(&self) -> bool {
+        self.sprite_limit
+    }
+
+    pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+        self.bg_color_palette.set_correction(correction);
+        self.obj_color_palette.set_correction(correction);
+    }
+
+    pub fn color_correction(&self) -> ColorCorrection {
+        self.bg_color_palette.correction
+    }
+
     pub fn frame_buffer(&self) -> &[(u8, u8, u8)] {
-        &self.frame_buffer
+        &self.frame_buffer[..]
+    }
+
+    #[cfg(feature = "pixel-source")]
+    pub fn pixel_sources(&self) -> &[PixelSource] {
+        &self.pixel_sources[..]
+    }
+
+    #[cfg(feature = "pixel-source")]
+    pub fn tile_data(&self, tile_id: u16) -> Option<[u8; 16]> {
+        // Each 512-tile bank holds 384 tiles of data followed by the two tile maps
+        if tile_id >= 1024 || tile_id % 512 >= 384 {
+            return None;
+        }
+        let address = tile_id as usize * 16;
+        Some(self.vram[address..address + 16].try_into().unwrap())
+    }
+
+    #[cfg(feature = "indexed-output")]
+    pub fn indexed_frame_buffer(&self) -> &[u8] {
+        &self.indexed_frame_buffer[..]
+    }
+
+    /// Colours for the indices in the indexed frame buffer, using the current palettes.
+    #[cfg(feature = "indexed-output")]
+    pub fn palette_table(&self, device_mode: DeviceMode) -> [(u8, u8, u8); 64] {
+        let palettes = [
+            self.bg_palettes(device_mode),
+            self.obj_palettes(device_mode),
+        ];
+        std::array::from_fn(|index| palettes[index / 32][index / 4 % 8][index % 4])
+    }
+
+    // In DMG mode BGP is palette 0 and the rest are white
+    pub fn bg_palettes(&self, device_mode: DeviceMode) -> [[(u8, u8, u8); 4]; 8] {
+        std::array::from_fn(|palette| {
+            std::array::from_fn(|color_id| match (device_mode, palette) {
+                (DeviceMode::GameBoy, 0) => self.bg_shade_color(color_id as u8),
+                (DeviceMode::GameBoy, _) => (0xFF, 0xFF, 0xFF),
+                (DeviceMode::GameBoyColor, _) => self
+                    .bg_color_palette
+                    .get_color(palette as u8, color_id as u8),
+            })
+        })
+    }
+
+    // In DMG mode OBP0 and OBP1 are palettes 0 and 1 and the rest are white
+    pub fn obj_palettes(&self, device_mode: DeviceMode) -> [[(u8, u8, u8); 4]; 8] {
+        std::array::from_fn(|palette| {
+            std::array::from_fn(|color_id| match (device_mode, palette) {
+                (DeviceMode::GameBoy, 0 | 1) => self.obj_shade_color(palette, color_id as u8),
+                (DeviceMode::GameBoy, _) => (0xFF, 0xFF, 0xFF),
+                (DeviceMode::GameBoyColor, _) => self
+                    .obj_color_palette
+                    .get_color(palette as u8, color_id as u8),
+            })
+        })
     }
 
     pub fn frame(&self) -> u64 {
         self.frame
     }
+
+    pub fn dots(&self) -> u64 {
+        self.dots
+    }
+
+    pub fn frame_dots(&self) -> u64 {
+        self.dots - self.frame_start
+    }
+
+    pub fn dirty_lines(&self) -> DirtyLines {
+        self.dirty_lines
+    }
+
+    fn finish_frame(&mut self) {
+        self.frame += 1;
+        self.frame_start = self.dots;
+        self.window_line_counter = 0;
+        self.window_y_triggered = false;
+        self.dirty_lines = std::mem::take(&mut self.pending_dirty_lines);
+        if let Some(tile_capture) = &mut self.tile_capture {
+            tile_capture.collect(&self.vram[..]);
+        }
+    }
+
+    pub fn latches(&self) -> PpuLatches {
+        PpuLatches {
+            window_line_counter: self.window_line_counter,
+            window_y_triggered: self.window_y_triggered,
+        }
+    }
+
+    pub fn set_latches(&mut self, latches: PpuLatches) {
+        self.window_line_counter = latches.window_line_counter;
+        self.window_y_triggered = latches.window_y_triggered;
+    }
+
+    pub fn set_tile_capture(&mut self, enabled: bool, device_mode: DeviceMode) {
+        let banks = match device_mode {
+            DeviceMode::GameBoy => 1,
+            DeviceMode::GameBoyColor => 2,
+        };
+        self.tile_capture = enabled.then(|| TileCapture::new(banks));
+    }
+
+    pub fn captured_tiles(&self) -> &[CapturedTile] {
+        self.tile_capture
+            .as_ref()
+            .map_or(&[], |tile_capture| tile_capture.tiles())
+    }
+
+    // BG colours as the current palettes show them
+    fn bg_color(&self, device_mode: DeviceMode, palette: u8, color_id: u8) -> (u8, u8, u8) {
+        match device_mode {
+            DeviceMode::GameBoy => self.bg_shade_color(color_id),
+            DeviceMode::GameBoyColor => self.bg_color_palette.get_color(palette, color_id),
+        }
+    }
+
+    fn tile_color_id(&self, tile_address: usize, x: usize, y: usize) -> u8 {
+        let low = (self.vram[tile_address + y * 2] >> (7 - x)) & 1;
+        let high = (self.vram[tile_address + y * 2 + 1] >> (7 - x)) & 1;
+        (high << 1) | low
+    }
+
+    // The 384 tiles of a bank, 16 to a row, in BG palette 0. Only bit 0 of the bank is
+    // used, as with VBK.
+    pub fn render_tile_data(&self, bank: u8, device_mode: DeviceMode) -> DebugImage {
+        let bank = bank & 0x01;
+        let mut image = DebugImage::new(TILE_DATA_WIDTH, TILE_DATA_HEIGHT);
+        for tile in 0..384 {
+            let tile_address = bank as usize * 0x2000 + tile * 16;
+            for y in 0..8 {
+                for x in 0..8 {
+                    let color_id = self.tile_color_id(tile_address, x, y);
+                    image.pixels[(tile / 16 * 8 + y) * TILE_DATA_WIDTH + tile % 16 * 8 + x] =
+                        self.bg_color(device_mode, 0, color_id);
+                }
+            }
+        }
+        image
+    }
+
+    // The whole 32x32 tile map, addressed as LCDC selects for the background
+    pub fn render_bg_map(
+        &self,
+        map: TileMap,
+        bank: Option<u8>,
+        device_mode: DeviceMode,
+    ) -> DebugImage {
+        let bank = bank.map(|bank| bank & 0x01);
+        let base_address = match map {
+            TileMap::Low => 0x1800,
+            TileMap::High => 0x1C00,
+        };
+        let mut image = DebugImage::new(BG_MAP_SIZE, BG_MAP_SIZE);
+        for tile_number in 0..32 * 32 {
+            let tile_map_address = base_address + tile_number;
+            let attributes = match device_mode {
+                DeviceMode::GameBoyColor => {
+                    CgbMapAttributes::from_bytes([self.vram[0x2000 + tile_map_address]])
+                }
+                DeviceMode::GameBoy => CgbMapAttributes::from_bytes([0]),
+            };
+            let tile_index = self.vram[tile_map_address] as usize;
+            let tile_address = match self.lcdc.bg_window_tile_data_select() {
+                true => tile_index * 16,
+                false => (0x1000_i16).wrapping_add((tile_index as i8 as i16) * 16) as usize,
+            } + bank.unwrap_or(attributes.is_bank() as u8) as usize * 0x2000;
+
+            let (tile_x, tile_y) = (tile_number % 32 * 8, tile_number / 32 * 8);
+            for y in 0..8 {
+                let pixel_y = if attributes.is_y_flip() { 7 - y } else { y };
+                for x in 0..8 {
+                    let pixel_x = if attributes.is_x_flip() { 7 - x } else { x };
+                    let color_id = self.tile_color_id(tile_address, pixel_x, pixel_y);
+                    image.pixels[(tile_y + y) * BG_MAP_SIZE + tile_x + x] =
+                        self.bg_color(device_mode, attributes.palette_number(), color_id);
+                }
+            }
+        }
+        image
+    }
+
+    pub fn oam_entries(&self) -> Vec<OamEntry> {
+        self.oam
+            .chunks_exact(4)
+            .map(|bytes| {
+                let obj_attr = ObjAttr::from_bytes(bytes.try_into().unwrap());
+                OamEntry {
+                    y: obj_attr.y(),
+                    x: obj_attr.x(),
+                    tile: obj_attr.tile_number(),
+                    dmg_palette: obj_attr.dmg_palette_number(),
+                    cgb_palette: obj_attr.cgb_palette_number(),
+                    bank: obj_attr.cgb_bank(),
+                    x_flip: obj_attr.x_flip(),
+                    y_flip: obj_attr.y_flip(),
+                    behind_bg: obj_attr.bg_window_priority_is_high(),
+                }
+            })
+            .collect()
+    }
+}
+
+const TILE_DATA_WIDTH: usize = 128;
+const TILE_DATA_HEIGHT: usize = 192;
+const BG_MAP_SIZE: usize = 256;
+
+/// An RGB image from one of the VRAM debug views, in row-major order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<(u8, u8, u8)>,
+}
+
+impl DebugImage {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![(0, 0, 0); width * height],
+        }
+    }
+}
+
+/// One of the two 32x32 tile maps in VRAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TileMap {
+    /// 0x9800-0x9BFF
+    Low,
+    /// 0x9C00-0x9FFF
+    High,
+}
+
+/// PPU state that no register shows, for save states and diagnosing rendering bugs.
+/// Both latches are cleared as each frame begins.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct PpuLatches {
+    /// Window line drawn next. It only advances on lines that draw the window.
+    pub window_line_counter: u8,
+    /// Whether LY has matched WY this frame, which lets the window be drawn from then on.
+    pub window_y_triggered: bool,
+}
+
+/// An object's attributes as OAM holds them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct OamEntry {
+    /// Screen position plus 16.
+    pub y: u8,
+    /// Screen position plus 8.
+    pub x: u8,
+    pub tile: u8,
+    /// OBP0 or OBP1, used in DMG mode.
+    pub dmg_palette: u8,
+    /// OBJ palette 0-7, used in CGB mode.
+    pub cgb_palette: u8,
+    /// VRAM bank of the tile, used in CGB mode.
+    pub bank: u8,
+    pub x_flip: bool,
+    pub y_flip: bool,
+    /// Whether BG colours 1-3 are drawn over the object.
+    pub behind_bg: bool,
+}
+
+/// What produced a pixel of the frame, so frontends can layer upscalers or tile
+/// replacement over the core's output.
+#[cfg(feature = "pixel-source")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PixelSource {
+    pub layer: SourceLayer,
+    /// Tile in VRAM tile data: 0-383 for bank 0 and 512-895 for bank 1. For 8x16 objects
+    /// this is the half being drawn.
+    pub tile_id: u16,
+    /// BG palettes are 0-7 and OBJ palettes 8-15. In DMG mode BGP is 0 and OBP0/OBP1 are 8 and 9.
+    pub palette: u8,
+    pub color_id: u8,
+    /// Position of the pixel within the tile data, after flipping.
+    pub tile_x: u8,
+    pub tile_y: u8,
+    pub x_flip: bool,
+    pub y_flip: bool,
+}
+
+#[cfg(feature = "pixel-source")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SourceLayer {
+    /// Nothing has been drawn since the LCD was turned on.
+    #[default]
+    Blank,
+    Background,
+    Window,
+    Object,
+}
+
+/// Bitmap of the scanlines whose pixels changed in the last completed frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirtyLines([u8; 18]);
+
+impl DirtyLines {
+    fn set(&mut self, line: usize) {
+        self.0[line / 8] |= 1 << (line % 8);
+    }
+
+    pub fn is_dirty(&self, line: usize) -> bool {
+        line < 144 && self.0[line / 8] & (1 << (line % 8)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&byte| byte == 0)
+    }
+
+    /// Line `n` is bit `n % 8` of byte `n / 8`.
+    pub fn as_bytes(&self) -> &[u8; 18] {
+        &self.0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..144).filter(|&line| self.is_dirty(line))
+    }
 }
 
 #[bitfield(bits = 8)]
@@ -559,12 +1281,12 @@ struct MonochromePalette {
 }
 
 impl MonochromePalette {
-    fn get_color(&self, index: u8) -> (u8, u8, u8) {
+    fn shade(&self, index: u8) -> u8 {
         match index {
-            0 => Self::to_rgb256(self.ID0()),
-            1 => Self::to_rgb256(self.ID1()),
-            2 => Self::to_rgb256(self.ID2()),
-            3 => Self::to_rgb256(self.ID3()),
+            0 => self.ID0(),
+            1 => self.ID1(),
+            2 => self.ID2(),
+            3 => self.ID3(),
             _ => unreachable!("Invalid color palette index: {}", index),
         }
     }
@@ -595,10 +1317,19 @@ struct ObjAttr {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(not(feature = "pixel-source"), allow(dead_code))]
 struct PixelInfo {
     layer: Layer,
     palette_number: Option<u8>,
     color_id: u8,
+    // CGB BG map attribute bit 7
+    bg_priority: bool,
+    window: bool,
+    tile_id: u16,
+    tile_x: u8,
+    tile_y: u8,
+    x_flip: bool,
+    y_flip: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -610,20 +1341,30 @@ enum Layer {
     Color_Obj,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct ColorPalette {
-    color_palette: Vec<u8>,
+    color_palette: Buffer<u8, 64>,
     color_palette_index: u8,
     enable_palette_index_auto_increment: bool,
+    // The 32 colours converted with `correction`, updated as the game writes them
+    rgb: [(u8, u8, u8); 32],
+    correction: ColorCorrection,
 }
 
-impl Default for ColorPalette {
-    fn default() -> Self {
-        Self {
-            color_palette: vec![0; 64],
-            color_palette_index: 0,
-            enable_palette_index_auto_increment: false,
-        }
+impl StateHash for ColorPalette {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let ColorPalette {
+            color_palette,
+            color_palette_index,
+            enable_palette_index_auto_increment,
+            // Derived from the palette for output
+            rgb: _,
+            correction: _,
+        } = self;
+        hasher
+            .write(&**color_palette)
+            .write(color_palette_index)
+            .write(enable_palette_index_auto_increment);
     }
 }
 
@@ -644,6 +1385,7 @@ impl ColorPalette {
             }
             1 => {
                 self.color_palette[self.color_palette_index as usize] = value;
+                self.convert(self.color_palette_index as usize / 2);
                 if self.enable_palette_index_auto_increment {
                     self.color_palette_index = (self.color_palette_index + 1) % 64;
                 }
@@ -653,23 +1395,64 @@ impl ColorPalette {
     }
 
     fn get_color(&self, palette: u8, index: u8) -> (u8, u8, u8) {
-        let color_index = (palette * 8 + index * 2) as usize;
-        let color = u16::from_le_bytes(
-            self.color_palette[color_index..color_index + 2]
-                .try_into()
-                .unwrap(),
-        );
-        Self::to_rgb256(color)
+        self.rgb[(palette * 4 + index) as usize]
+    }
+
+    fn fill(&mut self, value: u8) {
+        self.color_palette = Buffer::filled(value);
+        (0..32).for_each(|color| self.convert(color));
     }
 
-    fn to_rgb256(color: u16) -> (u8, u8, u8) {
+    fn set_palette(&mut self, palette: usize, colors: [u16; 4]) {
+        for (i, color) in colors.into_iter().enumerate() {
+            let index = palette * 4 + i;
+            self.color_palette[index * 2..index * 2 + 2].copy_from_slice(&color.to_le_bytes());
+            self.convert(index);
+        }
+    }
+
+    fn set_correction(&mut self, correction: ColorCorrection) {
+        self.correction = correction;
+        (0..32).for_each(|color| self.convert(color));
+    }
+
+    fn convert(&mut self, color: usize) {
+        let value = u16::from_le_bytes([
+            self.color_palette[color * 2],
+            self.color_palette[color * 2 + 1],
+        ]);
+        self.rgb[color] = Self::to_rgb256(value, self.correction);
+    }
+
+    fn to_rgb256(color: u16, correction: ColorCorrection) -> (u8, u8, u8) {
         let r = ((color >> 0) & 0x1F) as u8;
         let g = ((color >> 5) & 0x1F) as u8;
         let b = ((color >> 10) & 0x1F) as u8;
-        let r = r << 3 | r >> 2;
-        let g = g << 3 | g >> 2;
-        let b = b << 3 | b >> 2;
-        (r, g, b)
+        match correction {
+            ColorCorrection::Off => (r << 3 | r >> 2, g << 3 | g >> 2, b << 3 | b >> 2),
+            // The CGB screen mixes the channels and never reaches full brightness
+            ColorCorrection::CgbLcd => {
+                let (r, g, b) = (r as u32, g as u32, b as u32);
+                let mix = |value: u32| (value.min(960) * 255 / 960) as u8;
+                (
+                    mix(r * 26 + g * 4 + b * 2),
+                    mix(g * 24 + b * 8),
+                    mix(r * 6 + g * 4 + b * 22),
+                )
+            }
+            // The GBA screen is darker, so games were drawn with a steep gamma in mind
+            ColorCorrection::GbaLcd => {
+                let linear = |value: u8| (value as f32 / 31.0).powi(4);
+                let (r, g, b) = (linear(r), linear(g), linear(b));
+                let mix =
+                    |value: f32| ((value / 255.0).powf(1.0 / 2.2) * 255.0 * 255.0 / 280.0) as u8;
+                (
+                    mix(50.0 * g + 255.0 * r),
+                    mix(30.0 * b + 230.0 * g + 10.0 * r),
+                    mix(220.0 * b + 10.0 * g + 50.0 * r),
+                )
+            }
+        }
     }
 }
 
@@ -0,0 +1,194 @@
+use crate::interface::{DeviceCable, LinkCable, SerialDevice};
+use crate::link_tap::PRINTER_MAGIC;
+use crate::peripherals::{PrintedImage, PrinterSink};
+
+// Command, compression flag and data length follow the magic
+const HEADER_SIZE: usize = 4;
+const CHECKSUM_SIZE: usize = 2;
+const KEEP_ALIVE: u8 = 0x81;
+// Each band is two rows of 20 tiles, 160x16 pixels
+const BAND_SIZE: usize = 640;
+const MAX_BANDS: usize = 9;
+const PRINT_WIDTH: usize = 160;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_PRINTING: u8 = 0x02;
+const STATUS_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+
+#[derive(Debug, Clone, Copy)]
+enum Stage {
+    // Magic bytes matched so far
+    Magic(usize),
+    Packet,
+    KeepAlive,
+    Status,
+}
+
+/// The Game Boy Printer, for the link port through a [`DeviceCable`](crate::DeviceCable).
+/// Each picture the game prints goes to a [`PrinterSink`].
+///
+/// Printing finishes at once, so the game sees the printer busy for one status check.
+pub struct GameBoyPrinter {
+    sink: Box<dyn PrinterSink>,
+    stage: Stage,
+    // The packet after the magic, up to its checksum
+    packet: Vec<u8>,
+    // Tile data received since the last print
+    image: Vec<u8>,
+    status: u8,
+}
+
+impl GameBoyPrinter {
+    pub fn new(sink: Box<dyn PrinterSink>) -> Self {
+        Self {
+            sink,
+            stage: Stage::Magic(0),
+            packet: Vec::new(),
+            image: Vec::new(),
+            status: 0,
+        }
+    }
+
+    fn receive(&mut self) {
+        let (header, rest) = self.packet.split_at(HEADER_SIZE);
+        let (data, checksum) = rest.split_at(rest.len() - CHECKSUM_SIZE);
+        let expected = self.packet[..self.packet.len() - CHECKSUM_SIZE]
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        if u16::from_le_bytes([checksum[0], checksum[1]]) != expected {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        let (command, compressed) = (header[0], header[1] & 0x01 != 0);
+        match command {
+            // Init
+            0x01 => {
+                self.image.clear();
+                self.status = 0;
+            }
+            // Print: sheets, margins, palette and exposure
+            0x02 if data.len() >= 4 => {
+                let palette = data[2];
+                self.print(palette);
+            }
+            // Data, with an empty packet marking the end
+            0x04 => {
+                let mut image = std::mem::take(&mut self.image);
+                if compressed {
+                    decompress(data, &mut image);
+                } else {
+                    image.extend_from_slice(data);
+                }
+                image.truncate(BAND_SIZE * MAX_BANDS);
+                if image.len() == BAND_SIZE * MAX_BANDS {
+                    self.status |= STATUS_FULL;
+                }
+                if !image.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+                self.image = image;
+            }
+            // Break
+            0x08 => {
+                self.image.clear();
+                self.status &= !(STATUS_FULL | STATUS_UNPROCESSED);
+            }
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, palette: u8) {
+        // A palette of 0 is the usual one
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let bands = self.image.len() / BAND_SIZE;
+        let height = bands * 16;
+        let mut pixels = vec![0; PRINT_WIDTH * height];
+        for (tile, data) in self.image[..bands * BAND_SIZE].chunks_exact(16).enumerate() {
+            let (band, index) = (tile / 40, tile % 40);
+            let (tile_x, tile_y) = (index % 20, index / 20);
+            for (row, bytes) in data.chunks_exact(2).enumerate() {
+                let y = band * 16 + tile_y * 8 + row;
+                for bit in 0..8 {
+                    let color = (bytes[1] >> (7 - bit) & 1) << 1 | bytes[0] >> (7 - bit) & 1;
+                    pixels[y * PRINT_WIDTH + tile_x * 8 + bit] = palette >> (color * 2) & 0x03;
+                }
+            }
+        }
+        self.sink.print(&PrintedImage {
+            width: PRINT_WIDTH,
+            height,
+            pixels,
+        });
+        self.image.clear();
+        self.status = STATUS_PRINTING;
+    }
+}
+
+impl SerialDevice for GameBoyPrinter {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        match self.stage {
+            Stage::Magic(matched) => {
+                self.stage = if sent == PRINTER_MAGIC[matched] {
+                    match matched {
+                        0 => Stage::Magic(1),
+                        _ => {
+                            self.packet.clear();
+                            Stage::Packet
+                        }
+                    }
+                } else {
+                    Stage::Magic((sent == PRINTER_MAGIC[0]) as usize)
+                };
+                0x00
+            }
+            Stage::Packet => {
+                self.packet.push(sent);
+                if let [_, _, low, high, ..] = self.packet[..] {
+                    let length = u16::from_le_bytes([low, high]) as usize;
+                    if self.packet.len() == HEADER_SIZE + length + CHECKSUM_SIZE {
+                        self.receive();
+                        self.stage = Stage::KeepAlive;
+                    }
+                }
+                0x00
+            }
+            Stage::KeepAlive => {
+                self.stage = Stage::Status;
+                KEEP_ALIVE
+            }
+            Stage::Status => {
+                self.stage = Stage::Magic(0);
+                let status = self.status;
+                self.status &= !STATUS_PRINTING;
+                status
+            }
+        }
+    }
+}
+
+// Runs are a control byte with bit 7 set and a byte repeated (control & 0x7F) + 2
+// times; otherwise control + 1 bytes are copied as they are
+fn decompress(data: &[u8], output: &mut Vec<u8>) {
+    let mut i = 0;
+    while let Some(&control) = data.get(i) {
+        if control & 0x80 != 0 {
+            let count = (control & 0x7F) as usize + 2;
+            if let Some(&byte) = data.get(i + 1) {
+                output.extend(std::iter::repeat_n(byte, count));
+            }
+            i += 2;
+        } else {
+            let end = (i + 1 + control as usize + 1).min(data.len());
+            output.extend_from_slice(&data[i + 1..end]);
+            i = end;
+        }
+    }
+}
+
+// A printer plugged into the link port
+pub(crate) fn printer_cable(sink: Box<dyn PrinterSink>) -> Box<dyn LinkCable> {
+    Box::new(DeviceCable::new(GameBoyPrinter::new(sink)))
+}
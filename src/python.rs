@@ -0,0 +1,173 @@
+//! A PyO3 extension module for scripting and research: step frames, inject input, read
+//! and write memory and take screenshots from Python.
+
+use crate::config::{DeviceMode, ResetKind};
+use crate::gameboycolor::{GameBoyColor, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::joypad::{JoypadKey, JoypadKeyState};
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+fn parse_key(name: &str) -> PyResult<JoypadKey> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "right" => JoypadKey::Right,
+        "left" => JoypadKey::Left,
+        "up" => JoypadKey::Up,
+        "down" => JoypadKey::Down,
+        "a" => JoypadKey::A,
+        "b" => JoypadKey::B,
+        "select" => JoypadKey::Select,
+        "start" => JoypadKey::Start,
+        _ => return Err(PyValueError::new_err(format!("Unknown key: {}", name))),
+    })
+}
+
+/// A [`GameBoyColor`] exposed to Python as `rust_gameboycolor.GameBoy`.
+///
+/// Keys are named `"right"`, `"left"`, `"up"`, `"down"`, `"a"`, `"b"`, `"select"` and
+/// `"start"`, and stay held across [`step`](Self::step) calls until released.
+#[pyclass(name = "GameBoy", unsendable)]
+pub struct PyGameBoy {
+    gameboy: GameBoyColor,
+    keys: JoypadKeyState,
+}
+
+#[pymethods]
+impl PyGameBoy {
+    /// Loads `rom` as a Game Boy Color, or as a Game Boy unless `color` is set.
+    #[new]
+    #[pyo3(signature = (rom, color = true))]
+    fn new(rom: &[u8], color: bool) -> PyResult<Self> {
+        let device_mode = if color {
+            DeviceMode::GameBoyColor
+        } else {
+            DeviceMode::GameBoy
+        };
+        let gameboy = GameBoyColor::new(rom, device_mode, None)
+            .map_err(|error| PyValueError::new_err(error.to_string()))?;
+        Ok(Self {
+            gameboy,
+            keys: JoypadKeyState::new(),
+        })
+    }
+
+    #[getter]
+    fn width(&self) -> usize {
+        SCREEN_WIDTH
+    }
+
+    #[getter]
+    fn height(&self) -> usize {
+        SCREEN_HEIGHT
+    }
+
+    #[getter]
+    fn rom_name(&self) -> &str {
+        self.gameboy.rom_name()
+    }
+
+    /// Frames run so far.
+    #[getter]
+    fn frame_count(&self) -> u64 {
+        self.gameboy.frame_count()
+    }
+
+    /// Runs `frames` frames with the keys currently held.
+    #[pyo3(signature = (frames = 1))]
+    fn step(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.gameboy.run_frame(self.keys);
+        }
+    }
+
+    fn set_key(&mut self, key: &str, pressed: bool) -> PyResult<()> {
+        self.keys.set_key(parse_key(key)?, pressed);
+        Ok(())
+    }
+
+    /// Holds exactly the keys named in `keys`.
+    fn set_keys(&mut self, keys: Vec<String>) -> PyResult<()> {
+        let mut state = JoypadKeyState::new();
+        for key in keys {
+            state.set_key(parse_key(&key)?, true);
+        }
+        self.keys = state;
+        Ok(())
+    }
+
+    /// Memory as the CPU sees it, without side effects. I/O registers read as 0xFF.
+    fn read(&self, address: u16) -> u8 {
+        self.gameboy.peek(address)
+    }
+
+    /// `length` bytes from `address` on, wrapping at the end of the address space.
+    fn read_bytes<'py>(&self, py: Python<'py>, address: u16, length: usize) -> Bound<'py, PyBytes> {
+        let bytes: Vec<u8> = (0..length)
+            .map(|offset| self.gameboy.peek(address.wrapping_add(offset as u16)))
+            .collect();
+        PyBytes::new(py, &bytes)
+    }
+
+    /// Writes RAM without advancing the clock. Writes to ROM and I/O registers are
+    /// ignored.
+    fn write(&mut self, address: u16, value: u8) {
+        self.gameboy.poke(address, value);
+    }
+
+    /// The screen as `height * width * 3` bytes of RGB in row-major order.
+    fn screenshot<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        let pixels: Vec<u8> = self
+            .gameboy
+            .frame_buffer()
+            .iter()
+            .flat_map(|&(r, g, b)| [r, g, b])
+            .collect();
+        PyBytes::new(py, &pixels)
+    }
+
+    /// Runs the boot sequence again. The cartridge keeps its RAM.
+    fn reset(&mut self) {
+        self.gameboy.reset(ResetKind::Soft);
+    }
+
+    /// The battery-backed RAM, or `None` if the cartridge has none.
+    fn save_data<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyBytes>> {
+        self.gameboy.save_data().map(|save| PyBytes::new(py, &save))
+    }
+}
+
+#[pymodule]
+fn rust_gameboycolor(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyGameBoy>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A header and a program that loops forever at 0x0150
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        rom[0x0150..0x0152].copy_from_slice(&[0x18, 0xFE]);
+        rom
+    }
+
+    #[test]
+    fn runs_frames_and_refuses_a_bad_rom() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let mut gameboy = PyGameBoy::new(&rom(), true).unwrap();
+            gameboy.set_key("start", true).unwrap();
+            gameboy.step(2);
+            assert_eq!(gameboy.frame_count(), 2);
+            assert_eq!(gameboy.screenshot(py).as_bytes().len(), 160 * 144 * 3);
+
+            let error = PyGameBoy::new(&rom()[..0x100], true).err().unwrap();
+            assert!(error.is_instance_of::<PyValueError>(py));
+            let error = gameboy.set_key("turbo", true).unwrap_err();
+            assert!(error.is_instance_of::<PyValueError>(py));
+        });
+    }
+}
@@ -0,0 +1,172 @@
+use crate::context::EmulatorError;
+use crate::event::EmulatorEvent;
+use crate::gameboycolor::GameBoyColor;
+use crate::joypad::JoypadKeyState;
+
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TryRecvError, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// 70224 dots per frame at 4.194304 MHz
+const FRAME_DURATION: Duration = Duration::from_nanos(16_742_706);
+// Frames the frontend may fall behind before new ones are dropped
+const FRAME_QUEUE: usize = 2;
+
+/// Control messages for an [`EmulatorThread`].
+#[derive(Clone, Copy)]
+pub enum Command {
+    SetKeys(JoypadKeyState),
+    Pause,
+    Resume,
+    /// Runs a single frame while paused.
+    StepFrame,
+    Shutdown,
+}
+
+/// Output of one emulated frame.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Counts every frame executed, including ones dropped because the queue was full.
+    pub number: u64,
+    pub pixels: Vec<(u8, u8, u8)>,
+    pub audio: Vec<[i16; 2]>,
+    /// Events raised since the last delivered frame.
+    pub events: Vec<EmulatorEvent>,
+    /// See [`FrameOutput`](crate::FrameOutput) for these timestamps.
+    pub timestamp: u64,
+    pub audio_timestamp: u64,
+    pub overshoot: u64,
+}
+
+/// Runs the emulator on a background thread paced to the hardware frame rate.
+pub struct EmulatorThread {
+    commands: Sender<Command>,
+    frames: Receiver<Frame>,
+    handle: JoinHandle<Option<Vec<u8>>>,
+}
+
+impl EmulatorThread {
+    /// Builds the core on the new thread, since a link cable need not be `Send`.
+    pub fn spawn<F>(build: F) -> Result<Self, EmulatorError>
+    where
+        F: FnOnce() -> Result<GameBoyColor, EmulatorError> + Send + 'static,
+    {
+        let (command_tx, command_rx) = mpsc::channel();
+        let (frame_tx, frame_rx) = mpsc::sync_channel(FRAME_QUEUE);
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let handle = thread::spawn(move || match build() {
+            Ok(gameboy) => {
+                let _ = ready_tx.send(Ok(()));
+                run(gameboy, command_rx, frame_tx)
+            }
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                None
+            }
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self {
+                commands: command_tx,
+                frames: frame_rx,
+                handle,
+            }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => match handle.join() {
+                Err(panic) => std::panic::resume_unwind(panic),
+                Ok(_) => unreachable!("Emulator thread exited without reporting"),
+            },
+        }
+    }
+
+    /// Commands sent after the thread has exited are ignored.
+    pub fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    pub fn try_recv_frame(&self) -> Option<Frame> {
+        self.frames.try_recv().ok()
+    }
+
+    /// Blocks until the next frame, or returns `None` once the thread has exited.
+    pub fn recv_frame(&self) -> Option<Frame> {
+        self.frames.recv().ok()
+    }
+
+    /// Stops the thread and returns the cartridge save data.
+    pub fn shutdown(self) -> Option<Vec<u8>> {
+        self.send(Command::Shutdown);
+        drop(self.frames);
+        match self.handle.join() {
+            Ok(save_data) => save_data,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+fn run(
+    mut gameboy: GameBoyColor,
+    commands: Receiver<Command>,
+    frames: SyncSender<Frame>,
+) -> Option<Vec<u8>> {
+    let mut paused = false;
+    let mut number = 0;
+    let mut events = Vec::new();
+    let mut deadline = Instant::now();
+
+    loop {
+        let mut step = false;
+        loop {
+            let command = if paused && !step {
+                commands.recv().ok()
+            } else {
+                match commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => None,
+                }
+            };
+            match command {
+                Some(Command::SetKeys(key_state)) => gameboy.set_key(key_state),
+                Some(Command::Pause) => paused = true,
+                Some(Command::Resume) => {
+                    paused = false;
+                    deadline = Instant::now();
+                }
+                Some(Command::StepFrame) => step = true,
+                Some(Command::Shutdown) | None => return gameboy.save_data(),
+            }
+        }
+
+        let audio_timestamp = gameboy.timestamp();
+        gameboy.execute_frame();
+        number += 1;
+        events.extend(gameboy.take_events());
+        let frame = Frame {
+            number,
+            pixels: gameboy.frame_buffer().to_vec(),
+            audio: gameboy.audio_buffer().clone(),
+            events: std::mem::take(&mut events),
+            timestamp: gameboy.timestamp(),
+            audio_timestamp,
+            overshoot: gameboy.frame_overshoot(),
+        };
+        match frames.try_send(frame) {
+            Ok(()) => {}
+            // Keep the events of a dropped frame for the next one
+            Err(TrySendError::Full(frame)) => events = frame.events,
+            Err(TrySendError::Disconnected(_)) => return gameboy.save_data(),
+        }
+
+        if !paused {
+            deadline += FRAME_DURATION;
+            let now = Instant::now();
+            if deadline > now {
+                thread::sleep(deadline - now);
+            } else {
+                deadline = now;
+            }
+        }
+    }
+}
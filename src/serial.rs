@@ -1,13 +1,16 @@
 use crate::config::{DeviceMode, Speed};
 use crate::context;
+use crate::event::HardwareEvent;
 use crate::interface::LinkCable;
+use crate::link_tap::SerialExchange;
+use crate::state_hash::{StateHash, StateHasher};
 use log::debug;
 
 use modular_bitfield::bitfield;
 use modular_bitfield::prelude::*;
 
-trait Context: context::Interrupt + context::Config {}
-impl<T> Context for T where T: context::Interrupt + context::Config {}
+trait Context: context::Interrupt + context::Config + context::Event {}
+impl<T> Context for T where T: context::Interrupt + context::Config + context::Event {}
 
 #[derive(Default)]
 pub struct Serial {
@@ -15,8 +18,13 @@ pub struct Serial {
     receive_buf: Option<u8>,
     send_buf: Option<u8>,
     tick_timer: u16,
+    // Bits shifted so far by the internal clock
+    bit_counter: u8,
     sc: Sc,
     link_cable: Option<Box<dyn LinkCable>>,
+    // Counted the same way as the PPU's, so exchanges line up with GameBoyColor::timestamp
+    dots: u64,
+    tap: Option<Vec<SerialExchange>>,
 
     // For debugging
     rev_count: u16,
@@ -32,6 +40,30 @@ impl Serial {
         }
     }
 
+    pub fn link_attached(&self) -> bool {
+        self.link_cable.is_some()
+    }
+
+    pub fn set_link_cable(&mut self, link_cable: Option<Box<dyn LinkCable>>) {
+        self.link_cable = link_cable;
+    }
+
+    // Back to the power-on state, still connected to the same cable and tapped if it was
+    pub fn reset(&mut self) {
+        *self = Self {
+            tap: self.tap.take(),
+            ..Self::new(self.link_cable.take())
+        };
+    }
+
+    pub fn set_tap(&mut self, enabled: bool) {
+        self.tap = enabled.then(Vec::new);
+    }
+
+    pub fn tap_log(&self) -> &[SerialExchange] {
+        self.tap.as_deref().unwrap_or_default()
+    }
+
     pub fn read(&self, address: u16) -> u8 {
         match address {
             0xFF01 => self.buf,
@@ -50,7 +82,8 @@ impl Serial {
                 self.sc = Sc::from_bytes([value]);
                 if self.sc.transfer_requested_or_progress() && !prev_is_transfer {
                     self.send_buf = Some(self.buf);
-                    self.tick_timer = 128 * 8;
+                    self.tick_timer = 0;
+                    self.bit_counter = 0;
                 }
             }
             _ => unreachable!("Unreachable Serial write address: {:#06X}", address),
@@ -58,44 +91,104 @@ impl Serial {
     }
 
     pub fn tick(&mut self, context: &mut impl Context) {
-        if !self.sc.transfer_requested_or_progress() || self.link_cable.is_none() {
+        self.dots += match context.current_speed() {
+            Speed::Normal => 4,
+            Speed::Double => 2,
+        };
+        if !self.sc.transfer_requested_or_progress() {
             return;
         }
 
-        let link_cable = self.link_cable.as_mut().unwrap();
         match self.sc.clock_select() {
-            ClockSelect::External => {
-                let recv_val = link_cable.try_recv();
-                if recv_val.is_some() && self.send_buf.is_some() {
-                    self.buf = recv_val.unwrap();
-                    self.rev_count += 1;
-                    let send_val = self.send_buf.take().unwrap();
-                    // println!("External Serial receive: {:#04X}", recv_val.unwrap());
-                    link_cable.send(send_val);
-                    self.send_count += 1;
-
-                    self.sc.set_transfer_requested_or_progress(false);
-                    context.set_interrupt_serial(true);
-                    // println!("******************panic_counter: {}", self.panic_counter);
-                    self.panic_counter += 1;
-                }
+            ClockSelect::External => self.tick_external(context),
+            ClockSelect::Internal => self.tick_internal(context),
+        }
+    }
+
+    fn tick_external(&mut self, context: &mut impl Context) {
+        let Some(link_cable) = self.link_cable.as_mut() else {
+            return;
+        };
+        let recv_val = link_cable.try_recv();
+        if recv_val.is_some() && self.send_buf.is_some() {
+            self.rev_count += 1;
+            let send_val = self.send_buf.take().unwrap();
+            // println!("External Serial receive: {:#04X}", recv_val.unwrap());
+            link_cable.send(send_val);
+            self.send_count += 1;
+
+            self.complete_transfer(send_val, recv_val.unwrap(), context);
+            // println!("******************panic_counter: {}", self.panic_counter);
+            self.panic_counter += 1;
+        }
+    }
+
+    // As the clock master the byte goes out when the transfer starts, and the reply is
+    // latched once the eighth bit has been clocked.
+    fn tick_internal(&mut self, context: &mut impl Context) {
+        if let Some(send_val) = self.send_buf.take() {
+            if let Some(link_cable) = self.link_cable.as_mut() {
+                // println!("Internal Serial send: {:#04X}", send_val);
+                link_cable.send(send_val);
             }
-            ClockSelect::Internal => {
-                if let Some(send_val) = self.send_buf.take() {
-                    // println!("Internal Serial send: {:#04X}", send_val);
-                    link_cable.send(send_val);
-                }
+        }
 
-                if let Some(recv_val) = link_cable.try_recv().take() {
-                    self.send_count += 1;
-                    self.buf = recv_val;
-                    self.sc.set_transfer_requested_or_progress(false);
-                    context.set_interrupt_serial(true);
-                    // println!("******************panic_counter: {}", self.panic_counter);
-                    self.panic_counter += 1;
-                }
+        if self.bit_counter < 8 {
+            self.tick_timer += 1;
+            if self.tick_timer < self.get_tick_counter(context) as u16 {
+                return;
+            }
+            self.tick_timer = 0;
+            self.bit_counter += 1;
+            if self.bit_counter < 8 {
+                return;
             }
         }
+
+        // With nothing connected the line floats high; a cable may still be waiting for the peer
+        let recv_val = match self.link_cable.as_mut() {
+            Some(link_cable) => match link_cable.try_recv() {
+                Some(recv_val) => recv_val,
+                None => return,
+            },
+            None => 0xFF,
+        };
+        self.send_count += 1;
+        self.complete_transfer(self.buf, recv_val, context);
+        debug!("Serial transfer complete: {:#04X}", recv_val);
+    }
+
+    /// Shifts a full byte in from an external master, returning the byte shifted out.
+    pub(crate) fn clock_in(
+        &mut self,
+        value: u8,
+        context: &mut (impl context::Interrupt + context::Config + context::Event),
+    ) -> Option<u8> {
+        if !self.sc.transfer_requested_or_progress()
+            || self.sc.clock_select() != ClockSelect::External
+        {
+            return None;
+        }
+
+        let send_val = self.buf;
+        self.send_buf = None;
+        self.complete_transfer(send_val, value, context);
+        Some(send_val)
+    }
+
+    fn complete_transfer(&mut self, sent: u8, received: u8, context: &mut impl Context) {
+        if let Some(tap) = self.tap.as_mut() {
+            tap.push(SerialExchange {
+                timestamp: self.dots,
+                sent,
+                received,
+                control: self.sc.into(),
+            });
+        }
+        self.buf = received;
+        self.sc.set_transfer_requested_or_progress(false);
+        context.emit(HardwareEvent::SerialExchanged { sent, received });
+        context.set_interrupt_serial(true);
     }
 
     fn get_tick_counter(&self, context: &impl Context) -> u8 {
@@ -111,6 +204,34 @@ impl Serial {
     }
 }
 
+impl StateHash for Serial {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Serial {
+            buf,
+            receive_buf,
+            send_buf,
+            tick_timer,
+            bit_counter,
+            sc,
+            dots,
+            // Host side and debugging
+            link_cable: _,
+            tap: _,
+            rev_count: _,
+            send_count: _,
+            panic_counter: _,
+        } = self;
+        hasher
+            .write(buf)
+            .write(receive_buf)
+            .write(send_buf)
+            .write(tick_timer)
+            .write(bit_counter)
+            .write(&u8::from(*sc))
+            .write(dots);
+    }
+}
+
 #[bitfield(bits = 8)]
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Default)]
@@ -0,0 +1,439 @@
+use crate::gameboycolor::{SCREEN_HEIGHT, SCREEN_WIDTH, SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH};
+use crate::state_hash::{StateHash, StateHasher};
+use log::debug;
+
+const PACKET_BITS: usize = 16 * 8;
+const MAX_PACKETS: usize = 7;
+// The Game Boy screen sits in the middle of the border
+const SCREEN_LEFT: usize = (SGB_SCREEN_WIDTH - SCREEN_WIDTH) / 2;
+const SCREEN_TOP: usize = (SGB_SCREEN_HEIGHT - SCREEN_HEIGHT) / 2;
+const ATTRIBUTE_WIDTH: usize = SCREEN_WIDTH / 8;
+const ATTRIBUTE_HEIGHT: usize = SCREEN_HEIGHT / 8;
+// VRAM transfers read the first 256 tiles shown on screen
+const TRANSFER_SIZE: usize = 0x1000;
+const SYSTEM_PALETTES: usize = 512;
+const BORDER_TILES: usize = 256;
+const BORDER_MAP_WIDTH: usize = 32;
+// What the SGB shows before a game sets its own palettes
+const DEFAULT_PALETTE: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Mask {
+    #[default]
+    Off,
+    Freeze,
+    Black,
+    Color0,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    Palettes,
+    // Tiles 0x00-0x7F or 0x80-0xFF
+    BorderTiles(usize),
+    BorderMap,
+}
+
+// The SNES side of a Super Game Boy: commands sent as packets over P1, and the TV
+// picture with the border around the Game Boy screen.
+pub struct Sgb {
+    // Packets are sent a bit at a time, each bit written between releases of both lines
+    command: [u8; MAX_PACKETS * 16],
+    bits: usize,
+    ready_for_pulse: bool,
+    ready_for_write: bool,
+    ready_for_stop: bool,
+    last_p1: u8,
+
+    players: u8,
+    player: u8,
+
+    palettes: [[u16; 4]; 4],
+    system_palettes: Box<[[u16; 4]]>,
+    attributes: [u8; ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT],
+    mask: Mask,
+    // Starts with the next whole frame on screen
+    transfer: Option<(Transfer, bool)>,
+
+    border_tiles: Box<[u8]>,
+    border_map: Box<[u16]>,
+    border_palettes: [[u16; 16]; 4],
+    frame_buffer: Box<[(u8, u8, u8)]>,
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        Self {
+            command: [0; MAX_PACKETS * 16],
+            bits: 0,
+            ready_for_pulse: true,
+            ready_for_write: false,
+            ready_for_stop: false,
+            last_p1: 0x30,
+            players: 1,
+            player: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![[0; 4]; SYSTEM_PALETTES].into_boxed_slice(),
+            attributes: [0; ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT],
+            mask: Mask::Off,
+            transfer: None,
+            border_tiles: vec![0; BORDER_TILES * 32].into_boxed_slice(),
+            border_map: vec![0; BORDER_MAP_WIDTH * BORDER_MAP_WIDTH].into_boxed_slice(),
+            border_palettes: [[0; 16]; 4],
+            frame_buffer: vec![(0, 0, 0); SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT].into_boxed_slice(),
+        }
+    }
+
+    pub fn frame_buffer(&self) -> &[(u8, u8, u8)] {
+        &self.frame_buffer
+    }
+
+    // With several players, P1 reads the current controller's number while neither
+    // line is selected, and the other controllers have nothing pressed
+    pub fn joypad_read(&self, value: u8) -> u8 {
+        if self.players == 1 {
+            value
+        } else if self.last_p1 == 0x30 {
+            value & 0xF0 | (0x0F - self.player)
+        } else if self.player != 0 {
+            value | 0x0F
+        } else {
+            value
+        }
+    }
+
+    pub fn joypad_write(&mut self, value: u8) {
+        let value = value & 0x30;
+        // The next controller is selected when P15 goes high
+        if value & 0x20 != 0 && self.last_p1 & 0x20 == 0 && self.players > 1 {
+            self.player = (self.player + 1) % self.players;
+        }
+        self.last_p1 = value;
+
+        match value {
+            0x30 => self.ready_for_pulse = true,
+            // Reset pulse, which starts a packet
+            0x00 => {
+                if !self.ready_for_pulse {
+                    return;
+                }
+                self.ready_for_pulse = false;
+                self.ready_for_write = true;
+                if self.bits == 0 || self.bits % PACKET_BITS != 0 || self.ready_for_stop {
+                    self.bits = 0;
+                    self.command = [0; MAX_PACKETS * 16];
+                    self.ready_for_stop = false;
+                }
+            }
+            // P14 low sends a 0, P15 low a 1
+            _ => {
+                if !self.ready_for_pulse || !self.ready_for_write {
+                    return;
+                }
+                self.ready_for_pulse = false;
+                let bit = value == 0x10;
+                if self.ready_for_stop {
+                    // The stop bit is always 0
+                    if !bit && self.bits == self.command_size() * PACKET_BITS {
+                        self.run_command();
+                        self.bits = 0;
+                        self.command = [0; MAX_PACKETS * 16];
+                    }
+                    self.ready_for_write = false;
+                    self.ready_for_stop = false;
+                } else if self.bits < MAX_PACKETS * PACKET_BITS {
+                    self.command[self.bits / 8] |= (bit as u8) << (self.bits % 8);
+                    self.bits += 1;
+                    self.ready_for_stop = self.bits % PACKET_BITS == 0;
+                }
+            }
+        }
+    }
+
+    // In packets, from the first byte of the first one
+    fn command_size(&self) -> usize {
+        match self.command[0] & 0x07 {
+            0 => 1,
+            packets => packets as usize,
+        }
+    }
+
+    fn run_command(&mut self) {
+        let data = self.command;
+        match data[0] >> 3 {
+            0x00 => self.set_palette_pair(0, 1, &data),
+            0x01 => self.set_palette_pair(2, 3, &data),
+            0x02 => self.set_palette_pair(0, 3, &data),
+            0x03 => self.set_palette_pair(1, 2, &data),
+            0x04 => self.attribute_blocks(&data),
+            0x0A => self.set_system_palettes(&data),
+            0x0B => self.transfer = Some((Transfer::Palettes, false)),
+            0x11 => {
+                self.players = match data[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            0x13 => {
+                let half = (data[1] & 0x01) as usize;
+                self.transfer = Some((Transfer::BorderTiles(half), false));
+            }
+            0x14 => self.transfer = Some((Transfer::BorderMap, false)),
+            0x17 => {
+                self.mask = match data[1] & 0x03 {
+                    0 => Mask::Off,
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    _ => Mask::Color0,
+                }
+            }
+            command => debug!("Unsupported SGB command: {:#04X}", command),
+        }
+    }
+
+    // PAL01, PAL23, PAL03 and PAL12. Colour 0 is shared by every palette.
+    fn set_palette_pair(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |index: usize| u16::from_le_bytes([data[1 + index * 2], data[2 + index * 2]]);
+        for palette in &mut self.palettes {
+            palette[0] = color(0);
+        }
+        for i in 1..4 {
+            self.palettes[first][i] = color(i);
+            self.palettes[second][i] = color(i + 3);
+        }
+    }
+
+    // PAL_SET picks the four palettes from those sent by PAL_TRN
+    fn set_system_palettes(&mut self, data: &[u8]) {
+        for i in 0..4 {
+            let index = u16::from_le_bytes([data[1 + i * 2], data[2 + i * 2]]) as usize;
+            self.palettes[i] = self.system_palettes[index % SYSTEM_PALETTES];
+        }
+        let color0 = self.palettes[0][0];
+        for palette in &mut self.palettes {
+            palette[0] = color0;
+        }
+        if data[9] & 0x40 != 0 {
+            self.mask = Mask::Off;
+        }
+    }
+
+    // ATTR_BLK colours the inside, the edge and the outside of rectangles of tiles
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for block in data[2..].chunks_exact(6).take(count) {
+            let mut control = block[0] & 0x07;
+            let inside = block[1] & 0x03;
+            let mut edge = block[1] >> 2 & 0x03;
+            let outside = block[1] >> 4 & 0x03;
+            // Changing only the inside or the outside takes the edge along with it
+            match control {
+                0x01 => (control, edge) = (0x03, inside),
+                0x04 => (control, edge) = (0x06, outside),
+                _ => {}
+            }
+            let [left, top, right, bottom] = [2, 3, 4, 5].map(|i| (block[i] & 0x1F) as usize);
+
+            for y in 0..ATTRIBUTE_HEIGHT {
+                for x in 0..ATTRIBUTE_WIDTH {
+                    let within = (left..=right).contains(&x) && (top..=bottom).contains(&y);
+                    let on_edge = within && (x == left || x == right || y == top || y == bottom);
+                    let palette = if on_edge {
+                        (control & 0x02 != 0).then_some(edge)
+                    } else if within {
+                        (control & 0x01 != 0).then_some(inside)
+                    } else {
+                        (control & 0x04 != 0).then_some(outside)
+                    };
+                    if let Some(palette) = palette {
+                        self.attributes[y * ATTRIBUTE_WIDTH + x] = palette;
+                    }
+                }
+            }
+        }
+    }
+
+    // `shades` holds the shade of every Game Boy pixel in the frame just drawn
+    pub fn finish_frame(&mut self, shades: &[u8]) {
+        match self.transfer {
+            Some((transfer, true)) => {
+                self.receive(transfer, &Self::screen_data(shades));
+                self.transfer = None;
+            }
+            Some((transfer, false)) => self.transfer = Some((transfer, true)),
+            None => {}
+        }
+        self.draw_border();
+        self.draw_screen(shades);
+    }
+
+    // The screen read back as 2bpp tiles, left to right and then top to bottom
+    fn screen_data(shades: &[u8]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(TRANSFER_SIZE);
+        for tile in 0..TRANSFER_SIZE / 16 {
+            let (tile_x, tile_y) = (tile % ATTRIBUTE_WIDTH * 8, tile / ATTRIBUTE_WIDTH * 8);
+            for y in 0..8 {
+                let row = &shades[(tile_y + y) * SCREEN_WIDTH + tile_x..][..8];
+                let plane = |bit: u8| {
+                    row.iter()
+                        .fold(0, |byte, &shade| byte << 1 | (shade >> bit & 0x01))
+                };
+                data.push(plane(0));
+                data.push(plane(1));
+            }
+        }
+        data
+    }
+
+    fn receive(&mut self, transfer: Transfer, data: &[u8]) {
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        match transfer {
+            Transfer::Palettes => {
+                for (i, palette) in self.system_palettes.iter_mut().enumerate() {
+                    *palette = [0, 1, 2, 3].map(|color| word((i * 4 + color) * 2));
+                }
+            }
+            Transfer::BorderTiles(half) => {
+                self.border_tiles[half * TRANSFER_SIZE..][..TRANSFER_SIZE].copy_from_slice(data);
+            }
+            // The tile map, then the border's four 16-colour palettes
+            Transfer::BorderMap => {
+                for (i, entry) in self.border_map.iter_mut().enumerate() {
+                    *entry = word(i * 2);
+                }
+                for (i, palette) in self.border_palettes.iter_mut().enumerate() {
+                    *palette = std::array::from_fn(|color| word(0x800 + (i * 16 + color) * 2));
+                }
+            }
+        }
+    }
+
+    // Border tiles are SNES 4bpp: planes 0 and 1 row by row, then planes 2 and 3.
+    // Colour 0 shows the backdrop, which is the shared colour 0.
+    fn draw_border(&mut self) {
+        let backdrop = rgb(self.palettes[0][0]);
+        for y in 0..SGB_SCREEN_HEIGHT {
+            for x in 0..SGB_SCREEN_WIDTH {
+                if (SCREEN_LEFT..SCREEN_LEFT + SCREEN_WIDTH).contains(&x)
+                    && (SCREEN_TOP..SCREEN_TOP + SCREEN_HEIGHT).contains(&y)
+                {
+                    continue;
+                }
+                let entry = self.border_map[y / 8 * BORDER_MAP_WIDTH + x / 8];
+                let tile = &self.border_tiles[(entry & 0xFF) as usize * 32..][..32];
+                let row = if entry & 0x8000 != 0 {
+                    7 - y % 8
+                } else {
+                    y % 8
+                };
+                let column = if entry & 0x4000 != 0 {
+                    x % 8
+                } else {
+                    7 - x % 8
+                };
+                let color = [
+                    tile[row * 2],
+                    tile[row * 2 + 1],
+                    tile[16 + row * 2],
+                    tile[17 + row * 2],
+                ]
+                .iter()
+                .enumerate()
+                .fold(0, |color, (plane, &byte)| {
+                    color | (byte >> column & 0x01) << plane
+                });
+                let palette = (entry >> 10 & 0x03) as usize;
+                self.frame_buffer[y * SGB_SCREEN_WIDTH + x] = match color {
+                    0 => backdrop,
+                    _ => rgb(self.border_palettes[palette][color as usize]),
+                };
+            }
+        }
+    }
+
+    fn draw_screen(&mut self, shades: &[u8]) {
+        let backdrop = rgb(self.palettes[0][0]);
+        for y in 0..SCREEN_HEIGHT {
+            let line = &mut self.frame_buffer[(SCREEN_TOP + y) * SGB_SCREEN_WIDTH + SCREEN_LEFT..]
+                [..SCREEN_WIDTH];
+            match self.mask {
+                Mask::Off => {
+                    for (x, pixel) in line.iter_mut().enumerate() {
+                        let palette = self.attributes[y / 8 * ATTRIBUTE_WIDTH + x / 8] as usize;
+                        *pixel = rgb(self.palettes[palette][shades[y * SCREEN_WIDTH + x] as usize]);
+                    }
+                }
+                Mask::Freeze => {}
+                Mask::Black => line.fill((0, 0, 0)),
+                Mask::Color0 => line.fill(backdrop),
+            }
+        }
+    }
+}
+
+impl StateHash for Sgb {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Sgb {
+            command,
+            bits,
+            ready_for_pulse,
+            ready_for_write,
+            ready_for_stop,
+            last_p1,
+            players,
+            player,
+            palettes,
+            system_palettes,
+            attributes,
+            mask,
+            transfer,
+            border_tiles,
+            border_map,
+            border_palettes,
+            // Drawn from the rest
+            frame_buffer: _,
+        } = self;
+        hasher
+            .write(command)
+            .write(bits)
+            .write(ready_for_pulse)
+            .write(ready_for_write)
+            .write(ready_for_stop)
+            .write(last_p1)
+            .write(players)
+            .write(player)
+            .write(palettes)
+            .write(&**system_palettes)
+            .write(attributes)
+            .write(&(*mask as u8));
+        match *transfer {
+            Some((transfer, started)) => {
+                let (kind, tiles) = match transfer {
+                    Transfer::Palettes => (0u8, 0),
+                    Transfer::BorderTiles(tiles) => (1, tiles),
+                    Transfer::BorderMap => (2, 0),
+                };
+                hasher
+                    .write(&1u8)
+                    .write(&kind)
+                    .write(&tiles)
+                    .write(&started)
+            }
+            None => hasher.write(&0u8),
+        };
+        hasher
+            .write(&**border_tiles)
+            .write(&**border_map)
+            .write(border_palettes);
+    }
+}
+
+fn rgb(color: u16) -> (u8, u8, u8) {
+    let channel = |shift: u16| {
+        let value = (color >> shift & 0x1F) as u8;
+        value << 3 | value >> 2
+    };
+    (channel(0), channel(5), channel(10))
+}
@@ -0,0 +1,157 @@
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// A CPU read or write seen by a bus observer. `value` is the byte read or written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusAccess {
+    pub kind: AccessKind,
+    pub address: u16,
+    pub value: u8,
+}
+
+/// Returned by [`GameBoyColor::add_bus_observer`](crate::GameBoyColor::add_bus_observer)
+/// to remove the observer again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+struct Observer {
+    id: ObserverId,
+    range: RangeInclusive<u16>,
+    callback: Box<dyn FnMut(&BusAccess)>,
+}
+
+// Observers only watch, so the emulated result is the same with or without them
+#[derive(Default)]
+pub(crate) struct BusObservers {
+    observers: Vec<Observer>,
+    next_id: u64,
+}
+
+impl BusObservers {
+    pub fn add(
+        &mut self,
+        range: RangeInclusive<u16>,
+        callback: Box<dyn FnMut(&BusAccess)>,
+    ) -> ObserverId {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.observers.push(Observer {
+            id,
+            range,
+            callback,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: ObserverId) -> bool {
+        let len = self.observers.len();
+        self.observers.retain(|observer| observer.id != id);
+        self.observers.len() != len
+    }
+
+    pub fn notify(&mut self, kind: AccessKind, address: u16, value: u8) {
+        let access = BusAccess {
+            kind,
+            address,
+            value,
+        };
+        for observer in &mut self.observers {
+            if observer.range.contains(&address) {
+                (observer.callback)(&access);
+            }
+        }
+    }
+}
+
+/// What a watchpoint added with
+/// [`GameBoyColor::add_watchpoint`](crate::GameBoyColor::add_watchpoint) stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchpointCondition {
+    Read,
+    Write,
+    /// A write of a different value than the CPU would have read there just before.
+    Change,
+}
+
+/// Returned by [`GameBoyColor::add_watchpoint`](crate::GameBoyColor::add_watchpoint)
+/// to remove the watchpoint again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchpointId(u64);
+
+struct Watchpoint {
+    id: WatchpointId,
+    range: RangeInclusive<u16>,
+    condition: WatchpointCondition,
+}
+
+impl Watchpoint {
+    fn applies_to(&self, kind: AccessKind, address: u16) -> bool {
+        let condition = match self.condition {
+            WatchpointCondition::Read => AccessKind::Read,
+            WatchpointCondition::Write | WatchpointCondition::Change => AccessKind::Write,
+        };
+        condition == kind && self.range.contains(&address)
+    }
+}
+
+// The first access that met a watchpoint: address, old value and new value
+pub(crate) type WatchpointHit = (u16, u8, u8);
+
+#[derive(Default)]
+pub(crate) struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    next_id: u64,
+    hit: Option<WatchpointHit>,
+}
+
+impl Watchpoints {
+    pub fn add(
+        &mut self,
+        range: RangeInclusive<u16>,
+        condition: WatchpointCondition,
+    ) -> WatchpointId {
+        let id = WatchpointId(self.next_id);
+        self.next_id += 1;
+        self.watchpoints.push(Watchpoint {
+            id,
+            range,
+            condition,
+        });
+        id
+    }
+
+    pub fn remove(&mut self, id: WatchpointId) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.id != id);
+        self.watchpoints.len() != len
+    }
+
+    // Whether an access needs checking, so the old value is only looked up when it does
+    pub fn watches(&self, kind: AccessKind, address: u16) -> bool {
+        self.watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.applies_to(kind, address))
+    }
+
+    pub fn check(&mut self, kind: AccessKind, address: u16, old: u8, new: u8) {
+        if self.hit.is_some() {
+            return;
+        }
+        let hit = self.watchpoints.iter().any(|watchpoint| {
+            watchpoint.applies_to(kind, address)
+                && (watchpoint.condition != WatchpointCondition::Change || old != new)
+        });
+        if hit {
+            self.hit = Some((address, old, new));
+        }
+    }
+
+    pub fn take_hit(&mut self) -> Option<WatchpointHit> {
+        self.hit.take()
+    }
+}
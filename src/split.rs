@@ -0,0 +1,72 @@
+use crate::context::Context;
+use crate::event::EmulatorEvent;
+
+/// What a [`Split`] checks its byte of memory for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitCondition {
+    Equals(u8),
+    AtLeast(u8),
+    /// Every bit of the mask is set.
+    BitsSet(u8),
+    /// The byte differs from the end of the previous frame.
+    Changed,
+}
+
+/// A named memory condition for speedrun timers such as LiveSplit. It is checked at the
+/// end of every frame and raises [`EmulatorEvent::Split`] on the frame it starts to hold,
+/// so a condition that already holds when the split is added waits until it changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Split {
+    pub name: String,
+    pub address: u16,
+    pub condition: SplitCondition,
+}
+
+impl Split {
+    pub fn new(name: &str, address: u16, condition: SplitCondition) -> Self {
+        Self {
+            name: name.to_string(),
+            address,
+            condition,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Splits {
+    // Each split with the byte it saw last frame
+    splits: Vec<(Split, Option<u8>)>,
+}
+
+impl Splits {
+    pub fn add(&mut self, split: Split) {
+        self.splits.push((split, None));
+    }
+
+    pub fn clear(&mut self) {
+        self.splits.clear();
+    }
+
+    pub fn check(&mut self, context: &mut Context, frame: u64) {
+        for (split, last) in &mut self.splits {
+            let value = context.peek(split.address);
+            let holds = |value: u8| match split.condition {
+                SplitCondition::Equals(expected) => value == expected,
+                SplitCondition::AtLeast(minimum) => value >= minimum,
+                SplitCondition::BitsSet(mask) => value & mask == mask,
+                SplitCondition::Changed => false,
+            };
+            let started = match split.condition {
+                SplitCondition::Changed => last.is_some_and(|last| last != value),
+                _ => last.is_some_and(|last| !holds(last)) && holds(value),
+            };
+            if started {
+                context.push_event(EmulatorEvent::Split {
+                    name: split.name.clone(),
+                    frame,
+                });
+            }
+            *last = Some(value);
+        }
+    }
+}
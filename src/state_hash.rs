@@ -0,0 +1,127 @@
+use crate::buffer::Buffer;
+use crate::support::Fnv1aHasher;
+
+use std::hash::Hasher;
+
+// Fields are written little-endian and lengths as u64, so a hash is the same on every
+// platform. Only state that decides what the console does next goes in: frontend
+// settings and outputs such as the frame buffer are left out.
+#[derive(Default)]
+pub(crate) struct StateHasher(Fnv1aHasher);
+
+impl StateHasher {
+    pub fn write(&mut self, value: &(impl StateHash + ?Sized)) -> &mut Self {
+        value.hash_state(self);
+        self
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+pub(crate) trait StateHash {
+    fn hash_state(&self, hasher: &mut StateHasher);
+}
+
+macro_rules! impl_state_hash_for_int {
+    ($($ty:ty),*) => {
+        $(
+            impl StateHash for $ty {
+                fn hash_state(&self, hasher: &mut StateHasher) {
+                    hasher.0.write(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_state_hash_for_int!(u8, u16, u32, u64, i32, i64);
+
+impl StateHash for usize {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        (*self as u64).hash_state(hasher);
+    }
+}
+
+impl StateHash for bool {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        (*self as u8).hash_state(hasher);
+    }
+}
+
+impl<T: StateHash> StateHash for [T] {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        self.len().hash_state(hasher);
+        for value in self {
+            value.hash_state(hasher);
+        }
+    }
+}
+
+impl<T: StateHash, const N: usize> StateHash for [T; N] {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        self[..].hash_state(hasher);
+    }
+}
+
+impl<T: StateHash> StateHash for Vec<T> {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        self[..].hash_state(hasher);
+    }
+}
+
+impl<T: StateHash, const N: usize> StateHash for Buffer<T, N> {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        self[..].hash_state(hasher);
+    }
+}
+
+impl<T: StateHash> StateHash for Option<T> {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        match self {
+            Some(value) => hasher.write(&1u8).write(value),
+            None => hasher.write(&0u8),
+        };
+    }
+}
+
+/// Hashes of each part of the console's state, from
+/// [`GameBoyColor::state_hashes`](crate::GameBoyColor::state_hashes).
+///
+/// Two consoles that ran the same ROM with the same inputs have equal hashes, on any
+/// platform. Comparing them part by part narrows down where two runs diverged, such as
+/// two netplay peers that fell out of sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StateHashes {
+    /// Registers, IME, HALT and STOP, the cycle count, and any overclocked cycles still
+    /// to run.
+    pub cpu: u64,
+    /// VRAM, OAM, the LCD registers and palettes, and where the PPU is in the frame.
+    pub ppu: u64,
+    /// The channels, frame sequencer and wave RAM.
+    pub apu: u64,
+    /// WRAM, HRAM, the DMA transfers, and the generator behind unpredictable values.
+    pub ram: u64,
+    /// The mapper's registers, cartridge RAM and the real-time clock.
+    pub cartridge: u64,
+    /// The timer, interrupts, joypad, serial and infrared ports, the speed switch, and a
+    /// Super Game Boy's packets, palettes, attributes, border and selected player.
+    pub io: u64,
+}
+
+impl StateHashes {
+    /// All the hashes combined into one, as returned by
+    /// [`GameBoyColor::state_hash`](crate::GameBoyColor::state_hash).
+    pub fn combined(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+        hasher
+            .write(&self.cpu)
+            .write(&self.ppu)
+            .write(&self.apu)
+            .write(&self.ram)
+            .write(&self.cartridge)
+            .write(&self.io);
+        hasher.finish()
+    }
+}
@@ -0,0 +1,432 @@
+use crate::gameboycolor::SCREEN_WIDTH;
+use dirs::data_dir;
+use log::info;
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub const DEFAULT_SLOT: &str = "default";
+
+/// Writes the save for `rom_name` to its [`DEFAULT_SLOT`] in the application data
+/// directory.
+pub fn save_data(rom_name: &str, sram_data: &[u8]) -> Result<(), io::Error> {
+    SaveSlots::in_data_dir(rom_name)?.save(DEFAULT_SLOT, sram_data)
+}
+
+/// Reads the save for `rom_name` from its [`DEFAULT_SLOT`] in the application data
+/// directory, or `None` if there is none yet.
+pub fn load_save_data(rom_name: &str) -> Result<Option<Vec<u8>>, io::Error> {
+    SaveSlots::in_data_dir(rom_name)?.load(DEFAULT_SLOT)
+}
+
+/// Named saves for one game, kept as `<root>/<ROM title>/<slot>.srm`.
+///
+/// Opening the slots moves a save in the older single-file layout, `<root>/<ROM
+/// title>.srm`, into [`DEFAULT_SLOT`].
+#[derive(Debug, Clone)]
+pub struct SaveSlots {
+    dir: PathBuf,
+}
+
+impl SaveSlots {
+    pub fn open(root: impl AsRef<Path>, rom_name: &str) -> Result<Self, io::Error> {
+        let root = root.as_ref();
+        let slots = Self {
+            dir: root.join(rom_name),
+        };
+        let legacy = root.join(format!("{}.srm", rom_name));
+        if legacy.is_file() && !slots.path(DEFAULT_SLOT)?.exists() {
+            info!("Moving {:?} into the {:?} slot", legacy, DEFAULT_SLOT);
+            fs::create_dir_all(&slots.dir)?;
+            fs::rename(&legacy, slots.path(DEFAULT_SLOT)?)?;
+        }
+        Ok(slots)
+    }
+
+    /// Opens the slots in the application data directory.
+    pub fn in_data_dir(rom_name: &str) -> Result<Self, io::Error> {
+        let root = data_dir()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "Failed to find the application data directory",
+                )
+            })?
+            .join("rust-gameboycolor");
+        Self::open(root, rom_name)
+    }
+
+    /// Names of the slots holding a save, in alphabetical order.
+    pub fn list(&self) -> Result<Vec<String>, io::Error> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut slots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "srm") {
+                if let Some(slot) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    slots.push(slot.to_string());
+                }
+            }
+        }
+        slots.sort();
+        Ok(slots)
+    }
+
+    pub fn load(&self, slot: &str) -> Result<Option<Vec<u8>>, io::Error> {
+        let path = self.path(slot)?;
+        info!("Loading save data from {:?}", path);
+        match fs::read(&path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, slot: &str, sram_data: &[u8]) -> Result<(), io::Error> {
+        let path = self.path(slot)?;
+        fs::create_dir_all(&self.dir)?;
+        info!("Saving data to {:?}", path);
+        fs::write(&path, sram_data)
+    }
+
+    /// Overwrites `to` with the save in `from`. Fails with `NotFound` if `from` is empty.
+    pub fn copy(&self, from: &str, to: &str) -> Result<(), io::Error> {
+        fs::copy(self.path(from)?, self.path(to)?)?;
+        Ok(())
+    }
+
+    /// Returns whether the slot held a save.
+    pub fn delete(&self, slot: &str) -> Result<bool, io::Error> {
+        match fs::remove_file(self.path(slot)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Slot names become file names, so they cannot leave the directory
+    fn path(&self, slot: &str) -> Result<PathBuf, io::Error> {
+        let valid = !slot.is_empty()
+            && !slot.starts_with('.')
+            && !slot.contains(['/', '\\', ':'])
+            && !slot.chars().any(char::is_control);
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid save slot name: {:?}", slot),
+            ));
+        }
+        Ok(self.dir.join(format!("{}.srm", slot)))
+    }
+}
+
+// FNV-1a, used where a hash has to be stable across platforms and releases.
+pub(crate) struct Fnv1aHasher(u64);
+
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Self(0xCBF2_9CE4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01B3);
+        }
+    }
+}
+
+pub fn encode_png(width: usize, height: usize, pixels: &[(u8, u8, u8)]) -> Vec<u8> {
+    assert_eq!(pixels.len(), width * height, "Invalid pixel count");
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_png_chunk(&mut png, b"IHDR", &png_header(width, height));
+    write_png_chunk(&mut png, b"IDAT", &png_image_data(width, pixels));
+    write_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub(crate) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// IHDR contents for an 8-bit RGB image
+pub(crate) fn png_header(width: usize, height: usize) -> Vec<u8> {
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB, no interlace
+    ihdr
+}
+
+// The zlib stream for IDAT, left uncompressed
+pub(crate) fn png_image_data(width: usize, pixels: &[(u8, u8, u8)]) -> Vec<u8> {
+    // Every scanline starts with filter type 0 (None)
+    let mut raw = Vec::with_capacity(pixels.len() / width * (width * 3 + 1));
+    for line in pixels.chunks(width) {
+        raw.push(0);
+        for &(r, g, b) in line {
+            raw.extend_from_slice(&[r, g, b]);
+        }
+    }
+
+    // zlib stream made of uncompressed deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+        zlib.push(is_final as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+    zlib
+}
+
+pub fn save_png(
+    path: impl AsRef<Path>,
+    width: usize,
+    height: usize,
+    pixels: &[(u8, u8, u8)],
+) -> Result<(), io::Error> {
+    fs::write(path, encode_png(width, height, pixels))
+}
+
+pub(crate) fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+/// Result of [`diff_frames`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffReport {
+    pub mismatched_pixels: usize,
+    /// Position `(x, y)` of the first differing pixel in row-major order.
+    pub first_mismatch: Option<(usize, usize)>,
+    /// Largest difference of a single color channel.
+    pub max_channel_delta: u8,
+    /// The first frame dimmed with differing pixels in red, only when the frames differ.
+    /// It can be written out with [`save_png`].
+    pub image: Option<Vec<(u8, u8, u8)>>,
+}
+
+impl DiffReport {
+    pub fn is_identical(&self) -> bool {
+        self.mismatched_pixels == 0
+    }
+}
+
+/// Compares two frames from [`GameBoyColor::frame_buffer`](crate::GameBoyColor::frame_buffer)
+/// pixel by pixel.
+pub fn diff_frames(a: &[(u8, u8, u8)], b: &[(u8, u8, u8)]) -> DiffReport {
+    assert_eq!(a.len(), b.len(), "Frames differ in size");
+
+    let mut report = DiffReport {
+        mismatched_pixels: 0,
+        first_mismatch: None,
+        max_channel_delta: 0,
+        image: None,
+    };
+    for (i, (&(r1, g1, b1), &(r2, g2, b2))) in a.iter().zip(b).enumerate() {
+        let delta = r1.abs_diff(r2).max(g1.abs_diff(g2)).max(b1.abs_diff(b2));
+        if delta == 0 {
+            continue;
+        }
+        report.mismatched_pixels += 1;
+        report.max_channel_delta = report.max_channel_delta.max(delta);
+        report
+            .first_mismatch
+            .get_or_insert((i % SCREEN_WIDTH, i / SCREEN_WIDTH));
+    }
+
+    if !report.is_identical() {
+        let image = a
+            .iter()
+            .zip(b)
+            .map(|(&pa, &pb)| {
+                if pa == pb {
+                    // Keep the picture recognizable without competing with the red
+                    let luma = (pa.0 as u16 * 3 + pa.1 as u16 * 6 + pa.2 as u16) / 10;
+                    let dimmed = (luma / 4) as u8;
+                    (dimmed, dimmed, dimmed)
+                } else {
+                    (0xFF, 0x00, 0x00)
+                }
+            })
+            .collect();
+        report.image = Some(image);
+    }
+    report
+}
+
+#[derive(Debug, Error)]
+pub enum SaveFormatError {
+    #[error("Save is {actual} bytes but the cartridge has {sram_size} bytes of RAM")]
+    UnexpectedSize { sram_size: usize, actual: usize },
+}
+
+// Second byte of the current day-high register in the RTC footer
+const BATTERY_LOW_OFFSET: usize = 17;
+
+/// Layout of the RTC footer that BGB and VBA-M append to MBC3 saves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcFooterFormat {
+    /// 44 bytes with a 32-bit timestamp, written by older VBA-M builds.
+    Short,
+    /// 48 bytes with a 64-bit timestamp, written by BGB and current VBA-M.
+    Long,
+}
+
+impl RtcFooterFormat {
+    pub fn size(self) -> usize {
+        match self {
+            RtcFooterFormat::Short => 44,
+            RtcFooterFormat::Long => 48,
+        }
+    }
+}
+
+/// MBC3 clock registers 08h-0Ch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub days_low: u8,
+    /// Bit 0 is day bit 8, bit 6 halt and bit 7 the day counter carry.
+    pub days_high: u8,
+}
+
+impl RtcRegisters {
+    fn to_bytes(self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days_low,
+            self.days_high,
+        ]
+    }
+
+    // Each register is stored as a little-endian u32
+    fn parse(data: &[u8]) -> Self {
+        let register = |index: usize| data[index * 4];
+        Self {
+            seconds: register(0),
+            minutes: register(1),
+            hours: register(2),
+            days_low: register(3),
+            days_high: register(4),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RtcFooter {
+    pub current: RtcRegisters,
+    pub latched: RtcRegisters,
+    /// Unix time in seconds when the save was written.
+    pub timestamp: u64,
+    /// The clock battery has run down and the clock has stopped counting. Kept in the
+    /// second byte of the current day-high word, which other emulators leave at 0.
+    pub battery_low: bool,
+}
+
+/// Battery-backed cartridge RAM, with the MBC3 clock when the save carried one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SaveFile {
+    pub sram: Vec<u8>,
+    pub rtc: Option<RtcFooter>,
+}
+
+/// Reads a `.sav` from another emulator: a raw SRAM dump, or SRAM followed by a 44- or
+/// 48-byte RTC footer. `sram_size` is the cartridge RAM size in bytes (512 for MBC2),
+/// which is what [`GameBoyColor::save_data`](crate::GameBoyColor::save_data) returns.
+pub fn import_save(data: &[u8], sram_size: usize) -> Result<SaveFile, SaveFormatError> {
+    let format = match data.len().checked_sub(sram_size) {
+        Some(0) => None,
+        Some(44) => Some(RtcFooterFormat::Short),
+        Some(48) => Some(RtcFooterFormat::Long),
+        _ => {
+            return Err(SaveFormatError::UnexpectedSize {
+                sram_size,
+                actual: data.len(),
+            })
+        }
+    };
+
+    let (sram, footer) = data.split_at(sram_size);
+    let rtc = format.map(|format| {
+        let timestamp = match format {
+            RtcFooterFormat::Short => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+            RtcFooterFormat::Long => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+        };
+        RtcFooter {
+            current: RtcRegisters::parse(&footer[..20]),
+            latched: RtcRegisters::parse(&footer[20..40]),
+            timestamp,
+            battery_low: footer[BATTERY_LOW_OFFSET] & 0x01 != 0,
+        }
+    });
+    Ok(SaveFile {
+        sram: sram.to_vec(),
+        rtc,
+    })
+}
+
+/// Writes a save in the layout BGB and VBA-M read. The RTC footer is only written when
+/// `save` has one.
+pub fn export_save(save: &SaveFile, format: RtcFooterFormat) -> Vec<u8> {
+    let mut data = save.sram.clone();
+    if let Some(rtc) = save.rtc {
+        let footer_start = data.len();
+        for registers in [rtc.current, rtc.latched] {
+            for register in registers.to_bytes() {
+                data.extend_from_slice(&(register as u32).to_le_bytes());
+            }
+        }
+        data[footer_start + BATTERY_LOW_OFFSET] = rtc.battery_low as u8;
+        match format {
+            RtcFooterFormat::Short => data.extend_from_slice(&(rtc.timestamp as u32).to_le_bytes()),
+            RtcFooterFormat::Long => data.extend_from_slice(&rtc.timestamp.to_le_bytes()),
+        }
+    }
+    data
+}
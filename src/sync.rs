@@ -0,0 +1,123 @@
+use crate::gameboycolor::CLOCK_RATE;
+
+use std::time::Duration;
+
+// 70224 dots at CLOCK_RATE, in seconds
+const FRAME_SECONDS: f64 = 70224.0 / CLOCK_RATE as f64;
+// Falling further behind than this is treated as a stall, such as a pause or a dragged
+// window, and forgiven rather than caught up on
+const MAX_LAG_SECONDS: f64 = 0.25;
+// Weight of the newest frame in the speed and rate averages
+const SMOOTHING: f64 = 0.1;
+
+/// What a frontend should do at its next refresh to keep emulation in step with real time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncAdvice {
+    /// On schedule: run one frame.
+    RunFrame,
+    /// More than half a frame behind: run two frames, presenting only the second.
+    RunExtraFrame,
+    /// More than half a frame ahead: run no frame and show the last one again.
+    SkipFrame,
+}
+
+/// Compares emulated time with the host's presentation timestamps, for frontends that
+/// pace themselves by the display rather than [`EmulatorThread`](crate::EmulatorThread).
+///
+/// Call [`record_present`](Self::record_present) each time a frame is shown, then ask
+/// [`advice`](Self::advice) what to do at the next refresh. Audio and video stay in
+/// sync as long as the advice is followed.
+#[derive(Debug, Clone, Default)]
+pub struct SyncAdvisor {
+    // Host time and core timestamp that lag is measured from
+    origin: Option<(Duration, u64)>,
+    last: Option<(Duration, u64)>,
+    lag: f64,
+    speed: f64,
+    present_rate: f64,
+    resyncs: u64,
+}
+
+impl SyncAdvisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a presented frame: `host_time` is when it was shown, measured from any
+    /// fixed point, and `timestamp` is [`GameBoyColor::timestamp`](crate::GameBoyColor::timestamp)
+    /// after running it.
+    pub fn record_present(&mut self, host_time: Duration, timestamp: u64) {
+        let Some((origin_time, origin_timestamp)) = self.origin else {
+            self.start_from(host_time, timestamp);
+            return;
+        };
+
+        if let Some((last_time, last_timestamp)) = self.last {
+            let real = host_time.saturating_sub(last_time).as_secs_f64();
+            if real > 0.0 {
+                let emulated = timestamp.saturating_sub(last_timestamp) as f64 / CLOCK_RATE as f64;
+                self.speed += SMOOTHING * (emulated / real - self.speed);
+                self.present_rate += SMOOTHING * (1.0 / real - self.present_rate);
+            }
+        }
+        self.last = Some((host_time, timestamp));
+
+        let real = host_time.saturating_sub(origin_time).as_secs_f64();
+        let emulated = timestamp.saturating_sub(origin_timestamp) as f64 / CLOCK_RATE as f64;
+        self.lag = real - emulated;
+        if self.lag.abs() > MAX_LAG_SECONDS {
+            self.origin = Some((host_time, timestamp));
+            self.lag = 0.0;
+            self.resyncs += 1;
+        }
+    }
+
+    pub fn advice(&self) -> SyncAdvice {
+        if self.lag > FRAME_SECONDS / 2.0 {
+            SyncAdvice::RunExtraFrame
+        } else if self.lag < -FRAME_SECONDS / 2.0 {
+            SyncAdvice::SkipFrame
+        } else {
+            SyncAdvice::RunFrame
+        }
+    }
+
+    /// Seconds emulation is behind real time at the last presented frame; negative when
+    /// it is ahead.
+    pub fn lag(&self) -> f64 {
+        self.lag
+    }
+
+    /// Emulated time per real time over recent frames, so 1.0 is full speed.
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Frames presented per second over recent frames.
+    pub fn present_rate(&self) -> f64 {
+        self.present_rate
+    }
+
+    /// Times emulation fell more than a quarter of a second out of step and the advisor
+    /// started measuring afresh instead of asking to catch up.
+    pub fn resyncs(&self) -> u64 {
+        self.resyncs
+    }
+
+    /// Starts measuring afresh, for example after the frontend was paused or after
+    /// [`GameBoyColor::reset`](crate::GameBoyColor::reset), which restarts the timestamp.
+    pub fn reset(&mut self) {
+        *self = Self {
+            resyncs: self.resyncs,
+            ..Self::default()
+        };
+    }
+
+    fn start_from(&mut self, host_time: Duration, timestamp: u64) {
+        self.origin = Some((host_time, timestamp));
+        self.last = Some((host_time, timestamp));
+        self.lag = 0.0;
+        self.speed = 1.0;
+        self.present_rate = 1.0 / FRAME_SECONDS;
+    }
+}
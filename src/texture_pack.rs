@@ -0,0 +1,173 @@
+use crate::gameboycolor::{GameBoyColor, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::ppu::SourceLayer;
+use crate::support::Fnv1aHasher;
+
+use std::collections::HashMap;
+use std::hash::Hasher;
+use thiserror::Error;
+
+const MAGIC: &[u8; 4] = b"GBTP";
+const VERSION: u8 = 1;
+const MAX_SCALE: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum TexturePackError {
+    #[error("Not a texture pack")]
+    InvalidHeader,
+
+    #[error("Unsupported texture pack version: {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Invalid texture pack scale: {0}")]
+    InvalidScale(u8),
+
+    #[error("Texture pack is truncated")]
+    Truncated,
+}
+
+/// Replacement graphics for 8x8 tiles, matched by the hash of their tile data and drawn
+/// `scale` times larger than the original.
+///
+/// A pack file is the magic `GBTP`, a version byte (1) and the scale as a byte, followed
+/// by any number of entries. Each entry is a tile key as a little-endian u64 and then
+/// `(8 * scale)²` RGB pixels in row-major order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TexturePack {
+    scale: usize,
+    tiles: HashMap<u64, Vec<(u8, u8, u8)>>,
+}
+
+impl TexturePack {
+    pub fn new(scale: usize) -> Self {
+        assert!((1..=MAX_SCALE).contains(&scale), "Invalid scale: {}", scale);
+        Self {
+            scale,
+            tiles: HashMap::new(),
+        }
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    /// Identifies a tile by its 16 bytes of data and the palette it is drawn with, numbered
+    /// as in [`PixelSource::palette`](crate::PixelSource::palette).
+    pub fn tile_key(tile_data: &[u8; 16], palette: u8) -> u64 {
+        let mut hasher = Fnv1aHasher::default();
+        hasher.write(tile_data);
+        hasher.write(&[palette]);
+        hasher.finish()
+    }
+
+    /// `pixels` is the replacement tile, `8 * scale` pixels square in row-major order.
+    pub fn insert(&mut self, key: u64, pixels: Vec<(u8, u8, u8)>) {
+        let size = 8 * self.scale;
+        assert_eq!(pixels.len(), size * size, "Invalid tile pixel count");
+        self.tiles.insert(key, pixels);
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn load(data: &[u8]) -> Result<Self, TexturePackError> {
+        if data.len() < 6 || &data[..4] != MAGIC {
+            return Err(TexturePackError::InvalidHeader);
+        }
+        if data[4] != VERSION {
+            return Err(TexturePackError::UnsupportedVersion(data[4]));
+        }
+        let scale = data[5] as usize;
+        if !(1..=MAX_SCALE).contains(&scale) {
+            return Err(TexturePackError::InvalidScale(data[5]));
+        }
+
+        let mut pack = Self::new(scale);
+        let pixel_count = 64 * scale * scale;
+        let mut entries = &data[6..];
+        while !entries.is_empty() {
+            if entries.len() < 8 + pixel_count * 3 {
+                return Err(TexturePackError::Truncated);
+            }
+            let (key, rest) = entries.split_at(8);
+            let (pixels, rest) = rest.split_at(pixel_count * 3);
+            let pixels = pixels
+                .chunks_exact(3)
+                .map(|rgb| (rgb[0], rgb[1], rgb[2]))
+                .collect();
+            pack.insert(u64::from_le_bytes(key.try_into().unwrap()), pixels);
+            entries = rest;
+        }
+        Ok(pack)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend([VERSION, self.scale as u8]);
+        let mut keys: Vec<_> = self.tiles.keys().copied().collect();
+        keys.sort_unstable();
+        for key in keys {
+            data.extend(key.to_le_bytes());
+            for &(r, g, b) in &self.tiles[&key] {
+                data.extend([r, g, b]);
+            }
+        }
+        data
+    }
+
+    /// Draws the last frame `scale` times larger, replacing every tile found in the pack.
+    /// Tiles are matched against VRAM as it is now, so graphics changed while the frame
+    /// was drawn may be missed. Other pixels are scaled up unchanged.
+    pub fn compose(&self, gameboy: &GameBoyColor) -> Vec<(u8, u8, u8)> {
+        let scale = self.scale;
+        let width = SCREEN_WIDTH * scale;
+        let pixels = gameboy.frame_buffer();
+        let sources = gameboy.pixel_sources();
+
+        // Each tile on screen is hashed once per frame
+        let mut cache = HashMap::new();
+        let mut output = vec![(0, 0, 0); width * SCREEN_HEIGHT * scale];
+        for (index, (&pixel, source)) in pixels.iter().zip(sources).enumerate() {
+            let replacement = if source.layer == SourceLayer::Blank {
+                None
+            } else {
+                *cache
+                    .entry((source.tile_id, source.palette))
+                    .or_insert_with(|| {
+                        let data = gameboy.tile_data(source.tile_id)?;
+                        self.tiles.get(&Self::tile_key(&data, source.palette))
+                    })
+            };
+
+            let (x, y) = (index % SCREEN_WIDTH, index / SCREEN_WIDTH);
+            for sub_y in 0..scale {
+                for sub_x in 0..scale {
+                    let color = match replacement {
+                        None => pixel,
+                        Some(tile) => {
+                            let offset_x = if source.x_flip {
+                                scale - 1 - sub_x
+                            } else {
+                                sub_x
+                            };
+                            let offset_y = if source.y_flip {
+                                scale - 1 - sub_y
+                            } else {
+                                sub_y
+                            };
+                            let tile_x = source.tile_x as usize * scale + offset_x;
+                            let tile_y = source.tile_y as usize * scale + offset_y;
+                            tile[tile_y * 8 * scale + tile_x]
+                        }
+                    };
+                    output[(y * scale + sub_y) * width + x * scale + sub_x] = color;
+                }
+            }
+        }
+        output
+    }
+}
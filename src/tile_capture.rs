@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+// 384 tiles of tile data at 0x8000-0x97FF in each VRAM bank
+const TILES_PER_BANK: usize = 384;
+const TILE_DATA_SIZE: usize = TILES_PER_BANK * 16;
+const BANK_SIZE: usize = 0x2000;
+
+/// A tile returned by [`GameBoyColor::captured_tiles`](crate::GameBoyColor::captured_tiles),
+/// in the 2bpp format VRAM stores it in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedTile {
+    pub data: [u8; 16],
+    /// Times a frame ended with this tile newly written to one of the VRAM tile slots.
+    pub count: u32,
+}
+
+// Tile writes are only read back once the frame ends, so a tile copied within one
+// frame is recorded whole rather than byte by byte
+pub(crate) struct TileCapture {
+    dirty: Vec<bool>,
+    index: HashMap<[u8; 16], usize>,
+    tiles: Vec<CapturedTile>,
+}
+
+impl TileCapture {
+    // Tiles already in VRAM are left out until they are written again. Only bank 0 is
+    // watched in DMG mode, which has no bank 1.
+    pub fn new(banks: usize) -> Self {
+        Self {
+            dirty: vec![false; TILES_PER_BANK * banks],
+            index: HashMap::new(),
+            tiles: Vec::new(),
+        }
+    }
+
+    pub fn mark(&mut self, vram_address: usize) {
+        let (bank, offset) = (vram_address / BANK_SIZE, vram_address % BANK_SIZE);
+        if offset < TILE_DATA_SIZE {
+            if let Some(dirty) = self.dirty.get_mut(bank * TILES_PER_BANK + offset / 16) {
+                *dirty = true;
+            }
+        }
+    }
+
+    pub fn collect(&mut self, vram: &[u8]) {
+        for (tile, dirty) in self.dirty.iter_mut().enumerate() {
+            if !std::mem::take(dirty) {
+                continue;
+            }
+            let start = tile / TILES_PER_BANK * BANK_SIZE + tile % TILES_PER_BANK * 16;
+            let data: [u8; 16] = vram[start..start + 16].try_into().unwrap();
+            match self.index.get(&data) {
+                Some(&i) => self.tiles[i].count += 1,
+                None => {
+                    self.index.insert(data, self.tiles.len());
+                    self.tiles.push(CapturedTile { data, count: 1 });
+                }
+            }
+        }
+    }
+
+    pub fn tiles(&self) -> &[CapturedTile] {
+        &self.tiles
+    }
+}
@@ -1,5 +1,6 @@
-use crate::config::Speed;
+use crate::config::{Model, Speed};
 use crate::context;
+use crate::state_hash::{StateHash, StateHasher};
 
 trait Context: context::Interrupt + context::Config {}
 impl<T> Context for T where T: context::Interrupt + context::Config {}
@@ -28,7 +29,38 @@ impl Timer {
     }
 }
 
+impl StateHash for Timer {
+    fn hash_state(&self, hasher: &mut StateHasher) {
+        let Timer {
+            div,
+            tima,
+            tma,
+            tac,
+            div_counter,
+            tima_counter,
+            tima_enable,
+        } = self;
+        hasher
+            .write(div)
+            .write(&[*tima, *tma, *tac])
+            .write(div_counter)
+            .write(tima_counter)
+            .write(tima_enable);
+    }
+}
+
 impl Timer {
+    /// Leaves DIV where the model's boot ROM does. On the other models it depends on how
+    /// long the boot ROM ran, so it starts from 0.
+    pub fn skip_boot(&mut self, model: Model) {
+        let div = match model {
+            Model::Dmg0 => 0x18,
+            Model::Dmg | Model::Mgb => 0xAB,
+            Model::Sgb | Model::Sgb2 | Model::CgbC | Model::CgbD | Model::Cgb | Model::Agb => 0x00,
+        };
+        self.div = (div as u16) << 8;
+    }
+
     pub fn read(&self, address: u16) -> u8 {
         match address {
             0xFF04 => (self.div >> 8) as u8,
@@ -41,7 +73,7 @@ impl Timer {
 
     pub fn write(&mut self, address: u16, value: u8) {
         match address {
-            0xFF04 => self.div = 0,
+            0xFF04 => self.reset_div(),
             0xFF05 => self.tima = value,
             0xFF06 => self.tma = value,
             0xFF07 => {
@@ -52,6 +84,10 @@ impl Timer {
         }
     }
 
+    pub fn reset_div(&mut self) {
+        self.div = 0;
+    }
+
     pub fn tick(&mut self, context: &mut impl Context) {
         self.tick_div();
         self.tick_tima(context);
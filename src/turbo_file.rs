@@ -0,0 +1,187 @@
+use crate::interface::SerialDevice;
+use log::warn;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// 1 Mbit of battery-backed RAM, in 8 KiB banks read and written 64 bytes at a time
+const BANK_SIZE: usize = 0x2000;
+const BANKS: usize = 16;
+const BLOCK_SIZE: usize = 64;
+const BLOCKS: usize = BANK_SIZE / BLOCK_SIZE;
+
+const SYNC: u8 = 0x6C;
+const COMMAND_STATUS: u8 = 0x10;
+const COMMAND_SELECT_BANK: u8 = 0x20;
+const COMMAND_WRITE: u8 = 0x30;
+const COMMAND_READ: u8 = 0x40;
+
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_INVALID: u8 = 0x02;
+const STATUS_WRITE_ERROR: u8 = 0x04;
+
+#[derive(Debug, Clone, Copy)]
+enum Stage {
+    Sync,
+    // Command, parameters and checksum
+    Packet,
+    // Reply bytes clocked out so far
+    Reply(usize),
+}
+
+/// Link port storage in the spirit of the Turbo File GB, for the link port through a
+/// [`DeviceCable`](crate::DeviceCable). Its 128 KiB are kept in a file on the host, and
+/// every block the game writes goes straight to the file.
+///
+/// The real accessory's command set is not publicly documented, so this speaks a
+/// protocol of its own instead, and games written for the Turbo File GB will not
+/// recognise it. It suits homebrew and test ROMs that want storage on the link port.
+///
+/// The game sends packets of the sync byte 0x6C, a command, its parameters and a
+/// checksum that brings the sum of the packet to 0, then clocks the reply out:
+///
+/// - 0x10, status: replies with the command, the status and the selected bank.
+/// - 0x20 bank, select one of the 16 banks of 8 KiB: replies with the command and the
+///   status.
+/// - 0x30 block data, write 64 bytes to one of the 128 blocks in the bank: replies with
+///   the command and the status.
+/// - 0x40 block, read 64 bytes: replies with the command, the status and the data.
+///
+/// The status sets bit 0 for a bad checksum, bit 1 for an unknown command, bank or
+/// block, and bit 2 when the host file could not be written.
+pub struct TurboFile {
+    file: File,
+    contents: Vec<u8>,
+    bank: u8,
+    stage: Stage,
+    packet: Vec<u8>,
+    reply: Vec<u8>,
+}
+
+impl TurboFile {
+    /// Opens the storage kept in `path`, creating it empty if there is none yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        if contents.len() > BANK_SIZE * BANKS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Turbo File storage is larger than {} bytes",
+                    BANK_SIZE * BANKS
+                ),
+            ));
+        }
+        contents.resize(BANK_SIZE * BANKS, 0);
+        file.set_len(contents.len() as u64)?;
+        Ok(Self {
+            file,
+            contents,
+            bank: 0,
+            stage: Stage::Sync,
+            packet: Vec::new(),
+            reply: Vec::new(),
+        })
+    }
+
+    // Parameter bytes after each command. Unknown commands are taken to have none.
+    fn parameters(command: u8) -> usize {
+        match command {
+            COMMAND_SELECT_BANK | COMMAND_READ => 1,
+            COMMAND_WRITE => 1 + BLOCK_SIZE,
+            _ => 0,
+        }
+    }
+
+    fn receive(&mut self) {
+        let command = self.packet[0];
+        let sum = self
+            .packet
+            .iter()
+            .fold(SYNC, |sum, &byte| sum.wrapping_add(byte));
+        self.reply = vec![command];
+        if sum != 0 {
+            self.reply.push(STATUS_CHECKSUM_ERROR);
+            return;
+        }
+
+        let parameters = &self.packet[1..self.packet.len() - 1];
+        match command {
+            COMMAND_STATUS => self.reply.extend_from_slice(&[0, self.bank]),
+            COMMAND_SELECT_BANK if (parameters[0] as usize) < BANKS => {
+                self.bank = parameters[0];
+                self.reply.push(0);
+            }
+            COMMAND_WRITE if (parameters[0] as usize) < BLOCKS => {
+                let offset = self.offset(parameters[0]);
+                let data = &parameters[1..];
+                self.contents[offset..offset + BLOCK_SIZE].copy_from_slice(data);
+                let status = match self.write_block(offset) {
+                    Ok(()) => 0,
+                    Err(e) => {
+                        warn!("Failed to write the Turbo File storage: {}", e);
+                        STATUS_WRITE_ERROR
+                    }
+                };
+                self.reply.push(status);
+            }
+            COMMAND_READ if (parameters[0] as usize) < BLOCKS => {
+                let offset = self.offset(parameters[0]);
+                self.reply.push(0);
+                self.reply
+                    .extend_from_slice(&self.contents[offset..offset + BLOCK_SIZE]);
+            }
+            _ => self.reply.push(STATUS_INVALID),
+        }
+    }
+
+    fn offset(&self, block: u8) -> usize {
+        self.bank as usize * BANK_SIZE + block as usize * BLOCK_SIZE
+    }
+
+    fn write_block(&mut self, offset: usize) -> Result<(), io::Error> {
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file
+            .write_all(&self.contents[offset..offset + BLOCK_SIZE])?;
+        self.file.flush()
+    }
+}
+
+impl SerialDevice for TurboFile {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        match self.stage {
+            Stage::Sync => {
+                if sent == SYNC {
+                    self.packet.clear();
+                    self.stage = Stage::Packet;
+                }
+                0x00
+            }
+            Stage::Packet => {
+                self.packet.push(sent);
+                // Command, parameters and checksum
+                if self.packet.len() == 1 + Self::parameters(self.packet[0]) + 1 {
+                    self.receive();
+                    self.stage = Stage::Reply(0);
+                }
+                0x00
+            }
+            Stage::Reply(sent_so_far) => {
+                let byte = self.reply[sent_so_far];
+                self.stage = if sent_so_far + 1 == self.reply.len() {
+                    Stage::Sync
+                } else {
+                    Stage::Reply(sent_so_far + 1)
+                };
+                byte
+            }
+        }
+    }
+}
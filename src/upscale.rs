@@ -0,0 +1,65 @@
+use crate::gameboycolor::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::ppu::{PixelSource, SourceLayer};
+
+/// Reference 2x upscaler built on [`PixelSource`]: Scale2x (EPX) that only rounds edges
+/// between pixels of the same surface, so objects keep sharp outlines against the
+/// background. Elsewhere it is nearest-neighbour. Returns a frame of
+/// `SCREEN_WIDTH * 2` by `SCREEN_HEIGHT * 2` pixels.
+pub fn scale2x(pixels: &[(u8, u8, u8)], sources: &[PixelSource]) -> Vec<(u8, u8, u8)> {
+    assert_eq!(pixels.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+    assert_eq!(sources.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+
+    let width = SCREEN_WIDTH * 2;
+    let mut output = vec![(0, 0, 0); width * SCREEN_HEIGHT * 2];
+    for y in 0..SCREEN_HEIGHT {
+        for x in 0..SCREEN_WIDTH {
+            let index = y * SCREEN_WIDTH + x;
+            let center = pixels[index];
+            // Neighbours from another surface count as the centre colour
+            let neighbour = |nx: usize, ny: usize| {
+                let neighbour_index = ny * SCREEN_WIDTH + nx;
+                if same_surface(&sources[index], &sources[neighbour_index]) {
+                    pixels[neighbour_index]
+                } else {
+                    center
+                }
+            };
+            let up = neighbour(x, y.saturating_sub(1));
+            let down = neighbour(x, (y + 1).min(SCREEN_HEIGHT - 1));
+            let left = neighbour(x.saturating_sub(1), y);
+            let right = neighbour((x + 1).min(SCREEN_WIDTH - 1), y);
+
+            let mut block = [center; 4];
+            if up != down && left != right {
+                if left == up {
+                    block[0] = up;
+                }
+                if up == right {
+                    block[1] = right;
+                }
+                if left == down {
+                    block[2] = left;
+                }
+                if down == right {
+                    block[3] = down;
+                }
+            }
+
+            let top = y * 2 * width + x * 2;
+            output[top] = block[0];
+            output[top + 1] = block[1];
+            output[top + width] = block[2];
+            output[top + width + 1] = block[3];
+        }
+    }
+    output
+}
+
+fn same_surface(a: &PixelSource, b: &PixelSource) -> bool {
+    match (a.layer, b.layer) {
+        (SourceLayer::Object, SourceLayer::Object) => {
+            a.tile_id == b.tile_id && a.palette == b.palette
+        }
+        (a, b) => a == b,
+    }
+}
@@ -0,0 +1,256 @@
+//! Records frames to an animated GIF or APNG, timed by emulated time so the clip plays
+//! at the console's 59.73 Hz.
+
+use crate::gameboycolor::{CLOCK_RATE, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::support::{png_header, png_image_data, write_png_chunk, PNG_SIGNATURE};
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const FRAME_DOTS: u64 = 70224;
+// Viewers slow GIF frames shorter than two hundredths of a second right down, so such
+// frames are dropped and their time added to the next one
+const GIF_MIN_DELAY: u64 = 2;
+// APNG delays are in milliseconds
+const APNG_DELAY_DEN: u16 = 1000;
+
+enum Encoder {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Apng(ApngWriter),
+}
+
+impl Encoder {
+    // Delay units per second
+    fn time_base(&self) -> u64 {
+        match self {
+            Encoder::Gif(_) => 100,
+            Encoder::Apng(_) => APNG_DELAY_DEN as u64,
+        }
+    }
+
+    fn min_delay(&self) -> u64 {
+        match self {
+            Encoder::Gif(_) => GIF_MIN_DELAY,
+            Encoder::Apng(_) => 1,
+        }
+    }
+
+    fn write_frame(&mut self, pixels: &[(u8, u8, u8)], delay: u16) -> io::Result<()> {
+        match self {
+            Encoder::Gif(encoder) => {
+                let mut frame = indexed_frame(pixels).unwrap_or_else(|| {
+                    let rgb: Vec<u8> = pixels.iter().flat_map(|&(r, g, b)| [r, g, b]).collect();
+                    gif::Frame::from_rgb_speed(SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16, &rgb, 10)
+                });
+                frame.delay = delay;
+                encoder.write_frame(&frame).map_err(io::Error::other)
+            }
+            Encoder::Apng(writer) => writer.write_frame(pixels, delay),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Encoder::Gif(encoder) => encoder.into_inner()?.flush(),
+            Encoder::Apng(writer) => writer.finish(),
+        }
+    }
+}
+
+// A frame with its own palette, unless it has more than 256 colours
+fn indexed_frame(pixels: &[(u8, u8, u8)]) -> Option<gif::Frame<'static>> {
+    let mut palette = Vec::new();
+    let mut indices = HashMap::new();
+    let mut buffer = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT);
+    for &pixel in pixels {
+        let index = match indices.get(&pixel) {
+            Some(&index) => index,
+            None => {
+                let index = u8::try_from(indices.len()).ok()?;
+                indices.insert(pixel, index);
+                palette.extend([pixel.0, pixel.1, pixel.2]);
+                index
+            }
+        };
+        buffer.push(index);
+    }
+    Some(gif::Frame {
+        width: SCREEN_WIDTH as u16,
+        height: SCREEN_HEIGHT as u16,
+        buffer: buffer.into(),
+        palette: Some(palette),
+        ..Default::default()
+    })
+}
+
+pub(crate) struct VideoRecorder {
+    encoder: Encoder,
+    // The last frame, written once the next frame shows how long it stayed on screen
+    pending: Option<Vec<(u8, u8, u8)>>,
+    pending_timestamp: u64,
+    // Emulated time from the start of the recording to the pending frame
+    dots: u64,
+    // Delay units written so far, so rounding errors never add up
+    written: u64,
+    // Recording stops at the first error, which is reported when it is stopped
+    error: Option<io::Error>,
+}
+
+impl VideoRecorder {
+    // A GIF for a `.gif` path, otherwise an APNG
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        let gif = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gif"));
+        let encoder = if gif {
+            let mut encoder =
+                gif::Encoder::new(file, SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16, &[])
+                    .map_err(io::Error::other)?;
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .map_err(io::Error::other)?;
+            Encoder::Gif(encoder)
+        } else {
+            Encoder::Apng(ApngWriter::new(file)?)
+        };
+        Ok(Self {
+            encoder,
+            pending: None,
+            pending_timestamp: 0,
+            dots: 0,
+            written: 0,
+            error: None,
+        })
+    }
+
+    // `timestamp` is GameBoyColor::timestamp as the frame was completed
+    pub fn add_frame(&mut self, frame: &[(u8, u8, u8)], timestamp: u64) {
+        if self.error.is_some() {
+            return;
+        }
+        if self.pending.is_some() {
+            // A reset restarts the timestamp, and a stopped LCD can hold a frame for
+            // long; count either as a normal frame
+            let dots = timestamp
+                .checked_sub(self.pending_timestamp)
+                .filter(|&dots| dots > 0 && dots <= FRAME_DOTS * 2)
+                .unwrap_or(FRAME_DOTS);
+            if let Err(error) = self.write_pending(dots, false) {
+                self.error = Some(error);
+            }
+        }
+        self.pending = Some(frame.to_vec());
+        self.pending_timestamp = timestamp;
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        if self.pending.is_some() {
+            self.write_pending(FRAME_DOTS, true)?;
+        }
+        self.encoder.finish()
+    }
+
+    // Writes the pending frame as shown for `dots`, or drops it if that is too short to
+    // display and it isn't the last
+    fn write_pending(&mut self, dots: u64, last: bool) -> io::Result<()> {
+        let Some(pixels) = &self.pending else {
+            return Ok(());
+        };
+        self.dots += dots;
+        let time_base = self.encoder.time_base();
+        let end = (self.dots * time_base + CLOCK_RATE / 2) / CLOCK_RATE;
+        let mut delay = end.saturating_sub(self.written);
+        if delay < self.encoder.min_delay() {
+            if !last {
+                return Ok(());
+            }
+            delay = self.encoder.min_delay();
+        }
+        self.encoder
+            .write_frame(pixels, delay.min(u16::MAX as u64) as u16)?;
+        self.written += delay;
+        Ok(())
+    }
+}
+
+// Streams an APNG with uncompressed image data, then fills in the frame count
+struct ApngWriter {
+    file: BufWriter<File>,
+    frames: u32,
+    sequence: u32,
+}
+
+impl ApngWriter {
+    // After the signature and IHDR
+    const ANIMATION_CONTROL_OFFSET: u64 = 8 + 25;
+
+    fn new(mut file: BufWriter<File>) -> io::Result<Self> {
+        let mut header = PNG_SIGNATURE.to_vec();
+        write_png_chunk(
+            &mut header,
+            b"IHDR",
+            &png_header(SCREEN_WIDTH, SCREEN_HEIGHT),
+        );
+        // The frame count is filled in at the end; 0 plays loop forever
+        write_png_chunk(&mut header, b"acTL", &[0; 8]);
+        file.write_all(&header)?;
+        Ok(Self {
+            file,
+            frames: 0,
+            sequence: 0,
+        })
+    }
+
+    fn write_frame(&mut self, frame: &[(u8, u8, u8)], delay: u16) -> io::Result<()> {
+        let mut control = Vec::with_capacity(26);
+        control.extend(self.next_sequence().to_be_bytes());
+        control.extend((SCREEN_WIDTH as u32).to_be_bytes());
+        control.extend((SCREEN_HEIGHT as u32).to_be_bytes());
+        control.extend([0; 8]);
+        control.extend(delay.to_be_bytes());
+        control.extend(APNG_DELAY_DEN.to_be_bytes());
+        // No disposal, and the frame replaces the previous one
+        control.extend([0, 0]);
+        let mut chunks = Vec::new();
+        write_png_chunk(&mut chunks, b"fcTL", &control);
+
+        // The first frame doubles as the still image
+        let data = png_image_data(SCREEN_WIDTH, frame);
+        if self.frames == 0 {
+            write_png_chunk(&mut chunks, b"IDAT", &data);
+        } else {
+            let mut frame_data = self.next_sequence().to_be_bytes().to_vec();
+            frame_data.extend(data);
+            write_png_chunk(&mut chunks, b"fdAT", &frame_data);
+        }
+        self.file.write_all(&chunks)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        let mut end = Vec::new();
+        write_png_chunk(&mut end, b"IEND", &[]);
+        self.file.write_all(&end)?;
+
+        let mut control = self.frames.to_be_bytes().to_vec();
+        control.extend(0u32.to_be_bytes());
+        let mut animation_control = Vec::new();
+        write_png_chunk(&mut animation_control, b"acTL", &control);
+        self.file
+            .seek(SeekFrom::Start(Self::ANIMATION_CONTROL_OFFSET))?;
+        self.file.write_all(&animation_control)?;
+        self.file.flush()
+    }
+
+    fn next_sequence(&mut self) -> u32 {
+        self.sequence += 1;
+        self.sequence - 1
+    }
+}
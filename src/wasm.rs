@@ -0,0 +1,199 @@
+//! A wasm-bindgen class for web frontends, so they can drive the core without writing
+//! their own bindings.
+
+use crate::apu;
+use crate::config::{DeviceMode, EmulatorConfigBuilder};
+use crate::context::EmulatorError;
+use crate::gameboycolor::{GameBoyColor, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::joypad::{JoypadKey, JoypadKeyState};
+
+use js_sys::{Float32Array, Uint8ClampedArray};
+use std::io;
+use wasm_bindgen::prelude::*;
+
+/// A button on the joypad, for [`WasmGameBoy::set_key`].
+#[wasm_bindgen(js_name = Key)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WasmKey {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl From<WasmKey> for JoypadKey {
+    fn from(key: WasmKey) -> Self {
+        match key {
+            WasmKey::Right => JoypadKey::Right,
+            WasmKey::Left => JoypadKey::Left,
+            WasmKey::Up => JoypadKey::Up,
+            WasmKey::Down => JoypadKey::Down,
+            WasmKey::A => JoypadKey::A,
+            WasmKey::B => JoypadKey::B,
+            WasmKey::Select => JoypadKey::Select,
+            WasmKey::Start => JoypadKey::Start,
+        }
+    }
+}
+
+/// A [`GameBoyColor`] exposed to JavaScript as `GameBoy`.
+///
+/// Keys set with [`set_key`](Self::set_key) stay pressed until released, and each
+/// [`run_frame`](Self::run_frame) leaves the picture and the frame's audio ready to be
+/// fetched. Saves live in the page's own storage: nothing is read from or written to
+/// disk.
+#[wasm_bindgen(js_name = GameBoy)]
+pub struct WasmGameBoy {
+    gameboy: GameBoyColor,
+    // Kept to power on again with an imported save
+    rom: Vec<u8>,
+    device_mode: DeviceMode,
+    sample_rate: Option<u32>,
+    keys: JoypadKeyState,
+    frame: Vec<u8>,
+}
+
+#[wasm_bindgen(js_class = GameBoy)]
+impl WasmGameBoy {
+    /// Loads `rom` as a Game Boy Color, or as a Game Boy unless `color` is set, starting
+    /// from `save` when given.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8], color: bool, save: Option<Vec<u8>>) -> Result<WasmGameBoy, JsError> {
+        Ok(Self::try_new(rom, color, save)?)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        SCREEN_WIDTH
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> usize {
+        SCREEN_HEIGHT
+    }
+
+    #[wasm_bindgen(js_name = romName)]
+    pub fn rom_name(&self) -> String {
+        self.gameboy.rom_name().to_string()
+    }
+
+    #[wasm_bindgen(js_name = setKey)]
+    pub fn set_key(&mut self, key: WasmKey, pressed: bool) {
+        self.keys.set_key(key.into(), pressed);
+    }
+
+    /// Runs one frame with the keys currently held.
+    #[wasm_bindgen(js_name = runFrame)]
+    pub fn run_frame(&mut self) {
+        self.gameboy.run_frame(self.keys);
+        self.gameboy.frame_buffer_rgba(&mut self.frame);
+    }
+
+    /// The last frame as RGBA8, ready for an `ImageData`.
+    #[wasm_bindgen(js_name = frameRgba)]
+    pub fn frame_rgba(&self) -> Uint8ClampedArray {
+        Uint8ClampedArray::from(&self.frame[..])
+    }
+
+    /// The last frame's audio as interleaved left and right samples from -1.0 to 1.0.
+    pub fn audio(&self) -> Float32Array {
+        let samples: Vec<f32> = self
+            .gameboy
+            .audio_buffer()
+            .iter()
+            .flatten()
+            .map(|&sample| sample as f32 / 32768.0)
+            .collect();
+        Float32Array::from(&samples[..])
+    }
+
+    /// Sets the rate of [`audio`](Self::audio), usually an `AudioContext`'s `sampleRate`.
+    #[wasm_bindgen(js_name = setSampleRate)]
+    pub fn set_sample_rate(&mut self, hz: u32) -> Result<(), JsError> {
+        if !apu::SAMPLE_RATES.contains(&hz) {
+            return Err(JsError::new(&format!("Unsupported sample rate: {} Hz", hz)));
+        }
+        self.gameboy.set_sample_rate(hz);
+        self.sample_rate = Some(hz);
+        Ok(())
+    }
+
+    /// The battery-backed RAM, or `undefined` if the cartridge has none.
+    #[wasm_bindgen(js_name = exportSave)]
+    pub fn export_save(&self) -> Option<Vec<u8>> {
+        self.gameboy.save_data()
+    }
+
+    /// Powers on again from `save`, which must come from
+    /// [`export_save`](Self::export_save) for the same game.
+    #[wasm_bindgen(js_name = importSave)]
+    pub fn import_save(&mut self, save: Vec<u8>) -> Result<(), JsError> {
+        check_save_size(&self.gameboy, &save)?;
+        self.gameboy = power_on(&self.rom, self.device_mode, Some(save))?;
+        if let Some(hz) = self.sample_rate {
+            self.gameboy.set_sample_rate(hz);
+        }
+        Ok(())
+    }
+}
+
+impl WasmGameBoy {
+    /// [`new`](Self::new) for Rust callers, with the error left as an [`EmulatorError`].
+    /// A ROM too short for its header or a `save` of the wrong size is refused here
+    /// rather than trapping the module.
+    pub fn try_new(
+        rom: &[u8],
+        color: bool,
+        save: Option<Vec<u8>>,
+    ) -> Result<WasmGameBoy, EmulatorError> {
+        let device_mode = if color {
+            DeviceMode::GameBoyColor
+        } else {
+            DeviceMode::GameBoy
+        };
+        let mut gameboy = power_on(rom, device_mode, None)?;
+        if let Some(save) = save {
+            check_save_size(&gameboy, &save)?;
+            gameboy = power_on(rom, device_mode, Some(save))?;
+        }
+        Ok(Self {
+            gameboy,
+            rom: rom.to_vec(),
+            device_mode,
+            sample_rate: None,
+            keys: JoypadKeyState::new(),
+            frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4],
+        })
+    }
+}
+
+fn check_save_size(gameboy: &GameBoyColor, save: &[u8]) -> Result<(), EmulatorError> {
+    let expected = gameboy.save_data().map_or(0, |data| data.len());
+    if save.len() != expected {
+        return Err(EmulatorError::SaveDataError(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Save is {} bytes but the cartridge keeps {}",
+                save.len(),
+                expected
+            ),
+        )));
+    }
+    Ok(())
+}
+
+fn power_on(
+    rom: &[u8],
+    device_mode: DeviceMode,
+    save: Option<Vec<u8>>,
+) -> Result<GameBoyColor, EmulatorError> {
+    let mut builder = EmulatorConfigBuilder::default().device_mode(device_mode);
+    if let Some(save) = save {
+        builder = builder.save_data(save);
+    }
+    GameBoyColor::with_config(rom, builder.build()?)
+}
@@ -0,0 +1,114 @@
+use crate::gameboycolor::GameBoyColor;
+
+/// How the bytes of a watched value are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEncoding {
+    LittleEndian,
+    BigEndian,
+    /// Packed BCD, two digits per byte, most significant byte first.
+    Bcd,
+}
+
+/// A named value stored in cartridge RAM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watch {
+    pub name: String,
+    /// Offset into the save data, i.e. `bank * 0x2000 + address - 0xA000`.
+    pub offset: usize,
+    /// Number of bytes, at most 8.
+    pub size: usize,
+    pub encoding: WatchEncoding,
+}
+
+impl Watch {
+    pub fn new(name: &str, offset: usize, size: usize, encoding: WatchEncoding) -> Self {
+        Self {
+            name: name.to_string(),
+            offset,
+            size,
+            encoding,
+        }
+    }
+
+    fn decode(&self, data: &[u8]) -> Option<u64> {
+        if self.size == 0 || self.size > 8 {
+            return None;
+        }
+        let bytes = data.get(self.offset..self.offset.checked_add(self.size)?)?;
+        Some(self.encoding.decode(bytes))
+    }
+}
+
+impl WatchEncoding {
+    // At most 8 bytes
+    pub(crate) fn decode(self, bytes: &[u8]) -> u64 {
+        match self {
+            WatchEncoding::LittleEndian => bytes
+                .iter()
+                .rev()
+                .fold(0, |acc, &byte| acc << 8 | byte as u64),
+            WatchEncoding::BigEndian => bytes.iter().fold(0, |acc, &byte| acc << 8 | byte as u64),
+            WatchEncoding::Bcd => bytes.iter().fold(0, |acc, &byte| {
+                acc * 100 + (byte >> 4) as u64 * 10 + (byte & 0x0F) as u64
+            }),
+        }
+    }
+}
+
+/// A watched value that differs from the last poll.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchChange {
+    pub name: String,
+    /// `None` the first time the value is seen.
+    pub old: Option<u64>,
+    pub new: u64,
+}
+
+/// Tracks values in battery-backed cartridge RAM, such as high scores or game progress,
+/// and reports when they change.
+#[derive(Debug, Clone, Default)]
+pub struct SramWatcher {
+    watches: Vec<(Watch, Option<u64>)>,
+}
+
+impl SramWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, watch: Watch) {
+        self.watches.push((watch, None));
+    }
+
+    /// The value seen by the last poll.
+    pub fn value(&self, name: &str) -> Option<u64> {
+        self.watches
+            .iter()
+            .find(|(watch, _)| watch.name == name)
+            .and_then(|(_, value)| *value)
+    }
+
+    /// Reads the cartridge RAM in place and returns the watches whose value changed.
+    /// Watches that fall outside the RAM, or cartridges without RAM, are never reported.
+    pub fn poll(&mut self, gameboy: &GameBoyColor) -> Vec<WatchChange> {
+        let Some(data) = gameboy.battery_ram() else {
+            return Vec::new();
+        };
+
+        let mut changes = Vec::new();
+        for (watch, last) in &mut self.watches {
+            let Some(value) = watch.decode(data) else {
+                continue;
+            };
+            if *last != Some(value) {
+                changes.push(WatchChange {
+                    name: watch.name.clone(),
+                    old: *last,
+                    new: value,
+                });
+                *last = Some(value);
+            }
+        }
+        changes
+    }
+}
@@ -0,0 +1,44 @@
+use crate::event::StallReason;
+
+use std::mem::discriminant;
+
+pub const DEFAULT_CYCLE_LIMIT: u64 = 10_000_000;
+// M-cycles in one frame at normal speed
+const FRAME_CYCLES: u64 = 154 * 456 / 4;
+
+pub struct Watchdog {
+    cycle_limit: u64,
+    stall: Option<StallReason>,
+}
+
+impl Watchdog {
+    pub fn new(cycle_limit: u64) -> Self {
+        Self {
+            cycle_limit,
+            stall: None,
+        }
+    }
+
+    // Once a frame has failed to complete, hand control back after every frame's worth of cycles
+    pub fn frame_budget(&self) -> u64 {
+        match self.stall {
+            Some(StallReason::NoFrameProgress { .. }) => FRAME_CYCLES,
+            _ => self.cycle_limit,
+        }
+    }
+
+    /// Records the current stall condition, returning it only when it was not already reported.
+    pub fn update(&mut self, stall: Option<StallReason>) -> Option<StallReason> {
+        let is_new = match (&self.stall, &stall) {
+            (Some(prev), Some(cur)) => discriminant(prev) != discriminant(cur),
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        self.stall = stall;
+        if is_new {
+            stall
+        } else {
+            None
+        }
+    }
+}
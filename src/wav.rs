@@ -0,0 +1,138 @@
+//! Records the APU's output to 16-bit PCM WAV files.
+
+use crate::apu::Channel;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const HEADER_SIZE: u32 = 44;
+// The RIFF size after the header is a u32, kept to whole stereo samples
+const MAX_DATA_SIZE: u32 = (u32::MAX - (HEADER_SIZE - 8)) & !3;
+
+// Streams samples to a file, then fills in the sizes in the header once they are known
+struct WavWriter {
+    file: BufWriter<File>,
+    data_size: u32,
+}
+
+impl WavWriter {
+    fn create(path: &Path, channels: u16, sample_rate: u32) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let block_align = channels * 2;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(HEADER_SIZE - 8).to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        Ok(Self { file, data_size: 0 })
+    }
+
+    fn write(&mut self, samples: impl IntoIterator<Item = i16>) -> io::Result<()> {
+        for sample in samples {
+            self.data_size = self
+                .data_size
+                .checked_add(2)
+                .filter(|&size| size <= MAX_DATA_SIZE)
+                .ok_or_else(|| io::Error::other("The WAV file reached its 4 GiB limit"))?;
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    // Also run on drop, so a recording the emulator outlives is still readable
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_size.to_le_bytes())?;
+        self.file.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+// The mix in one stereo file, and optionally each channel in a mono file of its own
+pub(crate) struct AudioRecorder {
+    mix: WavWriter,
+    channels: Option<[WavWriter; 4]>,
+    // Writing stops at the first error, which is reported when the recording is stopped
+    error: Option<io::Error>,
+}
+
+impl AudioRecorder {
+    pub fn create(path: &Path, sample_rate: u32, channels: bool) -> io::Result<Self> {
+        let mix = WavWriter::create(path, 2, sample_rate)?;
+        let channels = if channels {
+            let channel = |channel| WavWriter::create(&channel_path(path, channel), 1, sample_rate);
+            Some([
+                channel(Channel::Pulse1)?,
+                channel(Channel::Pulse2)?,
+                channel(Channel::Wave)?,
+                channel(Channel::Noise)?,
+            ])
+        } else {
+            None
+        };
+        Ok(Self {
+            mix,
+            channels,
+            error: None,
+        })
+    }
+
+    pub fn records_channels(&self) -> bool {
+        self.channels.is_some()
+    }
+
+    // `channels` holds the four channels for each sample in `mix`
+    pub fn write(&mut self, mix: &[[i16; 2]], channels: &[[i16; 4]]) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut result = self.mix.write(mix.iter().flatten().copied());
+        if let Some(writers) = &mut self.channels {
+            for (i, writer) in writers.iter_mut().enumerate() {
+                result =
+                    result.and_then(|()| writer.write(channels.iter().map(|sample| sample[i])));
+            }
+        }
+        self.error = result.err();
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.mix.finish()?;
+        for writer in self.channels.iter_mut().flatten() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+// `<stem>_pulse1.wav` and so on beside `path`
+fn channel_path(path: &Path, channel: Channel) -> PathBuf {
+    let name = match channel {
+        Channel::Pulse1 => "pulse1",
+        Channel::Pulse2 => "pulse2",
+        Channel::Wave => "wave",
+        Channel::Noise => "noise",
+    };
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}_{}.wav", stem, name))
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::RomBuilder;
+use rust_gameboycolor::ai::{Environment, RamDelta};
+use rust_gameboycolor::{DeviceMode, GameBoyColor, JoypadKey, JoypadKeyState, WatchEncoding};
+
+// Counts frames at $C000 and holds the last P1 read at $C001
+fn counter_rom() -> Vec<u8> {
+    let program = [
+        0x21, 0x00, 0xC0, // ld hl, $C000
+        0x36, 0x00, // ld (hl), 0
+        // loop:
+        0xF0, 0x44, 0xFE, 0x90, 0x20, 0xFA, // wait for LY == 144
+        0x34, // inc (hl)
+        0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, // ld a, $10; ldh (P1), a; ldh a, (P1)
+        0xEA, 0x01, 0xC0, // ld ($C001), a
+        0xF0, 0x44, 0xFE, 0x90, 0x28, 0xFA, // wait for LY != 144
+        0x18, 0xE8, // jr loop
+    ];
+    RomBuilder::new("AIENV").program(&program).build()
+}
+
+fn pressing(key: JoypadKey) -> JoypadKeyState {
+    let mut keys = JoypadKeyState::new();
+    keys.set_key(key, true);
+    keys
+}
+
+#[test]
+fn steps_hold_the_action_and_sum_the_rewards() {
+    let mut env = Environment::new(&counter_rom(), DeviceMode::GameBoy).unwrap();
+    env.add_reward(RamDelta::new(0xC000, 1, WatchEncoding::LittleEndian));
+    // A penalty for each frame with A held
+    env.add_reward(|gameboy: &GameBoyColor| {
+        if gameboy.peek(0xC001) & 0x01 == 0 {
+            -0.5
+        } else {
+            0.0
+        }
+    });
+    env.set_max_steps(Some(3));
+    env.reset(1);
+
+    // RAM starts out random, so the first step also rewards the counter being cleared
+    let step = env.step(JoypadKeyState::new());
+    assert!(!step.terminated && !step.truncated);
+    let step = env.step(pressing(JoypadKey::A));
+    assert_eq!(step.reward, 4.0 - 2.0);
+    let step = env.step(JoypadKeyState::new());
+    assert_eq!(step.reward, 4.0);
+    assert!(step.truncated);
+    assert_eq!(env.steps(), 3);
+}
+
+#[test]
+fn episodes_terminate_partway_through_a_step() {
+    let mut env = Environment::new(&counter_rom(), DeviceMode::GameBoy).unwrap();
+    env.add_reward(RamDelta::new(0xC000, 1, WatchEncoding::LittleEndian).scale(2.0));
+    env.set_frame_skip(8);
+    env.set_termination(|gameboy: &GameBoyColor| gameboy.peek(0xC000) >= 10);
+    env.reset(0);
+
+    assert!(!env.step(JoypadKeyState::new()).terminated);
+    assert_eq!(env.gameboy().peek(0xC000), 8);
+    let step = env.step(JoypadKeyState::new());
+    assert!(step.terminated);
+    assert_eq!(step.reward, 4.0);
+    assert_eq!(env.gameboy().peek(0xC000), 10);
+
+    // Reset starts the count again from a freshly powered-on console
+    env.reset(0);
+    assert_eq!(env.steps(), 0);
+    env.step(JoypadKeyState::new());
+    assert_eq!(env.gameboy().peek(0xC000), 8);
+}
+
+#[test]
+fn the_seed_decides_everything_random() {
+    let play = |seed| {
+        let mut env = Environment::new(&counter_rom(), DeviceMode::GameBoyColor).unwrap();
+        env.set_noop_max(30);
+        env.reset(seed);
+        let ram: Vec<u8> = (0xD000..0xD100)
+            .map(|address| env.gameboy().peek(address))
+            .collect();
+        let screen = env.step(pressing(JoypadKey::Right)).screen.to_vec();
+        (ram, env.gameboy().frame_count(), screen)
+    };
+    assert_eq!(play(7), play(7));
+    let (ram, frames, _) = play(7);
+    let (other_ram, other_frames, _) = play(8);
+    assert_ne!(ram, other_ram);
+    assert!(frames <= 34 && other_frames <= 34);
+}
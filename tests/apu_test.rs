@@ -0,0 +1,422 @@
+mod common;
+
+use common::{boot, run_until_serial, RomBuilder, LOOP_FOREVER, SEND_SERIAL_A};
+use rust_gameboycolor::{AudioRing, Channel, DeviceMode, GameBoyColor, ResetKind};
+
+// Starts a constant square wave on channel 1 at full volume on both sides, at
+// 131072 / (2048 - period) Hz.
+fn square_wave_rom(period: u16) -> Vec<u8> {
+    let mut program = Vec::new();
+    for (register, value) in [
+        (0x26, 0x80),                       // NR52: APU on
+        (0x24, 0x77),                       // NR50: max volume
+        (0x25, 0xFF),                       // NR51: all channels to both sides
+        (0x11, 0x80),                       // NR11: 50% duty
+        (0x12, 0xF0),                       // NR12: volume 15, no envelope
+        (0x13, period as u8),               // NR13
+        (0x14, 0x80 | (period >> 8) as u8), // NR14: trigger
+    ] {
+        program.extend_from_slice(&[0x3E, value, 0xE0, register]);
+    }
+    program.extend_from_slice(&LOOP_FOREVER);
+    RomBuilder::new("SQUARE").program(&program).build()
+}
+
+fn third_frame_audio(configure: impl FnOnce(&mut GameBoyColor)) -> Vec<[i16; 2]> {
+    let mut gameboy =
+        GameBoyColor::new(&square_wave_rom(0x700), DeviceMode::GameBoy, None).unwrap();
+    configure(&mut gameboy);
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+    gameboy.audio_buffer().clone()
+}
+
+#[test]
+fn master_volume_scales_and_clips_output() {
+    let full = third_frame_audio(|_| {});
+    assert!(full
+        .iter()
+        .any(|&[left, _]| left as i32 * 16 > i16::MAX as i32));
+
+    let half = third_frame_audio(|gameboy| gameboy.set_master_volume(0.5));
+    let loud = third_frame_audio(|gameboy| gameboy.set_master_volume(16.0));
+    for ((full, half), loud) in full.iter().zip(&half).zip(&loud) {
+        for side in 0..2 {
+            let expected = (full[side] as f32 * 0.5).round() as i16;
+            assert_eq!(half[side], expected);
+            let expected = (full[side] as i32 * 16).clamp(i16::MIN as i32, i16::MAX as i32);
+            assert_eq!(loud[side] as i32, expected);
+        }
+    }
+}
+
+#[test]
+fn muted_output_is_silent() {
+    let muted = third_frame_audio(|gameboy| gameboy.set_muted(true));
+    assert!(!muted.is_empty());
+    assert!(muted.iter().all(|&sample| sample == [0, 0]));
+}
+
+#[test]
+fn disabled_channels_are_left_out_of_the_mix() {
+    let full = third_frame_audio(|_| {});
+    assert!(full.iter().any(|&sample| sample != [0, 0]));
+
+    // The ROM only plays channel 1
+    let without_pulse1 = third_frame_audio(|gameboy| {
+        gameboy.set_channel_enabled(Channel::Pulse1, false);
+        assert!(!gameboy.channel_enabled(Channel::Pulse1));
+    });
+    assert!(without_pulse1.iter().all(|&sample| sample == [0, 0]));
+
+    let pulse1_solo = third_frame_audio(|gameboy| gameboy.solo_channel(Channel::Pulse1));
+    assert_eq!(pulse1_solo, full);
+    let noise_solo = third_frame_audio(|gameboy| {
+        gameboy.solo_channel(Channel::Noise);
+        gameboy.reset(ResetKind::Soft);
+    });
+    assert!(noise_solo.iter().all(|&sample| sample == [0, 0]));
+}
+
+#[test]
+fn sample_rate_sets_samples_per_second() {
+    let rom = square_wave_rom(0x700);
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    assert_eq!(gameboy.sample_rate(), 48_000);
+    gameboy.set_sample_rate(44_100);
+    // 60 frames of 70224 dots at 4194304 Hz
+    let samples: usize = (0..60)
+        .map(|_| {
+            gameboy.execute_frame();
+            gameboy.audio_buffer().len()
+        })
+        .sum();
+    assert!((44_300..=44_303).contains(&samples), "{}", samples);
+}
+
+#[test]
+fn tones_above_the_sample_rate_average_out() {
+    // 131072 Hz, far above what 8 kHz can carry; point sampling would alias it
+    let rom = square_wave_rom(0x7FF);
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    gameboy.set_sample_rate(8_000);
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+    let left: Vec<i32> = gameboy
+        .audio_buffer()
+        .iter()
+        .map(|&[left, _]| left as i32)
+        .collect();
+    let spread = left.iter().max().unwrap() - left.iter().min().unwrap();
+    // Full scale for one channel at volume 15 is 2 * 15 * 256
+    assert!(spread < 15 * 256 / 4, "{}", spread);
+}
+
+#[test]
+fn audio_callback_receives_every_sample_once() {
+    let mut gameboy =
+        GameBoyColor::new(&square_wave_rom(0x700), DeviceMode::GameBoy, None).unwrap();
+    let ring = AudioRing::new(48_000);
+    let writer = ring.clone();
+    gameboy.set_audio_callback(move |samples| writer.push(samples));
+
+    let mut expected = Vec::new();
+    for _ in 0..3 {
+        gameboy.execute_frame();
+        expected.extend_from_slice(gameboy.audio_buffer());
+    }
+    // Single instructions add to the last frame's buffer
+    let frame_samples = gameboy.audio_buffer().len();
+    for _ in 0..1000 {
+        gameboy.execute_instruction();
+    }
+    expected.extend_from_slice(&gameboy.audio_buffer()[frame_samples..]);
+    gameboy.run_cycles(5000);
+    expected.extend_from_slice(gameboy.audio_buffer());
+    assert_eq!(ring.len(), expected.len());
+
+    // Pulled in device-sized blocks, as from another thread
+    let mut pulled = Vec::new();
+    let mut block = [[0; 2]; 512];
+    loop {
+        let count = ring.pop(&mut block);
+        if count == 0 {
+            break;
+        }
+        pulled.extend_from_slice(&block[..count]);
+    }
+    assert_eq!(pulled, expected);
+    assert!(ring.is_empty());
+
+    gameboy.clear_audio_callback();
+    gameboy.execute_frame();
+    assert!(ring.is_empty());
+}
+
+#[test]
+fn audio_ring_drops_the_oldest_samples_when_full() {
+    let ring = AudioRing::new(4);
+    ring.push(&[[1, 1], [2, 2], [3, 3]]);
+    ring.push(&[[4, 4], [5, 5]]);
+    assert_eq!(ring.len(), 4);
+
+    let mut out = [[0; 2]; 3];
+    assert_eq!(ring.pop(&mut out), 3);
+    assert_eq!(out, [[2, 2], [3, 3], [4, 4]]);
+
+    ring.push(&[[6, 6], [7, 7], [8, 8], [9, 9], [10, 10]]);
+    let mut out = [[0; 2]; 8];
+    assert_eq!(ring.pop(&mut out), 4);
+    assert_eq!(out[..4], [[7, 7], [8, 8], [9, 9], [10, 10]]);
+}
+
+#[test]
+fn nr52_channel_bits_follow_the_dacs() {
+    let steps: [&[(u8, u8)]; 10] = [
+        &[(0x26, 0x80), (0x12, 0xF0), (0x14, 0x80)], // NR12 volume 15, trigger channel 1
+        &[(0x12, 0x08)],                             // Volume 0 but increasing keeps the DAC on
+        &[(0x12, 0x00)],                             // DAC off silences channel 1
+        &[(0x14, 0x80)],                             // Triggering with the DAC off does nothing
+        &[(0x1A, 0x80), (0x1E, 0x80)],               // NR30 DAC on, trigger channel 3
+        &[(0x1A, 0x00)],                             // NR30 DAC off
+        &[(0x21, 0xF0), (0x23, 0x80)],               // NR42 volume 15, trigger channel 4
+        &[(0x21, 0x00)],                             // NR42 DAC off
+        &[(0x17, 0xF0), (0x19, 0x80), (0x26, 0x00)], // Channel 2 running, then APU off
+        &[(0x26, 0x80), (0x19, 0x80)],               // Power-off cleared NR22, so the DAC stays off
+    ];
+    let mut program = Vec::new();
+    for writes in steps {
+        for &(register, value) in writes {
+            program.extend_from_slice(&[0x3E, value, 0xE0, register]);
+        }
+        // ldh a, (NR52)
+        program.extend_from_slice(&[0xF0, 0x26]);
+        program.extend_from_slice(&SEND_SERIAL_A);
+    }
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("NR52").program(&program).build();
+    let (mut gameboy, log) = boot(&rom, DeviceMode::GameBoy);
+
+    let bytes = run_until_serial(&mut gameboy, &log, steps.len(), 10);
+    assert_eq!(
+        bytes,
+        [0xF1, 0xF1, 0xF0, 0xF0, 0xF4, 0xF0, 0xF8, 0xF0, 0x70, 0xF0]
+    );
+}
+
+// Powers the APU off, writes NR50, NR12, the NR11 length and wave RAM, powers it back
+// on and reads them, then triggers channel 1 with length enabled and reads NR52 a
+// frame later.
+fn power_cycle_bytes(device_mode: DeviceMode) -> Vec<u8> {
+    let send = |program: &mut Vec<u8>, register: u8| {
+        program.extend_from_slice(&[0xF0, register]);
+        program.extend_from_slice(&SEND_SERIAL_A);
+    };
+    let mut program = Vec::new();
+    program.extend_from_slice(&[0x3E, 0x00, 0xE0, 0x26]);
+    send(&mut program, 0x26);
+    for (register, value) in [(0x24, 0x77), (0x12, 0xF0), (0x11, 0x3E), (0x30, 0x5A)] {
+        program.extend_from_slice(&[0x3E, value, 0xE0, register]);
+    }
+    program.extend_from_slice(&[0x3E, 0x80, 0xE0, 0x26]);
+    for register in [0x24, 0x12, 0x30] {
+        send(&mut program, register);
+    }
+    program.extend_from_slice(&[0x3E, 0xF0, 0xE0, 0x12, 0x3E, 0xC0, 0xE0, 0x14]);
+    // Until LY passes 144 twice
+    program.extend_from_slice(&[
+        0x0E, 0x02, // ld c, 2
+        0xF0, 0x44, 0xFE, 0x90, 0x20, 0xFA, // w1: ldh a, (LY); cp 144; jr nz, w1
+        0xF0, 0x44, 0xFE, 0x90, 0x28, 0xFA, // w2: ldh a, (LY); cp 144; jr z, w2
+        0x0D, 0x20, 0xF1, // dec c; jr nz, w1
+    ]);
+    send(&mut program, 0x26);
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("POWERCYCLE")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let (mut gameboy, log) = boot(&rom, device_mode);
+    run_until_serial(&mut gameboy, &log, 5, 10)
+}
+
+#[test]
+fn powered_off_apu_ignores_register_writes() {
+    // NR11 sets a length of 2 on DMG, so channel 1 has stopped by the last read
+    assert_eq!(
+        power_cycle_bytes(DeviceMode::GameBoy),
+        [0x70, 0x00, 0x00, 0x5A, 0xF0]
+    );
+    // CGB ignores length writes too, so the channel plays its full 64 steps
+    assert_eq!(
+        power_cycle_bytes(DeviceMode::GameBoyColor),
+        [0x70, 0x00, 0x00, 0x5A, 0xF1]
+    );
+}
+
+// Powers the APU on, which restarts the frame sequencer, and plays channel 1 with a
+// length of 1 but length disabled. After `delay` iterations of a 7 M-cycle loop it
+// enables length and reads NR52.
+fn length_enable_nr52(delay: u16) -> u8 {
+    let mut program = vec![0x3E, 0x00, 0xE0, 0x26];
+    for (register, value) in [(0x26, 0x80), (0x12, 0xF0), (0x11, 0x3F), (0x14, 0x80)] {
+        program.extend_from_slice(&[0x3E, value, 0xE0, register]);
+    }
+    let [low, high] = delay.to_le_bytes();
+    program.extend_from_slice(&[
+        0x01, low, high, // ld bc, delay
+        0x0B, 0x78, 0xB1, 0x20, 0xFB, // loop: dec bc; ld a, b; or c; jr nz, loop
+        0x3E, 0x40, 0xE0, 0x14, // enable length
+        0xF0, 0x26,
+    ]);
+    program.extend_from_slice(&SEND_SERIAL_A);
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("LENGTHCLOCK").program(&program).build();
+    let (mut gameboy, log) = boot(&rom, DeviceMode::GameBoy);
+    run_until_serial(&mut gameboy, &log, 1, 10)[0]
+}
+
+#[test]
+fn enabling_length_in_the_first_half_of_a_period_clocks_it() {
+    // The first step after power-on clocks length, so enabling it straight away doesn't
+    assert_eq!(length_enable_nr52(1), 0xF1);
+    // 2800 M-cycles in, step 0 has run and step 1 won't clock length, so the enable
+    // clocks it once and the channel stops
+    assert_eq!(length_enable_nr52(400), 0xF0);
+}
+
+// Fills wave RAM with 0x40-0x4F, turns on the DAC with `frequency` and runs `program`,
+// which sends `sent` bytes, then turns the DAC off and sends the first four bytes of
+// wave RAM.
+fn wave_ram_bytes(device_mode: DeviceMode, frequency: u16, program: &[u8], sent: usize) -> Vec<u8> {
+    let mut code = Vec::new();
+    for offset in 0..16 {
+        code.extend_from_slice(&[0x3E, 0x40 | offset, 0xE0, 0x30 | offset]);
+    }
+    let [low, high] = frequency.to_le_bytes();
+    for (register, value) in [(0x1A, 0x80), (0x1C, 0x20), (0x1D, low), (0x1E, high)] {
+        code.extend_from_slice(&[0x3E, value, 0xE0, register]);
+    }
+    code.extend_from_slice(program);
+    code.extend_from_slice(&[0x3E, 0x00, 0xE0, 0x1A]);
+    for register in 0x30..0x34 {
+        code.extend_from_slice(&[0xF0, register]);
+        code.extend_from_slice(&SEND_SERIAL_A);
+    }
+    code.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("WAVERAM")
+        .cgb_flag(0x80)
+        .program(&code)
+        .build();
+    let (mut gameboy, log) = boot(&rom, device_mode);
+    run_until_serial(&mut gameboy, &log, sent + 4, 10)
+}
+
+#[test]
+fn wave_ram_reads_while_playing_see_the_current_byte() {
+    // Trigger, then read the last byte of wave RAM
+    let read = |device_mode, frequency: u16| {
+        let mut program = vec![0x3E, 0x80 | (frequency >> 8) as u8, 0xE0, 0x1E, 0xF0, 0x3F];
+        program.extend_from_slice(&SEND_SERIAL_A);
+        wave_ram_bytes(device_mode, frequency, &program, 1)
+    };
+
+    // The channel is still on byte 0, which a CGB reads wherever the address points
+    assert_eq!(
+        read(DeviceMode::GameBoyColor, 0),
+        [0x40, 0x40, 0x41, 0x42, 0x43]
+    );
+    // A DMG reads 0xFF unless a byte was fetched in the same cycle
+    assert_eq!(read(DeviceMode::GameBoy, 0), [0xFF, 0x40, 0x41, 0x42, 0x43]);
+    // Which at the highest frequency happens every cycle
+    let byte = read(DeviceMode::GameBoy, 0x7FF)[0];
+    assert!((0x40..=0x4F).contains(&byte), "{:#04X}", byte);
+}
+
+#[test]
+fn retriggering_the_wave_channel_corrupts_wave_ram_on_dmg() {
+    // Trigger at the highest frequency, wait `nops` and trigger again, just as a byte
+    // is fetched
+    let retrigger = |nops: usize| {
+        let mut program = vec![0x3E, 0x87, 0xE0, 0x1E];
+        program.extend(std::iter::repeat_n(0x00, nops));
+        program.extend_from_slice(&[0xE0, 0x1E]);
+        program
+    };
+
+    assert_eq!(
+        wave_ram_bytes(DeviceMode::GameBoyColor, 0x7FF, &retrigger(6), 0),
+        [0x40, 0x41, 0x42, 0x43]
+    );
+    // Fetching one of the first four bytes copies it to byte 0
+    assert_eq!(
+        wave_ram_bytes(DeviceMode::GameBoy, 0x7FF, &retrigger(0), 0),
+        [0x43, 0x41, 0x42, 0x43]
+    );
+    // Any later byte copies its aligned four to the start
+    assert_eq!(
+        wave_ram_bytes(DeviceMode::GameBoy, 0x7FF, &retrigger(6), 0),
+        [0x48, 0x49, 0x4A, 0x4B]
+    );
+}
+
+// The format fields and samples of a 16-bit PCM WAV file
+fn read_wav(path: &std::path::Path) -> (u16, u32, Vec<i16>) {
+    let data = std::fs::read(path).unwrap();
+    assert_eq!(&data[..4], b"RIFF");
+    assert_eq!(
+        u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize,
+        data.len() - 8
+    );
+    assert_eq!(&data[8..16], b"WAVEfmt ");
+    assert_eq!(&data[36..40], b"data");
+    assert_eq!(
+        u32::from_le_bytes(data[40..44].try_into().unwrap()) as usize,
+        data.len() - 44
+    );
+    let channels = u16::from_le_bytes(data[22..24].try_into().unwrap());
+    let sample_rate = u32::from_le_bytes(data[24..28].try_into().unwrap());
+    let samples = data[44..]
+        .chunks_exact(2)
+        .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect();
+    (channels, sample_rate, samples)
+}
+
+#[test]
+fn audio_recording_writes_the_mix_and_each_channel() {
+    let dir = std::env::temp_dir().join(format!("rust-gameboycolor-wav-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("square.wav");
+
+    let mut gameboy =
+        GameBoyColor::new(&square_wave_rom(0x700), DeviceMode::GameBoy, None).unwrap();
+    gameboy.set_sample_rate(44_100);
+    gameboy.execute_frame();
+    gameboy.start_audio_recording(&path, true).unwrap();
+    let mut mix = Vec::new();
+    for _ in 0..3 {
+        gameboy.execute_frame();
+        mix.extend(gameboy.audio_buffer().iter().flatten());
+    }
+    gameboy.stop_audio_recording().unwrap();
+    // Later frames are not recorded
+    gameboy.execute_frame();
+
+    assert_eq!(read_wav(&path), (2, 44_100, mix.clone()));
+    let channel = |name: &str| {
+        let (channels, sample_rate, samples) = read_wav(&dir.join(format!("square_{}.wav", name)));
+        assert_eq!((channels, sample_rate), (1, 44_100));
+        assert_eq!(samples.len(), mix.len() / 2);
+        samples
+    };
+    // Only channel 1 plays
+    let pulse1 = channel("pulse1");
+    assert!(pulse1.iter().any(|&sample| sample != 0));
+    for name in ["pulse2", "wave", "noise"] {
+        assert!(channel(name).iter().all(|&sample| sample == 0), "{}", name);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
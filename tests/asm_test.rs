@@ -0,0 +1,112 @@
+mod common;
+
+use common::asm::assemble;
+use common::{boot, run_until_serial, RomBuilder, LOOP_FOREVER, SEND_SERIAL_A};
+use rust_gameboycolor::DeviceMode;
+
+#[test]
+fn assembles_the_shared_snippets() {
+    assert_eq!(assemble(0x0150, "jr @"), LOOP_FOREVER);
+    assert_eq!(
+        assemble(
+            0x0150,
+            "
+                ldh [SB], a
+                ld a, $81
+                ldh [SC], a
+            wait:
+                ldh a, [SC]
+                bit 7, a
+                jr nz, wait
+            "
+        ),
+        SEND_SERIAL_A
+    );
+}
+
+#[test]
+fn encodes_each_operand_form() {
+    let cases: &[(&str, &[u8])] = &[
+        ("ld b, c", &[0x41]),
+        ("ld (hl), $12", &[0x36, 0x12]),
+        ("LD A, [HL-]", &[0x3A]),
+        ("ld (de), a", &[0x12]),
+        ("ld sp, $DFFF", &[0x31, 0xFF, 0xDF]),
+        ("ld ($C000), sp", &[0x08, 0x00, 0xC0]),
+        ("ld hl, sp-2", &[0xF8, 0xFE]),
+        ("ld a, ($FF00+c)", &[0xF2]),
+        ("ldh a, ($FF44)", &[0xF0, 0x44]),
+        ("ldh (LCDC), a", &[0xE0, 0x40]),
+        ("add hl, de", &[0x19]),
+        ("add sp, -1", &[0xE8, 0xFF]),
+        ("adc a, (hl)", &[0x8E]),
+        ("cp %1010", &[0xFE, 0x0A]),
+        ("dec sp", &[0x3B]),
+        ("inc l", &[0x2C]),
+        ("swap a", &[0xCB, 0x37]),
+        ("res 0, (hl)", &[0xCB, 0x86]),
+        ("push af", &[0xF5]),
+        ("jp hl", &[0xE9]),
+        ("call nc, $4000", &[0xD4, 0x00, 0x40]),
+        ("ret z", &[0xC8]),
+        ("rst $38", &[0xFF]),
+        ("stop", &[0x10, 0x00]),
+        ("db 1, $FF, -1", &[0x01, 0xFF, 0xFF]),
+    ];
+    for &(source, bytes) in cases {
+        assert_eq!(assemble(0, source), bytes, "{}", source);
+    }
+}
+
+#[test]
+fn resolves_labels_forwards_and_backwards() {
+    let code = assemble(
+        0xC000,
+        "
+        start:
+            jr z, end
+            call start+1
+        end:
+            jp start ; back
+        ",
+    );
+    assert_eq!(code, [0x28, 0x03, 0xCD, 0x01, 0xC0, 0xC3, 0x00, 0xC0]);
+}
+
+#[test]
+#[should_panic(expected = "Line 2: ld a, missing: Unknown value \"missing\"")]
+fn reports_the_line_of_an_error() {
+    assemble(0, "nop\nld a, missing\n");
+}
+
+#[test]
+fn assembled_programs_run() {
+    let rom = RomBuilder::new("ASM")
+        .asm(
+            "
+                ld a, 6
+                ld b, 7
+                ld c, a
+                xor a
+            multiply:
+                add a, c
+                dec b
+                jr nz, multiply
+                call send
+                jr @
+            send:
+                ldh [SB], a
+                ld a, $81
+                ldh [SC], a
+            wait:
+                ldh a, [SC]
+                bit 7, a
+                jr nz, wait
+                ret
+            ",
+        )
+        .build();
+    let (mut gameboy, log) = boot(&rom, DeviceMode::GameBoy);
+
+    assert_eq!(run_until_serial(&mut gameboy, &log, 1, 10), [42]);
+}
@@ -1,3 +1,6 @@
+mod common;
+
+use common::dump_screen;
 use rust_gameboycolor::{DeviceMode, GameBoyColor, LinkCable};
 
 use std::cell::RefCell;
@@ -59,8 +62,15 @@ fn blagg_test(rom_name: &str) -> Result<()> {
     let completed_ref = completed.borrow();
     match completed_ref.as_ref() {
         Some(Ok(())) => Ok(()),
-        Some(Err(e)) => bail!("Test failed: {}", e),
-        None => bail!("Test did not complete"),
+        Some(Err(e)) => bail!(
+            "Test failed: {} (screen saved to {})",
+            e,
+            dump_screen(&gameboy, rom_name).display()
+        ),
+        None => bail!(
+            "Test did not complete (screen saved to {})",
+            dump_screen(&gameboy, rom_name).display()
+        ),
     }
 }
 
@@ -98,4 +108,10 @@ generate_rom_tests!(
     "10-bit ops.gb",
     test_11_op_a_hl,
     "11-op a,(hl).gb",
+    test_dmg_sound_03_trigger,
+    "03-trigger.gb",
+    test_dmg_sound_09_wave_read_while_on,
+    "09-wave read while on.gb",
+    test_dmg_sound_10_wave_trigger_while_on,
+    "10-wave trigger while on.gb",
 );
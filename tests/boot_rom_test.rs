@@ -0,0 +1,65 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, EmulatorConfigBuilder, EmulatorError, GameBoyColor, Model};
+
+// Reads 0x0000 into B and the header byte at 0x0104 into D, then unmaps itself
+// with its last instruction so execution falls through to the cartridge at 0x0100.
+fn boot_rom(size: usize) -> Vec<u8> {
+    let mut boot_rom = vec![0x00; size];
+    boot_rom[..8].copy_from_slice(&[
+        0xFA, 0x00, 0x00, 0x47, // ld a, ($0000); ld b, a
+        0xFA, 0x04, 0x01, 0x57, // ld a, ($0104); ld d, a
+    ]);
+    boot_rom[0xFC..0x100].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]); // ld a, 1; ldh ($50), a
+    boot_rom
+}
+
+fn run(rom: &[u8], device_mode: DeviceMode, boot_rom: Vec<u8>) -> GameBoyColor {
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(device_mode)
+        .boot_rom(boot_rom)
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(rom, config).unwrap();
+    assert_eq!(gameboy.cpu_state().pc, 0x0000);
+    for _ in 0..400 {
+        gameboy.execute_instruction();
+    }
+    gameboy
+}
+
+#[test]
+fn boot_rom_runs_then_unmaps_on_ff50_write() {
+    // ld a, ($0000); ld c, a
+    let mut program = vec![0xFA, 0x00, 0x00, 0x4F];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("BOOTROM").program(&program).build();
+
+    for (device_mode, size) in [
+        (DeviceMode::GameBoy, 0x100),
+        (DeviceMode::GameBoyColor, 0x900),
+    ] {
+        let state = run(&rom, device_mode, boot_rom(size)).cpu_state();
+        // Boot ROM mapped at reset, header visible through it, cartridge mapped after FF50
+        assert_eq!(state.b, 0xFA);
+        assert_eq!(state.d, rom[0x0104]);
+        assert_eq!(state.c, rom[0x0000]);
+        assert!((0x0150..0x0160).contains(&state.pc));
+    }
+}
+
+#[test]
+fn boot_rom_of_the_wrong_size_is_rejected() {
+    let rom = RomBuilder::new("BOOTROM").program(&LOOP_FOREVER).build();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .model(Model::Dmg)
+        .boot_rom(vec![0; 0x900])
+        .build()
+        .unwrap();
+    assert!(matches!(
+        GameBoyColor::with_config(&rom, config),
+        Err(EmulatorError::BootRomError(_))
+    ));
+}
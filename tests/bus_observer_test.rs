@@ -0,0 +1,58 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{AccessKind, BusAccess, DeviceMode, GameBoyColor};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn gameboy() -> GameBoyColor {
+    let mut program = vec![
+        0x3E, 0x42, 0xEA, 0x00, 0xC0, // ld a, $42; ld ($C000), a
+        0xFA, 0x00, 0xC0, // ld a, ($C000)
+        0xEA, 0x00, 0xD0, // ld ($D000), a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("SNOOP").program(&program).build();
+    GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap()
+}
+
+#[test]
+fn observers_see_cpu_accesses_in_their_range() {
+    let mut gameboy = gameboy();
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let log = seen.clone();
+    gameboy.add_bus_observer(0xC000..=0xCFFF, move |access| {
+        log.borrow_mut().push(*access)
+    });
+    gameboy.execute_frame();
+
+    assert_eq!(
+        *seen.borrow(),
+        [
+            BusAccess {
+                kind: AccessKind::Write,
+                address: 0xC000,
+                value: 0x42,
+            },
+            BusAccess {
+                kind: AccessKind::Read,
+                address: 0xC000,
+                value: 0x42,
+            },
+        ]
+    );
+}
+
+#[test]
+fn removed_observers_are_no_longer_called() {
+    let mut gameboy = gameboy();
+    let count = Rc::new(RefCell::new(0));
+    let counter = count.clone();
+    let id = gameboy.add_bus_observer(0x0000..=0xFFFF, move |_| *counter.borrow_mut() += 1);
+    assert!(gameboy.remove_bus_observer(id));
+    assert!(!gameboy.remove_bus_observer(id));
+
+    gameboy.execute_frame();
+    assert_eq!(*count.borrow(), 0);
+}
@@ -0,0 +1,94 @@
+#![cfg(feature = "capi")]
+
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::capi::*;
+use rust_gameboycolor::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use std::ffi::CStr;
+
+unsafe fn last_error(emulator: *const GbcEmulator) -> String {
+    CStr::from_ptr(gbc_last_error(emulator))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn runs_frames_and_hands_out_video_and_audio() {
+    let rom = RomBuilder::new("CAPIFRAME").program(&LOOP_FOREVER).build();
+    unsafe {
+        let emulator = gbc_create();
+        assert_eq!(gbc_run_frame(emulator), -1);
+        assert_eq!(last_error(emulator), "No ROM loaded");
+
+        assert_eq!(gbc_load_rom(emulator, rom.as_ptr(), rom.len(), false), 0);
+        gbc_set_keys(emulator, 0x81);
+        assert_eq!(gbc_run_frame(emulator), 0);
+        let frame =
+            std::slice::from_raw_parts(gbc_framebuffer(emulator), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+        assert!(frame.chunks_exact(4).all(|pixel| pixel[3] == 0xFF));
+
+        // About 804 samples a frame at 48 kHz, each taken once
+        let mut audio = vec![0; 2000];
+        let pulled = gbc_audio_pull(emulator, audio.as_mut_ptr(), 1000);
+        assert!((790..=820).contains(&pulled), "{}", pulled);
+        assert_eq!(gbc_audio_pull(emulator, audio.as_mut_ptr(), 1000), 0);
+
+        assert_eq!(gbc_set_sample_rate(emulator, 1), -1);
+        assert_eq!(last_error(emulator), "Unsupported sample rate: 1 Hz");
+        gbc_destroy(emulator);
+    }
+}
+
+#[test]
+fn bad_roms_fail_without_unwinding() {
+    let rom = RomBuilder::new("CAPIBAD").cartridge_type(0x42).build();
+    unsafe {
+        let emulator = gbc_create();
+        assert_eq!(gbc_load_rom(emulator, rom.as_ptr(), 0x100, true), -1);
+        assert_eq!(
+            last_error(emulator),
+            "Invalid ROM: ROM is 256 bytes, too short to hold a header"
+        );
+        assert_eq!(gbc_load_rom(emulator, rom.as_ptr(), rom.len(), true), -1);
+        assert_eq!(gbc_run_frame(emulator), -1);
+        gbc_destroy(emulator);
+    }
+}
+
+#[test]
+fn battery_saves_go_out_and_come_back_in() {
+    // Enable RAM and write 0x42 to its first byte
+    let program = [
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, // ld a, $0A; ld ($0000), a
+        0x3E, 0x42, 0xEA, 0x00, 0xA0, // ld a, $42; ld ($A000), a
+        0x18, 0xFE, // jr -2
+    ];
+    let rom = RomBuilder::new("CAPISAVE")
+        .cartridge_type(0x03)
+        .ram_size(0x02)
+        .program(&program)
+        .build();
+    unsafe {
+        let emulator = gbc_create();
+        assert_eq!(gbc_save_size(emulator), 0);
+        assert_eq!(gbc_load_rom(emulator, rom.as_ptr(), rom.len(), false), 0);
+        gbc_run_frame(emulator);
+
+        let mut save = vec![0; gbc_save_size(emulator)];
+        assert_eq!(save.len(), 0x2000);
+        assert_eq!(gbc_save(emulator, save.as_mut_ptr(), 16), -1);
+        assert_eq!(gbc_save(emulator, save.as_mut_ptr(), save.len()), 0);
+        assert_eq!(save[..2], [0x42, 0x00]);
+
+        // The game starts again from the loaded RAM
+        save[1] = 0x99;
+        assert_eq!(gbc_load_save(emulator, save.as_ptr(), save.len()), 0);
+        gbc_run_frame(emulator);
+        let mut reloaded = vec![0; save.len()];
+        gbc_save(emulator, reloaded.as_mut_ptr(), reloaded.len());
+        assert_eq!(reloaded, save);
+        gbc_destroy(emulator);
+    }
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use common::{boot, run_until_serial, send_serial_imm, RomBuilder, LOOP_FOREVER, PROGRAM_START};
+use rust_gameboycolor::{CheatError, DeviceMode, ResetKind, RomPatch};
+
+#[test]
+fn game_genie_codes_decode() {
+    let patch: RomPatch = "AB2-34E-AE2".parse().unwrap();
+    assert_eq!(patch, RomPatch::new(0x1234, 0xAB).with_compare(0x12));
+    assert_eq!(
+        RomPatch::from_game_genie("00a-17b"),
+        Ok(RomPatch::new(0x4A17, 0x00))
+    );
+
+    for code in [
+        "AB2-34",
+        "AB2-34E-AE",
+        "AB2-34E-AE2-000",
+        "XB2-34E",
+        "AB2-340",
+    ] {
+        assert_eq!(
+            RomPatch::from_game_genie(code),
+            Err(CheatError::InvalidGameGenie(code.to_string())),
+            "{}",
+            code
+        );
+    }
+}
+
+#[test]
+fn rom_patches_follow_the_mapped_bank_and_toggle_live() {
+    let mut program = send_serial_imm(0x11).to_vec();
+    // ld a, 2; ld (0x2000), a
+    program.extend_from_slice(&[0x3E, 0x02, 0xEA, 0x00, 0x20]);
+    program.extend_from_slice(&LOOP_FOREVER);
+    // MBC5, 4 banks, each starting with its number
+    let mut rom = RomBuilder::new("PATCHES")
+        .cartridge_type(0x19)
+        .rom_size(0x01)
+        .program(&program)
+        .build();
+    for bank in 1..4 {
+        rom[bank * 0x4000] = bank as u8;
+    }
+    let (mut gameboy, log) = boot(&rom, DeviceMode::GameBoy);
+
+    // The immediate of the first instruction
+    gameboy.add_rom_patch(RomPatch::new(PROGRAM_START as u16 + 1, 0x22));
+    gameboy.add_rom_patch(RomPatch::new(0x4000, 0xEE).in_bank(1));
+    gameboy.add_rom_patch(RomPatch::new(0x4000, 0x99).with_compare(1));
+    let bank_2 = gameboy.add_rom_patch(RomPatch::new(0x4001, 0x77).in_bank(2));
+    assert_eq!(gameboy.peek(0x4000), 0xEE);
+
+    assert_eq!(run_until_serial(&mut gameboy, &log, 1, 10), [0x22]);
+    gameboy.execute_frame();
+    assert_eq!(gameboy.peek(0x4000), 0x02);
+    assert_eq!(gameboy.peek(0x4001), 0x77);
+
+    assert!(gameboy.set_rom_patch_enabled(bank_2, false));
+    assert_eq!(gameboy.peek(0x4001), 0x00);
+    assert!(gameboy.set_rom_patch_enabled(bank_2, true));
+    assert_eq!(gameboy.peek(0x4001), 0x77);
+    assert!(gameboy.remove_rom_patch(bank_2));
+    assert!(!gameboy.remove_rom_patch(bank_2));
+    assert!(!gameboy.set_rom_patch_enabled(bank_2, true));
+    assert_eq!(gameboy.peek(0x4001), 0x00);
+
+    // Kept across a reset, until cleared
+    gameboy.reset(ResetKind::Soft);
+    assert_eq!(run_until_serial(&mut gameboy, &log, 2, 10), [0x22, 0x22]);
+    gameboy.clear_rom_patches();
+    gameboy.reset(ResetKind::Soft);
+    assert_eq!(
+        run_until_serial(&mut gameboy, &log, 3, 10),
+        [0x22, 0x22, 0x11]
+    );
+}
+
+#[test]
+fn rom_patches_follow_the_8_kib_banks_of_mbc6() {
+    // ld a, 5; ld (0x2000), a; ld a, 6; ld (0x3000), a
+    let mut program = vec![0x3E, 0x05, 0xEA, 0x00, 0x20, 0x3E, 0x06, 0xEA, 0x00, 0x30];
+    program.extend_from_slice(&LOOP_FOREVER);
+    // MBC6, 16 banks of 8 KiB, each starting with its number
+    let mut rom = RomBuilder::new("MBC6PATCH")
+        .cartridge_type(0x20)
+        .rom_size(0x02)
+        .program(&program)
+        .build();
+    for bank in 2..16 {
+        rom[bank * 0x2000] = bank as u8;
+    }
+    let (mut gameboy, _) = boot(&rom, DeviceMode::GameBoy);
+    gameboy.execute_frame();
+    assert_eq!((gameboy.peek(0x4000), gameboy.peek(0x6000)), (5, 6));
+
+    gameboy.add_rom_patch(RomPatch::new(0x4000, 0x55).in_bank(5));
+    gameboy.add_rom_patch(RomPatch::new(0x6000, 0x66).in_bank(5));
+    assert_eq!((gameboy.peek(0x4000), gameboy.peek(0x6000)), (0x55, 6));
+}
@@ -0,0 +1,435 @@
+//! Assembles SM83 source into machine code, so tests can write programs as text instead
+//! of opcode bytes.
+//!
+//! One instruction per line, in the syntax Pan Docs and RGBDS use: `ld a, [hl+]` or
+//! `ld a, (hl+)`, `ldh (LY), a`, `jr nz, loop`. Labels end with `:`, `;` starts a
+//! comment and `db` emits bytes. Numbers are decimal, `$` or `0x` hex, or `%` binary,
+//! and can be added to and subtracted from labels, I/O register names such as `LY` and
+//! `@`, the address of the instruction.
+
+use std::collections::HashMap;
+
+const IO_REGISTERS: [(&str, u16); 41] = [
+    ("P1", 0xFF00),
+    ("SB", 0xFF01),
+    ("SC", 0xFF02),
+    ("DIV", 0xFF04),
+    ("TIMA", 0xFF05),
+    ("TMA", 0xFF06),
+    ("TAC", 0xFF07),
+    ("IF", 0xFF0F),
+    ("NR10", 0xFF10),
+    ("NR11", 0xFF11),
+    ("NR12", 0xFF12),
+    ("NR13", 0xFF13),
+    ("NR14", 0xFF14),
+    ("NR50", 0xFF24),
+    ("NR51", 0xFF25),
+    ("NR52", 0xFF26),
+    ("LCDC", 0xFF40),
+    ("STAT", 0xFF41),
+    ("SCY", 0xFF42),
+    ("SCX", 0xFF43),
+    ("LY", 0xFF44),
+    ("LYC", 0xFF45),
+    ("DMA", 0xFF46),
+    ("BGP", 0xFF47),
+    ("OBP0", 0xFF48),
+    ("OBP1", 0xFF49),
+    ("WY", 0xFF4A),
+    ("WX", 0xFF4B),
+    ("KEY1", 0xFF4D),
+    ("VBK", 0xFF4F),
+    ("HDMA1", 0xFF51),
+    ("HDMA2", 0xFF52),
+    ("HDMA3", 0xFF53),
+    ("HDMA4", 0xFF54),
+    ("HDMA5", 0xFF55),
+    ("RP", 0xFF56),
+    ("BCPS", 0xFF68),
+    ("BCPD", 0xFF69),
+    ("OCPS", 0xFF6A),
+    ("OCPD", 0xFF6B),
+    ("SVBK", 0xFF70),
+];
+
+const R8: [&str; 8] = ["b", "c", "d", "e", "h", "l", "(hl)", "a"];
+const R16: [&str; 4] = ["bc", "de", "hl", "sp"];
+const R16_STACK: [&str; 4] = ["bc", "de", "hl", "af"];
+const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+const SHIFTS: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+const NO_OPERANDS: [(&str, &[u8]); 14] = [
+    ("nop", &[0x00]),
+    ("stop", &[0x10, 0x00]),
+    ("halt", &[0x76]),
+    ("di", &[0xF3]),
+    ("ei", &[0xFB]),
+    ("rlca", &[0x07]),
+    ("rrca", &[0x0F]),
+    ("rla", &[0x17]),
+    ("rra", &[0x1F]),
+    ("daa", &[0x27]),
+    ("cpl", &[0x2F]),
+    ("scf", &[0x37]),
+    ("ccf", &[0x3F]),
+    ("reti", &[0xD9]),
+];
+
+type Result<T> = std::result::Result<T, String>;
+
+/// Assembles `source` to run at `origin`, panicking with the line on an error.
+pub fn assemble(origin: u16, source: &str) -> Vec<u8> {
+    let fail = |number: usize, line: &str, error: String| -> ! {
+        panic!("Line {}: {}: {}", number + 1, line.trim(), error)
+    };
+
+    // Instructions have the same size whatever their operands' values, so a first pass
+    // with the labels unknown finds where each one is
+    let mut labels = HashMap::new();
+    let mut address = origin;
+    for (number, line) in source.lines().enumerate() {
+        let (label, instruction) = split_line(line);
+        if let Some(label) = label {
+            if labels.insert(label.to_string(), address).is_some() {
+                fail(number, line, format!("Duplicate label {:?}", label));
+            }
+        }
+        if let Some(instruction) = instruction {
+            let line_assembler = Line {
+                labels: &labels,
+                address,
+                resolve: false,
+            };
+            match line_assembler.instruction(instruction) {
+                Ok(bytes) => address = address.wrapping_add(bytes.len() as u16),
+                Err(error) => fail(number, line, error),
+            }
+        }
+    }
+
+    let mut code = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        if let (_, Some(instruction)) = split_line(line) {
+            let line_assembler = Line {
+                labels: &labels,
+                address: origin.wrapping_add(code.len() as u16),
+                resolve: true,
+            };
+            match line_assembler.instruction(instruction) {
+                Ok(bytes) => code.extend(bytes),
+                Err(error) => fail(number, line, error),
+            }
+        }
+    }
+    code
+}
+
+fn split_line(line: &str) -> (Option<&str>, Option<&str>) {
+    let line = line.split(';').next().unwrap().trim();
+    let (label, rest) = match line.split_once(':') {
+        Some((label, rest)) if is_identifier(label.trim()) => (Some(label.trim()), rest),
+        _ => (None, line),
+    };
+    let rest = rest.trim();
+    (label, (!rest.is_empty()).then_some(rest))
+}
+
+// One instruction at `address`
+struct Line<'a> {
+    labels: &'a HashMap<String, u16>,
+    address: u16,
+    // Labels not defined yet count as 0 in the first pass
+    resolve: bool,
+}
+
+impl Line<'_> {
+    fn instruction(&self, text: &str) -> Result<Vec<u8>> {
+        let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic, operands.trim()),
+            None => (text, ""),
+        };
+        let mnemonic = mnemonic.to_ascii_lowercase();
+        let operands: Vec<String> = if operands.is_empty() {
+            Vec::new()
+        } else {
+            operands.split(',').map(normalize).collect()
+        };
+        let operands: Vec<&str> = operands.iter().map(String::as_str).collect();
+
+        if let Some((_, bytes)) = NO_OPERANDS.iter().find(|(name, _)| *name == mnemonic) {
+            if !operands.is_empty() {
+                return Err(format!("{} takes no operands", mnemonic));
+            }
+            return Ok(bytes.to_vec());
+        }
+        if let Some(op) = position(&ALU, &mnemonic) {
+            // `add a, b` and `add b` are the same, but `add hl, bc` and `add sp, e8` are not
+            return match operands[..] {
+                ["hl", r16] if op == 0 => Ok(vec![0x09 | self.r16(r16)? << 4]),
+                ["sp", offset] if op == 0 => Ok(vec![0xE8, self.imm8(offset)?]),
+                ["a", source] | [source] => match position(&R8, source) {
+                    Some(r) => Ok(vec![0x80 | op << 3 | r]),
+                    None => Ok(vec![0xC6 | op << 3, self.imm8(source)?]),
+                },
+                _ => Err("Invalid operands".to_string()),
+            };
+        }
+        if let Some(op) = position(&SHIFTS, &mnemonic) {
+            return match operands[..] {
+                [register] => Ok(vec![0xCB, op << 3 | self.r8(register)?]),
+                _ => Err("Expected one operand".to_string()),
+            };
+        }
+
+        let bytes = match (mnemonic.as_str(), &operands[..]) {
+            ("db", bytes) => bytes
+                .iter()
+                .map(|byte| self.imm8(byte))
+                .collect::<Result<_>>()?,
+            ("ld", [destination, source]) => self.ld(destination, source)?,
+            ("ldh", ["(c)", "a"]) => vec![0xE2],
+            ("ldh", ["a", "(c)"]) => vec![0xF2],
+            ("ldh", [destination, "a"]) => vec![0xE0, self.high_page(destination)?],
+            ("ldh", ["a", source]) => vec![0xF0, self.high_page(source)?],
+            ("inc" | "dec", [operand]) => {
+                let dec = (mnemonic == "dec") as u8;
+                match position(&R8, operand) {
+                    Some(r) => vec![0x04 | r << 3 | dec],
+                    None => vec![0x03 | self.r16(operand)? << 4 | dec << 3],
+                }
+            }
+            ("jr", [target]) => vec![0x18, self.relative(target)?],
+            ("jr", [condition, target]) => {
+                vec![
+                    0x20 | self.condition(condition)? << 3,
+                    self.relative(target)?,
+                ]
+            }
+            ("jp", ["hl" | "(hl)"]) => vec![0xE9],
+            ("jp", [target]) => with_u16(0xC3, self.imm16(target)?),
+            ("jp", [condition, target]) => {
+                with_u16(0xC2 | self.condition(condition)? << 3, self.imm16(target)?)
+            }
+            ("call", [target]) => with_u16(0xCD, self.imm16(target)?),
+            ("call", [condition, target]) => {
+                with_u16(0xC4 | self.condition(condition)? << 3, self.imm16(target)?)
+            }
+            ("ret", []) => vec![0xC9],
+            ("ret", [condition]) => vec![0xC0 | self.condition(condition)? << 3],
+            ("rst", [vector]) => match self.imm8(vector)? {
+                vector if vector & !0x38 == 0 => vec![0xC7 | vector],
+                vector => return Err(format!("Invalid RST vector {:#04X}", vector)),
+            },
+            ("push", [register]) => vec![0xC5 | self.r16_stack(register)? << 4],
+            ("pop", [register]) => vec![0xC1 | self.r16_stack(register)? << 4],
+            ("bit" | "res" | "set", [bit, register]) => {
+                let op = match mnemonic.as_str() {
+                    "bit" => 0x40,
+                    "res" => 0x80,
+                    _ => 0xC0,
+                };
+                match self.imm8(bit)? {
+                    bit @ 0..=7 => vec![0xCB, op | bit << 3 | self.r8(register)?],
+                    bit => return Err(format!("Invalid bit {}", bit)),
+                }
+            }
+            _ => return Err("Unknown instruction".to_string()),
+        };
+        Ok(bytes)
+    }
+
+    fn ld(&self, destination: &str, source: &str) -> Result<Vec<u8>> {
+        let memory = |operand: &str| match operand {
+            "(bc)" => Some(0),
+            "(de)" => Some(1),
+            "(hl+)" | "(hli)" => Some(2),
+            "(hl-)" | "(hld)" => Some(3),
+            _ => None,
+        };
+        let bytes = match (destination, source) {
+            ("(c)" | "($ff00+c)" | "(0xff00+c)", "a") => vec![0xE2],
+            ("a", "(c)" | "($ff00+c)" | "(0xff00+c)") => vec![0xF2],
+            ("sp", "hl") => vec![0xF9],
+            ("hl", source) if source.starts_with("sp+") || source.starts_with("sp-") => {
+                vec![0xF8, self.imm8(&source[2..])?]
+            }
+            (destination, "a") if memory(destination).is_some() => {
+                vec![0x02 | memory(destination).unwrap() << 4]
+            }
+            ("a", source) if memory(source).is_some() => vec![0x0A | memory(source).unwrap() << 4],
+            (destination, source) if position(&R8, destination).is_some() => {
+                let d = position(&R8, destination).unwrap();
+                match position(&R8, source) {
+                    Some(6) if d == 6 => return Err("ld (hl), (hl) is halt".to_string()),
+                    Some(s) => vec![0x40 | d << 3 | s],
+                    None if d == 7 && is_indirect(source) => {
+                        with_u16(0xFA, self.imm16(inner(source))?)
+                    }
+                    None => vec![0x06 | d << 3, self.imm8(source)?],
+                }
+            }
+            (destination, source) if position(&R16, destination).is_some() => {
+                with_u16(0x01 | self.r16(destination)? << 4, self.imm16(source)?)
+            }
+            (destination, "a") if is_indirect(destination) => {
+                with_u16(0xEA, self.imm16(inner(destination))?)
+            }
+            (destination, "sp") if is_indirect(destination) => {
+                with_u16(0x08, self.imm16(inner(destination))?)
+            }
+            _ => return Err("Invalid operands".to_string()),
+        };
+        Ok(bytes)
+    }
+
+    // `(n)` for LDH, taking either the full address or its low byte
+    fn high_page(&self, operand: &str) -> Result<u8> {
+        if !is_indirect(operand) {
+            return Err(format!("Expected (address), got {:?}", operand));
+        }
+        match self.value(inner(operand))? {
+            address @ (0x00..=0xFF | 0xFF00..=0xFFFF) => Ok(address as u8),
+            address => Err(format!("LDH can't reach {:#06X}", address)),
+        }
+    }
+
+    fn relative(&self, target: &str) -> Result<u8> {
+        let offset = self.value(target)? - (self.address as i64 + 2);
+        if self.resolve && !(-128..=127).contains(&offset) {
+            return Err(format!("{} is out of JR range", target));
+        }
+        Ok(offset as u8)
+    }
+
+    fn r8(&self, operand: &str) -> Result<u8> {
+        position(&R8, operand).ok_or_else(|| "Expected an 8-bit register".to_string())
+    }
+
+    fn r16(&self, operand: &str) -> Result<u8> {
+        position(&R16, operand).ok_or_else(|| "Expected bc, de, hl or sp".to_string())
+    }
+
+    fn r16_stack(&self, operand: &str) -> Result<u8> {
+        position(&R16_STACK, operand).ok_or_else(|| "Expected bc, de, hl or af".to_string())
+    }
+
+    fn condition(&self, operand: &str) -> Result<u8> {
+        position(&CONDITIONS, operand).ok_or_else(|| "Expected nz, z, nc or c".to_string())
+    }
+
+    fn imm8(&self, operand: &str) -> Result<u8> {
+        match self.value(operand)? {
+            value @ -128..=255 => Ok(value as u8),
+            _ if !self.resolve => Ok(0),
+            _ => Err(format!("{} doesn't fit in a byte", operand)),
+        }
+    }
+
+    fn imm16(&self, operand: &str) -> Result<u16> {
+        match self.value(operand)? {
+            value @ -32768..=65535 => Ok(value as u16),
+            _ if !self.resolve => Ok(0),
+            _ => Err(format!("{} doesn't fit in 16 bits", operand)),
+        }
+    }
+
+    // Numbers and names added together, such as `label+1` or `-2`
+    fn value(&self, expression: &str) -> Result<i64> {
+        let mut total = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+        for c in expression.chars().chain(std::iter::once('+')) {
+            if (c == '+' || c == '-') && !term.is_empty() {
+                total += sign * self.term(&term)?;
+                term.clear();
+                sign = if c == '-' { -1 } else { 1 };
+            } else if c == '-' {
+                sign = -sign;
+            } else if c != '+' {
+                term.push(c);
+            }
+        }
+        Ok(total)
+    }
+
+    fn term(&self, term: &str) -> Result<i64> {
+        let parsed = if let Some(hex) = term.strip_prefix('$') {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(hex) = term.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(binary) = term.strip_prefix('%') {
+            i64::from_str_radix(binary, 2).ok()
+        } else if term == "@" {
+            Some(self.address as i64)
+        } else {
+            term.parse().ok()
+        };
+        if let Some(value) = parsed {
+            return Ok(value);
+        }
+        if let Some(&(_, address)) = IO_REGISTERS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(term))
+        {
+            return Ok(address as i64);
+        }
+        match self.labels.get(term) {
+            Some(&address) => Ok(address as i64),
+            None if !self.resolve && is_identifier(term) => Ok(0),
+            None => Err(format!("Unknown value {:?}", term)),
+        }
+    }
+}
+
+// Lower case without spaces, with brackets as parentheses. Labels keep their case.
+fn normalize(operand: &str) -> String {
+    let operand: String = operand
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c {
+            '[' => '(',
+            ']' => ')',
+            c => c,
+        })
+        .collect();
+    let lower = operand.to_ascii_lowercase();
+    let keyword = R8.contains(&lower.as_str())
+        || R16.contains(&lower.as_str())
+        || R16_STACK.contains(&lower.as_str())
+        || CONDITIONS.contains(&lower.as_str())
+        || lower.starts_with("(hl")
+        || lower.starts_with("sp+")
+        || lower.starts_with("sp-")
+        || ["(bc)", "(de)", "(c)", "($ff00+c)", "(0xff00+c)"].contains(&lower.as_str());
+    if keyword {
+        lower
+    } else {
+        operand
+    }
+}
+
+fn position(names: &[&str], name: &str) -> Option<u8> {
+    names.iter().position(|&n| n == name).map(|i| i as u8)
+}
+
+fn is_indirect(operand: &str) -> bool {
+    operand.starts_with('(') && operand.ends_with(')')
+}
+
+// The address inside `(address)`
+fn inner(operand: &str) -> &str {
+    &operand[1..operand.len() - 1]
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn with_u16(opcode: u8, value: u16) -> Vec<u8> {
+    let [low, high] = value.to_le_bytes();
+    vec![opcode, low, high]
+}
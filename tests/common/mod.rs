@@ -0,0 +1,214 @@
+#![allow(dead_code)]
+
+pub mod asm;
+pub mod ocr;
+
+use rust_gameboycolor::{DeviceMode, GameBoyColor, LinkCable};
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub const PROGRAM_START: usize = 0x0150;
+
+/// Builds ROM images with a valid header around a raw program placed at 0x0150.
+pub struct RomBuilder {
+    data: Vec<u8>,
+}
+
+impl RomBuilder {
+    pub fn new(title: &str) -> Self {
+        let mut data = vec![0; 0x8000];
+        // nop; jp $0150
+        data[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+        let title = title.as_bytes();
+        assert!(title.len() <= 15, "Title too long");
+        data[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        Self { data }
+    }
+
+    pub fn cartridge_type(mut self, code: u8) -> Self {
+        self.data[0x0147] = code;
+        self
+    }
+
+    /// Sets the ROM size header code and resizes the image to match.
+    pub fn rom_size(mut self, code: u8) -> Self {
+        self.data.resize((32 * 1024) << code, 0);
+        self.data[0x0148] = code;
+        self
+    }
+
+    pub fn ram_size(mut self, code: u8) -> Self {
+        self.data[0x0149] = code;
+        self
+    }
+
+    pub fn cgb_flag(mut self, flag: u8) -> Self {
+        self.data[0x0143] = flag;
+        self
+    }
+
+    pub fn program(self, code: &[u8]) -> Self {
+        self.patch(PROGRAM_START, code)
+    }
+
+    /// Assembles `source` with [`asm::assemble`] and places it at 0x0150.
+    pub fn asm(self, source: &str) -> Self {
+        self.program(&asm::assemble(PROGRAM_START as u16, source))
+    }
+
+    pub fn patch(mut self, address: usize, bytes: &[u8]) -> Self {
+        self.data[address..address + bytes.len()].copy_from_slice(bytes);
+        self
+    }
+
+    pub fn build(mut self) -> Vec<u8> {
+        let mut checksum: u8 = 0;
+        for &byte in &self.data[0x0134..=0x014C] {
+            checksum = checksum.wrapping_sub(byte).wrapping_sub(1);
+        }
+        self.data[0x014D] = checksum;
+        let global = self
+            .data
+            .iter()
+            .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16))
+            .wrapping_sub(self.data[0x014E] as u16 + self.data[0x014F] as u16);
+        self.data[0x014E..=0x014F].copy_from_slice(&global.to_be_bytes());
+        self.data
+    }
+}
+
+/// Link cable that records every byte the ROM sends, with nothing connected on the other end.
+#[derive(Clone, Default)]
+pub struct SerialLog {
+    buffer: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SerialLog {
+    pub fn bytes(&self) -> Vec<u8> {
+        self.buffer.borrow().clone()
+    }
+}
+
+impl LinkCable for SerialLog {
+    fn send(&mut self, data: u8) {
+        self.buffer.borrow_mut().push(data);
+    }
+
+    fn try_recv(&mut self) -> Option<u8> {
+        Some(0xFF)
+    }
+}
+
+pub fn boot(rom: &[u8], device_mode: DeviceMode) -> (GameBoyColor, SerialLog) {
+    let log = SerialLog::default();
+    let gameboy = GameBoyColor::new(rom, device_mode, Some(Box::new(log.clone()))).unwrap();
+    (gameboy, log)
+}
+
+/// Runs frames until the ROM has sent `count` bytes over serial or `max_frames` elapse.
+pub fn run_until_serial(
+    gameboy: &mut GameBoyColor,
+    log: &SerialLog,
+    count: usize,
+    max_frames: usize,
+) -> Vec<u8> {
+    for _ in 0..max_frames {
+        if log.bytes().len() >= count {
+            break;
+        }
+        gameboy.execute_frame();
+    }
+    log.bytes()
+}
+
+/// Powers on a scene for the frame output tests and runs three frames: tile 1 in colour
+/// 1 at the top-left of the map, with an x-flipped object of tile 2 in colour 3 beside
+/// it. The object uses OBP1, or CGB palette 1 in colour mode.
+pub fn tile_scene(device_mode: DeviceMode) -> GameBoyColor {
+    let rom = RomBuilder::new("TILESCENE")
+        .cgb_flag(0x80)
+        .asm(
+            "
+                xor a
+                ldh [LCDC], a
+                ld hl, $8010
+                ld b, 8
+            tile1:
+                ld a, $FF
+                ld [hl+], a
+                xor a
+                ld [hl+], a
+                dec b
+                jr nz, tile1
+                ld a, $FF
+                ld b, 16
+            tile2:
+                ld [hl+], a
+                dec b
+                jr nz, tile2
+                ld a, 1
+                ld [$9800], a
+
+                ; y = 16, x = 16, tile 2, flipped horizontally with OBP1 and palette 1
+                ld hl, $FE00
+                ld a, 16
+                ld [hl+], a
+                ld [hl+], a
+                ld a, 2
+                ld [hl+], a
+                ld a, $31
+                ld [hl+], a
+
+                ld a, $E4
+                ldh [BGP], a
+                ld a, $1B
+                ldh [OBP1], a
+                ld a, $93
+                ldh [LCDC], a
+                jr @
+            ",
+        )
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, device_mode, None).unwrap();
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+    gameboy
+}
+
+/// Saves the screen to `target/test-screenshots/<name>.png`, where a failing test can
+/// point to it, and returns the path.
+pub fn dump_screen(gameboy: &GameBoyColor, name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("test-screenshots");
+    let stem = Path::new(name).with_extension("");
+    let path = dir.join(format!("{}.png", stem.to_string_lossy().replace('/', "_")));
+    std::fs::create_dir_all(&dir)
+        .and_then(|()| gameboy.screenshot_png(&path))
+        .unwrap();
+    path
+}
+
+/// `ld a, value; ld (address), a`
+pub fn write_imm(address: u16, value: u8) -> Vec<u8> {
+    vec![0x3E, value, 0xEA, address as u8, (address >> 8) as u8]
+}
+
+/// `ld a, value`, then [`SEND_SERIAL_A`]
+pub fn send_serial_imm(value: u8) -> [u8; 14] {
+    let mut code = [0; 14];
+    code[..2].copy_from_slice(&[0x3E, value]);
+    code[2..].copy_from_slice(&SEND_SERIAL_A);
+    code
+}
+
+/// `ldh (SB), a; ld a, $81; ldh (SC), a; wait: ldh a, (SC); bit 7, a; jr nz, wait`
+pub const SEND_SERIAL_A: [u8; 12] = [
+    0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0xF0, 0x02, 0xCB, 0x7F, 0x20, 0xFA,
+];
+
+/// `jr @`
+pub const LOOP_FOREVER: [u8; 2] = [0x18, 0xFE];
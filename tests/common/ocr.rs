@@ -0,0 +1,88 @@
+//! Reads text off the frame buffer by matching 8x8 tiles against font glyphs.
+
+use rust_gameboycolor::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Monochrome 8x8 glyphs, one byte per row with bit 7 as the leftmost pixel.
+pub struct Font {
+    glyphs: Vec<(char, [u8; 8])>,
+}
+
+impl Font {
+    /// Builds a font from 1bpp tile data, as most test ROMs store their font in ROM.
+    pub fn from_1bpp(chars: &str, data: &[u8]) -> Self {
+        let glyphs = chars
+            .chars()
+            .zip(data.chunks_exact(8))
+            .map(|(c, rows)| (c, rows.try_into().unwrap()))
+            .collect();
+        Self { glyphs }
+    }
+
+    /// Builds a font from 2bpp VRAM tile data; any non-zero colour index counts as ink.
+    pub fn from_2bpp(chars: &str, data: &[u8]) -> Self {
+        let glyphs = chars
+            .chars()
+            .zip(data.chunks_exact(16))
+            .map(|(c, tile)| {
+                let mut rows = [0; 8];
+                for (row, bytes) in rows.iter_mut().zip(tile.chunks_exact(2)) {
+                    *row = bytes[0] | bytes[1];
+                }
+                (c, rows)
+            })
+            .collect();
+        Self { glyphs }
+    }
+
+    fn recognize(&self, rows: &[u8; 8]) -> char {
+        if rows.iter().all(|&row| row == 0) {
+            return ' ';
+        }
+        self.glyphs
+            .iter()
+            .find(|(_, glyph)| glyph == rows)
+            .map_or('?', |&(c, _)| c)
+    }
+}
+
+fn is_ink((r, g, b): (u8, u8, u8)) -> bool {
+    (r as u16 + g as u16 + b as u16) < 3 * 0x80
+}
+
+fn tile_rows(frame: &[(u8, u8, u8)], tile_x: usize, tile_y: usize) -> [u8; 8] {
+    let mut rows = [0; 8];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for x in 0..8 {
+            let pixel = frame[(tile_y * 8 + y) * SCREEN_WIDTH + tile_x * 8 + x];
+            if is_ink(pixel) {
+                *row |= 0x80 >> x;
+            }
+        }
+    }
+    rows
+}
+
+/// Reads a `columns` x `rows` region of tiles starting at tile (`tile_x`, `tile_y`),
+/// returning one string per tile row. Unknown glyphs read as `?`.
+pub fn read_text(
+    frame: &[(u8, u8, u8)],
+    font: &Font,
+    (tile_x, tile_y): (usize, usize),
+    (columns, rows): (usize, usize),
+) -> Vec<String> {
+    assert!(tile_x + columns <= SCREEN_WIDTH / 8 && tile_y + rows <= SCREEN_HEIGHT / 8);
+    (tile_y..tile_y + rows)
+        .map(|y| {
+            (tile_x..tile_x + columns)
+                .map(|x| font.recognize(&tile_rows(frame, x, y)))
+                .collect()
+        })
+        .collect()
+}
+
+/// Whether `text` appears on any tile row of the screen.
+pub fn screen_contains(frame: &[(u8, u8, u8)], font: &Font, text: &str) -> bool {
+    read_text(frame, font, (0, 0), (SCREEN_WIDTH / 8, SCREEN_HEIGHT / 8))
+        .iter()
+        .any(|line| line.contains(text))
+}
@@ -0,0 +1,418 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{
+    CpuState, DeviceMode, EmulatorConfigBuilder, EmulatorError, GameBoyColor, JoypadKey,
+    JoypadKeyState, Model, WatchpointCondition,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn power_on(model: Model, device_mode: DeviceMode) -> Result<CpuState, EmulatorError> {
+    let rom = RomBuilder::new("CPUINIT")
+        .cgb_flag(0x80)
+        .program(&LOOP_FOREVER)
+        .build();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(device_mode)
+        .model(model)
+        .build()?;
+    Ok(GameBoyColor::with_config(&rom, config)?.cpu_state())
+}
+
+fn registers(state: CpuState) -> [u8; 8] {
+    [
+        state.a, state.f, state.b, state.c, state.d, state.e, state.h, state.l,
+    ]
+}
+
+#[test]
+fn power_on_registers_match_each_model() {
+    let cases = [
+        (
+            Model::Dmg0,
+            DeviceMode::GameBoy,
+            [0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03],
+        ),
+        (
+            Model::Dmg,
+            DeviceMode::GameBoy,
+            [0x01, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+        ),
+        (
+            Model::Mgb,
+            DeviceMode::GameBoy,
+            [0xFF, 0xB0, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+        ),
+        (
+            Model::Sgb,
+            DeviceMode::GameBoy,
+            [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+        ),
+        (
+            Model::Sgb2,
+            DeviceMode::GameBoy,
+            [0xFF, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+        ),
+        (
+            Model::Cgb,
+            DeviceMode::GameBoyColor,
+            [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        ),
+        (
+            Model::Cgb,
+            DeviceMode::GameBoy,
+            [0x11, 0x80, 0x00, 0x00, 0x00, 0x08, 0x00, 0x7C],
+        ),
+        (
+            Model::Agb,
+            DeviceMode::GameBoyColor,
+            [0x11, 0x00, 0x01, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        ),
+        (
+            Model::Agb,
+            DeviceMode::GameBoy,
+            [0x11, 0x00, 0x01, 0x00, 0x00, 0x08, 0x00, 0x7C],
+        ),
+    ];
+
+    for (model, device_mode, expected) in cases {
+        let state = power_on(model, device_mode).unwrap();
+        assert_eq!(
+            registers(state),
+            expected,
+            "{:?} in {:?}",
+            model,
+            device_mode
+        );
+        assert_eq!((state.sp, state.pc), (0xFFFE, 0x0100));
+    }
+}
+
+// DIV, LCDC, NR52 and BGP as the first instructions of the cartridge see them
+fn post_boot_io(model: Model, device_mode: DeviceMode) -> [u8; 4] {
+    let mut program = vec![
+        0xF0, 0x04, 0x47, // ldh a, (DIV); ld b, a
+        0xF0, 0x40, 0x4F, // ldh a, (LCDC); ld c, a
+        0xF0, 0x26, 0x57, // ldh a, (NR52); ld d, a
+        0xF0, 0x47, 0x5F, // ldh a, (BGP); ld e, a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("IOINIT")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(device_mode)
+        .model(model)
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(&rom, config).unwrap();
+    for _ in 0..10 {
+        gameboy.execute_instruction();
+    }
+    let state = gameboy.cpu_state();
+    [state.b, state.c, state.d, state.e]
+}
+
+#[test]
+fn io_registers_start_where_the_boot_rom_leaves_them() {
+    assert_eq!(
+        post_boot_io(Model::Dmg0, DeviceMode::GameBoy),
+        [0x18, 0x91, 0xF1, 0xFC]
+    );
+    assert_eq!(
+        post_boot_io(Model::Dmg, DeviceMode::GameBoy),
+        [0xAB, 0x91, 0xF1, 0xFC]
+    );
+    assert_eq!(post_boot_io(Model::Mgb, DeviceMode::GameBoy)[0], 0xAB);
+    assert_eq!(post_boot_io(Model::Sgb, DeviceMode::GameBoy)[2], 0xF0);
+    assert_eq!(
+        post_boot_io(Model::Cgb, DeviceMode::GameBoyColor)[1..3],
+        [0x91, 0xF1]
+    );
+}
+
+#[test]
+fn model_defaults_to_the_device_mode() {
+    let rom = RomBuilder::new("CPUINIT").cgb_flag(0x80).build();
+    let dmg = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    assert_eq!(dmg.cpu_state().a, 0x01);
+    let cgb = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    assert_eq!(cgb.cpu_state().a, 0x11);
+}
+
+#[test]
+fn monochrome_models_cannot_run_in_color_mode() {
+    assert!(matches!(
+        power_on(Model::Dmg, DeviceMode::GameBoyColor),
+        Err(EmulatorError::UnsupportedMode(_))
+    ));
+}
+
+fn run_program(program: &[u8], device_mode: DeviceMode) -> GameBoyColor {
+    let rom = RomBuilder::new("STOP")
+        .cgb_flag(0x80)
+        .program(program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, device_mode, None).unwrap();
+    gameboy.execute_frame();
+    gameboy
+}
+
+fn start_pressed() -> JoypadKeyState {
+    let mut key_state = JoypadKeyState::new();
+    key_state.set_key(JoypadKey::Start, true);
+    key_state
+}
+
+#[test]
+fn stop_switches_speed_when_armed() {
+    let gameboy = run_program(
+        &[
+            0x3E, 0x01, 0xE0, 0x4D, // ld a, $01; ldh (KEY1), a
+            0x10, 0x00, // stop
+            0xF0, 0x4D, // ldh a, (KEY1)
+            0x18, 0xFE, // jr @
+        ],
+        DeviceMode::GameBoyColor,
+    );
+    assert_eq!(gameboy.cpu_state().a, 0xFE);
+}
+
+#[test]
+fn stop_waits_for_a_button_press() {
+    let mut gameboy = run_program(
+        &[
+            0xAF, 0xE0, 0x00, // xor a; ldh (P1), a
+            0x10, 0x00, // stop
+            0x3E, 0x42, // ld a, $42
+            0x18, 0xFE, // jr @
+        ],
+        DeviceMode::GameBoy,
+    );
+    gameboy.execute_frame();
+    assert_eq!(gameboy.cpu_state().a, 0x00);
+
+    gameboy.set_key(start_pressed());
+    gameboy.execute_frame();
+    assert_eq!(gameboy.cpu_state().a, 0x42);
+}
+
+#[test]
+fn stop_checks_buttons_and_resets_div_off_the_bus() {
+    let rom = RomBuilder::new("STOP")
+        .asm(
+            "
+                xor a
+                ldh [P1], a
+                stop
+                ld a, $42
+                jr @
+            ",
+        )
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    gameboy.add_watchpoint(0xFF00..=0xFF00, WatchpointCondition::Read);
+    gameboy.add_watchpoint(0xFF04..=0xFF04, WatchpointCondition::Write);
+
+    for _ in 0..2 {
+        assert_eq!(gameboy.execute_frame(), None);
+        assert!(gameboy.polled_keys().is_empty());
+    }
+    gameboy.set_key(start_pressed());
+    assert_eq!(gameboy.execute_frame(), None);
+    assert_eq!(gameboy.cpu_state().a, 0x42);
+}
+
+#[test]
+fn stop_with_a_button_held_halts_and_skips_a_byte() {
+    let rom = RomBuilder::new("STOP")
+        .program(&[
+            0xAF, 0xE0, 0x00, // xor a; ldh (P1), a
+            0x10, // stop
+            0x3C, // inc a
+            0x18, 0xFE, // jr @
+        ])
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    gameboy.set_key(start_pressed());
+    gameboy.execute_frame();
+
+    let state = gameboy.cpu_state();
+    assert!(state.halted);
+    assert_eq!(state.a, 0x00);
+}
+
+// ld a, $10; ldh (IE), a; xor a
+const ENABLE_JOYPAD_INTERRUPT: [u8; 5] = [0x3E, 0x10, 0xE0, 0xFF, 0xAF];
+
+#[test]
+fn halt_with_ime_clear_resumes_without_dispatch() {
+    let mut program = ENABLE_JOYPAD_INTERRUPT.to_vec();
+    program.extend_from_slice(&[
+        0x76, // halt
+        0xF0, 0x0F, // ldh a, (IF)
+        0x18, 0xFE, // jr @
+    ]);
+    let mut gameboy = run_program(&program, DeviceMode::GameBoy);
+    assert!(gameboy.cpu_state().halted);
+
+    gameboy.set_key(start_pressed());
+    gameboy.execute_instruction();
+    let state = gameboy.cpu_state();
+    assert!(!state.halted);
+    assert_eq!(state.pc, 0x0156);
+
+    gameboy.execute_instruction();
+    let state = gameboy.cpu_state();
+    assert_eq!(state.pc, 0x0158);
+    assert_eq!(state.a & 0x10, 0x10, "IF must not be acknowledged");
+}
+
+#[test]
+fn halt_with_a_pending_interrupt_and_ime_clear_repeats_the_next_byte() {
+    let mut program = ENABLE_JOYPAD_INTERRUPT.to_vec();
+    program.extend_from_slice(&[
+        0x76, // halt
+        0x3C, // inc a
+        0x18, 0xFE, // jr @
+    ]);
+    let rom = RomBuilder::new("HALTBUG").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    gameboy.set_key(start_pressed());
+    gameboy.execute_frame();
+
+    let state = gameboy.cpu_state();
+    assert!(!state.halted);
+    assert_eq!(state.a, 0x02);
+}
+
+// Dots from the GDMA start write until the instruction after it has run
+fn gdma_dots(blocks: u8, double_speed: bool) -> u64 {
+    let mut program = Vec::new();
+    if double_speed {
+        program.extend_from_slice(&[0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00]);
+    }
+    program.extend_from_slice(&[
+        0x3E,
+        0xC0,
+        0xE0,
+        0x51, // ld a, $C0; ldh (HDMA1), a
+        0xAF,
+        0xE0,
+        0x52, // xor a; ldh (HDMA2), a
+        0x3E,
+        0x80,
+        0xE0,
+        0x53, // ld a, $80; ldh (HDMA3), a
+        0xAF,
+        0xE0,
+        0x54, // xor a; ldh (HDMA4), a
+        0x3E,
+        blocks - 1, // ld a, blocks - 1
+    ]);
+    let start = (common::PROGRAM_START + program.len()) as u16;
+    program.extend_from_slice(&[0xE0, 0x55, 0x00]); // ldh (HDMA5), a; nop
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("GDMA")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+
+    while gameboy.cpu_state().pc != start {
+        gameboy.execute_instruction();
+    }
+    let begin = gameboy.timestamp();
+    while gameboy.cpu_state().pc != start + 3 {
+        gameboy.execute_instruction();
+    }
+    gameboy.timestamp() - begin
+}
+
+#[test]
+fn gdma_pauses_the_cpu_for_each_block() {
+    // 8 M-cycles per block
+    assert_eq!(gdma_dots(4, false) - gdma_dots(1, false), 3 * 8 * 4);
+    // 16 M-cycles per block at double speed take the same time
+    assert_eq!(gdma_dots(4, true) - gdma_dots(1, true), 3 * 8 * 4);
+}
+
+#[test]
+fn trace_hook_sees_each_instruction_in_doctor_format() {
+    let rom = RomBuilder::new("TRACE").program(&LOOP_FOREVER).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let trace = lines.clone();
+    gameboy.set_trace_hook(move |state, pcmem| trace.borrow_mut().push(state.doctor_line(pcmem)));
+
+    for _ in 0..3 {
+        gameboy.execute_instruction();
+    }
+    assert_eq!(
+        *lines.borrow(),
+        [
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01",
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:C3,50,01,00",
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0150 PCMEM:18,FE,00,00",
+        ]
+    );
+
+    gameboy.clear_trace_hook();
+    gameboy.execute_instruction();
+    assert_eq!(lines.borrow().len(), 3);
+}
+
+#[test]
+fn run_cycles_runs_whole_instructions_to_a_cycle_budget() {
+    let rom = RomBuilder::new("CYCLES").program(&LOOP_FOREVER).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    assert_eq!(gameboy.total_cycles(), 0);
+
+    let (ran, _) = gameboy.run_cycles(1000);
+    // jr @ takes 3 M-cycles
+    assert!((1000..1003).contains(&ran), "{}", ran);
+    assert_eq!(gameboy.total_cycles(), ran);
+
+    // One frame's worth of M-cycles gives one frame's worth of audio, 803.7 samples at 48 kHz
+    let before = gameboy.total_cycles();
+    let (ran, _) = gameboy.run_cycles(154 * 456 / 4);
+    assert_eq!(gameboy.total_cycles(), before + ran);
+    assert!((803..=805).contains(&gameboy.audio_buffer().len()));
+    assert_eq!(gameboy.frame_count(), 0);
+}
+
+#[test]
+fn overclock_gives_the_cpu_extra_cycles_without_changing_frame_timing() {
+    // inc bc; jr -3, 5 M-cycles per iteration
+    let rom = RomBuilder::new("OVERCLOCK")
+        .program(&[0x03, 0x18, 0xFD])
+        .build();
+    let run = |overclock: u32| {
+        let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+        gameboy.set_overclock(overclock);
+        assert_eq!(gameboy.overclock(), overclock);
+        gameboy.run_frame(JoypadKeyState::new());
+
+        let count = |gameboy: &GameBoyColor| {
+            let state = gameboy.cpu_state();
+            u16::from_be_bytes([state.b, state.c])
+        };
+        let (start, timestamp) = (count(&gameboy), gameboy.timestamp());
+        let samples = gameboy.run_frame(JoypadKeyState::new()).audio.len();
+        let iterations = count(&gameboy).wrapping_sub(start);
+        (iterations, gameboy.timestamp() - timestamp, samples)
+    };
+
+    let (normal, dots, samples) = run(0);
+    assert!((3510..=3512).contains(&normal), "{}", normal);
+    let (overclocked, overclocked_dots, overclocked_samples) = run(2000);
+    assert!(
+        (399..=401).contains(&(overclocked - normal)),
+        "{}",
+        overclocked
+    );
+    assert_eq!(overclocked_dots, dots);
+    assert_eq!(overclocked_samples, samples);
+}
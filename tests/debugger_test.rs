@@ -0,0 +1,231 @@
+mod common;
+
+use common::{boot, RomBuilder, PROGRAM_START};
+use rust_gameboycolor::{DebugEvent, DeviceMode, JoypadKeyState, ResetKind, WatchpointCondition};
+
+const LOOP: u16 = PROGRAM_START as u16 + 2;
+
+// ld a, 0; loop: inc a; jr loop
+fn counter_rom() -> Vec<u8> {
+    RomBuilder::new("DEBUGGER")
+        .program(&[0x3E, 0x00, 0x3C, 0x18, 0xFD])
+        .build()
+}
+
+#[test]
+fn run_until_break_stops_before_the_breakpoint_and_resumes_past_it() {
+    let (mut gameboy, _) = boot(&counter_rom(), DeviceMode::GameBoy);
+    gameboy.add_breakpoint(LOOP);
+
+    assert_eq!(
+        gameboy.run_until_break(),
+        DebugEvent::Breakpoint { pc: LOOP }
+    );
+    assert_eq!(gameboy.cpu_state().a, 0);
+    assert_eq!(
+        gameboy.run_until_break(),
+        DebugEvent::Breakpoint { pc: LOOP }
+    );
+    assert_eq!(gameboy.cpu_state().a, 1);
+
+    assert_eq!(gameboy.step(), DebugEvent::Stepped { pc: LOOP + 1 });
+    assert_eq!(gameboy.cpu_state().a, 2);
+    assert_eq!(gameboy.step(), DebugEvent::Breakpoint { pc: LOOP });
+
+    assert!(gameboy.remove_breakpoint(LOOP));
+    assert!(!gameboy.remove_breakpoint(LOOP));
+    assert_eq!(gameboy.run_until_break(), DebugEvent::FrameEnded);
+}
+
+#[test]
+fn breakpoints_survive_reset() {
+    let (mut gameboy, _) = boot(&counter_rom(), DeviceMode::GameBoy);
+    gameboy.add_breakpoint(LOOP);
+    gameboy.execute_frame();
+
+    gameboy.reset(ResetKind::Soft);
+    assert_eq!(
+        gameboy.run_until_break(),
+        DebugEvent::Breakpoint { pc: LOOP }
+    );
+    assert_eq!(gameboy.cpu_state().a, 0);
+}
+
+#[test]
+fn watchpoints_stop_the_frame_at_the_access() {
+    // ld a, 0; loop: inc a; ld ($C000), a; ld ($C000), a; jr loop
+    let program = [
+        0x3E, 0x00, 0x3C, 0xEA, 0x00, 0xC0, 0xEA, 0x00, 0xC0, 0x18, 0xF7,
+    ];
+    let rom = RomBuilder::new("WATCH").program(&program).build();
+    let (mut gameboy, _) = boot(&rom, DeviceMode::GameBoy);
+    gameboy.poke(0xC000, 0x55);
+
+    let write = gameboy.add_watchpoint(0xC000..=0xC000, WatchpointCondition::Write);
+    let hit = |old, new, pc| DebugEvent::WatchpointHit {
+        address: 0xC000,
+        old,
+        new,
+        pc,
+    };
+    assert_eq!(gameboy.execute_frame(), Some(hit(0x55, 1, LOOP + 1)));
+    assert_eq!(gameboy.execute_frame(), Some(hit(1, 1, LOOP + 4)));
+    assert_eq!(gameboy.frame_count(), 0);
+
+    // Writing the same value again does not count as a change
+    assert!(gameboy.remove_watchpoint(write));
+    gameboy.add_watchpoint(0xC000..=0xC000, WatchpointCondition::Change);
+    assert_eq!(gameboy.step(), DebugEvent::Stepped { pc: LOOP });
+    assert_eq!(gameboy.run_until_break(), hit(1, 2, LOOP + 1));
+    assert_eq!(gameboy.run_until_break(), hit(2, 3, LOOP + 1));
+}
+
+#[test]
+fn execute_frame_finishes_the_frame_without_watchpoints() {
+    let (mut gameboy, _) = boot(&counter_rom(), DeviceMode::GameBoy);
+    let id = gameboy.add_watchpoint(0x8000..=0x9FFF, WatchpointCondition::Write);
+    assert!(gameboy.remove_watchpoint(id));
+    assert_eq!(gameboy.execute_frame(), None);
+    assert_eq!(gameboy.frame_count(), 1);
+}
+
+#[test]
+fn run_frame_returns_the_watchpoint_hit() {
+    let rom = RomBuilder::new("WATCH")
+        .asm(
+            "
+                ld a, 7
+                ld [$C000], a
+                jr @
+            ",
+        )
+        .build();
+    let (mut gameboy, _) = boot(&rom, DeviceMode::GameBoy);
+    gameboy.add_watchpoint(0xC000..=0xC000, WatchpointCondition::Write);
+
+    let output = gameboy.run_frame(JoypadKeyState::new());
+    assert_eq!(
+        output.debug_event,
+        Some(DebugEvent::WatchpointHit {
+            address: 0xC000,
+            old: 0,
+            new: 7,
+            pc: PROGRAM_START as u16 + 2,
+        })
+    );
+    assert_eq!(gameboy.frame_count(), 0);
+    assert_eq!(gameboy.run_frame(JoypadKeyState::new()).debug_event, None);
+    assert_eq!(gameboy.frame_count(), 1);
+}
+
+#[test]
+fn run_cycles_stops_at_watchpoints_but_not_breakpoints() {
+    let (mut gameboy, _) = boot(&counter_rom(), DeviceMode::GameBoy);
+    gameboy.add_breakpoint(LOOP);
+    assert_eq!(gameboy.run_cycles(100).1, None);
+
+    let rom = RomBuilder::new("WATCH")
+        .asm(
+            "
+                nop
+                ld a, [$C000]
+                jr @
+            ",
+        )
+        .build();
+    let (mut gameboy, _) = boot(&rom, DeviceMode::GameBoy);
+    gameboy.add_watchpoint(0xC000..=0xC000, WatchpointCondition::Read);
+
+    // The header's nop and jp, then the program's nop and load
+    let (ran, hit) = gameboy.run_cycles(1000);
+    assert_eq!(ran, 1 + 4 + 1 + 4);
+    assert_eq!(
+        hit,
+        Some(DebugEvent::WatchpointHit {
+            address: 0xC000,
+            old: 0,
+            new: 0,
+            pc: PROGRAM_START as u16 + 1,
+        })
+    );
+    assert_eq!(gameboy.run_cycles(1000).1, None);
+}
+
+// Writes $C000 about every 7000 M-cycles, so a watchpoint on it stops most frames twice
+fn interrupting_rom() -> Vec<u8> {
+    RomBuilder::new("WATCH")
+        .asm(
+            "
+            again:
+                ld bc, 1000
+            delay:
+                dec bc
+                ld a, b
+                or c
+                jr nz, delay
+                ld [$C000], a
+                jr again
+            ",
+        )
+        .build()
+}
+
+#[test]
+fn a_resumed_frame_keeps_the_audio_of_the_whole_frame() {
+    let (mut gameboy, _) = boot(&interrupting_rom(), DeviceMode::GameBoy);
+    gameboy.execute_frame();
+    let frame_length = gameboy.run_frame(JoypadKeyState::new()).audio.len();
+
+    let id = gameboy.add_watchpoint(0xC000..=0xC000, WatchpointCondition::Write);
+    let start = gameboy.timestamp();
+    let mut hits = 0;
+    let output = loop {
+        let output = gameboy.run_frame(JoypadKeyState::new());
+        if output.debug_event.is_none() {
+            break output;
+        }
+        assert!(output.audio.len() < frame_length);
+        hits += 1;
+    };
+    assert!(hits > 0);
+    assert_eq!(output.audio_timestamp, start);
+    assert!(output.audio.len().abs_diff(frame_length) <= 1);
+
+    // Finishing the frame by stepping leaves the next one to start afresh
+    assert!(gameboy.execute_frame().is_some());
+    while gameboy.run_until_break() != DebugEvent::FrameEnded {}
+    assert!(gameboy.remove_watchpoint(id));
+    let start = gameboy.timestamp();
+    let output = gameboy.run_frame(JoypadKeyState::new());
+    assert_eq!(output.audio_timestamp, start);
+    assert!(output.audio.len().abs_diff(frame_length) <= 1);
+}
+
+#[test]
+fn change_watchpoints_see_the_old_value_of_io_registers() {
+    let rom = RomBuilder::new("WATCH")
+        .asm(
+            "
+                ld a, 5
+                ldh [$43], a
+                ldh [$43], a
+                inc a
+                ldh [$43], a
+                jr @
+            ",
+        )
+        .build();
+    let (mut gameboy, _) = boot(&rom, DeviceMode::GameBoy);
+    // SCX
+    gameboy.add_watchpoint(0xFF43..=0xFF43, WatchpointCondition::Change);
+
+    let hit = |old, new, pc: usize| DebugEvent::WatchpointHit {
+        address: 0xFF43,
+        old,
+        new,
+        pc: pc as u16,
+    };
+    assert_eq!(gameboy.execute_frame(), Some(hit(0, 5, PROGRAM_START + 2)));
+    assert_eq!(gameboy.execute_frame(), Some(hit(5, 6, PROGRAM_START + 7)));
+    assert_eq!(gameboy.execute_frame(), None);
+}
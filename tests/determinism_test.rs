@@ -0,0 +1,155 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{
+    DeviceMode, EmulatorConfigBuilder, EmulatorEvent, GameBoyColor, JoypadKey, JoypadKeyState,
+    ResetKind, UnpredictableValues,
+};
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+const KEYS: [JoypadKey; 8] = [
+    JoypadKey::Right,
+    JoypadKey::Left,
+    JoypadKey::Up,
+    JoypadKey::Down,
+    JoypadKey::A,
+    JoypadKey::B,
+    JoypadKey::Select,
+    JoypadKey::Start,
+];
+
+// Mixes joypad, DIV and TIMA into sound, tile data and cartridge RAM on every pass
+fn busy_rom() -> Vec<u8> {
+    let program = [
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, // ld a, $0A; ld ($0000), a
+        0x3E, 0x80, 0xE0, 0x26, // ld a, $80; ldh (NR52), a
+        0x3E, 0x77, 0xE0, 0x24, 0x3E, 0xFF, 0xE0, 0x25, // NR50 = $77, NR51 = $FF
+        0x3E, 0xF0, 0xE0, 0x12, // ld a, $F0; ldh (NR12), a
+        0x3E, 0x05, 0xE0, 0x07, // ld a, $05; ldh (TAC), a
+        0x21, 0x00, 0x80, // ld hl, $8000
+        // loop:
+        0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, // ld a, $10; ldh (P1), a; ldh a, (P1)
+        0x47, 0xF0, 0x04, 0xA8, 0x47, // ld b, a; ldh a, (DIV); xor b; ld b, a
+        0xF0, 0x05, 0x80, // ldh a, (TIMA); add b
+        0xE0, 0x13, 0x3E, 0x87, 0xE0, 0x14, // ldh (NR13), a; ld a, $87; ldh (NR14), a
+        0x78, 0x22, 0xCB, 0xA4, // ld a, b; ld (hl+), a; res 4, h
+        0xEA, 0x00, 0xA0, // ld ($A000), a
+        0x18, 0xDD, // jr loop
+    ];
+    // MBC1 with 8 KiB of battery-backed RAM
+    RomBuilder::new("DETERMINISM")
+        .cartridge_type(0x03)
+        .ram_size(0x02)
+        .cgb_flag(0x80)
+        .program(&program)
+        .build()
+}
+
+// Presses a different combination of keys every few frames
+fn scripted_input(frame: u64) -> JoypadKeyState {
+    let mut key_state = JoypadKeyState::new();
+    let pattern = (frame / 7).wrapping_mul(0x9E37_79B9) >> 5;
+    for (bit, key) in KEYS.into_iter().enumerate() {
+        key_state.set_key(key, pattern >> bit & 1 == 1);
+    }
+    key_state
+}
+
+// The state hash leaves out what the console outputs, so the screen and sound go in too
+fn state_hash(gameboy: &GameBoyColor, hasher: &mut DefaultHasher) {
+    gameboy.frame_buffer().hash(hasher);
+    gameboy.audio_buffer().hash(hasher);
+    gameboy.state_hash().hash(hasher);
+}
+
+fn run(device_mode: DeviceMode, frames: u64) -> Vec<u64> {
+    let rom = busy_rom();
+    let mut gameboy = GameBoyColor::new(&rom, device_mode, None).unwrap();
+    let mut hashes = Vec::new();
+    let mut hasher = DefaultHasher::new();
+    for frame in 0..frames {
+        let output = gameboy.run_frame(scripted_input(frame));
+        // The script sometimes presses A, B, Select and Start together
+        let unexpected = output
+            .events
+            .iter()
+            .any(|event| !matches!(event, EmulatorEvent::SoftResetCombo));
+        assert!(!unexpected, "{:?}", output.events);
+        state_hash(&gameboy, &mut hasher);
+        if frame % 50 == 49 {
+            hashes.push(hasher.finish());
+        }
+    }
+    hashes
+}
+
+fn assert_deterministic(frames: u64) {
+    for device_mode in [DeviceMode::GameBoy, DeviceMode::GameBoyColor] {
+        let first = run(device_mode, frames);
+        let second = run(device_mode, frames);
+        let diverged = first.iter().zip(&second).position(|(a, b)| a != b);
+        assert_eq!(
+            diverged,
+            None,
+            "{:?} diverged within 50 frames of frame {:?}",
+            device_mode,
+            diverged.map(|index| index * 50)
+        );
+    }
+}
+
+#[test]
+fn identical_inputs_give_identical_state() {
+    assert_deterministic(100);
+}
+
+#[test]
+#[ignore = "slow in debug builds; CI runs it with `cargo test --release -- --ignored`"]
+fn identical_inputs_give_identical_state_for_10k_frames() {
+    assert_deterministic(10_000);
+}
+
+// WRAM and HRAM after power-on and after a reset, with the unusable area read once in between
+fn unpredictable_state(values: UnpredictableValues) -> Vec<u8> {
+    let mut program = vec![
+        0xFA, 0xA0, 0xFE, // ld a, ($FEA0)
+        0xE0, 0x80, // ldh ($FF80), a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("GARBAGE").program(&program).build();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .unpredictable_values(values)
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(&rom, config).unwrap();
+    let ram = |gameboy: &GameBoyColor| {
+        (0xC000..=0xDFFF)
+            .chain(0xFF80..=0xFFFE)
+            .map(|address| gameboy.peek(address))
+            .collect::<Vec<_>>()
+    };
+    let mut state = ram(&gameboy);
+    gameboy.execute_frame();
+    state.push(gameboy.peek(0xFF80));
+    gameboy.reset(ResetKind::Soft);
+    state.extend(ram(&gameboy));
+    state
+}
+
+#[test]
+fn unpredictable_values_follow_the_seed() {
+    let fixed = unpredictable_state(UnpredictableValues::Fixed);
+    let (ram, rest) = fixed.split_at(0x207F);
+    assert!(ram.iter().all(|&byte| byte == 0));
+    assert_eq!(rest[0], 0xFF);
+
+    let seeded = unpredictable_state(UnpredictableValues::Seeded(1));
+    assert_eq!(seeded, unpredictable_state(UnpredictableValues::Seeded(1)));
+    assert_ne!(seeded, unpredictable_state(UnpredictableValues::Seeded(2)));
+    // A reset draws new RAM contents instead of repeating the power-on ones
+    assert_ne!(seeded[..0x207F], seeded[0x2080..]);
+    assert!(seeded.iter().filter(|&&byte| byte == 0).count() < seeded.len() / 64);
+}
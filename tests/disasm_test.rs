@@ -0,0 +1,88 @@
+mod common;
+
+use common::{RomBuilder, PROGRAM_START};
+use rust_gameboycolor::disasm::disassemble;
+use rust_gameboycolor::{DeviceMode, GameBoyColor};
+
+#[test]
+fn decodes_each_operand_form() {
+    for (bytes, expected) in [
+        (&[0x00][..], "nop"),
+        (&[0x01, 0x34, 0x12], "ld bc, $1234"),
+        (&[0x08, 0x00, 0xC0], "ld ($C000), sp"),
+        (&[0x10, 0x00], "stop"),
+        (&[0x18, 0xFE], "jr $0200"),
+        (&[0x20, 0x05], "jr nz, $0207"),
+        (&[0x22], "ld (hl+), a"),
+        (&[0x3A], "ld a, (hl-)"),
+        (&[0x36, 0x7F], "ld (hl), $7F"),
+        (&[0x76], "halt"),
+        (&[0x78], "ld a, b"),
+        (&[0x9E], "sbc a, (hl)"),
+        (&[0xC2, 0x50, 0x01], "jp nz, $0150"),
+        (&[0xCD, 0x00, 0x40], "call $4000"),
+        (&[0xE0, 0x44], "ldh ($FF44), a"),
+        (&[0xE2], "ldh (c), a"),
+        (&[0xE8, 0xFE], "add sp, -2"),
+        (&[0xE9], "jp hl"),
+        (&[0xF1], "pop af"),
+        (&[0xF8, 0x05], "ld hl, sp+5"),
+        (&[0xFA, 0x00, 0xA0], "ld a, ($A000)"),
+        (&[0xFE, 0x90], "cp a, $90"),
+        (&[0xFF], "rst $38"),
+        (&[0xCB, 0x37], "swap a"),
+        (&[0xCB, 0x7E], "bit 7, (hl)"),
+        (&[0xCB, 0x80], "res 0, b"),
+        (&[0xCB, 0xFF], "set 7, a"),
+    ] {
+        let (text, length) = disassemble(0x0200, bytes);
+        assert_eq!(text, expected, "{:02X?}", bytes);
+        assert_eq!(length, bytes.len(), "{:02X?}", bytes);
+    }
+}
+
+#[test]
+fn unused_and_truncated_opcodes_decode_as_data() {
+    for opcode in [
+        0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+    ] {
+        assert_eq!(
+            disassemble(0, &[opcode]),
+            (format!("db ${:02X}", opcode), 1)
+        );
+    }
+    assert_eq!(disassemble(0, &[0xC3, 0x50]), ("db $C3".to_string(), 1));
+    assert_eq!(disassemble(0, &[]), (String::new(), 0));
+}
+
+#[test]
+fn every_opcode_decodes() {
+    for opcode in 0..=0xFF {
+        let (text, length) = disassemble(0, &[opcode, 0x00, 0x00]);
+        assert!(
+            !text.is_empty() && (1..=3).contains(&length),
+            "{:02X}",
+            opcode
+        );
+        let (text, length) = disassemble(0, &[0xCB, opcode]);
+        assert!(!text.starts_with("db") && length == 2, "CB {:02X}", opcode);
+    }
+}
+
+#[test]
+fn disassemble_range_reads_through_the_bus() {
+    // ld a, $01; ld ($C000), a; jr -7
+    let program = [0x3E, 0x01, 0xEA, 0x00, 0xC0, 0x18, 0xF9];
+    let rom = RomBuilder::new("DISASM").program(&program).build();
+    let gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+
+    let start = PROGRAM_START as u16;
+    assert_eq!(
+        gameboy.disassemble_range(start, 3),
+        [
+            (start, "ld a, $01".to_string()),
+            (start + 2, "ld ($C000), a".to_string()),
+            (start + 5, format!("jr ${:04X}", start)),
+        ]
+    );
+}
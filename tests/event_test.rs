@@ -0,0 +1,101 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{
+    DeviceMode, DmaKind, EmulatorEvent, GameBoyColor, HardwareEvent, InterruptKind, PpuMode,
+    Subsystem,
+};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn warnings(events: &[EmulatorEvent]) -> Vec<(Subsystem, &str)> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            EmulatorEvent::Warning { subsystem, detail } => Some((*subsystem, detail.as_str())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn corrupted_header_checksum_is_reported() {
+    let mut rom = RomBuilder::new("CHECKSUM").program(&LOOP_FOREVER).build();
+    rom[0x014D] ^= 0xFF;
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+
+    let events = gameboy.take_events();
+    assert_eq!(
+        warnings(&events),
+        [
+            (Subsystem::Cartridge, "Invalid header checksum"),
+            (Subsystem::Cartridge, "Invalid global checksum"),
+        ]
+    );
+}
+
+#[test]
+fn repeated_invalid_access_is_reported_once_per_drain() {
+    let mut program = vec![
+        0x3E, 0x01, // ld a, 1
+        0xE0, 0x4F, 0xE0, 0x4F, 0xE0, 0x4F, // ldh (VBK), a three times
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("WARNINGS").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    assert!(gameboy.take_events().is_empty());
+
+    gameboy.execute_frame();
+    let events = gameboy.take_events();
+    assert_eq!(
+        warnings(&events),
+        [(Subsystem::Ppu, "Attempted to write to FF4F in DMG mode")]
+    );
+    gameboy.execute_frame();
+    assert!(gameboy.take_events().is_empty());
+}
+
+#[test]
+fn subscribers_see_hardware_events_as_they_happen() {
+    // DMA from VRAM leaves the bus to the program in ROM free
+    let mut program = vec![
+        0x3E, 0x80, 0xE0, 0x46, // ld a, $80; ldh (DMA), a
+        0x3E, 0x42, 0xE0, 0x01, // ld a, $42; ldh (SB), a
+        0x3E, 0x81, 0xE0, 0x02, // ld a, $81; ldh (SC), a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("HWEVENTS").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let log = seen.clone();
+    gameboy.subscribe(move |event| log.borrow_mut().push(*event));
+    gameboy.execute_frame();
+    gameboy.execute_frame();
+
+    let seen = seen.borrow();
+    for expected in [
+        HardwareEvent::DmaStarted {
+            kind: DmaKind::Oam,
+            source: 0x8000,
+        },
+        // Nothing is connected, so 0xFF comes back
+        HardwareEvent::SerialExchanged {
+            sent: 0x42,
+            received: 0xFF,
+        },
+        HardwareEvent::InterruptRaised(InterruptKind::Serial),
+        HardwareEvent::InterruptRaised(InterruptKind::VBlank),
+        HardwareEvent::PpuModeChanged(PpuMode::VBlank),
+    ] {
+        assert!(seen.contains(&expected), "{:?} was not published", expected);
+    }
+    let position = |event| seen.iter().position(|seen| *seen == event).unwrap();
+    assert!(
+        position(HardwareEvent::SerialExchanged {
+            sent: 0x42,
+            received: 0xFF
+        }) < position(HardwareEvent::InterruptRaised(InterruptKind::Serial))
+    );
+}
@@ -0,0 +1,32 @@
+use rust_gameboycolor::utils::{diff_frames, encode_png};
+use rust_gameboycolor::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+fn frame() -> Vec<(u8, u8, u8)> {
+    vec![(0xE0, 0xF8, 0xD0); SCREEN_WIDTH * SCREEN_HEIGHT]
+}
+
+#[test]
+fn identical_frames_have_no_diff_image() {
+    let report = diff_frames(&frame(), &frame());
+    assert!(report.is_identical());
+    assert_eq!(report.first_mismatch, None);
+    assert_eq!(report.image, None);
+}
+
+#[test]
+fn differing_pixels_are_counted_and_marked_red() {
+    let mut other = frame();
+    other[3 * SCREEN_WIDTH + 7] = (0xE0, 0xF8, 0xC0);
+    other[100 * SCREEN_WIDTH] = (0x08, 0x18, 0x20);
+
+    let report = diff_frames(&frame(), &other);
+    assert_eq!(report.mismatched_pixels, 2);
+    assert_eq!(report.first_mismatch, Some((7, 3)));
+    assert_eq!(report.max_channel_delta, 0xF8 - 0x18);
+
+    let image = report.image.unwrap();
+    assert_eq!(image[3 * SCREEN_WIDTH + 7], (0xFF, 0x00, 0x00));
+    assert_eq!(image[100 * SCREEN_WIDTH], (0xFF, 0x00, 0x00));
+    assert_ne!(image[0], (0xFF, 0x00, 0x00));
+    assert!(encode_png(SCREEN_WIDTH, SCREEN_HEIGHT, &image).starts_with(b"\x89PNG"));
+}
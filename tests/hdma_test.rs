@@ -0,0 +1,89 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, GameBoyColor};
+
+fn wait_for_line(line: u8) -> [u8; 6] {
+    [
+        0xF0, 0x44, // wait: ldh a, (LY)
+        0xFE, line, // cp line
+        0x20, 0xFA, // jr nz, wait
+    ]
+}
+
+// ldh a, (HDMA5); ld (hl+), a
+const LOG_HDMA5: [u8; 3] = [0xF0, 0x55, 0x22];
+
+#[test]
+fn hdma_copies_a_block_per_hblank_until_stopped_or_restarted() {
+    let mut program = vec![
+        0x21, 0x00, 0xC0, // ld hl, $C000
+        0x3E, 0x40, // ld a, $40
+        0x22, // fill: ld (hl+), a
+        0x3C, // inc a
+        0xFE, 0x80, // cp $80
+        0x20, 0xFA, // jr nz, fill
+        0x3E, 0xC0, 0xE0, 0x51, // ld a, $C0; ldh (HDMA1), a
+        0xAF, 0xE0, 0x52, // xor a; ldh (HDMA2), a
+        0x3E, 0x80, 0xE0, 0x53, // ld a, $80; ldh (HDMA3), a
+        0xAF, 0xE0, 0x54, // xor a; ldh (HDMA4), a
+        0x21, 0x00, 0xC1, // ld hl, $C100
+    ];
+    program.extend_from_slice(&wait_for_line(0x10));
+    // 4 blocks, started after line 0x10's HBlank
+    program.extend_from_slice(&[0x3E, 0x83, 0xE0, 0x55]);
+    program.extend_from_slice(&LOG_HDMA5);
+    program.extend_from_slice(&wait_for_line(0x11));
+    program.extend_from_slice(&LOG_HDMA5);
+    // Writing with bit 7 set carries on with a new length
+    program.extend_from_slice(&[0x3E, 0x81, 0xE0, 0x55]);
+    program.extend_from_slice(&LOG_HDMA5);
+    // Stop, then check no more blocks come
+    program.extend_from_slice(&[0xAF, 0xE0, 0x55]);
+    program.extend_from_slice(&LOG_HDMA5);
+    program.extend_from_slice(&wait_for_line(0x14));
+    program.extend_from_slice(&LOG_HDMA5);
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("HDMA")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    gameboy.execute_frame();
+
+    // Bit 7 clear while active, then set with the blocks left after stopping
+    let log: Vec<u8> = (0xC100..0xC105)
+        .map(|address| gameboy.peek(address))
+        .collect();
+    assert_eq!(log, [0x03, 0x02, 0x01, 0x81, 0x81]);
+    for offset in 0..0x10 {
+        assert_eq!(gameboy.peek(0x8000 + offset), 0x40 + offset as u8);
+    }
+    assert_eq!(gameboy.peek(0x8010), 0x00);
+}
+
+#[test]
+fn gdma_past_the_end_of_vram_stops_and_reports_done() {
+    let mut program = vec![
+        0x3E, 0xC0, 0xE0, 0x51, // ld a, $C0; ldh (HDMA1), a
+        0xAF, 0xE0, 0x52, // xor a; ldh (HDMA2), a
+        0x3E, 0x1F, 0xE0, 0x53, // ld a, $1F; ldh (HDMA3), a
+        0x3E, 0xF0, 0xE0, 0x54, // ld a, $F0; ldh (HDMA4), a
+        0x21, 0x00, 0xC1, // ld hl, $C100
+        0x3E, 0x41, 0xEA, 0x00, 0xC0, // ld a, $41; ld ($C000), a
+        0x3E, 0x01, 0xE0, 0x55, // ld a, $01; ldh (HDMA5), a, 2 blocks
+    ];
+    program.extend_from_slice(&LOG_HDMA5);
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("GDMA")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    gameboy.execute_frame();
+
+    assert_eq!(gameboy.peek(0xC100), 0xFF);
+    assert_eq!(gameboy.peek(0x9FF0), 0x41);
+    // The second block doesn't wrap around to 0x8000
+    assert_eq!(gameboy.peek(0x8000), 0x00);
+}
@@ -0,0 +1,22 @@
+#![cfg(feature = "indexed-output")]
+
+mod common;
+
+use common::tile_scene;
+use rust_gameboycolor::DeviceMode;
+
+#[test]
+fn indexed_frame_matches_rgb_frame() {
+    for device_mode in [DeviceMode::GameBoy, DeviceMode::GameBoyColor] {
+        let gameboy = tile_scene(device_mode);
+        let indices = gameboy.indexed_frame_buffer();
+        let table = gameboy.palette_table();
+        assert_eq!(indices[4 * 160 + 4], 1, "{:?}", device_mode);
+        // OBJ palette 1, colour 3
+        assert_eq!(indices[4 * 160 + 12], 32 + 4 + 3, "{:?}", device_mode);
+
+        for (&index, &rgb) in indices.iter().zip(gameboy.frame_buffer()) {
+            assert_eq!(table[index as usize], rgb, "{:?}", device_mode);
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use rust_gameboycolor::input::{Action, Bindings, BindingsError, Hotkey};
+use rust_gameboycolor::{JoypadKey, JoypadKeyState};
+
+#[test]
+fn default_bindings_drive_the_joypad_and_report_hotkeys() {
+    let bindings = Bindings::default();
+    assert_eq!(bindings.validate(), Ok(()));
+
+    let mut state = JoypadKeyState::new();
+    assert_eq!(bindings.apply("X", true, &mut state), None);
+    assert!(state.is_pressed(JoypadKey::A));
+    bindings.apply("X", false, &mut state);
+    assert!(state.is_empty());
+
+    assert_eq!(bindings.apply("P", true, &mut state), Some(Hotkey::Pause));
+    assert_eq!(bindings.apply("P", false, &mut state), None);
+    assert_eq!(bindings.apply("Unbound", true, &mut state), None);
+    assert!(state.is_empty());
+}
+
+#[test]
+fn validation_needs_every_button_and_named_keys() {
+    let mut bindings = Bindings::default();
+    bindings.bind("K", Action::Joypad(JoypadKey::Start));
+    assert_eq!(
+        bindings.unbind("Return"),
+        Some(Action::Joypad(JoypadKey::Start))
+    );
+    assert_eq!(bindings.validate(), Ok(()));
+
+    bindings.unbind("K");
+    assert_eq!(
+        bindings.validate(),
+        Err(BindingsError::Unbound(JoypadKey::Start))
+    );
+
+    let mut bindings = Bindings::default();
+    bindings.bind("", Action::Hotkey(Hotkey::Reset));
+    assert_eq!(bindings.validate(), Err(BindingsError::EmptyKey));
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn bindings_round_trip_through_serde() {
+    let mut bindings = Bindings::default();
+    bindings.bind("A", Action::Joypad(JoypadKey::B));
+    let json = serde_json::to_string(&bindings).unwrap();
+    assert!(json.contains(r#""A":"B""#) && json.contains(r#""Tab":"FastForward""#));
+    assert_eq!(serde_json::from_str::<Bindings>(&json).unwrap(), bindings);
+
+    let missing_start = r#"{"Right":"Right","Left":"Left","Up":"Up","Down":"Down","X":"A","Z":"B","Space":"Select"}"#;
+    let error = serde_json::from_str::<Bindings>(missing_start).unwrap_err();
+    assert!(error.to_string().contains("Start"), "{}", error);
+}
@@ -0,0 +1,182 @@
+mod common;
+
+use common::{boot, RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, GameBoyColor, JoypadKey, JoypadKeyState};
+
+// Handlers append a tag to the log at HL, which only they touch once the program has
+// set it to 0xC000. The tags are the vector addresses: timer 0x50, serial 0x58 and
+// joypad 0x60.
+const LOG_START: u16 = 0xC000;
+const TIMER: u8 = 0x50;
+const SERIAL: u8 = 0x58;
+const JOYPAD: u8 = 0x60;
+
+/// `push af; ld a, tag; ld (hl+), a; pop af; reti`, which fits in a vector
+fn log_and_return(tag: u8) -> [u8; 6] {
+    [0xF5, 0x3E, tag, 0x22, 0xF1, 0xD9]
+}
+
+/// `ld a, tag; ld (hl+), a`
+fn log(tag: u8) -> [u8; 3] {
+    [0x3E, tag, 0x22]
+}
+
+/// `ld a, value; ldh (register), a`
+fn write_io(register: u8, value: u8) -> [u8; 4] {
+    [0x3E, value, 0xE0, register]
+}
+
+/// `ldh a, (IF); and $1C; ld (hl+), a`, leaving out the VBlank and STAT bits the
+/// running PPU sets
+fn log_if() -> [u8; 5] {
+    [0xF0, 0x0F, 0xE6, 0x1C, 0x22]
+}
+
+fn storm_rom(title: &str, program: &[u8], handlers: &[(usize, &[u8])]) -> Vec<u8> {
+    // di; ld hl, LOG_START
+    let mut code = vec![0xF3, 0x21, LOG_START as u8, (LOG_START >> 8) as u8];
+    code.extend_from_slice(program);
+    code.extend_from_slice(&LOOP_FOREVER);
+    let mut builder = RomBuilder::new(title)
+        .program(&code)
+        .patch(TIMER as usize, &log_and_return(TIMER))
+        .patch(SERIAL as usize, &log_and_return(SERIAL))
+        .patch(JOYPAD as usize, &log_and_return(JOYPAD));
+    for &(address, handler) in handlers {
+        builder = builder.patch(address, handler);
+    }
+    builder.build()
+}
+
+fn service_log(gameboy: &GameBoyColor) -> Vec<u8> {
+    (LOG_START..)
+        .map(|address| gameboy.peek(address))
+        .take_while(|&tag| tag != 0)
+        .collect()
+}
+
+#[test]
+fn pending_interrupts_are_serviced_by_priority_and_masked_by_ie() {
+    let mut program = Vec::new();
+    program.extend(write_io(0xFF, 0x14)); // IE: timer and joypad
+    program.extend(write_io(0x0F, 0x1C)); // IF: timer, serial and joypad at once
+    program.extend([0xFB, 0x00]); // ei; nop
+    program.extend(log(0xAA));
+    // Serial stays requested while IE masks it, and is serviced once IE allows it
+    program.extend(log_if());
+    program.extend(write_io(0xFF, 0x08));
+    program.push(0x00);
+    // HALT with IME off wakes on a pending interrupt without servicing it
+    program.push(0xF3);
+    program.extend(write_io(0x0F, 0x04));
+    program.extend(write_io(0xFF, 0x04));
+    program.extend([0x76, 0x00]); // halt; nop
+    program.extend(log(0xBB));
+    program.extend(log_if());
+
+    let rom = storm_rom("PRIORITY", &program, &[]);
+    let (mut gameboy, _) = boot(&rom, DeviceMode::GameBoy);
+    gameboy.execute_frame();
+    assert_eq!(
+        service_log(&gameboy),
+        [TIMER, JOYPAD, 0xAA, 0x08, SERIAL, 0xBB, 0x04]
+    );
+}
+
+#[test]
+fn handlers_nest_only_after_ei() {
+    // The serial handler re-enables interrupts and then requests the timer
+    let mut serial = vec![0xF5];
+    serial.extend(log(SERIAL));
+    serial.extend(write_io(0x0F, 0x04));
+    serial.extend([0xFB, 0x00]); // ei; nop
+    serial.extend(log(SERIAL + 1));
+    serial.extend([0xF1, 0xD9]);
+    // The joypad handler requests the timer with IME still off
+    let mut joypad = vec![0xF5];
+    joypad.extend(log(JOYPAD));
+    joypad.extend(write_io(0x0F, 0x04));
+    joypad.push(0x00);
+    joypad.extend(log(JOYPAD + 1));
+    joypad.extend([0xF1, 0xD9]);
+
+    let mut program = Vec::new();
+    program.extend(write_io(0xFF, 0x1C));
+    program.extend(write_io(0x0F, 0x08));
+    program.extend([0xFB, 0x00]);
+    program.extend(log(0xAA));
+    program.extend(write_io(0x0F, 0x10));
+    program.push(0x00);
+    program.extend(log(0xBB));
+
+    let rom = storm_rom(
+        "NESTING",
+        &program,
+        &[
+            (SERIAL as usize, &[0xC3, 0x00, 0x02]), // jp $0200
+            (JOYPAD as usize, &[0xC3, 0x40, 0x02]), // jp $0240
+            (0x0200, &serial),
+            (0x0240, &joypad),
+        ],
+    );
+    let (mut gameboy, _) = boot(&rom, DeviceMode::GameBoy);
+    gameboy.execute_frame();
+    assert_eq!(
+        service_log(&gameboy),
+        [
+            SERIAL,
+            TIMER,
+            SERIAL + 1,
+            0xAA,
+            JOYPAD,
+            JOYPAD + 1,
+            TIMER,
+            0xBB
+        ]
+    );
+}
+
+#[test]
+fn scripted_storm_is_serviced_in_order() {
+    let mut program = Vec::new();
+    program.extend(write_io(0xFF, 0x1C));
+    // Timer overflows every 64 ticks of 256 M-cycles
+    program.extend(write_io(0x06, 0xC0)); // TMA
+    program.extend(write_io(0x05, 0xC0)); // TIMA
+    program.extend(write_io(0x07, 0x04)); // TAC: on, 4096 Hz
+
+    // One byte over serial on the internal clock, done after 1024 M-cycles
+    program.extend(write_io(0x01, 0x42));
+    program.extend(write_io(0x02, 0x81));
+    // ei; wait: halt; nop; jr wait
+    program.extend([0xFB, 0x76, 0x00, 0x18, 0xFC]);
+    let rom = storm_rom("STORM", &program, &[]);
+    let (mut gameboy, _) = boot(&rom, DeviceMode::GameBoy);
+
+    let keys = |pressed: &[JoypadKey]| {
+        let mut state = JoypadKeyState::new();
+        for &key in pressed {
+            state.set_key(key, true);
+        }
+        state
+    };
+    // Presses raise the joypad interrupt, releases do not
+    let script = [
+        (500, keys(&[JoypadKey::A])),
+        (8_000, keys(&[JoypadKey::A, JoypadKey::B])),
+        (12_000, keys(&[])),
+        (20_000, keys(&[JoypadKey::Start])),
+        (24_000, keys(&[JoypadKey::Start])),
+        (40_000, keys(&[])),
+    ];
+    let start = gameboy.total_cycles();
+    for (cycle, state) in script {
+        let elapsed = gameboy.total_cycles() - start;
+        gameboy.run_cycles(cycle - elapsed);
+        gameboy.set_key(state);
+    }
+    assert_eq!(
+        service_log(&gameboy),
+        [JOYPAD, SERIAL, JOYPAD, TIMER, JOYPAD, TIMER]
+    );
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, GameBoyColor};
+
+// Writes each pattern to every register in FF00-FFFF and reads it back, which a
+// malicious or buggy ROM is free to do.
+fn sweep_program(patterns: &[u8]) -> Vec<u8> {
+    let mut program = Vec::new();
+    for &value in patterns {
+        for register in 0x00..=0xFF {
+            // ld a, value; ldh (register), a; ldh a, (register)
+            program.extend_from_slice(&[0x3E, value, 0xE0, register, 0xF0, register]);
+        }
+    }
+    program.extend_from_slice(&LOOP_FOREVER);
+    program
+}
+
+fn run_sweep(device_mode: DeviceMode, cgb_flag: u8) {
+    let rom = RomBuilder::new("IOSWEEP")
+        .cgb_flag(cgb_flag)
+        .program(&sweep_program(&[0xFF, 0x00, 0x55, 0xAA, 0x81]))
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, device_mode, None).unwrap();
+    for _ in 0..10 {
+        gameboy.execute_frame();
+    }
+}
+
+#[test]
+fn io_register_sweep_does_not_panic_on_dmg() {
+    run_sweep(DeviceMode::GameBoy, 0x00);
+}
+
+#[test]
+fn io_register_sweep_does_not_panic_on_cgb() {
+    run_sweep(DeviceMode::GameBoyColor, 0x80);
+}
@@ -0,0 +1,29 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, GameBoyColor, JoypadKey, JoypadKeyState};
+
+#[test]
+fn polled_keys_follow_the_selected_lines() {
+    let program = [
+        0x3E, 0x10, 0xE0, 0x00, // ld a, $10; ldh (P1), a (buttons only)
+        0xF0, 0x00, 0x18, 0xFC, // loop: ldh a, (P1); jr loop
+    ];
+    let rom = RomBuilder::new("POLLED").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    gameboy.run_frame(JoypadKeyState::new());
+
+    let polled = gameboy.polled_keys();
+    assert!(polled.is_pressed(JoypadKey::A));
+    assert!(polled.is_pressed(JoypadKey::Start));
+    assert!(!polled.is_pressed(JoypadKey::Right));
+    assert!(!polled.is_pressed(JoypadKey::Down));
+}
+
+#[test]
+fn lag_frames_poll_no_keys() {
+    let rom = RomBuilder::new("LAGFRAME").program(&LOOP_FOREVER).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    gameboy.run_frame(JoypadKeyState::new());
+    assert!(gameboy.polled_keys().is_empty());
+}
@@ -0,0 +1,518 @@
+mod common;
+
+use common::{
+    boot, run_until_serial, write_imm, RomBuilder, SerialLog, LOOP_FOREVER, PROGRAM_START,
+    SEND_SERIAL_A,
+};
+use rust_gameboycolor::utils::{import_save, RtcRegisters};
+use rust_gameboycolor::{
+    ClockSource, DeviceMode, EmulatorConfigBuilder, EmulatorEvent, GameBoyColor,
+};
+
+use std::cell::Cell;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+/// Writes the bank number (low byte, then high byte) at the start of every 16 KiB bank.
+fn tag_banks(mut rom: Vec<u8>) -> Vec<u8> {
+    for bank in 0..rom.len() / 0x4000 {
+        rom[bank * 0x4000] = bank as u8;
+        rom[bank * 0x4000 + 1] = (bank >> 8) as u8;
+    }
+    rom
+}
+
+/// Sends the two bank tag bytes visible at 0x4000 over serial.
+fn send_switchable_tag() -> Vec<u8> {
+    let mut code = vec![0xFA, 0x00, 0x40];
+    code.extend_from_slice(&SEND_SERIAL_A);
+    code.extend_from_slice(&[0xFA, 0x01, 0x40]);
+    code.extend_from_slice(&SEND_SERIAL_A);
+    code
+}
+
+#[test]
+fn mbc5_selects_banks_above_0xff_and_bank_0() {
+    let mut program = Vec::new();
+    program.extend(write_imm(0x3000, 0x01));
+    program.extend(write_imm(0x2000, 0x00));
+    program.extend(send_switchable_tag());
+    program.extend(write_imm(0x2000, 0xFF));
+    program.extend(send_switchable_tag());
+    program.extend(write_imm(0x3000, 0x00));
+    program.extend(write_imm(0x2000, 0x00));
+    program.extend(send_switchable_tag());
+    // Writes to 0x6000-0x7FFF are ignored on MBC5
+    program.extend(write_imm(0x6000, 0x01));
+    program.extend(write_imm(0x2000, 0x42));
+    program.extend(send_switchable_tag());
+    program.extend_from_slice(&LOOP_FOREVER);
+
+    // 8 MiB ROM, 512 banks
+    let rom = RomBuilder::new("MBC5BANKS")
+        .cartridge_type(0x19)
+        .rom_size(0x08)
+        .program(&program)
+        .build();
+    let (mut gameboy, log) = boot(&tag_banks(rom), DeviceMode::GameBoy);
+
+    let bytes = run_until_serial(&mut gameboy, &log, 8, 10);
+    assert_eq!(
+        bytes,
+        [0x00, 0x01, 0xFF, 0x01, 0x00, 0x00, 0x42, 0x00],
+        "expected banks 0x100, 0x1FF, 0x000, 0x042"
+    );
+}
+
+fn size_warnings(gameboy: &mut GameBoyColor) -> Vec<String> {
+    gameboy
+        .take_events()
+        .into_iter()
+        .filter_map(|event| match event {
+            EmulatorEvent::Warning { detail, .. } if detail.starts_with("ROM image") => {
+                Some(detail)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn rom_images_larger_than_the_header_keep_their_extra_banks() {
+    let mut program = write_imm(0x2000, 0x05);
+    program.extend(send_switchable_tag());
+    program.extend_from_slice(&LOOP_FOREVER);
+    // MBC1 declaring 32 KiB, with 128 KiB of data
+    let mut rom = RomBuilder::new("OVERSIZE")
+        .cartridge_type(0x01)
+        .program(&program)
+        .build();
+    rom.resize(0x20000, 0);
+    let (mut gameboy, log) = boot(&tag_banks(rom), DeviceMode::GameBoy);
+
+    assert_eq!(
+        size_warnings(&mut gameboy),
+        ["ROM image is 131072 bytes but the header declares 32768"]
+    );
+    assert_eq!(run_until_serial(&mut gameboy, &log, 2, 10), [0x05, 0x00]);
+}
+
+#[test]
+fn rom_images_smaller_than_the_header_read_open_bus_past_the_end() {
+    let mut program = write_imm(0x2000, 0x06);
+    program.extend(send_switchable_tag());
+    program.extend_from_slice(&LOOP_FOREVER);
+    // MBC1 declaring 128 KiB, with only the first 32 KiB
+    let rom = RomBuilder::new("TRUNCATED")
+        .cartridge_type(0x01)
+        .patch(0x0148, &[0x02])
+        .program(&program)
+        .build();
+    let (mut gameboy, log) = boot(&rom, DeviceMode::GameBoy);
+
+    assert_eq!(
+        size_warnings(&mut gameboy),
+        ["ROM image is 32768 bytes but the header declares 131072"]
+    );
+    assert_eq!(run_until_serial(&mut gameboy, &log, 2, 10), [0xFF, 0xFF]);
+}
+
+#[test]
+fn mbc1_aliases_banks_0x20_0x40_0x60() {
+    let mut program = Vec::new();
+    // Power-on state maps bank 1
+    program.extend(send_switchable_tag());
+    program.extend(write_imm(0x4000, 0x01));
+    program.extend(write_imm(0x2000, 0x00));
+    program.extend(send_switchable_tag());
+    program.extend(write_imm(0x4000, 0x03));
+    program.extend(write_imm(0x2000, 0x05));
+    program.extend(send_switchable_tag());
+    // Mode 1 applies the upper bits to 0x0000-0x3FFF as well
+    program.extend(write_imm(0x4000, 0x02));
+    program.extend(write_imm(0x6000, 0x01));
+    program.extend_from_slice(&[0xFA, 0x00, 0x00]);
+    program.extend_from_slice(&SEND_SERIAL_A);
+    program.extend_from_slice(&LOOP_FOREVER);
+
+    // 2 MiB ROM, 128 banks
+    // The program keeps running from bank 0x40 once mode 1 remaps 0x0000-0x3FFF
+    let rom = RomBuilder::new("MBC1BANKS")
+        .cartridge_type(0x01)
+        .rom_size(0x06)
+        .program(&program)
+        .patch(0x40 * 0x4000 + PROGRAM_START, &program)
+        .build();
+    let (mut gameboy, log) = boot(&tag_banks(rom), DeviceMode::GameBoy);
+
+    let bytes = run_until_serial(&mut gameboy, &log, 7, 10);
+    assert_eq!(
+        bytes,
+        [0x01, 0x00, 0x21, 0x00, 0x65, 0x00, 0x40],
+        "expected banks 0x01, 0x21, 0x65, then 0x40 at 0x0000"
+    );
+}
+
+#[test]
+fn mbc2_ram_is_512_half_bytes_with_upper_bits_set() {
+    let mut program = Vec::new();
+    // A8 clear: RAMG, A8 set: ROMB
+    program.extend(write_imm(0x0000, 0x0A));
+    program.extend(write_imm(0x2100, 0x03));
+    program.extend(send_switchable_tag());
+    program.extend(write_imm(0xA000, 0x5A));
+    program.extend(write_imm(0xA001, 0x03));
+    for address in [0xA000u16, 0xA001, 0xA200, 0xBE01] {
+        program.extend_from_slice(&[0xFA, address as u8, (address >> 8) as u8]);
+        program.extend_from_slice(&SEND_SERIAL_A);
+    }
+    program.extend_from_slice(&LOOP_FOREVER);
+
+    // 256 KiB ROM, 16 banks
+    let rom = RomBuilder::new("MBC2RAM")
+        .cartridge_type(0x05)
+        .rom_size(0x03)
+        .program(&program)
+        .build();
+    let (mut gameboy, log) = boot(&tag_banks(rom), DeviceMode::GameBoy);
+
+    let bytes = run_until_serial(&mut gameboy, &log, 6, 10);
+    assert_eq!(bytes, [0x03, 0x00, 0xFA, 0xF3, 0xFA, 0xF3]);
+}
+
+#[test]
+fn mbc2_loads_saves_in_the_old_packed_layout() {
+    let rom = RomBuilder::new("MBC2SAVE").cartridge_type(0x06).build();
+    let load = |save: Vec<u8>| {
+        let config = EmulatorConfigBuilder::default()
+            .device_mode(DeviceMode::GameBoy)
+            .save_data(save)
+            .build()
+            .unwrap();
+        GameBoyColor::with_config(&rom, config)
+            .unwrap()
+            .save_data()
+            .unwrap()
+    };
+
+    // Cells 0 and 1 in the low nibble of byte 0, cells 2 and 3 in the high nibble of byte 1
+    let mut old = vec![0; 512];
+    old[0] = 0x05;
+    old[1] = 0xA0;
+    let mut cells = vec![0; 512];
+    cells[..4].copy_from_slice(&[0x05, 0x05, 0x0A, 0x0A]);
+    assert_eq!(load(old), cells);
+    assert_eq!(load(cells.clone()), cells);
+}
+
+#[test]
+fn huc1_switches_between_ram_and_ir_mode() {
+    let mut program = Vec::new();
+    program.extend(write_imm(0x2000, 0x3F));
+    program.extend(send_switchable_tag());
+    program.extend(write_imm(0x4000, 0x02));
+    program.extend(write_imm(0xA000, 0x77));
+    program.extend(write_imm(0x4000, 0x00));
+    program.extend(write_imm(0xA000, 0x11));
+    // IR mode: the sensor reads 0xC0 until the LED is lit
+    program.extend(write_imm(0x0000, 0x0E));
+    program.extend_from_slice(&[0xFA, 0x00, 0xA0]);
+    program.extend_from_slice(&SEND_SERIAL_A);
+    program.extend(write_imm(0xA000, 0x01));
+    program.extend_from_slice(&[0xFA, 0x00, 0xA0]);
+    program.extend_from_slice(&SEND_SERIAL_A);
+    program.extend(write_imm(0xA000, 0x00));
+    // Back to RAM mode, bank 2 is untouched by the IR writes
+    program.extend(write_imm(0x0000, 0x00));
+    program.extend(write_imm(0x4000, 0x02));
+    program.extend_from_slice(&[0xFA, 0x00, 0xA0]);
+    program.extend_from_slice(&SEND_SERIAL_A);
+    program.extend_from_slice(&LOOP_FOREVER);
+
+    // 1 MiB ROM, 32 KiB RAM
+    let rom = RomBuilder::new("HUC1TEST")
+        .cartridge_type(0xFF)
+        .rom_size(0x05)
+        .ram_size(0x03)
+        .program(&program)
+        .build();
+    let (mut gameboy, log) = boot(&tag_banks(rom), DeviceMode::GameBoy);
+
+    let bytes = run_until_serial(&mut gameboy, &log, 5, 10);
+    assert_eq!(bytes, [0x3F, 0x00, 0xC0, 0xC1, 0x77]);
+
+    let save = gameboy.save_data().expect("HuC1 is battery backed");
+    assert_eq!(save.len(), 0x8000);
+    assert_eq!(save[0x0000], 0x11);
+    assert_eq!(save[0x4000], 0x77);
+}
+
+#[test]
+fn huc3_banks_ram_and_drives_the_rtc_through_commands() {
+    let mut program = Vec::new();
+    program.extend(write_imm(0x2000, 0x3F));
+    program.extend(send_switchable_tag());
+    // RAM is writable in mode 0xA and read-only in mode 0
+    program.extend(write_imm(0x0000, 0x0A));
+    program.extend(write_imm(0x4000, 0x01));
+    program.extend(write_imm(0xA000, 0x5A));
+    program.extend(write_imm(0x0000, 0x00));
+    program.extend(write_imm(0xA000, 0x00));
+    program.extend_from_slice(&[0xFA, 0x00, 0xA0]);
+    program.extend_from_slice(&SEND_SERIAL_A);
+    // Set the clock to day 4, 04:51 (minute 0x123), then latch it back
+    program.extend(write_imm(0x0000, 0x0B));
+    for command in [
+        0x40, 0x50, 0x33, 0x32, 0x31, 0x34, 0x30, 0x30, 0x30, 0x61, 0x60,
+    ] {
+        program.extend(write_imm(0xA000, command));
+    }
+    for address in [0x40, 0x43] {
+        program.extend(write_imm(0x0000, 0x0B));
+        program.extend(write_imm(0xA000, address));
+        program.extend(write_imm(0xA000, 0x10));
+        program.extend(write_imm(0x0000, 0x0C));
+        program.extend_from_slice(&[0xFA, 0x00, 0xA0]);
+        program.extend_from_slice(&SEND_SERIAL_A);
+    }
+    program.extend(write_imm(0x0000, 0x0D));
+    program.extend_from_slice(&[0xFA, 0x00, 0xA0]);
+    program.extend_from_slice(&SEND_SERIAL_A);
+    program.extend_from_slice(&LOOP_FOREVER);
+
+    // 1 MiB ROM, 32 KiB RAM
+    let rom = RomBuilder::new("HUC3TEST")
+        .cartridge_type(0xFE)
+        .rom_size(0x05)
+        .ram_size(0x03)
+        .program(&program)
+        .build();
+    let (mut gameboy, log) = boot(&tag_banks(rom), DeviceMode::GameBoy);
+
+    let bytes = run_until_serial(&mut gameboy, &log, 6, 10);
+    // Read results carry the read command in the upper nibble
+    assert_eq!(bytes, [0x3F, 0x00, 0x5A, 0x93, 0x94, 0x01]);
+
+    let save = gameboy.save_data().expect("HuC3 is battery backed");
+    assert_eq!(save.len(), 0x8000 + 8, "RAM followed by the clock");
+    assert_eq!(save[0x2000], 0x5A);
+}
+
+#[test]
+fn mbc3_rtc_registers_are_writable_latched_and_saved() {
+    let mut program = Vec::new();
+    program.extend(write_imm(0x0000, 0x0A));
+    // Halt the clock first so the host clock can't move it
+    program.extend(write_imm(0x4000, 0x0C));
+    program.extend(write_imm(0xA000, 0x40));
+    for (register, value) in [(0x08, 0x3B), (0x09, 0x3A), (0x0A, 0x17), (0x0B, 0xFF)] {
+        program.extend(write_imm(0x4000, register));
+        program.extend(write_imm(0xA000, value));
+    }
+    program.extend(write_imm(0x4000, 0x0C));
+    program.extend(write_imm(0xA000, 0xC1));
+    // Reads see the latched registers until the next 0 -> 1 latch write
+    program.extend(write_imm(0x4000, 0x08));
+    program.extend_from_slice(&[0xFA, 0x00, 0xA0]);
+    program.extend_from_slice(&SEND_SERIAL_A);
+    program.extend(write_imm(0x6000, 0x00));
+    program.extend(write_imm(0x6000, 0x01));
+    for register in 0x08..=0x0C {
+        program.extend(write_imm(0x4000, register));
+        program.extend_from_slice(&[0xFA, 0x00, 0xA0]);
+        program.extend_from_slice(&SEND_SERIAL_A);
+    }
+    program.extend_from_slice(&LOOP_FOREVER);
+
+    let rom = RomBuilder::new("MBC3RTC")
+        .cartridge_type(0x10)
+        .ram_size(0x03)
+        .program(&program)
+        .build();
+    let (mut gameboy, log) = boot(&rom, DeviceMode::GameBoy);
+
+    let bytes = run_until_serial(&mut gameboy, &log, 6, 10);
+    assert_eq!(bytes, [0x00, 0x3B, 0x3A, 0x17, 0xFF, 0xC1]);
+
+    let save = gameboy
+        .save_data()
+        .expect("MBC3 with a timer is battery backed");
+    let save = import_save(&save, 0x8000).expect("RAM followed by a 48-byte RTC footer");
+    let rtc = save.rtc.unwrap();
+    assert_eq!(rtc.current, rtc.latched);
+    assert_eq!(
+        rtc.current,
+        RtcRegisters {
+            seconds: 0x3B,
+            minutes: 0x3A,
+            hours: 0x17,
+            days_low: 0xFF,
+            days_high: 0xC1,
+        }
+    );
+}
+
+#[derive(Clone)]
+struct TestClock(Rc<Cell<i64>>);
+
+impl ClockSource for TestClock {
+    fn now(&self) -> i64 {
+        self.0.get()
+    }
+}
+
+/// Powers on an MBC3 game that enables RAM and runs `setup`, then keeps latching the
+/// clock and sending `registers` over serial. The clock source starts at 1,000,000.
+fn run_rtc_rom(
+    title: &str,
+    setup: &[u8],
+    registers: RangeInclusive<u8>,
+    emulated_rtc: bool,
+) -> (GameBoyColor, SerialLog, TestClock) {
+    let mut program = write_imm(0x0000, 0x0A);
+    program.extend_from_slice(setup);
+    let mut report = Vec::new();
+    report.extend(write_imm(0x6000, 0x00));
+    report.extend(write_imm(0x6000, 0x01));
+    for register in registers {
+        report.extend(write_imm(0x4000, register));
+        report.extend_from_slice(&[0xFA, 0x00, 0xA0]);
+        report.extend_from_slice(&SEND_SERIAL_A);
+    }
+    report.extend_from_slice(&[0x18, (-(report.len() as i8) - 2) as u8]);
+    program.extend(report);
+
+    let rom = RomBuilder::new(title)
+        .cartridge_type(0x10)
+        .ram_size(0x03)
+        .program(&program)
+        .build();
+    let clock = TestClock(Rc::new(Cell::new(1_000_000)));
+    let log = SerialLog::default();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .link_cable(Box::new(log.clone()))
+        .clock_source(Box::new(clock.clone()))
+        .emulated_rtc(emulated_rtc)
+        .build()
+        .unwrap();
+    let gameboy = GameBoyColor::with_config(&rom, config).unwrap();
+    (gameboy, log, clock)
+}
+
+#[test]
+fn mbc3_rtc_follows_the_clock_source_and_carries_past_day_511() {
+    // Day 511, 23:59:59
+    let mut setup = Vec::new();
+    setup.extend(write_imm(0x4000, 0x0C));
+    setup.extend(write_imm(0xA000, 0x40));
+    for (register, value) in [
+        (0x08, 59),
+        (0x09, 59),
+        (0x0A, 23),
+        (0x0B, 0xFF),
+        (0x0C, 0x01),
+    ] {
+        setup.extend(write_imm(0x4000, register));
+        setup.extend(write_imm(0xA000, value));
+    }
+    let (mut gameboy, log, clock) = run_rtc_rom("MBC3CLOCK", &setup, 0x08..=0x0C, false);
+    assert!(gameboy.rtc_supported());
+
+    let last_report = |gameboy: &mut GameBoyColor| {
+        for _ in 0..2 {
+            gameboy.execute_frame();
+        }
+        let bytes = log.bytes();
+        let end = bytes.len() / 5 * 5;
+        bytes[end - 5..end].to_vec()
+    };
+    assert_eq!(last_report(&mut gameboy), [59, 59, 23, 0xFF, 0x01]);
+    // Two seconds later the day counter has wrapped and set the carry
+    clock.0.set(1_000_002);
+    assert_eq!(last_report(&mut gameboy), [1, 0, 0, 0x00, 0x80]);
+}
+
+#[test]
+fn emulated_rtc_follows_emulated_time_instead_of_the_clock_source() {
+    let (mut gameboy, log, clock) = run_rtc_rom("MBC3EMU", &[], 0x08..=0x08, true);
+    assert!(gameboy.rtc_supported());
+
+    let mut seconds_after = |frames: usize| {
+        for _ in 0..frames {
+            gameboy.execute_frame();
+        }
+        *log.bytes().last().unwrap()
+    };
+    assert_eq!(seconds_after(30), 0);
+    // The clock source only sets the starting time
+    clock.0.set(1_000_100);
+    assert_eq!(seconds_after(20), 0);
+    // 130 frames of 70224 dots at 4194304 Hz are just over two seconds
+    assert_eq!(seconds_after(80), 2);
+}
+
+#[test]
+fn mbc3_rtc_stops_while_the_battery_is_low() {
+    let (mut gameboy, log, clock) = run_rtc_rom("MBC3BATTERY", &[], 0x08..=0x08, false);
+    assert!(!gameboy.rtc_battery_low());
+
+    let seconds_at = |gameboy: &mut GameBoyColor, now: i64| {
+        clock.0.set(now);
+        for _ in 0..2 {
+            gameboy.execute_frame();
+        }
+        *log.bytes().last().unwrap()
+    };
+    assert_eq!(seconds_at(&mut gameboy, 1_000_003), 3);
+    gameboy.set_rtc_battery_low(true);
+    assert_eq!(seconds_at(&mut gameboy, 1_000_010), 3);
+
+    let save = gameboy.save_data().unwrap();
+    let rtc = import_save(&save, 0x8000).unwrap().rtc.unwrap();
+    assert!(rtc.battery_low);
+
+    // The time the clock was stopped is lost
+    gameboy.set_rtc_battery_low(false);
+    assert_eq!(seconds_at(&mut gameboy, 1_000_012), 5);
+}
+
+#[test]
+fn rtc_support_follows_header_and_build() {
+    let timer = RomBuilder::new("MBC3TIMER")
+        .cartridge_type(0x10)
+        .ram_size(0x03)
+        .build();
+    let (gameboy, _) = boot(&timer, DeviceMode::GameBoy);
+    assert_eq!(gameboy.rtc_supported(), cfg!(feature = "rtc"));
+
+    let no_timer = RomBuilder::new("MBC3").cartridge_type(0x13).build();
+    let (gameboy, _) = boot(&no_timer, DeviceMode::GameBoy);
+    assert!(!gameboy.rtc_supported());
+}
+
+#[test]
+fn configured_save_data_replaces_the_save_on_disk() {
+    let mut program = write_imm(0x0000, 0x0A);
+    program.extend_from_slice(&[0xFA, 0x00, 0xA0]);
+    program.extend_from_slice(&SEND_SERIAL_A);
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("SAVEDATA")
+        .cartridge_type(0x03)
+        .ram_size(0x02)
+        .program(&program)
+        .build();
+    let mut save = vec![0x00; 0x2000];
+    save[0] = 0x5A;
+    let log = SerialLog::default();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .link_cable(Box::new(log.clone()))
+        .save_data(save.clone())
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(&rom, config).unwrap();
+
+    assert_eq!(run_until_serial(&mut gameboy, &log, 1, 10), [0x5A]);
+    assert_eq!(gameboy.save_data(), Some(save));
+}
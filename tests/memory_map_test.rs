@@ -0,0 +1,125 @@
+mod common;
+
+use common::RomBuilder;
+use rust_gameboycolor::{DeviceMode, EmulatorConfigBuilder, GameBoyColor, Model};
+
+fn run(model: Model, device_mode: DeviceMode, source: &str) -> GameBoyColor {
+    let rom = RomBuilder::new("MEMORY").cgb_flag(0x80).asm(source).build();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(device_mode)
+        .model(model)
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(&rom, config).unwrap();
+    gameboy.execute_frame();
+    gameboy
+}
+
+#[test]
+fn echo_ram_mirrors_work_ram_and_its_selected_bank() {
+    let gameboy = run(
+        Model::Cgb,
+        DeviceMode::GameBoyColor,
+        "
+            ld a, 2
+            ldh [SVBK], a
+            ld a, $5A
+            ld [$C123], a
+            ld a, $A5
+            ld [$F456], a
+            ld a, [$E123]
+            ld [$C000], a
+            ld a, [$D456]
+            ld [$C001], a
+            ld a, 3
+            ldh [SVBK], a
+            ld a, [$F456]
+            ld [$C002], a
+            jr @
+        ",
+    );
+
+    assert_eq!(gameboy.peek(0xC000), 0x5A);
+    assert_eq!(gameboy.peek(0xC001), 0xA5);
+    assert_eq!(gameboy.peek(0xC002), 0x00);
+    assert_eq!(gameboy.peek(0xE123), 0x5A);
+}
+
+// Writes to FEA0, FEB8, FEC5 and FEF5 with the LCD off, then reads them back
+const PROHIBITED_AREA: &str = "
+        xor a
+        ldh [LCDC], a
+        ld a, $11
+        ld [$FEA0], a
+        ld a, $22
+        ld [$FEB8], a
+        ld a, $33
+        ld [$FEC5], a
+        ld a, $44
+        ld [$FEF5], a
+        ld a, [$FEA0]
+        ld [$C000], a
+        ld a, [$FEB8]
+        ld [$C001], a
+        ld a, [$FEC5]
+        ld [$C002], a
+        ld a, [$FEF5]
+        ld [$C003], a
+        jr @
+";
+
+fn prohibited_area(model: Model, device_mode: DeviceMode) -> [u8; 4] {
+    let gameboy = run(model, device_mode, PROHIBITED_AREA);
+    std::array::from_fn(|i| gameboy.peek(0xC000 + i as u16))
+}
+
+#[test]
+fn prohibited_area_depends_on_the_revision() {
+    assert_eq!(
+        prohibited_area(Model::Dmg, DeviceMode::GameBoy),
+        [0x00, 0x00, 0x00, 0x00]
+    );
+    assert_eq!(
+        prohibited_area(Model::Sgb, DeviceMode::GameBoy),
+        [0x00, 0x00, 0x00, 0x00]
+    );
+    // FEB8 mirrors FEA0, and FEF5 mirrors FEE5
+    assert_eq!(
+        prohibited_area(Model::CgbC, DeviceMode::GameBoyColor),
+        [0x22, 0x22, 0x33, 0x44]
+    );
+    // FEC5 mirrors FEF5
+    assert_eq!(
+        prohibited_area(Model::CgbD, DeviceMode::GameBoyColor),
+        [0x11, 0x22, 0x44, 0x44]
+    );
+    assert_eq!(
+        prohibited_area(Model::Cgb, DeviceMode::GameBoyColor),
+        [0xAA, 0xBB, 0xCC, 0xFF]
+    );
+    assert_eq!(
+        prohibited_area(Model::Agb, DeviceMode::GameBoy),
+        [0xAA, 0xBB, 0xCC, 0xFF]
+    );
+}
+
+#[test]
+fn prohibited_area_reads_0xff_while_oam_is_blocked() {
+    let gameboy = run(
+        Model::Cgb,
+        DeviceMode::GameBoyColor,
+        "
+            ; Line 10 starts with 63 M-cycles of OAM scan and drawing
+        wait:
+            ldh a, [LY]
+            cp 10
+            jr nz, wait
+            ld a, [$FEA0]
+            ld [$C000], a
+            jr @
+        ",
+    );
+
+    assert_eq!(gameboy.peek(0xC000), 0xFF);
+    assert_eq!(gameboy.peek(0xFEA0), 0xAA);
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use common::dump_screen;
+use rust_gameboycolor::{DeviceMode, GameBoyColor, LinkCable};
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use anyhow::{bail, Result};
+
+struct Cable {
+    buffer: Rc<RefCell<Vec<u8>>>,
+}
+
+impl LinkCable for Cable {
+    fn send(&mut self, data: u8) {
+        self.buffer.borrow_mut().push(data);
+    }
+
+    // Nothing is connected, so the line reads high and every transfer completes.
+    fn try_recv(&mut self) -> Option<u8> {
+        Some(0xFF)
+    }
+}
+
+fn mooneye_check(buffer: &[u8]) -> Option<Result<()>> {
+    const PASS: &[u8] = &[3, 5, 8, 13, 21, 34];
+    const FAIL: &[u8] = &[0x42, 0x42, 0x42, 0x42, 0x42, 0x42];
+
+    if buffer.ends_with(PASS) {
+        return Some(Ok(()));
+    } else if buffer.ends_with(FAIL) {
+        return Some(Err(anyhow::anyhow!("Failed: {:02X?}", buffer)));
+    }
+    None
+}
+
+fn mooneye_test(rom_name: &str, device_mode: DeviceMode) -> Result<()> {
+    let rom_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("cartridge")
+        .join("mooneye")
+        .join(rom_name);
+    let rom = std::fs::read(rom_path)?;
+
+    let buffer = Rc::new(RefCell::new(Vec::new()));
+    let cable = Cable {
+        buffer: buffer.clone(),
+    };
+    let mut gameboy = GameBoyColor::new(&rom, device_mode, Some(Box::new(cable))).unwrap();
+    let mut frame = 0;
+    let mut completed = None;
+    while completed.is_none() && frame < 60 * 30 {
+        gameboy.execute_frame();
+        completed = mooneye_check(&buffer.borrow());
+        frame += 1;
+    }
+
+    match completed {
+        Some(Ok(())) => Ok(()),
+        Some(Err(e)) => bail!(
+            "Test failed: {} (screen saved to {})",
+            e,
+            dump_screen(&gameboy, rom_name).display()
+        ),
+        None => bail!(
+            "Test did not complete (screen saved to {})",
+            dump_screen(&gameboy, rom_name).display()
+        ),
+    }
+}
+
+macro_rules! generate_rom_tests {
+    ($($test_name:ident, $rom_path:expr, $device_mode:expr),* $(,)?) => {
+        $(
+            #[test]
+            fn $test_name() -> Result<()> {
+                mooneye_test($rom_path, $device_mode)
+            }
+        )*
+    };
+}
+
+generate_rom_tests!(
+    test_boot_regs_dmg_abc,
+    "acceptance/boot_regs-dmgABC.gb",
+    DeviceMode::GameBoy,
+    test_halt_ime0_ei,
+    "acceptance/halt_ime0_ei.gb",
+    DeviceMode::GameBoy,
+    test_halt_ime0_nointr_timing,
+    "acceptance/halt_ime0_nointr_timing.gb",
+    DeviceMode::GameBoy,
+    test_halt_ime1_timing,
+    "acceptance/halt_ime1_timing.gb",
+    DeviceMode::GameBoy,
+    test_halt_ime1_timing2_gs,
+    "acceptance/halt_ime1_timing2-GS.gb",
+    DeviceMode::GameBoy,
+    test_oam_dma_basic,
+    "acceptance/oam_dma/basic.gb",
+    DeviceMode::GameBoy,
+    test_oam_dma_reg_read,
+    "acceptance/oam_dma/reg_read.gb",
+    DeviceMode::GameBoy,
+    test_oam_dma_sources_gs,
+    "acceptance/oam_dma/sources-GS.gb",
+    DeviceMode::GameBoy,
+    test_oam_dma_restart,
+    "acceptance/oam_dma_restart.gb",
+    DeviceMode::GameBoy,
+    test_oam_dma_start,
+    "acceptance/oam_dma_start.gb",
+    DeviceMode::GameBoy,
+    test_oam_dma_timing,
+    "acceptance/oam_dma_timing.gb",
+    DeviceMode::GameBoy,
+    test_ppu_intr_2_0_timing,
+    "acceptance/ppu/intr_2_0_timing.gb",
+    DeviceMode::GameBoy,
+    test_ppu_intr_2_mode0_timing,
+    "acceptance/ppu/intr_2_mode0_timing.gb",
+    DeviceMode::GameBoy,
+    test_ppu_intr_2_mode3_timing,
+    "acceptance/ppu/intr_2_mode3_timing.gb",
+    DeviceMode::GameBoy,
+    test_ppu_intr_2_oam_ok_timing,
+    "acceptance/ppu/intr_2_oam_ok_timing.gb",
+    DeviceMode::GameBoy,
+    test_ppu_stat_irq_blocking,
+    "acceptance/ppu/stat_irq_blocking.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_bits_bank1,
+    "emulator-only/mbc1/bits_bank1.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_bits_bank2,
+    "emulator-only/mbc1/bits_bank2.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_bits_mode,
+    "emulator-only/mbc1/bits_mode.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_bits_ramg,
+    "emulator-only/mbc1/bits_ramg.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_rom_512kb,
+    "emulator-only/mbc1/rom_512kb.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_rom_1mb,
+    "emulator-only/mbc1/rom_1Mb.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_rom_2mb,
+    "emulator-only/mbc1/rom_2Mb.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_rom_4mb,
+    "emulator-only/mbc1/rom_4Mb.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_rom_8mb,
+    "emulator-only/mbc1/rom_8Mb.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_rom_16mb,
+    "emulator-only/mbc1/rom_16Mb.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_ram_64kb,
+    "emulator-only/mbc1/ram_64kb.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_ram_256kb,
+    "emulator-only/mbc1/ram_256kb.gb",
+    DeviceMode::GameBoy,
+    test_mbc1_multicart_rom_8mb,
+    "emulator-only/mbc1/multicart_rom_8Mb.gb",
+    DeviceMode::GameBoy,
+    test_mbc2_bits_ramg,
+    "emulator-only/mbc2/bits_ramg.gb",
+    DeviceMode::GameBoy,
+    test_mbc2_bits_romb,
+    "emulator-only/mbc2/bits_romb.gb",
+    DeviceMode::GameBoy,
+    test_mbc2_bits_unused,
+    "emulator-only/mbc2/bits_unused.gb",
+    DeviceMode::GameBoy,
+    test_mbc2_rom_512kb,
+    "emulator-only/mbc2/rom_512kb.gb",
+    DeviceMode::GameBoy,
+    test_mbc2_rom_1mb,
+    "emulator-only/mbc2/rom_1Mb.gb",
+    DeviceMode::GameBoy,
+    test_mbc2_rom_2mb,
+    "emulator-only/mbc2/rom_2Mb.gb",
+    DeviceMode::GameBoy,
+    test_mbc2_ram,
+    "emulator-only/mbc2/ram.gb",
+    DeviceMode::GameBoy,
+);
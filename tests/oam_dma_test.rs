@@ -0,0 +1,138 @@
+mod common;
+
+use common::asm::assemble;
+use common::RomBuilder;
+use rust_gameboycolor::{DeviceMode, GameBoyColor};
+
+const ROUTINE: u16 = 0x0200;
+const HRAM: u16 = 0xFF80;
+
+// Fills C100-C19F with 0x40-0xDF, copies `routine` to HRAM like games do, since the
+// CPU can't fetch from ROM during OAM DMA on DMG, and calls it.
+fn run_from_hram(device_mode: DeviceMode, routine: &str) -> GameBoyColor {
+    let routine = assemble(HRAM, routine);
+    assert!(routine.len() < 0x70, "Routine too long for HRAM");
+    let rom = RomBuilder::new("DMA")
+        .cgb_flag(0x80)
+        .asm(&format!(
+            "
+                ld hl, $C100
+                ld a, $40
+            fill:
+                ld [hl+], a
+                inc a
+                cp $E0
+                jr nz, fill
+                ld hl, {ROUTINE}
+                ld c, {HRAM}-$FF00
+                ld b, {}
+            copy:
+                ld a, [hl+]
+                ldh [c], a
+                inc c
+                dec b
+                jr nz, copy
+                call {HRAM}
+                jr @
+            ",
+            routine.len()
+        ))
+        .patch(ROUTINE as usize, &routine)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, device_mode, None).unwrap();
+    gameboy.execute_frame();
+    gameboy
+}
+
+// Counts down long enough for any transfer to finish
+const WAIT_FOR_DMA: &str = "
+        ld a, 40
+    wait:
+        dec a
+        jr nz, wait
+";
+
+fn in_flight_routine() -> String {
+    format!(
+        "
+            ld a, $C1
+            ldh [DMA], a
+            ld a, [$0134] ; 4 M-cycles after the write
+            ld b, a
+            ld a, [$D000] ; 9 M-cycles after
+            ld c, a
+            ld a, [$FE00]
+            ld d, a
+            ldh a, [$80]
+            ld e, a
+            {WAIT_FOR_DMA}
+            ld a, b
+            ld [$C000], a
+            ld a, c
+            ld [$C001], a
+            ld a, d
+            ld [$C002], a
+            ld a, e
+            ld [$C003], a
+            ret
+        "
+    )
+}
+
+fn assert_oam_copied(gameboy: &GameBoyColor) {
+    for offset in 0..0xA0 {
+        assert_eq!(gameboy.peek(0xFE00 + offset), 0x40 + offset as u8);
+    }
+}
+
+#[test]
+fn dmg_reads_on_the_external_bus_return_the_byte_in_flight() {
+    let gameboy = run_from_hram(DeviceMode::GameBoy, &in_flight_routine());
+
+    // The first byte is copied 2 M-cycles after the write, and ROM shares the bus with WRAM
+    assert_eq!(gameboy.peek(0xC000), 0x42);
+    assert_eq!(gameboy.peek(0xC001), 0x47);
+    // OAM is unreachable, HRAM isn't
+    assert_eq!(gameboy.peek(0xC002), 0xFF);
+    assert_eq!(gameboy.peek(0xC003), 0x3E);
+    assert_oam_copied(&gameboy);
+}
+
+#[test]
+fn cgb_work_ram_transfers_leave_the_cartridge_bus_free() {
+    let gameboy = run_from_hram(DeviceMode::GameBoyColor, &in_flight_routine());
+
+    // The first letter of the title
+    assert_eq!(gameboy.peek(0xC000), b'D');
+    assert_eq!(gameboy.peek(0xC001), 0x47);
+    assert_eq!(gameboy.peek(0xC002), 0xFF);
+    assert_eq!(gameboy.peek(0xC003), 0x3E);
+    assert_oam_copied(&gameboy);
+}
+
+#[test]
+fn oam_dma_blocks_oam_for_160_m_cycles() {
+    let routine = "
+            ld sp, $FE00
+            ld a, $C1
+            ldh [DMA], a
+            ld b, 39
+        wait:
+            dec b
+            jr nz, wait
+            nop
+            nop
+            pop bc ; reading FE00 161 M-cycles after the write and FE01 162 after
+            ld sp, $FFFC
+            ld a, b
+            ld [$C000], a
+            ld a, c
+            ld [$C001], a
+            ret
+    ";
+    let gameboy = run_from_hram(DeviceMode::GameBoy, routine);
+
+    assert_eq!(gameboy.peek(0xC001), 0xFF);
+    assert_eq!(gameboy.peek(0xC000), 0x41);
+    assert_oam_copied(&gameboy);
+}
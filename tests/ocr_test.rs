@@ -0,0 +1,43 @@
+mod common;
+
+use common::ocr::{read_text, screen_contains, Font};
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, GameBoyColor};
+
+const FONT_ADDRESS: usize = 0x0200;
+const FONT: [u8; 16] = [
+    0x3C, 0x66, 0x66, 0x66, 0x66, 0x66, 0x3C, 0x00, // O
+    0x66, 0x6C, 0x78, 0x70, 0x78, 0x6C, 0x66, 0x00, // K
+];
+
+#[test]
+fn reads_background_text_from_the_frame_buffer() {
+    let mut program = vec![
+        0xF0, 0x44, 0xFE, 0x90, 0x20, 0xFA, // wait: ldh a, (LY); cp 144; jr nz, wait
+        0xAF, 0xE0, 0x40, // xor a; ldh (LCDC), a
+        0x21, 0x00, 0x02, // ld hl, FONT_ADDRESS
+        0x11, 0x10, 0x80, // ld de, $8010
+        0x06, 0x10, // ld b, 16
+        // copy: ld a, (hl+); ld (de), a; inc de; ld (de), a; inc de; dec b; jr nz, copy
+        0x2A, 0x12, 0x13, 0x12, 0x13, 0x05, 0x20, 0xF8, 0x3E, 0x01, 0xEA, 0x00,
+        0x98, // ld a, 1; ld ($9800), a
+        0x3E, 0x02, 0xEA, 0x01, 0x98, // ld a, 2; ld ($9801), a
+        0x3E, 0xE4, 0xE0, 0x47, // ld a, $E4; ldh (BGP), a
+        0x3E, 0x91, 0xE0, 0x40, // ld a, $91; ldh (LCDC), a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("OCR")
+        .program(&program)
+        .patch(FONT_ADDRESS, &FONT)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+
+    let font = Font::from_1bpp("OK", &rom[FONT_ADDRESS..FONT_ADDRESS + FONT.len()]);
+    let frame = gameboy.frame_buffer();
+    assert_eq!(read_text(frame, &font, (0, 0), (3, 2)), ["OK ", "   "]);
+    assert!(screen_contains(frame, &font, "OK"));
+    assert!(!screen_contains(frame, &font, "KO"));
+}
@@ -0,0 +1,128 @@
+use rust_gameboycolor::disasm::disassemble;
+use rust_gameboycolor::opcodes::{table, table_csv, OpcodeInfo};
+
+// M-cycles of each unprefixed opcode, not branching, from Pan Docs and blargg's
+// instr_timing. 0 marks the opcodes that lock up the CPU, and the 0xCB prefix.
+#[rustfmt::skip]
+const CYCLES: [[u8; 16]; 16] = [
+    [1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1],
+    [1, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1],
+    [2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1],
+    [2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1],
+    [1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1],
+    [1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1],
+    [1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1],
+    [2, 2, 2, 2, 2, 2, 1, 2, 1, 1, 1, 1, 1, 1, 2, 1],
+    [1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1],
+    [1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1],
+    [1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1],
+    [1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1],
+    [2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 0, 3, 6, 2, 4],
+    [2, 3, 3, 0, 3, 4, 2, 4, 2, 4, 3, 0, 3, 0, 2, 4],
+    [3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4],
+    [3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4],
+];
+
+#[rustfmt::skip]
+const LENGTHS: [[u8; 16]; 16] = [
+    [1, 3, 1, 1, 1, 1, 2, 1, 3, 1, 1, 1, 1, 1, 2, 1],
+    [2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1],
+    [2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1],
+    [2, 3, 1, 1, 1, 1, 2, 1, 2, 1, 1, 1, 1, 1, 2, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 3, 3, 3, 1, 2, 1, 1, 1, 3, 0, 3, 3, 2, 1],
+    [1, 1, 3, 0, 3, 1, 2, 1, 1, 1, 3, 0, 3, 0, 2, 1],
+    [2, 1, 1, 0, 0, 1, 2, 1, 2, 1, 3, 0, 0, 0, 2, 1],
+    [2, 1, 1, 1, 0, 1, 2, 1, 2, 1, 3, 1, 0, 0, 2, 1],
+];
+
+// M-cycles of conditional instructions when they branch
+fn branch_cycles(opcode: u8) -> Option<u8> {
+    match opcode {
+        0x20 | 0x28 | 0x30 | 0x38 => Some(3),
+        0xC0 | 0xC8 | 0xD0 | 0xD8 => Some(5),
+        0xC2 | 0xCA | 0xD2 | 0xDA => Some(4),
+        0xC4 | 0xCC | 0xD4 | 0xDC => Some(6),
+        _ => None,
+    }
+}
+
+fn reference(opcode: u8, prefixed: bool) -> Option<OpcodeInfo> {
+    if prefixed {
+        // Reading (hl) costs a cycle, and writing it back another, which BIT doesn't
+        let cycles = match (opcode & 0x07, opcode >> 6) {
+            (6, 1) => 3,
+            (6, _) => 4,
+            _ => 2,
+        };
+        return Some(OpcodeInfo {
+            opcode,
+            prefixed,
+            length: 2,
+            cycles,
+            branch_cycles: None,
+        });
+    }
+    let cycles = CYCLES[opcode as usize >> 4][opcode as usize & 0x0F];
+    let length = LENGTHS[opcode as usize >> 4][opcode as usize & 0x0F];
+    (cycles != 0).then_some(OpcodeInfo {
+        opcode,
+        prefixed,
+        length,
+        cycles,
+        branch_cycles: branch_cycles(opcode),
+    })
+}
+
+#[test]
+fn table_matches_the_reference_timings() {
+    let expected: Vec<OpcodeInfo> = [false, true]
+        .into_iter()
+        .flat_map(|prefixed| (0..=0xFF).filter_map(move |opcode| reference(opcode, prefixed)))
+        .collect();
+    let table = table();
+
+    let mismatches: Vec<String> = expected
+        .iter()
+        .filter(|info| !table.contains(info))
+        .map(|info| {
+            let measured = table
+                .iter()
+                .find(|m| m.opcode == info.opcode && m.prefixed == info.prefixed);
+            format!("expected {:?}, measured {:?}", info, measured)
+        })
+        .collect();
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+    assert_eq!(table.len(), expected.len());
+}
+
+#[test]
+fn table_lengths_agree_with_the_disassembler() {
+    for info in table() {
+        let bytes = if info.prefixed {
+            vec![0xCB, info.opcode, 0x00]
+        } else {
+            vec![info.opcode, 0x00, 0x00]
+        };
+        let (text, length) = disassemble(0, &bytes);
+        assert_eq!(length, info.length as usize, "{}", text);
+    }
+}
+
+#[test]
+fn csv_has_a_line_per_opcode() {
+    let csv = table_csv();
+    let lines: Vec<&str> = csv.lines().collect();
+
+    assert_eq!(lines[0], "opcode,length,cycles,branch_cycles");
+    assert_eq!(lines.len(), 1 + 244 + 256);
+    assert!(lines.contains(&"C4,3,3,6"));
+    assert!(lines.contains(&"CB 7E,2,3,"));
+}
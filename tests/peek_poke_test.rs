@@ -0,0 +1,60 @@
+mod common;
+
+use common::{RomBuilder, PROGRAM_START};
+use rust_gameboycolor::{DeviceMode, GameBoyColor};
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+// Enables cartridge RAM, then keeps copying ($C000) into B
+fn gameboy() -> GameBoyColor {
+    let program = [
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, // ld a, $0A; ld ($0000), a
+        0xFA, 0x00, 0xC0, // ld a, ($C000)
+        0x47, // ld b, a
+        0x18, 0xFA, // jr -6
+    ];
+    let rom = RomBuilder::new("PEEKPOKE")
+        .cartridge_type(0x03)
+        .ram_size(0x02)
+        .program(&program)
+        .build();
+    GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap()
+}
+
+#[test]
+fn poked_memory_is_seen_by_the_game_and_by_peek() {
+    let mut gameboy = gameboy();
+    gameboy.execute_frame();
+
+    for (address, value) in [
+        (0xC000, 0x5A),
+        (0xFF90, 0x11),
+        (0x9800, 0x22),
+        (0xA123, 0x33),
+    ] {
+        gameboy.poke(address, value);
+        assert_eq!(gameboy.peek(address), value, "{:#06X}", address);
+    }
+    gameboy.execute_frame();
+    assert_eq!(gameboy.cpu_state().b, 0x5A);
+    assert_eq!(gameboy.save_data().unwrap()[0x0123], 0x33);
+}
+
+#[test]
+fn peek_and_poke_leave_rom_and_the_clock_alone() {
+    let mut gameboy = gameboy();
+    let accesses = Rc::new(Cell::new(0));
+    let counter = accesses.clone();
+    gameboy.add_bus_observer(0x0000..=0xFFFF, move |_| counter.set(counter.get() + 1));
+    let timestamp = gameboy.timestamp();
+
+    let opcode = gameboy.peek(PROGRAM_START as u16);
+    gameboy.poke(PROGRAM_START as u16, 0x00);
+    assert_eq!(gameboy.peek(PROGRAM_START as u16), opcode);
+    gameboy.peek(0xC000);
+    gameboy.poke(0xC000, 0x01);
+
+    assert_eq!(gameboy.timestamp(), timestamp);
+    assert_eq!(accesses.get(), 0);
+}
@@ -0,0 +1,255 @@
+mod common;
+
+use common::{
+    boot, run_until_serial, send_serial_imm, write_imm, RomBuilder, SerialLog, LOOP_FOREVER,
+    SEND_SERIAL_A,
+};
+use rust_gameboycolor::{
+    CameraSource, DeviceMode, EmulatorConfigBuilder, GameBoyColor, GameBoyPrinter,
+    InfraredTransceiver, PrintedImage, PrinterSink, RumbleSink, SerialDevice, CAMERA_WIDTH,
+};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// `ld a, (address)`, then [`SEND_SERIAL_A`]
+fn send_memory(address: u16) -> Vec<u8> {
+    let mut code = vec![0xFA, address as u8, (address >> 8) as u8];
+    code.extend_from_slice(&SEND_SERIAL_A);
+    code
+}
+
+/// Records LED changes and always sees light from the other side.
+#[derive(Clone, Default)]
+struct Transceiver(Rc<RefCell<Vec<bool>>>);
+
+impl InfraredTransceiver for Transceiver {
+    fn set_led(&mut self, on: bool) {
+        self.0.borrow_mut().push(on);
+    }
+
+    fn receiving(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Default)]
+struct Motor(Rc<RefCell<Vec<bool>>>);
+
+impl RumbleSink for Motor {
+    fn set_rumble(&mut self, on: bool) {
+        self.0.borrow_mut().push(on);
+    }
+}
+
+#[test]
+fn cgb_infrared_port_drives_and_reads_the_transceiver() {
+    let mut program = Vec::new();
+    // LED on with reading disabled, then LED off with reading enabled
+    for value in [0x01, 0xC0] {
+        program.extend_from_slice(&[0x3E, value, 0xE0, 0x56]);
+        program.extend(send_memory(0xFF56));
+    }
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("INFRARED")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let (mut gameboy, log) = boot(&rom, DeviceMode::GameBoyColor);
+    let transceiver = Transceiver::default();
+    gameboy.set_infrared(Some(Box::new(transceiver.clone())));
+
+    // Bit 1 reads 0 for received light only while reading is enabled
+    assert_eq!(run_until_serial(&mut gameboy, &log, 2, 10), [0x3F, 0xFC]);
+    assert_eq!(*transceiver.0.borrow(), [false, true, false]);
+}
+
+#[test]
+fn huc1_infrared_mode_reaches_the_transceiver() {
+    let mut program = Vec::new();
+    program.extend(write_imm(0x0000, 0x0E));
+    program.extend(send_memory(0xA000));
+    program.extend(write_imm(0xA000, 0x01));
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("HUC1IR")
+        .cartridge_type(0xFF)
+        .ram_size(0x02)
+        .program(&program)
+        .build();
+    let log = SerialLog::default();
+    let transceiver = Transceiver::default();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .link_cable(Box::new(log.clone()))
+        .infrared(Box::new(transceiver.clone()))
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(&rom, config).unwrap();
+
+    assert_eq!(run_until_serial(&mut gameboy, &log, 1, 10), [0xC1]);
+    gameboy.execute_frame();
+    assert_eq!(*transceiver.0.borrow(), [true]);
+}
+
+#[test]
+fn mbc5_rumble_bit_drives_the_motor_without_banking_ram() {
+    let mut program = Vec::new();
+    program.extend(write_imm(0x0000, 0x0A));
+    program.extend(write_imm(0xA000, 0x42));
+    // Bank 0 with the motor on, then off again
+    program.extend(write_imm(0x4000, 0x08));
+    program.extend(send_memory(0xA000));
+    program.extend(write_imm(0x4000, 0x00));
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("RUMBLE")
+        .cartridge_type(0x1D)
+        .ram_size(0x03)
+        .program(&program)
+        .build();
+    let (mut gameboy, log) = boot(&rom, DeviceMode::GameBoy);
+    let motor = Motor::default();
+    gameboy.set_rumble(Some(Box::new(motor.clone())));
+
+    assert_eq!(run_until_serial(&mut gameboy, &log, 1, 10), [0x42]);
+    gameboy.execute_frame();
+    assert_eq!(*motor.0.borrow(), [false, true, false]);
+}
+
+/// Repeats black, dark, light and white every four pixels.
+struct Stripes;
+
+impl CameraSource for Stripes {
+    fn capture(&mut self, picture: &mut [u8]) {
+        for (i, pixel) in picture.iter_mut().enumerate() {
+            *pixel = [0x00, 0x50, 0x90, 0xFF][i % CAMERA_WIDTH % 4];
+        }
+    }
+}
+
+#[test]
+fn camera_capture_is_dithered_into_ram() {
+    let mut program = Vec::new();
+    program.extend(write_imm(0x0000, 0x0A));
+    program.extend(write_imm(0x4000, 0x10));
+    program.extend_from_slice(&[
+        0x21, 0x06, 0xA0, // ld hl, $A006
+        0x06, 0x10, // ld b, 16
+        // Thresholds 0x40, 0x80 and 0xC0 in every cell of the matrix
+        0x3E, 0x40, 0x22, 0x3E, 0x80, 0x22, 0x3E, 0xC0, 0x22, // ld (hl+) x3
+        0x05, 0x20, 0xF4, // dec b; jr nz
+    ]);
+    program.extend(write_imm(0xA000, 0x01));
+    program.extend(send_memory(0xA000));
+    program.extend(write_imm(0x4000, 0x00));
+    program.extend(send_memory(0xA100));
+    program.extend(send_memory(0xA101));
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("CAMERA")
+        .cartridge_type(0xFC)
+        .ram_size(0x04)
+        .program(&program)
+        .build();
+    let log = SerialLog::default();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .link_cable(Box::new(log.clone()))
+        .camera(Box::new(Stripes))
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(&rom, config).unwrap();
+
+    // The capture is done at once, and the first row of the first tile holds colours
+    // 3, 2, 1, 0, 3, 2, 1, 0
+    assert_eq!(
+        run_until_serial(&mut gameboy, &log, 3, 10),
+        [0x00, 0xAA, 0xCC]
+    );
+}
+
+#[derive(Clone, Default)]
+struct Sheets(Rc<RefCell<Vec<PrintedImage>>>);
+
+impl PrinterSink for Sheets {
+    fn print(&mut self, image: &PrintedImage) {
+        self.0.borrow_mut().push(image.clone());
+    }
+}
+
+/// Sends a whole packet and returns the printer's keep-alive and status bytes.
+fn send_packet(
+    printer: &mut GameBoyPrinter,
+    command: u8,
+    compressed: bool,
+    data: &[u8],
+) -> [u8; 2] {
+    let mut body = vec![command, compressed as u8];
+    body.extend((data.len() as u16).to_le_bytes());
+    body.extend_from_slice(data);
+    let checksum = body
+        .iter()
+        .fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+    for byte in [0x88, 0x33]
+        .into_iter()
+        .chain(body)
+        .chain(checksum.to_le_bytes())
+    {
+        assert_eq!(printer.exchange(byte), 0x00);
+    }
+    [printer.exchange(0x00), printer.exchange(0x00)]
+}
+
+#[test]
+fn printer_prints_received_bands_through_the_palette() {
+    let sheets = Sheets::default();
+    let mut printer = GameBoyPrinter::new(Box::new(sheets.clone()));
+    assert_eq!(send_packet(&mut printer, 0x01, false, &[]), [0x81, 0x00]);
+
+    // A band of colour 1, then a compressed band of colour 3
+    let band: Vec<u8> = [0xFF, 0x00].repeat(320);
+    assert_eq!(send_packet(&mut printer, 0x04, false, &band), [0x81, 0x08]);
+    let mut runs = [0xFF, 0xFF].repeat(4);
+    runs.extend_from_slice(&[0xFA, 0xFF]);
+    assert_eq!(send_packet(&mut printer, 0x04, true, &runs), [0x81, 0x08]);
+    assert_eq!(send_packet(&mut printer, 0x04, false, &[]), [0x81, 0x08]);
+
+    // A bad checksum is reported and the packet ignored
+    let mut bad = vec![0x88, 0x33, 0x02, 0x00, 0x04, 0x00, 0x01, 0x13, 0xE4, 0x40];
+    bad.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+    let replies: Vec<u8> = bad.iter().map(|&byte| printer.exchange(byte)).collect();
+    assert_eq!(replies[replies.len() - 2..], [0x81, 0x09]);
+    assert!(sheets.0.borrow().is_empty());
+
+    // Palette 0 prints as 0xE4, and the printer is busy for one status check
+    assert_eq!(
+        send_packet(&mut printer, 0x02, false, &[0x01, 0x13, 0x00, 0x40]),
+        [0x81, 0x02]
+    );
+    assert_eq!(send_packet(&mut printer, 0x0F, false, &[]), [0x81, 0x00]);
+
+    let sheets = sheets.0.borrow();
+    let [image] = &sheets[..] else {
+        panic!("{} images printed", sheets.len());
+    };
+    assert_eq!((image.width, image.height), (160, 32));
+    assert!(image.pixels[..160 * 16].iter().all(|&shade| shade == 1));
+    assert!(image.pixels[160 * 16..].iter().all(|&shade| shade == 3));
+}
+
+#[test]
+fn printer_can_be_plugged_into_a_running_emulator() {
+    let mut program = Vec::new();
+    for byte in [0x88, 0x33, 0x0F, 0x00, 0x00, 0x00, 0x0F, 0x00, 0x00, 0x00] {
+        program.extend(send_serial_imm(byte));
+    }
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("PRINTER").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    gameboy.set_printer(Some(Box::new(Sheets::default())));
+    gameboy.set_link_tap(true);
+    for _ in 0..10 {
+        gameboy.execute_frame();
+    }
+
+    let received: Vec<u8> = gameboy.link_log().iter().map(|e| e.received).collect();
+    assert_eq!(received[8..10], [0x81, 0x00]);
+}
@@ -0,0 +1,96 @@
+#![cfg(feature = "pixel-source")]
+
+mod common;
+
+use common::tile_scene;
+use rust_gameboycolor::{DeviceMode, PixelSource, SourceLayer, SCREEN_WIDTH};
+
+#[test]
+fn pixel_sources_describe_the_tile_behind_each_pixel() {
+    let gameboy = tile_scene(DeviceMode::GameBoy);
+    let sources = gameboy.pixel_sources();
+    let at = |x: usize, y: usize| sources[y * SCREEN_WIDTH + x];
+
+    assert_eq!(
+        at(1, 2),
+        PixelSource {
+            layer: SourceLayer::Background,
+            tile_id: 1,
+            palette: 0,
+            color_id: 1,
+            tile_x: 1,
+            tile_y: 2,
+            x_flip: false,
+            y_flip: false,
+        }
+    );
+    assert_eq!(
+        at(9, 2),
+        PixelSource {
+            layer: SourceLayer::Object,
+            tile_id: 2,
+            palette: 9,
+            color_id: 3,
+            tile_x: 6,
+            tile_y: 2,
+            x_flip: true,
+            y_flip: false,
+        }
+    );
+    assert_eq!(at(100, 100).layer, SourceLayer::Background);
+    assert_eq!(at(100, 100).color_id, 0);
+}
+
+#[test]
+fn tile_data_covers_only_the_tiles_of_each_bank() {
+    let gameboy = tile_scene(DeviceMode::GameBoy);
+    assert_eq!(
+        gameboy.tile_data(1),
+        Some([0xFF, 0x00].repeat(8).try_into().unwrap())
+    );
+    assert_eq!(gameboy.tile_data(2), Some([0xFF; 16]));
+    for tile_id in [0, 383, 512, 895] {
+        assert!(gameboy.tile_data(tile_id).is_some(), "{}", tile_id);
+    }
+    // The tile maps and past the end of VRAM
+    for tile_id in [384, 511, 896, 1023, 1024, u16::MAX] {
+        assert_eq!(gameboy.tile_data(tile_id), None, "{}", tile_id);
+    }
+}
+
+#[test]
+fn scale2x_rounds_edges_only_within_a_surface() {
+    use rust_gameboycolor::{scale2x, SCREEN_HEIGHT};
+
+    // A black triangle below the diagonal on a white background
+    let (black, white) = ((0, 0, 0), (0xFF, 0xFF, 0xFF));
+    let pixels: Vec<_> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+        .map(|i| {
+            if i % SCREEN_WIDTH < i / SCREEN_WIDTH {
+                black
+            } else {
+                white
+            }
+        })
+        .collect();
+    let background = PixelSource {
+        layer: SourceLayer::Background,
+        ..PixelSource::default()
+    };
+    let object = PixelSource {
+        layer: SourceLayer::Object,
+        ..PixelSource::default()
+    };
+
+    // Bottom-left quarter of the white pixel at (5, 5)
+    let corner = (5 * 2 + 1) * SCREEN_WIDTH * 2 + 5 * 2;
+    let same_layer = scale2x(&pixels, &vec![background; pixels.len()]);
+    assert_eq!(same_layer.len(), pixels.len() * 4);
+    assert_eq!(same_layer[corner], black);
+
+    let sources: Vec<_> = pixels
+        .iter()
+        .map(|&pixel| if pixel == black { object } else { background })
+        .collect();
+    assert_eq!(scale2x(&pixels, &sources)[corner], white);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{
+    DeviceMode, GameBoyColor, JoypadKey, JoypadKeyState, PowerAdvice, PowerAdvisor,
+};
+
+// Sleeps in HALT between VBlank interrupts, which read the buttons
+fn halting_rom() -> Vec<u8> {
+    let program = [
+        0x3E, 0x01, 0xE0, 0xFF, // ld a, $01; ldh (IE), a
+        0xFB, // ei
+        0x76, 0x18, 0xFD, // halt; jr -3
+    ];
+    // ld a, $10; ldh (P1), a; ldh a, (P1); reti
+    let vblank = [0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, 0xD9];
+    RomBuilder::new("POWER")
+        .patch(0x0040, &vblank)
+        .program(&program)
+        .build()
+}
+
+fn run(
+    gameboy: &mut GameBoyColor,
+    advisor: &mut PowerAdvisor,
+    frames: usize,
+    keys: JoypadKeyState,
+) {
+    for _ in 0..frames {
+        gameboy.run_frame(keys);
+        advisor.record_frame(&gameboy.frame_activity());
+    }
+}
+
+#[test]
+fn static_screens_spent_in_halt_are_idle() {
+    let mut gameboy = GameBoyColor::new(&halting_rom(), DeviceMode::GameBoy, None).unwrap();
+    let mut advisor = PowerAdvisor::new();
+    run(&mut gameboy, &mut advisor, 10, JoypadKeyState::new());
+
+    let activity = gameboy.frame_activity();
+    assert!(activity.halted_fraction() > 0.9, "{:?}", activity);
+    assert!(!activity.screen_changed && activity.audio_silent && !activity.input, "{:?}", activity);
+    assert!(activity.is_idle());
+    assert_eq!(advisor.advice(), PowerAdvice::Active);
+
+    // The first frame after power-on draws the screen
+    run(&mut gameboy, &mut advisor, 50, JoypadKeyState::new());
+    assert_eq!(advisor.idle_frames(), 59);
+    assert_eq!(advisor.advice(), PowerAdvice::Active);
+    run(&mut gameboy, &mut advisor, 1, JoypadKeyState::new());
+    assert_eq!(advisor.advice(), PowerAdvice::Idle);
+
+    // Holding a button the game reads wakes it up
+    let mut keys = JoypadKeyState::new();
+    keys.set_key(JoypadKey::A, true);
+    run(&mut gameboy, &mut advisor, 1, keys);
+    assert!(gameboy.frame_activity().input);
+    assert_eq!(advisor.advice(), PowerAdvice::Active);
+
+    run(&mut gameboy, &mut advisor, 60, JoypadKeyState::new());
+    assert_eq!(advisor.advice(), PowerAdvice::Idle);
+    advisor.wake();
+    assert_eq!(advisor.advice(), PowerAdvice::Active);
+}
+
+#[test]
+fn busy_loops_are_never_idle() {
+    let rom = RomBuilder::new("POWERBUSY").program(&LOOP_FOREVER).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    let mut advisor = PowerAdvisor::new();
+    advisor.set_idle_after(5);
+    run(&mut gameboy, &mut advisor, 30, JoypadKeyState::new());
+
+    let activity = gameboy.frame_activity();
+    // 70224 dots at 4 a cycle
+    assert_eq!(activity.cycles, 17556);
+    assert_eq!(activity.halted_cycles, 0);
+    assert!(!activity.is_idle());
+    assert_eq!(advisor.advice(), PowerAdvice::Active);
+}
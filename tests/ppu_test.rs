@@ -0,0 +1,519 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{
+    ColorCorrection, DeviceMode, EmulatorConfigBuilder, GameBoyColor, OamEntry, TileMap,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+#[test]
+fn dirty_lines_cover_only_changed_scanlines() {
+    let mut program = vec![
+        0x3E, 0xE4, 0xE0, 0x47, // ld a, $E4; ldh (BGP), a
+        0x3E, 0x91, 0xE0, 0x40, // ld a, $91; ldh (LCDC), a
+        0x0E, 0x03, // ld c, 3
+        0xF0, 0x44, 0xFE, 0x90, 0x20, 0xFA, // w1: ldh a, (LY); cp 144; jr nz, w1
+        0xF0, 0x44, 0xFE, 0x90, 0x28, 0xFA, // w2: ldh a, (LY); cp 144; jr z, w2
+        0x0D, 0x20, 0xF1, // dec c; jr nz, w1
+        // Fill tile 1 with colour 3 and show it at the top-left of the map
+        0x21, 0x10, 0x80, 0x3E, 0xFF, 0x06, 0x10, // ld hl, $8010; ld a, $FF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC, // fill: ld (hl+), a; dec b; jr nz, fill
+        0x3E, 0x01, 0xEA, 0x00, 0x98, // ld a, 1; ld ($9800), a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("DIRTYLINES").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+
+    let mut dirty = Vec::new();
+    for _ in 0..10 {
+        gameboy.execute_frame();
+        dirty.push(gameboy.dirty_lines().iter().collect::<Vec<_>>());
+    }
+
+    // The first drawn frame repaints everything, after that only the tile's rows change once
+    let first = dirty.iter().position(|lines| lines.len() == 144).unwrap();
+    let changes = dirty[first + 1..]
+        .iter()
+        .filter(|lines| !lines.is_empty())
+        .collect::<Vec<_>>();
+    assert_eq!(changes, [&(0..8).collect::<Vec<_>>()], "{:?}", dirty);
+}
+
+// Shows tile $03 as an 8x16 object in the top-left corner, with tile 2 in colour 1 and
+// tile 3 in colour 3, and returns the colours of its top and bottom halves.
+fn tall_sprite_halves(attributes: u8) -> ((u8, u8, u8), (u8, u8, u8)) {
+    let mut program = vec![
+        0xAF, 0xE0, 0x40, // xor a; ldh (LCDC), a
+        0x21, 0x20, 0x80, 0x06, 0x08, // ld hl, $8020; ld b, 8
+        0x3E, 0xFF, 0x22, 0xAF, 0x22, 0x05, 0x20,
+        0xF8, // t2: ld a, $FF; ld (hl+), a; xor a; ld (hl+), a; dec b; jr nz, t2
+        0x3E, 0xFF, 0x06, 0x10, // ld a, $FF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC, // t3: ld (hl+), a; dec b; jr nz, t3
+        0x21, 0x00, 0xFE, // ld hl, $FE00
+        0x3E, 0x10, 0x22, 0x3E, 0x08, 0x22, // y = 16, x = 8
+        0x3E, 0x03, 0x22, 0x3E, attributes, 0x22, // tile $03, attributes
+        0x3E, 0xE4, 0xE0, 0x47, 0xE0, 0x48, // ld a, $E4; ldh (BGP), a; ldh (OBP0), a
+        0x3E, 0x87, 0xE0, 0x40, // ld a, $87; ldh (LCDC), a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("TALLSPRITE").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+
+    let frame = gameboy.frame_buffer();
+    let background = frame[100 * 160 + 100];
+    let (top, bottom) = (frame[3 * 160 + 4], frame[12 * 160 + 4]);
+    assert_ne!(top, background);
+    assert_ne!(bottom, background);
+    (top, bottom)
+}
+
+#[test]
+fn tall_sprites_use_even_tile_on_top_and_odd_tile_below() {
+    let (top, bottom) = tall_sprite_halves(0x00);
+    assert_ne!(top, bottom);
+
+    let (flipped_top, flipped_bottom) = tall_sprite_halves(0x40);
+    assert_eq!((flipped_top, flipped_bottom), (bottom, top));
+}
+
+/// `ld a, value; ldh (register), a`
+fn ldh_imm(register: u8, value: u8) -> [u8; 4] {
+    [0x3E, value, 0xE0, register]
+}
+
+// Two BG tiles in colour 1 with an object over each; only the left tile has the BG
+// priority attribute. Returns the colours at the left and right object.
+fn cgb_priority_colors(lcdc: u8, obj_attributes: u8) -> ((u8, u8, u8), (u8, u8, u8)) {
+    let mut program = ldh_imm(0x40, 0x00).to_vec();
+    // BG palette 0: white, then red; OBJ palette 0: blue
+    program.extend(ldh_imm(0x68, 0x80));
+    for byte in [0xFF, 0x7F, 0x1F, 0x00, 0x1F, 0x00, 0x1F, 0x00] {
+        program.extend(ldh_imm(0x69, byte));
+    }
+    program.extend(ldh_imm(0x6A, 0x80));
+    for byte in [0xFF, 0x7F, 0x00, 0x7C, 0x00, 0x7C, 0x00, 0x7C] {
+        program.extend(ldh_imm(0x6B, byte));
+    }
+    program.extend([
+        0x21, 0x10, 0x80, 0x06, 0x08, // ld hl, $8010; ld b, 8
+        0x3E, 0xFF, 0x22, 0xAF, 0x22, 0x05, 0x20,
+        0xF8, // t1: ld a, $FF; ld (hl+), a; xor a; ld (hl+), a; dec b; jr nz, t1
+        0x3E, 0xFF, 0x06, 0x10, // ld a, $FF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC, // t2: ld (hl+), a; dec b; jr nz, t2
+        0x3E, 0x01, 0xEA, 0x00, 0x98, 0xEA, 0x01,
+        0x98, // ld a, 1; ld ($9800), a; ld ($9801), a
+    ]);
+    program.extend(ldh_imm(0x4F, 0x01));
+    program.extend([0x3E, 0x80, 0xEA, 0x00, 0x98]); // ld a, $80; ld ($9800), a
+    program.extend(ldh_imm(0x4F, 0x00));
+    program.extend([0x21, 0x00, 0xFE]); // ld hl, $FE00
+    for x in [8, 16] {
+        for byte in [16, x, 0x02, obj_attributes] {
+            program.extend([0x3E, byte, 0x22]); // ld a, byte; ld (hl+), a
+        }
+    }
+    program.extend(ldh_imm(0x40, lcdc));
+    program.extend_from_slice(&LOOP_FOREVER);
+
+    let rom = RomBuilder::new("CGBPRIORITY")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+    let frame = gameboy.frame_buffer();
+    (frame[4 * 160 + 4], frame[4 * 160 + 12])
+}
+
+#[test]
+fn cgb_bg_attribute_priority_covers_objects() {
+    let (left, right) = cgb_priority_colors(0x93, 0x00);
+    assert_ne!(left, right);
+    let (bg, obj) = (left, right);
+
+    // OAM priority lets both BG tiles win
+    assert_eq!(cgb_priority_colors(0x93, 0x80), (bg, bg));
+    // LCDC bit 0 clear puts objects above everything
+    assert_eq!(cgb_priority_colors(0x92, 0x80), (obj, obj));
+}
+
+// Two solid objects stacked at the same position over a BG tile in colour 1: the first
+// in OAM uses OBJ palette 0 (blue) with `first_attributes`, the second OBJ palette 1
+// (green). Returns the colour where they overlap.
+fn cgb_overlapping_objects_color(first_attributes: u8) -> (u8, u8, u8) {
+    let mut program = ldh_imm(0x40, 0x00).to_vec();
+    // BG palette 0: white, then red
+    program.extend(ldh_imm(0x68, 0x80));
+    for byte in [0xFF, 0x7F, 0x1F, 0x00, 0x1F, 0x00, 0x1F, 0x00] {
+        program.extend(ldh_imm(0x69, byte));
+    }
+    // OBJ palette 0: white, then blue; OBJ palette 1: white, then green
+    program.extend(ldh_imm(0x6A, 0x80));
+    for byte in [
+        0xFF, 0x7F, 0x00, 0x7C, 0x00, 0x7C, 0x00, 0x7C, //
+        0xFF, 0x7F, 0xE0, 0x03, 0xE0, 0x03, 0xE0, 0x03,
+    ] {
+        program.extend(ldh_imm(0x6B, byte));
+    }
+    program.extend([
+        0x21, 0x10, 0x80, 0x06, 0x08, // ld hl, $8010; ld b, 8
+        0x3E, 0xFF, 0x22, 0xAF, 0x22, 0x05, 0x20,
+        0xF8, // t1: ld a, $FF; ld (hl+), a; xor a; ld (hl+), a; dec b; jr nz, t1
+        0x3E, 0xFF, 0x06, 0x10, // ld a, $FF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC, // t2: ld (hl+), a; dec b; jr nz, t2
+        0x3E, 0x01, 0xEA, 0x00, 0x98, // ld a, 1; ld ($9800), a
+    ]);
+    program.extend([0x21, 0x00, 0xFE]); // ld hl, $FE00
+    for attributes in [first_attributes, 0x01] {
+        for byte in [16, 8, 0x02, attributes] {
+            program.extend([0x3E, byte, 0x22]); // ld a, byte; ld (hl+), a
+        }
+    }
+    program.extend(ldh_imm(0x40, 0x93));
+    program.extend_from_slice(&LOOP_FOREVER);
+
+    let rom = RomBuilder::new("CGBOVERLAP")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+    gameboy.frame_buffer()[4 * 160 + 4]
+}
+
+#[test]
+fn cgb_first_object_in_oam_wins_even_behind_the_background() {
+    let front = cgb_overlapping_objects_color(0x00);
+    let behind = cgb_overlapping_objects_color(0x80);
+    assert_eq!(
+        front.2, 0xFF,
+        "the first object is drawn in blue: {:?}",
+        front
+    );
+    // The first object still hides the second where the background covers it
+    assert_eq!(
+        behind.0, 0xFF,
+        "the background is drawn in red: {:?}",
+        behind
+    );
+}
+
+// Eleven solid objects side by side on the top line. Returns the colours at the
+// first and the eleventh object, and the background.
+fn eleven_sprites_colors(sprite_limit: bool) -> [(u8, u8, u8); 3] {
+    let mut program = ldh_imm(0x40, 0x00).to_vec();
+    program.extend([
+        0x21, 0x20, 0x80, 0x3E, 0xFF, 0x06, 0x10, // ld hl, $8020; ld a, $FF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC, // t2: ld (hl+), a; dec b; jr nz, t2
+        0x21, 0x00, 0xFE, // ld hl, $FE00
+    ]);
+    for i in 0..11 {
+        for byte in [16, 8 + i * 8, 0x02, 0x00] {
+            program.extend([0x3E, byte, 0x22]); // ld a, byte; ld (hl+), a
+        }
+    }
+    program.extend(ldh_imm(0x47, 0xE4));
+    program.extend(ldh_imm(0x48, 0xE4));
+    program.extend(ldh_imm(0x40, 0x83));
+    program.extend_from_slice(&LOOP_FOREVER);
+
+    let rom = RomBuilder::new("SPRITELIMIT").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    gameboy.set_sprite_limit(sprite_limit);
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+    let frame = gameboy.frame_buffer();
+    [
+        frame[4 * 160 + 4],
+        frame[4 * 160 + 84],
+        frame[100 * 160 + 100],
+    ]
+}
+
+#[test]
+fn sprite_limit_hides_the_eleventh_object_unless_lifted() {
+    let [first, eleventh, background] = eleven_sprites_colors(true);
+    assert_ne!(first, background);
+    assert_eq!(eleventh, background);
+
+    assert_eq!(eleven_sprites_colors(false), [first, first, background]);
+}
+
+#[test]
+fn tile_capture_records_distinct_tiles_with_counts() {
+    let mut program = vec![
+        0x21, 0x10, 0x80, // ld hl, $8010
+        0x3E, 0xAA, // ld a, $AA
+        0x06, 0x20, // ld b, 32
+        0x22, 0x05, 0x20, 0xFC, // loop: ld (hl+), a; dec b; jr nz, loop
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("TILES").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    assert!(gameboy.captured_tiles().is_empty());
+
+    gameboy.set_tile_capture(true);
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+    // Tiles 1 and 2 hold the same data, and the blank tiles already there are left out
+    let tiles = gameboy.captured_tiles();
+    assert_eq!(tiles.len(), 1);
+    assert_eq!((tiles[0].data, tiles[0].count), ([0xAA; 16], 2));
+
+    gameboy.set_tile_capture(false);
+    assert!(gameboy.captured_tiles().is_empty());
+}
+
+#[test]
+fn vram_views_show_tiles_maps_and_oam() {
+    let rom = RomBuilder::new("VRAMVIEW").program(&LOOP_FOREVER).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    for address in 0x8010..0x8020 {
+        gameboy.poke(address, 0xFF);
+    }
+    gameboy.poke(0x9800, 1);
+    gameboy.poke(0x9C21, 1);
+    for (i, value) in [40, 16, 1, 0xBD].into_iter().enumerate() {
+        gameboy.poke(0xFE04 + i as u16, value);
+    }
+    for _ in 0..2 {
+        gameboy.execute_frame();
+    }
+    let frame = gameboy.frame_buffer();
+    let (black, white) = (frame[0], frame[8]);
+    assert_ne!(black, white);
+
+    let tiles = gameboy.render_tile_data(0);
+    assert_eq!((tiles.width, tiles.height), (128, 192));
+    assert_eq!(tiles.pixels[7], white);
+    assert_eq!(tiles.pixels[8], black);
+    assert_eq!(tiles.pixels[7 * 128 + 15], black);
+    // Banks wrap like VBK
+    assert_eq!(gameboy.render_tile_data(2), tiles);
+    assert_eq!(
+        gameboy.render_bg_map(TileMap::Low, Some(3)),
+        gameboy.render_bg_map(TileMap::Low, Some(1))
+    );
+
+    // With no scrolling the screen is the top-left corner of the map
+    let low = gameboy.render_bg_map(TileMap::Low, None);
+    assert_eq!((low.width, low.height), (256, 256));
+    for y in 0..SCREEN_HEIGHT {
+        assert_eq!(
+            low.pixels[y * 256..][..SCREEN_WIDTH],
+            frame[y * SCREEN_WIDTH..][..SCREEN_WIDTH]
+        );
+    }
+    let high = gameboy.render_bg_map(TileMap::High, None);
+    assert_eq!(
+        (high.pixels[7 * 256 + 7], high.pixels[8 * 256 + 8]),
+        (white, black)
+    );
+
+    let oam = gameboy.oam_entries();
+    assert_eq!(oam.len(), 40);
+    assert_eq!(
+        oam[1],
+        OamEntry {
+            y: 40,
+            x: 16,
+            tile: 1,
+            dmg_palette: 1,
+            cgb_palette: 5,
+            bank: 1,
+            x_flip: true,
+            y_flip: false,
+            behind_bg: true,
+        }
+    );
+    assert_eq!(oam[0], OamEntry::default());
+}
+
+#[test]
+fn frame_buffer_packs_into_rgba8_and_rgb565() {
+    let mut program = vec![
+        0x3E, 0xE4, 0xE0, 0x47, // ld a, $E4; ldh (BGP), a
+        0x21, 0x10, 0x80, 0x3E, 0xFF, 0x06, 0x10, // ld hl, $8010; ld a, $FF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC, // fill: ld (hl+), a; dec b; jr nz, fill
+        0x3E, 0x01, 0xEA, 0x00, 0x98, // ld a, 1; ld ($9800), a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("PACKEDFRAME").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+
+    let mut rgba = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 4];
+    let mut rgb565 = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+    gameboy.frame_buffer_rgba(&mut rgba);
+    gameboy.frame_buffer_rgb565(&mut rgb565);
+
+    let frame = gameboy.frame_buffer();
+    assert_ne!(frame[0], frame[100 * SCREEN_WIDTH + 100]);
+    for (i, &(r, g, b)) in frame.iter().enumerate() {
+        assert_eq!(rgba[i * 4..i * 4 + 4], [r, g, b, 0xFF]);
+        let (r5, g6, b5) = (rgb565[i] >> 11, rgb565[i] >> 5 & 0x3F, rgb565[i] & 0x1F);
+        assert_eq!((r5, g6, b5), (r as u16 >> 3, g as u16 >> 2, b as u16 >> 3));
+    }
+}
+
+#[test]
+fn color_correction_adjusts_cgb_palette_colours() {
+    // BG palette 0 colour 0 is pure red, which the blank background shows everywhere
+    let mut program = ldh_imm(0x68, 0x80).to_vec();
+    program.extend(ldh_imm(0x69, 0x1F));
+    program.extend(ldh_imm(0x69, 0x00));
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("COLORFIX")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let config = EmulatorConfigBuilder::default()
+        .color_correction(ColorCorrection::CgbLcd)
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(&rom, config).unwrap();
+    let red_after_frame = |gameboy: &mut GameBoyColor| {
+        gameboy.execute_frame();
+        gameboy.frame_buffer()[72 * 160 + 80]
+    };
+
+    for _ in 0..2 {
+        red_after_frame(&mut gameboy);
+    }
+    assert_eq!(red_after_frame(&mut gameboy), (214, 0, 49));
+    gameboy.set_color_correction(ColorCorrection::Off);
+    assert_eq!(red_after_frame(&mut gameboy), (0xFF, 0, 0));
+    gameboy.set_color_correction(ColorCorrection::GbaLcd);
+    let (r, g, b) = red_after_frame(&mut gameboy);
+    assert!(r < 0xFF && g > 0 && b > g, "{:?}", (r, g, b));
+}
+
+#[test]
+fn palette_views_decode_palette_ram_and_registers() {
+    // BG palette 1 colour 2 is green and OBJ palette 7 colour 3 is blue
+    let mut program = ldh_imm(0x68, 0x8C).to_vec();
+    program.extend(ldh_imm(0x69, 0xE0));
+    program.extend(ldh_imm(0x69, 0x03));
+    program.extend(ldh_imm(0x6A, 0xBE));
+    program.extend(ldh_imm(0x6B, 0x00));
+    program.extend(ldh_imm(0x6B, 0x7C));
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("PALETTES")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    gameboy.execute_frame();
+    assert_eq!(gameboy.bg_palettes()[1][2], (0, 0xFF, 0));
+    assert_eq!(gameboy.obj_palettes()[7][3], (0, 0, 0xFF));
+
+    // BGP is inverted and OBP1 keeps the shades in order
+    let mut program = ldh_imm(0x47, 0x1B).to_vec();
+    program.extend(ldh_imm(0x49, 0xE4));
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("DMGPALETTES").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    for _ in 0..2 {
+        gameboy.execute_frame();
+    }
+    let (bg, obj) = (gameboy.bg_palettes(), gameboy.obj_palettes());
+    assert_eq!(gameboy.frame_buffer()[0], bg[0][0]);
+    assert_eq!(bg[0][0], obj[1][3]);
+    assert_eq!(bg[0][3], obj[1][0]);
+    assert_ne!(bg[0][0], bg[0][3]);
+    assert_eq!(bg[1], [(0xFF, 0xFF, 0xFF); 4]);
+}
+
+fn compat_background(old_licensee: u8) -> (u8, u8, u8) {
+    // BGP maps colour 0, which the blank background shows everywhere, to shade 1
+    let mut program = ldh_imm(0x47, 0x01).to_vec();
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("TETRIS")
+        .patch(0x014B, &[old_licensee])
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+    gameboy.frame_buffer()[72 * 160 + 80]
+}
+
+#[test]
+fn dmg_games_get_the_cgb_boot_rom_palettes() {
+    // Nintendo's Tetris has its own palette, yellow for shade 1
+    assert_eq!(compat_background(0x01), (0xFF, 0xFF, 0x00));
+    // Anyone else's game gets the default green
+    assert_eq!(compat_background(0x00), (123, 0xFF, 49));
+}
+
+// A black window from line 40 over a white background
+fn window_rom() -> Vec<u8> {
+    let mut program = ldh_imm(0x40, 0x00).to_vec();
+    program.extend_from_slice(&[
+        0x21, 0x10, 0x80, 0x3E, 0xFF, 0x06, 0x10, // ld hl, $8010; ld a, $FF; ld b, 16
+        0x22, 0x05, 0x20, 0xFC, // tile: ld (hl+), a; dec b; jr nz, tile
+        // Window map at $9800 all tile 1; the BG map at $9C00 stays tile 0
+        0x21, 0x00, 0x98, 0x01, 0x00, 0x04, // ld hl, $9800; ld bc, $400
+        // map: ld a, 1; ld (hl+), a; dec bc; ld a, b; or c; jr nz, map
+        0x3E, 0x01, 0x22, 0x0B, 0x78, 0xB1, 0x20, 0xF7,
+    ]);
+    program.extend(ldh_imm(0x47, 0xE4));
+    program.extend(ldh_imm(0x4A, 40));
+    program.extend(ldh_imm(0x4B, 7));
+    // LCD, window, unsigned tile data, BG map at $9C00, BG on
+    program.extend(ldh_imm(0x40, 0xB9));
+    program.extend_from_slice(&LOOP_FOREVER);
+    RomBuilder::new("WINDOW").program(&program).build()
+}
+
+fn line_color(gameboy: &GameBoyColor, line: usize) -> (u8, u8, u8) {
+    gameboy.frame_buffer()[line * SCREEN_WIDTH + 80]
+}
+
+#[test]
+fn window_latches_can_be_read_and_forced() {
+    let mut gameboy = GameBoyColor::new(&window_rom(), DeviceMode::GameBoy, None).unwrap();
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+    let white = line_color(&gameboy, 0);
+    let black = line_color(&gameboy, 40);
+    assert_ne!(white, black);
+    assert_eq!(line_color(&gameboy, 39), white);
+
+    // 100 lines of 114 M-cycles into the frame, 60 of them with the window
+    gameboy.run_cycles(114 * 100);
+    let latches = gameboy.ppu_latches();
+    assert!(latches.window_y_triggered);
+    assert!(
+        (59..=61).contains(&latches.window_line_counter),
+        "{:?}",
+        latches
+    );
+
+    // Forcing the trigger early shows the window above WY for the rest of this frame only
+    gameboy.execute_frame();
+    gameboy.run_cycles(114 * 20);
+    let mut latches = gameboy.ppu_latches();
+    assert_eq!(latches.window_line_counter, 0);
+    assert!(!latches.window_y_triggered);
+    latches.window_y_triggered = true;
+    gameboy.set_ppu_latches(latches);
+    gameboy.execute_frame();
+    assert_eq!(line_color(&gameboy, 10), white);
+    assert_eq!(line_color(&gameboy, 30), black);
+    gameboy.execute_frame();
+    assert_eq!(line_color(&gameboy, 30), white);
+}
@@ -0,0 +1,196 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::utils::diff_frames;
+use rust_gameboycolor::{
+    DeviceMode, EmulatorConfig, EmulatorConfigBuilder, EmulatorError, GameBoyColor, JoypadKey,
+    JoypadKeyState, LinkCable, Model, NetworkCable, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+struct Cable;
+
+impl LinkCable for Cable {
+    fn send(&mut self, _data: u8) {}
+
+    fn try_recv(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+#[test]
+fn facade_is_reachable_from_the_crate_root() -> Result<(), EmulatorError> {
+    let config: EmulatorConfig = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .link_cable(Box::new(Cable))
+        .build()?;
+
+    let mut key_state = JoypadKeyState::new();
+    key_state.set_key(JoypadKey::Start, true);
+
+    let _: fn(&[u8], EmulatorConfig) -> Result<GameBoyColor, EmulatorError> =
+        GameBoyColor::with_config;
+    let _: fn(String, String) -> NetworkCable = NetworkCable::new;
+    let _ = config;
+    assert_eq!(SCREEN_WIDTH * SCREEN_HEIGHT, 160 * 144);
+    Ok(())
+}
+
+#[test]
+fn screenshot_captures_frame_and_metadata() {
+    let rom = RomBuilder::new("SCREENSHOT").program(&LOOP_FOREVER).build();
+    let mut first = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    let mut second = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    for _ in 0..3 {
+        first.execute_frame();
+        second.execute_frame();
+    }
+
+    let screenshot = first.screenshot();
+    assert_eq!(screenshot.frame, 3);
+    assert_eq!(screenshot.rom_title, "SCREENSHOT");
+    assert_eq!(screenshot.pixels.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+    assert_eq!(screenshot.hash, second.screenshot().hash);
+    assert!(screenshot.to_png().starts_with(b"\x89PNG\r\n\x1a\n"));
+}
+
+#[test]
+fn screenshot_png_writes_the_screen_to_a_file() {
+    let rom = RomBuilder::new("SCREENSHOTPNG")
+        .program(&LOOP_FOREVER)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    gameboy.execute_frame();
+
+    let path = std::env::temp_dir().join(format!(
+        "rust-gameboycolor-screenshot-{}.png",
+        std::process::id()
+    ));
+    gameboy.screenshot_png(&path).unwrap();
+    let png = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(png, gameboy.screenshot().to_png());
+    // IHDR holds the size
+    assert_eq!(png[16..24], [0, 0, 0, 160, 0, 0, 0, 144]);
+}
+
+#[test]
+fn run_frame_bundles_video_audio_and_events() {
+    let rom = RomBuilder::new("RUNFRAME").program(&LOOP_FOREVER).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    let mut reference = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+
+    let mut key_state = JoypadKeyState::new();
+    key_state.set_key(JoypadKey::A, true);
+    let output = gameboy.run_frame(key_state);
+    reference.set_key(key_state);
+    reference.execute_frame();
+
+    let diff = diff_frames(output.video, reference.frame_buffer());
+    assert!(diff.is_identical(), "{:?}", diff.first_mismatch);
+    assert_eq!(output.audio, &reference.audio_buffer()[..]);
+    assert!(!output.audio.is_empty());
+    assert!(output.events.is_empty());
+}
+
+#[test]
+fn frame_timestamps_line_up_audio_with_video() {
+    let rom = RomBuilder::new("TIMESTAMP").program(&LOOP_FOREVER).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+
+    let first = gameboy.run_frame(JoypadKeyState::new()).timestamp;
+    let second = gameboy.run_frame(JoypadKeyState::new());
+    assert_eq!(second.audio_timestamp, first);
+    // 154 lines of 456 dots, give or take the instruction that crosses the boundary
+    let frame_length = second.timestamp - first;
+    assert!((70224 - 16..=70224 + 16).contains(&frame_length));
+    assert_eq!(gameboy.timestamp(), first + frame_length);
+}
+
+#[test]
+fn frame_overshoot_carries_into_the_next_frame() {
+    let rom = RomBuilder::new("OVERSHOOT").program(&LOOP_FOREVER).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+
+    let mut previous = gameboy.run_frame(JoypadKeyState::new());
+    let (mut timestamp, mut overshoot) = (previous.timestamp, previous.overshoot);
+    for _ in 0..8 {
+        previous = gameboy.run_frame(JoypadKeyState::new());
+        // Less than the longest instruction, 6 M-cycles
+        assert!(previous.overshoot < 24);
+        assert_eq!(
+            previous.timestamp - timestamp,
+            70224 - overshoot + previous.overshoot
+        );
+        (timestamp, overshoot) = (previous.timestamp, previous.overshoot);
+    }
+    assert_eq!(gameboy.frame_overshoot(), overshoot);
+}
+
+#[test]
+fn capabilities_describe_the_cartridge_and_setup() {
+    let rom = RomBuilder::new("CAPS")
+        .cartridge_type(0x10)
+        .ram_size(0x03)
+        .build();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .link_cable(Box::new(Cable))
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(&rom, config).unwrap();
+    gameboy.set_sprite_limit(false);
+
+    let capabilities = gameboy.capabilities();
+    assert_eq!(capabilities.model, Model::Dmg);
+    assert_eq!(capabilities.device_mode, DeviceMode::GameBoy);
+    assert!(!capabilities.boot_rom);
+    assert_eq!(capabilities.mapper, "Mbc3");
+    assert_eq!(capabilities.rtc, cfg!(feature = "rtc"));
+    assert!(capabilities.link_cable);
+    assert!(!capabilities.sprite_limit);
+    assert!(!capabilities.super_game_boy);
+    assert_eq!(
+        capabilities.features.contains(&"rtc"),
+        cfg!(feature = "rtc")
+    );
+
+    let rom = RomBuilder::new("CAPS").build();
+    let gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    let capabilities = gameboy.capabilities();
+    assert_eq!(capabilities.mapper, "RomOnly");
+    assert!(!capabilities.link_cable);
+    assert!(capabilities.sprite_limit);
+}
+
+#[test]
+fn compiled_features_list_every_cargo_feature() {
+    let manifest = include_str!("../Cargo.toml");
+    let mut declared: Vec<&str> = manifest
+        .split("[features]")
+        .nth(1)
+        .unwrap()
+        .lines()
+        .take_while(|line| !line.starts_with('['))
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.split('=').next().unwrap().trim())
+        .filter(|&name| name != "default")
+        .collect();
+
+    // Entries of COMPILED_FEATURES look like ("name", cfg!(feature = "name")),
+    let source = include_str!("../src/context.rs");
+    let list = source.split("const COMPILED_FEATURES").nth(1).unwrap();
+    let list = &list[..list.find("];").unwrap()];
+    let mut reported: Vec<&str> = list
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("(\""))
+        .map(|entry| {
+            let (name, rest) = entry.split_once('"').unwrap();
+            assert_eq!(rest, format!(", cfg!(feature = \"{}\")),", name));
+            name
+        })
+        .collect();
+
+    declared.sort_unstable();
+    reported.sort_unstable();
+    assert_eq!(reported, declared);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{
+    DeviceMode, EmulatorConfigBuilder, EmulatorEvent, GameBoyColor, JoypadKey, JoypadKeyState,
+};
+
+// Counts boots in cartridge RAM and copies the count into B
+fn boot_counter_rom() -> Vec<u8> {
+    let mut program = vec![
+        0x3E, 0x0A, 0xEA, 0x00, 0x00, // ld a, $0A; ld ($0000), a
+        0x21, 0x00, 0xA0, // ld hl, $A000
+        0x34, 0x46, // inc (hl); ld b, (hl)
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    RomBuilder::new("SOFTRESET")
+        .cartridge_type(0x02)
+        .ram_size(0x02)
+        .program(&program)
+        .build()
+}
+
+fn combo(held: bool) -> JoypadKeyState {
+    let mut key_state = JoypadKeyState::new();
+    for key in [
+        JoypadKey::A,
+        JoypadKey::B,
+        JoypadKey::Start,
+        JoypadKey::Select,
+    ] {
+        key_state.set_key(key, held);
+    }
+    key_state
+}
+
+fn boot(soft_reset_combo: bool) -> GameBoyColor {
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .soft_reset_combo(soft_reset_combo)
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(&boot_counter_rom(), config).unwrap();
+    gameboy.run_frame(JoypadKeyState::new());
+    assert_eq!(gameboy.cpu_state().b, 1);
+    gameboy
+}
+
+#[test]
+fn combo_is_reported_once_while_held() {
+    let mut gameboy = boot(false);
+    assert_eq!(
+        gameboy.run_frame(combo(true)).events,
+        [EmulatorEvent::SoftResetCombo]
+    );
+    assert!(gameboy.run_frame(combo(true)).events.is_empty());
+    gameboy.run_frame(combo(false));
+    assert_eq!(
+        gameboy.run_frame(combo(true)).events,
+        [EmulatorEvent::SoftResetCombo]
+    );
+    // Nothing restarted
+    assert_eq!(gameboy.cpu_state().b, 1);
+}
+
+#[test]
+fn combo_soft_resets_when_enabled_and_keeps_cartridge_ram() {
+    let mut gameboy = boot(true);
+    assert_eq!(
+        gameboy.run_frame(combo(true)).events,
+        [EmulatorEvent::SoftResetCombo]
+    );
+    assert_eq!(gameboy.cpu_state().b, 2);
+    assert!((0x0150..0x0160).contains(&gameboy.cpu_state().pc));
+
+    // Holding the buttons does not reset again
+    gameboy.run_frame(combo(true));
+    assert_eq!(gameboy.cpu_state().b, 2);
+}
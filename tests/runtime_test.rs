@@ -0,0 +1,62 @@
+#![cfg(feature = "std-runtime")]
+
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{
+    Command, DeviceMode, EmulatorError, EmulatorThread, GameBoyColor, JoypadKey, JoypadKeyState,
+    SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+use std::time::Duration;
+
+fn spawn() -> EmulatorThread {
+    // MBC1 with 8 KiB of battery-backed RAM
+    let rom = RomBuilder::new("THREAD")
+        .cartridge_type(0x03)
+        .ram_size(0x02)
+        .program(&LOOP_FOREVER)
+        .build();
+    EmulatorThread::spawn(move || GameBoyColor::new(&rom, DeviceMode::GameBoy, None)).unwrap()
+}
+
+#[test]
+fn thread_delivers_frames_and_returns_save_data() {
+    let emulator = spawn();
+    let first = emulator.recv_frame().unwrap();
+    let second = emulator.recv_frame().unwrap();
+    assert!(second.number > first.number);
+    assert_eq!(first.pixels.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+
+    let mut key_state = JoypadKeyState::new();
+    key_state.set_key(JoypadKey::A, true);
+    emulator.send(Command::SetKeys(key_state));
+
+    assert_eq!(emulator.shutdown().map(|data| data.len()), Some(0x2000));
+}
+
+#[test]
+fn paused_thread_only_runs_stepped_frames() {
+    let emulator = spawn();
+    emulator.send(Command::Pause);
+    std::thread::sleep(Duration::from_millis(50));
+    while emulator.try_recv_frame().is_some() {}
+
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(emulator.try_recv_frame().is_none());
+
+    emulator.send(Command::StepFrame);
+    let stepped = emulator.recv_frame().unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(emulator.try_recv_frame().is_none());
+
+    emulator.send(Command::Resume);
+    assert!(emulator.recv_frame().unwrap().number > stepped.number);
+    emulator.shutdown();
+}
+
+#[test]
+fn build_errors_are_returned_from_spawn() {
+    let result = EmulatorThread::spawn(|| Err(EmulatorError::UnsupportedMode("test".to_string())));
+    assert!(matches!(result, Err(EmulatorError::UnsupportedMode(_))));
+}
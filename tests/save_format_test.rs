@@ -0,0 +1,82 @@
+use rust_gameboycolor::utils::{
+    export_save, import_save, RtcFooter, RtcFooterFormat, RtcRegisters, SaveFile, SaveFormatError,
+};
+
+fn sram() -> Vec<u8> {
+    (0..0x2000).map(|i| i as u8).collect()
+}
+
+#[test]
+fn raw_dumps_import_as_sram_only() {
+    let save = import_save(&sram(), 0x2000).unwrap();
+    assert_eq!(save.sram, sram());
+    assert_eq!(save.rtc, None);
+    assert_eq!(export_save(&save, RtcFooterFormat::Long), sram());
+}
+
+#[test]
+fn rtc_footers_round_trip_in_both_sizes() {
+    let save = SaveFile {
+        sram: sram(),
+        rtc: Some(RtcFooter {
+            current: RtcRegisters {
+                seconds: 12,
+                minutes: 34,
+                hours: 5,
+                days_low: 0xFF,
+                days_high: 0x81,
+            },
+            latched: RtcRegisters {
+                seconds: 10,
+                ..RtcRegisters::default()
+            },
+            timestamp: 1_700_000_000,
+            battery_low: false,
+        }),
+    };
+
+    for format in [RtcFooterFormat::Short, RtcFooterFormat::Long] {
+        let data = export_save(&save, format);
+        assert_eq!(data.len(), 0x2000 + format.size());
+        assert_eq!(import_save(&data, 0x2000).unwrap(), save);
+    }
+
+    // Registers are little-endian u32s: current seconds, then minutes
+    let data = export_save(&save, RtcFooterFormat::Long);
+    assert_eq!(data[0x2000..0x2008], [12, 0, 0, 0, 34, 0, 0, 0]);
+}
+
+#[test]
+fn battery_low_flag_is_stored_beside_the_day_high_register() {
+    let mut save = SaveFile {
+        sram: sram(),
+        rtc: Some(RtcFooter {
+            current: RtcRegisters {
+                days_high: 0x40,
+                ..RtcRegisters::default()
+            },
+            battery_low: true,
+            ..RtcFooter::default()
+        }),
+    };
+
+    let data = export_save(&save, RtcFooterFormat::Long);
+    assert_eq!(data[0x2010..0x2014], [0x40, 0x01, 0, 0]);
+    assert_eq!(import_save(&data, 0x2000).unwrap(), save);
+
+    save.rtc.as_mut().unwrap().battery_low = false;
+    let data = export_save(&save, RtcFooterFormat::Long);
+    assert_eq!(data[0x2010..0x2014], [0x40, 0, 0, 0]);
+}
+
+#[test]
+fn saves_of_an_unknown_size_are_rejected() {
+    assert!(matches!(
+        import_save(&[0; 0x2010], 0x2000),
+        Err(SaveFormatError::UnexpectedSize {
+            sram_size: 0x2000,
+            actual: 0x2010
+        })
+    ));
+    assert!(import_save(&[0; 0x100], 0x2000).is_err());
+}
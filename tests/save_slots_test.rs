@@ -0,0 +1,66 @@
+use rust_gameboycolor::utils::{SaveSlots, DEFAULT_SLOT};
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// A fresh directory under the system temp directory, unique to the test
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("rust-gameboycolor-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn slots_are_saved_listed_copied_and_deleted() {
+    let root = scratch_dir("slots");
+    let slots = SaveSlots::open(&root, "POKEMON").unwrap();
+    assert!(slots.list().unwrap().is_empty());
+    assert_eq!(slots.load("slot1").unwrap(), None);
+
+    slots.save("slot1", &[1, 2, 3]).unwrap();
+    slots.save("practice", &[4, 5]).unwrap();
+    assert_eq!(slots.list().unwrap(), ["practice", "slot1"]);
+    assert_eq!(slots.load("slot1").unwrap(), Some(vec![1, 2, 3]));
+
+    slots.copy("practice", "slot1").unwrap();
+    assert_eq!(slots.load("slot1").unwrap(), Some(vec![4, 5]));
+    let error = slots.copy("missing", "slot2").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+
+    assert!(slots.delete("practice").unwrap());
+    assert!(!slots.delete("practice").unwrap());
+    assert_eq!(slots.list().unwrap(), ["slot1"]);
+
+    // Other games keep their own slots
+    let other = SaveSlots::open(&root, "TETRIS").unwrap();
+    assert!(other.list().unwrap().is_empty());
+
+    for name in ["", "../escape", "a/b", ".hidden"] {
+        let error = slots.save(name, &[0]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{:?}", name);
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn single_file_saves_move_into_the_default_slot() {
+    let root = scratch_dir("migration");
+    fs::write(root.join("ZELDA.srm"), [7, 8, 9]).unwrap();
+
+    let slots = SaveSlots::open(&root, "ZELDA").unwrap();
+    assert_eq!(slots.list().unwrap(), [DEFAULT_SLOT]);
+    assert_eq!(slots.load(DEFAULT_SLOT).unwrap(), Some(vec![7, 8, 9]));
+    assert!(!root.join("ZELDA.srm").exists());
+
+    // An existing default slot is never overwritten by a stray old file
+    fs::write(root.join("ZELDA.srm"), [0]).unwrap();
+    let slots = SaveSlots::open(&root, "ZELDA").unwrap();
+    assert_eq!(slots.load(DEFAULT_SLOT).unwrap(), Some(vec![7, 8, 9]));
+    assert!(root.join("ZELDA.srm").exists());
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,223 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{
+    analyze_link, DeviceCable, DeviceMode, GameBoyColor, LinkCable, LinkProtocol, SerialDevice,
+    SerialExchange,
+};
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[test]
+fn serial_clock_in_exchanges_a_byte_as_link_master() {
+    let mut program = vec![
+        0x3E, 0x5A, 0xE0, 0x01, // ld a, $5A; ldh (SB), a
+        0x3E, 0x80, 0xE0, 0x02, // ld a, $80; ldh (SC), a
+        0xF0, 0x02, 0xCB, 0x7F, 0x20, 0xFA, // wait: ldh a, (SC); bit 7, a; jr nz, wait
+        0xF0, 0x01, 0x3C, 0xE0, 0x01, // ldh a, (SB); inc a; ldh (SB), a
+        0x3E, 0x80, 0xE0, 0x02, // ld a, $80; ldh (SC), a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("SERIALSLAVE").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+
+    assert_eq!(gameboy.serial_clock_in(0x10), None);
+    gameboy.execute_frame();
+    assert_eq!(gameboy.serial_clock_in(0x10), Some(0x5A));
+    gameboy.execute_frame();
+    assert_eq!(gameboy.serial_clock_in(0x00), Some(0x11));
+    gameboy.execute_frame();
+    assert_eq!(gameboy.serial_clock_in(0x00), None);
+}
+
+/// Echoes every byte back, like a cable plugged into its own port.
+#[derive(Default)]
+struct Loopback {
+    byte: Option<u8>,
+}
+
+impl LinkCable for Loopback {
+    fn send(&mut self, data: u8) {
+        self.byte = Some(data);
+    }
+
+    fn try_recv(&mut self) -> Option<u8> {
+        self.byte.take()
+    }
+}
+
+// Starts an internal-clock transfer of $A5 and counts 9 M-cycle polls of SC until it ends
+fn count_transfer_polls(prelude: &[u8], sc: u8) -> (u8, u8, u8) {
+    let mut program = prelude.to_vec();
+    program.extend_from_slice(&[
+        0xAF, 0xE0, 0x0F, 0x47, // xor a; ldh (IF), a; ld b, a
+        0x3E, 0xA5, 0xE0, 0x01, // ld a, $A5; ldh (SB), a
+        0x3E, sc, 0xE0, 0x02, // ld a, sc; ldh (SC), a
+        0x04, 0xF0, 0x02, 0xCB, 0x7F, 0x20,
+        0xF9, // wait: inc b; ldh a, (SC); bit 7, a; jr nz, wait
+        0xF0, 0x0F, 0x4F, // ldh a, (IF); ld c, a
+        0xF0, 0x01, // ldh a, (SB)
+    ]);
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("SERIALTIMING")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(
+        &rom,
+        DeviceMode::GameBoyColor,
+        Some(Box::new(Loopback::default())),
+    )
+    .unwrap();
+    gameboy.execute_frame();
+    let state = gameboy.cpu_state();
+    (state.b, state.c, state.a)
+}
+
+#[test]
+fn internal_clock_completes_after_eight_bits() {
+    // ld a, $01; ldh (KEY1), a; stop
+    const DOUBLE_SPEED: [u8; 6] = [0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00];
+    let cases: [(&[u8], u8, u32); 3] = [
+        (&[], 0x81, 128 * 8),
+        (&DOUBLE_SPEED, 0x81, 64 * 8),
+        (&[], 0x83, 4 * 8),
+    ];
+
+    for (prelude, sc, cycles) in cases {
+        let (polls, interrupt_flag, received) = count_transfer_polls(prelude, sc);
+        let expected = cycles.div_ceil(9) as i32;
+        assert!(
+            (polls as i32 - expected).abs() <= 1,
+            "SC={:#04X} speed prelude {:?}: {} polls, expected about {}",
+            sc,
+            prelude,
+            polls,
+            expected
+        );
+        assert_eq!(interrupt_flag & 0x08, 0x08);
+        assert_eq!(received, 0xA5);
+    }
+}
+
+#[test]
+fn link_tap_records_each_exchange() {
+    let mut program = Vec::new();
+    for value in [0x12, 0x34] {
+        program.extend_from_slice(&[
+            0x3E, value, 0xE0, 0x01, // ld a, value; ldh (SB), a
+            0x3E, 0x81, 0xE0, 0x02, // ld a, $81; ldh (SC), a
+            0xF0, 0x02, 0xCB, 0x7F, 0x20, 0xFA, // wait: ldh a, (SC); bit 7, a; jr nz, wait
+        ]);
+    }
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("LINKTAP").program(&program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+
+    gameboy.set_link_tap(true);
+    gameboy.execute_frame();
+
+    let log = gameboy.link_log();
+    assert_eq!(log.len(), 2);
+    assert_eq!((log[0].sent, log[0].received), (0x12, 0xFF));
+    assert_eq!((log[1].sent, log[1].received), (0x34, 0xFF));
+    assert!(log.iter().all(|exchange| exchange.control == 0x81));
+    assert!(log[0].is_master());
+    assert!(log[0].timestamp < log[1].timestamp);
+    assert!(log[1].timestamp <= gameboy.timestamp());
+
+    gameboy.set_link_tap(false);
+    assert!(gameboy.link_log().is_empty());
+}
+
+fn exchanges(sent: &[u8], received: &[u8]) -> Vec<SerialExchange> {
+    sent.iter()
+        .zip(received.iter().chain(std::iter::repeat(&0x00)))
+        .enumerate()
+        .map(|(i, (&sent, &received))| SerialExchange {
+            timestamp: i as u64 * 4096,
+            sent,
+            received,
+            control: 0x81,
+        })
+        .collect()
+}
+
+#[test]
+fn analyzer_annotates_printer_packets() {
+    // Init, then a Data packet with two bytes and a checksum that does not match
+    let sent = [
+        0x88, 0x33, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, // Init
+        0x88, 0x33, 0x04, 0x00, 0x02, 0x00, 0xAA, 0x55, 0x00, 0x00, 0x00, 0x00, // Data
+    ];
+    let mut received = [0x00; 22];
+    received[8] = 0x81;
+    received[9] = 0x00;
+    received[21] = 0x08;
+
+    let annotations = analyze_link(&exchanges(&sent, &received));
+    assert_eq!(annotations.len(), 2);
+    assert_eq!(annotations[0].range, 0..10);
+    assert_eq!(annotations[0].protocol, LinkProtocol::Printer);
+    assert_eq!(
+        annotations[0].description,
+        "Init packet, 0 bytes of data, status $00"
+    );
+    assert_eq!(annotations[1].range, 10..22);
+    assert_eq!(
+        annotations[1].description,
+        "Data packet, 2 bytes of data, bad checksum, status $08"
+    );
+}
+
+#[test]
+fn analyzer_annotates_trade_blocks() {
+    let sent = [
+        0x00, 0x01, // Unrecognised
+        0xFD, 0xFD, 0xFD, 0x11, 0x22, 0x33, // First block
+        0xFD, 0xFD, 0xFD, 0xFD, 0x44, // Second block
+    ];
+
+    let annotations = analyze_link(&exchanges(&sent, &[]));
+    assert_eq!(annotations.len(), 2);
+    assert_eq!(annotations[0].range, 2..8);
+    assert_eq!(annotations[0].protocol, LinkProtocol::PokemonTrade);
+    assert_eq!(
+        annotations[0].description,
+        "Data block, 3 bytes after the preamble"
+    );
+    assert_eq!(annotations[1].range, 8..13);
+}
+
+/// Answers each byte with its complement and keeps what it was sent.
+struct Complement(Rc<RefCell<Vec<u8>>>);
+
+impl SerialDevice for Complement {
+    fn exchange(&mut self, sent: u8) -> u8 {
+        self.0.borrow_mut().push(sent);
+        !sent
+    }
+}
+
+#[test]
+fn serial_devices_answer_each_byte_as_it_is_clocked() {
+    let mut program = vec![0x3E, 0x3C, 0xE0, 0x01]; // ld a, $3C; ldh (SB), a
+    for _ in 0..2 {
+        program.extend_from_slice(&[
+            0x3E, 0x81, 0xE0, 0x02, // ld a, $81; ldh (SC), a
+            0xF0, 0x02, 0xCB, 0x7F, 0x20, 0xFA, // wait: ldh a, (SC); bit 7, a; jr nz, wait
+        ]);
+    }
+    program.extend_from_slice(&[0xF0, 0x01]); // ldh a, (SB)
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("SERIALDEVICE").program(&program).build();
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let cable = DeviceCable::new(Complement(received.clone()));
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, Some(Box::new(cable))).unwrap();
+
+    gameboy.execute_frame();
+    // The second transfer sends back what the device answered to the first
+    assert_eq!(*received.borrow(), [0x3C, 0xC3]);
+    assert_eq!(gameboy.cpu_state().a, 0x3C);
+}
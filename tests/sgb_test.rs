@@ -0,0 +1,136 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, EmulatorConfigBuilder, GameBoyColor, Model, SGB_SCREEN_WIDTH};
+
+const SEND_PACKET_ADDRESS: usize = 0x0200;
+const WAIT_FRAMES_ADDRESS: usize = 0x0240;
+const PACKETS_ADDRESS: usize = 0x0300;
+
+// Sends the 16-byte packet at HL over P1, least significant bit first
+const SEND_PACKET: [u8; 42] = [
+    0xAF, 0xE0, 0x00, // xor a; ldh (P1), a (reset pulse)
+    0x3E, 0x30, 0xE0, 0x00, // ld a, $30; ldh (P1), a
+    0x06, 0x10, // ld b, 16
+    0x2A, 0x5F, 0x16, 0x08, // byte: ld a, (hl+); ld e, a; ld d, 8
+    0x3E, 0x10, 0xCB, 0x1B, // bit: ld a, $10; rr e
+    0x38, 0x02, 0x3E, 0x20, // jr c, send; ld a, $20
+    0xE0, 0x00, 0x3E, 0x30, 0xE0, 0x00, // send: ldh (P1), a; ld a, $30; ldh (P1), a
+    0x15, 0x20, 0xEF, // dec d; jr nz, bit
+    0x05, 0x20, 0xE8, // dec b; jr nz, byte
+    0x3E, 0x20, 0xE0, 0x00, // ld a, $20; ldh (P1), a (stop bit)
+    0x3E, 0x30, 0xE0, 0x00, // ld a, $30; ldh (P1), a
+    0xC9, // ret
+];
+
+// Waits for three frames to start
+const WAIT_FRAMES: [u8; 18] = [
+    0x0E, 0x03, // ld c, 3
+    0xF0, 0x44, 0xFE, 0x90, 0x20, 0xFA, // wait: ldh a, (LY); cp 144; jr nz, wait
+    0xF0, 0x44, 0xFE, 0x90, 0x28, 0xFA, // leave: ldh a, (LY); cp 144; jr z, leave
+    0x0D, 0x20, 0xF1, // dec c; jr nz, wait
+    0xC9, // ret
+];
+
+fn send_packet(index: u16) -> [u8; 6] {
+    let [low, high] = (PACKETS_ADDRESS as u16 + index * 16).to_le_bytes();
+    // ld hl, packet; call send_packet
+    [0x21, low, high, 0xCD, 0x00, 0x02]
+}
+
+const CALL_WAIT_FRAMES: [u8; 3] = [0xCD, 0x40, 0x02];
+
+// Palette 0 is red, green, blue and black; palette 1 has blue for colour 1
+const PAL01: [u8; 16] = [
+    0x01, 0x1F, 0x00, 0xE0, 0x03, 0x00, 0x7C, 0x00, 0x00, 0x00, 0x7C, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+fn sgb_gameboy(program: &[u8], packets: &[[u8; 16]], sgb_flag: bool) -> GameBoyColor {
+    let mut program = program.to_vec();
+    program.extend_from_slice(&LOOP_FOREVER);
+    let mut rom = RomBuilder::new("SGBTEST")
+        .program(&program)
+        .patch(SEND_PACKET_ADDRESS, &SEND_PACKET)
+        .patch(WAIT_FRAMES_ADDRESS, &WAIT_FRAMES)
+        .patch(PACKETS_ADDRESS, packets.concat().as_slice());
+    if sgb_flag {
+        rom = rom.patch(0x0146, &[0x03]).patch(0x014B, &[0x33]);
+    }
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .model(Model::Sgb)
+        .build()
+        .unwrap();
+    GameBoyColor::with_config(&rom.build(), config).unwrap()
+}
+
+fn pixel(gameboy: &GameBoyColor, x: usize, y: usize) -> (u8, u8, u8) {
+    gameboy.sgb_frame_buffer().unwrap()[y * SGB_SCREEN_WIDTH + x]
+}
+
+#[test]
+fn palette_and_attribute_packets_colour_the_screen() {
+    // Left half of the screen in palette 1
+    let attr_blk = [0x21, 0x01, 0x01, 0x01, 0, 0, 9, 17, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut program = vec![0x3E, 0x01, 0xE0, 0x47]; // ld a, $01; ldh (BGP), a
+    program.extend(send_packet(0));
+    program.extend(send_packet(1));
+    let mut gameboy = sgb_gameboy(&program, &[PAL01, attr_blk], true);
+    assert!(gameboy.capabilities().super_game_boy);
+    for _ in 0..3 {
+        gameboy.execute_frame();
+    }
+
+    // The blank screen shows shade 1, and the empty border the shared colour 0
+    assert_eq!(pixel(&gameboy, 48 + 10, 40 + 72), (0x00, 0x00, 0xFF));
+    assert_eq!(pixel(&gameboy, 48 + 150, 40 + 72), (0x00, 0xFF, 0x00));
+    assert_eq!(pixel(&gameboy, 5, 5), (0xFF, 0x00, 0x00));
+    // The Game Boy's own frame stays in DMG shades
+    assert_eq!(gameboy.frame_buffer()[72 * 160 + 10], (0xAA, 0xAA, 0xAA));
+}
+
+#[test]
+fn border_is_transferred_from_the_screen() {
+    // A screen of shade 3 sends all ones: tile $FF everywhere in palette 7 colour 15
+    let chr_trn = [0x99, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let pct_trn = [0xA1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut program = vec![0x3E, 0xFF, 0xE0, 0x47]; // ld a, $FF; ldh (BGP), a
+    program.extend(send_packet(0));
+    program.extend(send_packet(1));
+    program.extend(CALL_WAIT_FRAMES);
+    program.extend(send_packet(2));
+    program.extend(CALL_WAIT_FRAMES);
+    let mut gameboy = sgb_gameboy(&program, &[PAL01, chr_trn, pct_trn], true);
+
+    gameboy.execute_frame();
+    assert_eq!(pixel(&gameboy, 5, 5), (0xFF, 0x00, 0x00));
+    for _ in 0..10 {
+        gameboy.execute_frame();
+    }
+    assert_eq!(pixel(&gameboy, 5, 5), (0xFF, 0xFF, 0xFF));
+    assert_eq!(pixel(&gameboy, 250, 220), (0xFF, 0xFF, 0xFF));
+    assert_eq!(pixel(&gameboy, 48 + 80, 40 + 72), (0x00, 0x00, 0x00));
+}
+
+#[test]
+fn multiplayer_request_reports_the_controller_number() {
+    let mlt_req = [0x89, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut program = send_packet(0).to_vec();
+    program.extend([
+        0x3E, 0x10, 0xE0, 0x00, // ld a, $10; ldh (P1), a
+        0x3E, 0x30, 0xE0, 0x00, // ld a, $30; ldh (P1), a (next controller)
+        0xF0, 0x00, // ldh a, (P1)
+    ]);
+    let mut gameboy = sgb_gameboy(&program, &[mlt_req], true);
+    for _ in 0..2 {
+        gameboy.execute_frame();
+    }
+    assert_eq!(gameboy.cpu_state().a & 0x0F, 0x0E);
+}
+
+#[test]
+fn games_without_the_sgb_flag_get_no_sgb_picture() {
+    let gameboy = sgb_gameboy(&send_packet(0), &[PAL01], false);
+    assert!(gameboy.sgb_frame_buffer().is_none());
+    assert!(!gameboy.capabilities().super_game_boy);
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::{boot, RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, EmulatorEvent, JoypadKeyState, Split, SplitCondition};
+
+#[test]
+fn splits_fire_once_when_their_condition_starts_to_hold() {
+    let mut program = vec![
+        0x01, 0xFF, 0xFF, // ld bc, $FFFF
+        0x0B, 0x78, 0xB1, 0x20, 0xFB, // wait: dec bc; ld a, b; or c; jr nz, wait
+        0x3E, 0x03, 0xEA, 0x00, 0xC0, // ld a, 3; ld ($C000), a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("SPLITS").program(&program).build();
+    let (mut gameboy, _) = boot(&rom, DeviceMode::GameBoy);
+    gameboy.add_split(Split::new("start", 0xC000, SplitCondition::BitsSet(0x02)));
+    gameboy.add_split(Split::new("changed", 0xC000, SplitCondition::Changed));
+    gameboy.add_split(Split::new("never", 0xC000, SplitCondition::AtLeast(4)));
+
+    let mut splits = Vec::new();
+    for _ in 0..60 {
+        for event in gameboy.run_frame(JoypadKeyState::new()).events {
+            if let EmulatorEvent::Split { name, frame } = event {
+                splits.push((name, frame));
+            }
+        }
+    }
+
+    let frame = splits.first().expect("the condition was met").1;
+    assert!((10..50).contains(&frame), "split at frame {}", frame);
+    assert_eq!(
+        splits,
+        [("start".to_string(), frame), ("changed".to_string(), frame)]
+    );
+}
+
+#[test]
+fn frames_without_joypad_reads_are_lag_frames() {
+    let rom = RomBuilder::new("LAGCOUNT").program(&LOOP_FOREVER).build();
+    let (mut gameboy, _) = boot(&rom, DeviceMode::GameBoy);
+    for _ in 0..5 {
+        gameboy.run_frame(JoypadKeyState::new());
+    }
+    assert_eq!(gameboy.frame_count(), 5);
+    assert_eq!(gameboy.lag_frames(), 5);
+    assert!(gameboy.is_lag_frame());
+}
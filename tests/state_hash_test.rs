@@ -0,0 +1,93 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, GameBoyColor, StateHashes};
+
+// MBC1 with cartridge RAM, which the program enables
+fn gameboy() -> GameBoyColor {
+    let mut program = vec![0x3E, 0x0A, 0xEA, 0x00, 0x00]; // ld a, $0A; ld ($0000), a
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("STATEHASH")
+        .cartridge_type(0x03)
+        .ram_size(0x02)
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    gameboy.execute_frame();
+    gameboy
+}
+
+// Which of the hashes differ
+fn changed(before: StateHashes, after: StateHashes) -> Vec<&'static str> {
+    [
+        ("cpu", before.cpu != after.cpu),
+        ("ppu", before.ppu != after.ppu),
+        ("apu", before.apu != after.apu),
+        ("ram", before.ram != after.ram),
+        ("cartridge", before.cartridge != after.cartridge),
+        ("io", before.io != after.io),
+    ]
+    .into_iter()
+    .filter_map(|(name, changed)| changed.then_some(name))
+    .collect()
+}
+
+#[test]
+fn consoles_in_the_same_state_hash_the_same() {
+    let mut first = gameboy();
+    let mut second = gameboy();
+    assert_eq!(first.state_hashes(), second.state_hashes());
+    assert_eq!(first.state_hash(), first.state_hashes().combined());
+
+    let before = first.state_hash();
+    first.execute_frame();
+    assert_ne!(first.state_hash(), before);
+    second.execute_frame();
+    assert_eq!(first.state_hash(), second.state_hash());
+}
+
+#[test]
+fn each_hash_covers_its_own_subsystem() {
+    let mut gameboy = gameboy();
+    for (address, subsystem) in [
+        (0xC123, "ram"),
+        (0xFF90, "ram"),
+        (0x8123, "ppu"),
+        (0xFE10, "ppu"),
+        (0xA123, "cartridge"),
+    ] {
+        let before = gameboy.state_hashes();
+        gameboy.poke(address, gameboy.peek(address) ^ 0xFF);
+        assert_eq!(
+            changed(before, gameboy.state_hashes()),
+            [subsystem],
+            "{:#06X}",
+            address
+        );
+    }
+}
+
+// Leaves A at 1 after writing `rp` to the infrared port
+fn infrared(rp: u8) -> StateHashes {
+    let rom = RomBuilder::new("STATEHASH")
+        .cgb_flag(0x80)
+        .asm(&format!(
+            "
+                ld a, {}
+                ldh [RP], a
+                ld a, 1
+                jr @
+            ",
+            rp
+        ))
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    gameboy.execute_frame();
+    gameboy.state_hashes()
+}
+
+#[test]
+fn io_hash_covers_the_infrared_port() {
+    assert_eq!(changed(infrared(0), infrared(1)), ["io"]);
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "static-buffers")]
+
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, GameBoyColor};
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn frames_run_without_heap_allocation() {
+    let mut program = vec![
+        0x3E, 0x80, 0xE0, 0x26, // ld a, $80; ldh (NR52), a
+        0x3E, 0x91, 0xE0, 0x40, // ld a, $91; ldh (LCDC), a
+    ];
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("NOALLOC")
+        .cgb_flag(0x80)
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    gameboy.execute_frame();
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    for _ in 0..30 {
+        gameboy.execute_frame();
+    }
+    assert_eq!(ALLOCATIONS.load(Ordering::SeqCst), before);
+}
@@ -0,0 +1,65 @@
+use rust_gameboycolor::{SyncAdvice, SyncAdvisor};
+
+use std::time::Duration;
+
+const FRAME_DOTS: u64 = 70224;
+
+// Follows the advice at every refresh of a display running at `refresh_rate`, returning
+// the advisor and how often each piece of advice was given
+fn follow_advice(refresh_rate: f64, refreshes: u32) -> (SyncAdvisor, [u32; 3]) {
+    let mut advisor = SyncAdvisor::new();
+    let mut timestamp = 0;
+    let mut counts = [0; 3];
+    for refresh in 0..refreshes {
+        let advice = advisor.advice();
+        let frames = match advice {
+            SyncAdvice::RunFrame => 1,
+            SyncAdvice::RunExtraFrame => 2,
+            SyncAdvice::SkipFrame => 0,
+        };
+        counts[frames as usize] += 1;
+        timestamp += frames * FRAME_DOTS;
+        let host_time = Duration::from_secs_f64(refresh as f64 / refresh_rate);
+        advisor.record_present(host_time, timestamp);
+    }
+    (advisor, counts)
+}
+
+#[test]
+fn a_60hz_display_skips_a_frame_now_and_then() {
+    let (advisor, [skipped, _, extra]) = follow_advice(60.0, 600);
+    // The hardware runs at 59.73 Hz, so about 3 of 600 refreshes have no new frame
+    assert!((2..=4).contains(&skipped), "{}", skipped);
+    assert_eq!(extra, 0);
+    assert!(advisor.lag().abs() < 0.017, "{}", advisor.lag());
+    assert!((advisor.speed() - 1.0).abs() < 0.1, "{}", advisor.speed());
+    assert!((advisor.present_rate() - 60.0).abs() < 0.5);
+    assert_eq!(advisor.resyncs(), 0);
+}
+
+#[test]
+fn a_display_under_load_runs_extra_frames() {
+    let (advisor, [skipped, _, extra]) = follow_advice(30.0, 300);
+    assert!(extra > 250, "{}", extra);
+    assert!(skipped < 5, "{}", skipped);
+    assert!(advisor.lag().abs() < 0.05, "{}", advisor.lag());
+    assert!((advisor.speed() - 1.0).abs() < 0.2, "{}", advisor.speed());
+}
+
+#[test]
+fn a_stall_is_forgiven_instead_of_caught_up() {
+    let mut advisor = SyncAdvisor::new();
+    advisor.record_present(Duration::ZERO, 0);
+    advisor.record_present(Duration::from_millis(17), FRAME_DOTS);
+    assert_eq!(advisor.advice(), SyncAdvice::RunFrame);
+
+    // A second passes while one frame runs
+    advisor.record_present(Duration::from_millis(1017), FRAME_DOTS * 2);
+    assert_eq!(advisor.resyncs(), 1);
+    assert_eq!(advisor.lag(), 0.0);
+    assert_eq!(advisor.advice(), SyncAdvice::RunFrame);
+    assert!(advisor.speed() < 1.0);
+
+    advisor.record_present(Duration::from_millis(1050), FRAME_DOTS * 3);
+    assert_eq!(advisor.advice(), SyncAdvice::RunExtraFrame);
+}
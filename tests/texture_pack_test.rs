@@ -0,0 +1,51 @@
+#![cfg(feature = "texture-pack")]
+
+mod common;
+
+use common::tile_scene;
+use rust_gameboycolor::{DeviceMode, TexturePack, TexturePackError};
+
+fn pack() -> TexturePack {
+    let mut pack = TexturePack::new(2);
+    let bg_tile = [0xFF, 0x00].repeat(8).try_into().unwrap();
+    pack.insert(TexturePack::tile_key(&bg_tile, 0), vec![(0xFF, 0, 0); 256]);
+    // Each pixel holds its own coordinates so flipping can be checked
+    let gradient = (0..256)
+        .map(|i| ((i % 16) as u8, (i / 16) as u8, 0))
+        .collect();
+    pack.insert(TexturePack::tile_key(&[0xFF; 16], 9), gradient);
+    pack
+}
+
+#[test]
+fn compose_replaces_matching_tiles() {
+    let gameboy = tile_scene(DeviceMode::GameBoy);
+    let output = pack().compose(&gameboy);
+    let width = 160 * 2;
+    assert_eq!(output.len(), width * 144 * 2);
+
+    assert!(output[..16].iter().all(|&pixel| pixel == (0xFF, 0, 0)));
+    // Screen pixel (9, 2) is column 6 of the flipped object, so its left half comes
+    // from column 13 of the replacement
+    assert_eq!(output[4 * width + 18], (13, 4, 0));
+    assert_eq!(output[4 * width + 19], (12, 4, 0));
+    // Tiles missing from the pack are scaled up unchanged
+    let background = gameboy.frame_buffer()[100 * 160 + 100];
+    assert_eq!(output[200 * width + 201], background);
+}
+
+#[test]
+fn packs_round_trip_through_bytes() {
+    let pack = pack();
+    let bytes = pack.to_bytes();
+    assert_eq!(TexturePack::load(&bytes).unwrap(), pack);
+
+    assert!(matches!(
+        TexturePack::load(&bytes[..bytes.len() - 1]),
+        Err(TexturePackError::Truncated)
+    ));
+    assert!(matches!(
+        TexturePack::load(b"PNG\0\x01\x02"),
+        Err(TexturePackError::InvalidHeader)
+    ));
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER, SEND_SERIAL_A};
+use rust_gameboycolor::{DeviceCable, DeviceMode, GameBoyColor, TurboFile};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BLOCK: [u8; 64] = {
+    let mut block = [0; 64];
+    let mut i = 0;
+    while i < 64 {
+        block[i] = i as u8 * 3;
+        i += 1;
+    }
+    block
+};
+
+// A fresh file path under the system temp directory, unique to the test
+fn scratch_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "rust-gameboycolor-{}-{}.bin",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+// The sync byte, the command, its parameters and the checksum, then a byte to clock
+// out each byte of the reply
+fn packet(command: u8, parameters: &[u8], reply_length: usize) -> Vec<u8> {
+    let mut packet = vec![0x6C, command];
+    packet.extend_from_slice(parameters);
+    let sum = packet.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    packet.push(sum.wrapping_neg());
+    packet.resize(packet.len() + reply_length, 0x00);
+    packet
+}
+
+// Sends `bytes` from a ROM to a Turbo File kept in `path`, returning every byte it answered
+fn run(path: &Path, bytes: &[u8]) -> Vec<u8> {
+    let [low, high] = (bytes.len() as u16).to_le_bytes();
+    let mut program = vec![
+        0x21, 0x00, 0x10, // ld hl, $1000
+        0x11, 0x00, 0xC0, // ld de, $C000
+        0x01, low, high, // ld bc, length
+        0x2A, // loop: ld a, (hl+)
+    ];
+    program.extend_from_slice(&SEND_SERIAL_A);
+    program.extend_from_slice(&[
+        0xF0, 0x01, 0x12, 0x13, // ldh a, (SB); ld (de), a; inc de
+        0x0B, 0x78, 0xB1, 0x20, 0xEA, // dec bc; ld a, b; or c; jr nz, loop
+    ]);
+    program.extend_from_slice(&LOOP_FOREVER);
+    let rom = RomBuilder::new("TURBOFILE")
+        .program(&program)
+        .patch(0x1000, bytes)
+        .build();
+    let cable = DeviceCable::new(TurboFile::open(path).unwrap());
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, Some(Box::new(cable))).unwrap();
+    for _ in 0..10 {
+        gameboy.execute_frame();
+    }
+    (0..bytes.len())
+        .map(|i| gameboy.peek(0xC000 + i as u16))
+        .collect()
+}
+
+#[test]
+fn blocks_written_by_the_game_are_kept_on_the_host() {
+    let path = scratch_file("turbo-file");
+    let mut bytes = packet(0x20, &[3], 2);
+    let mut parameters = vec![5];
+    parameters.extend_from_slice(&BLOCK);
+    bytes.extend(packet(0x30, &parameters, 2));
+    let received = run(&path, &bytes);
+
+    // Select bank 3, then write block 5
+    assert_eq!(received[4..6], [0x20, 0x00]);
+    assert_eq!(received[bytes.len() - 2..], [0x30, 0x00]);
+    let contents = fs::read(&path).unwrap();
+    assert_eq!(contents.len(), 128 * 1024);
+    let offset = 3 * 0x2000 + 5 * 64;
+    assert_eq!(contents[offset..offset + 64], BLOCK);
+
+    // A new session reads the block back
+    let mut bytes = packet(0x20, &[3], 2);
+    bytes.extend(packet(0x40, &[5], 66));
+    bytes.extend(packet(0x10, &[], 3));
+    let received = run(&path, &bytes);
+    let read = &received[6 + 4..6 + 4 + 66];
+    assert_eq!(read[..2], [0x40, 0x00]);
+    assert_eq!(read[2..], BLOCK);
+    assert_eq!(received[bytes.len() - 3..], [0x10, 0x00, 3]);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn bad_packets_are_answered_with_an_error_status() {
+    let path = scratch_file("turbo-file-errors");
+    let mut bytes = packet(0x20, &[16], 2);
+    let mut corrupt = packet(0x40, &[0], 66);
+    corrupt[3] ^= 0xFF;
+    bytes.extend(corrupt[..4].iter().copied().chain([0, 0]));
+    let received = run(&path, &bytes);
+
+    assert_eq!(received[4..6], [0x20, 0x02]);
+    assert_eq!(received[10..12], [0x40, 0x01]);
+    fs::remove_file(&path).unwrap();
+}
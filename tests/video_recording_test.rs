@@ -0,0 +1,96 @@
+#![cfg(feature = "video-recording")]
+
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, GameBoyColor, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+fn recording_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rust-gameboycolor-video-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+// Records 60 frames, a little over a second
+fn record(path: &PathBuf) -> GameBoyColor {
+    let rom = RomBuilder::new("VIDEO").program(&LOOP_FOREVER).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoyColor, None).unwrap();
+    gameboy.execute_frame();
+    gameboy.start_video_recording(path).unwrap();
+    for _ in 0..60 {
+        gameboy.execute_frame();
+    }
+    gameboy.stop_video_recording().unwrap();
+    gameboy
+}
+
+#[test]
+fn apng_recordings_keep_every_frame_at_59_73_hz() {
+    let path = recording_path("clip.png");
+    let mut gameboy = record(&path);
+    // Stopping again does nothing
+    gameboy.stop_video_recording().unwrap();
+
+    let mut reader = png::Decoder::new(BufReader::new(File::open(&path).unwrap()))
+        .read_info()
+        .unwrap();
+    assert_eq!(reader.info().animation_control.unwrap().num_frames, 60);
+    let mut buffer = vec![0; reader.output_buffer_size().unwrap()];
+    let mut milliseconds = 0;
+    for _ in 0..60 {
+        reader.next_frame(&mut buffer).unwrap();
+        let control = reader.info().frame_control.unwrap();
+        assert_eq!(control.delay_den, 1000);
+        assert!((16..=17).contains(&control.delay_num));
+        milliseconds += control.delay_num as u32;
+    }
+    // 60 * 70224 dots at 4194304 Hz
+    assert_eq!(milliseconds, 1005);
+
+    let expected: Vec<u8> = gameboy
+        .frame_buffer()
+        .iter()
+        .flat_map(|&(r, g, b)| [r, g, b])
+        .collect();
+    assert_eq!(buffer.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+    assert_eq!(buffer, expected);
+}
+
+#[test]
+fn gif_recordings_drop_frames_to_keep_time() {
+    let path = recording_path("clip.gif");
+    let gameboy = record(&path);
+
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options
+        .read_info(BufReader::new(File::open(&path).unwrap()))
+        .unwrap();
+    assert_eq!(
+        (decoder.width(), decoder.height()),
+        (SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16)
+    );
+    let mut frames = 0;
+    let mut hundredths = 0;
+    let mut last = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert!(frame.delay >= 2);
+        frames += 1;
+        hundredths += frame.delay;
+        last = frame.buffer.to_vec();
+    }
+    assert!((35..=45).contains(&frames), "{}", frames);
+    // 1.0046 seconds, give or take the last frame, which is held for at least 2
+    assert!((100..=101).contains(&hundredths), "{}", hundredths);
+
+    let expected: Vec<u8> = gameboy
+        .frame_buffer()
+        .iter()
+        .flat_map(|&(r, g, b)| [r, g, b, 0xFF])
+        .collect();
+    assert_eq!(last, expected);
+}
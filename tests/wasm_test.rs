@@ -0,0 +1,37 @@
+#![cfg(feature = "wasm")]
+
+mod common;
+
+use common::{RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{EmulatorError, WasmGameBoy};
+
+#[test]
+fn a_truncated_rom_is_refused_instead_of_trapping() {
+    let rom = RomBuilder::new("WASMSHORT").program(&LOOP_FOREVER).build();
+    let result = WasmGameBoy::try_new(&rom[..0x100], true, None);
+    assert!(matches!(result, Err(EmulatorError::RomError(_))));
+}
+
+#[test]
+fn an_unemulated_cartridge_type_is_refused() {
+    // MBC7
+    let rom = RomBuilder::new("WASMMBC7")
+        .cartridge_type(0x22)
+        .program(&LOOP_FOREVER)
+        .build();
+    let result = WasmGameBoy::try_new(&rom, true, None);
+    assert!(matches!(result, Err(EmulatorError::RomError(_))));
+}
+
+#[test]
+fn a_save_of_the_wrong_size_is_refused() {
+    // MBC1 with 8 KiB of battery-backed RAM
+    let rom = RomBuilder::new("WASMSAVE")
+        .cartridge_type(0x03)
+        .ram_size(0x02)
+        .program(&LOOP_FOREVER)
+        .build();
+    assert!(WasmGameBoy::try_new(&rom, true, Some(vec![0; 0x2000])).is_ok());
+    let result = WasmGameBoy::try_new(&rom, true, Some(vec![0; 0x100]));
+    assert!(matches!(result, Err(EmulatorError::SaveDataError(_))));
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{write_imm, RomBuilder, LOOP_FOREVER};
+use rust_gameboycolor::{DeviceMode, GameBoyColor, SramWatcher, Watch, WatchChange, WatchEncoding};
+
+// Waits for the start of the next vertical blank
+const WAIT_VBLANK: [u8; 12] = [
+    0xF0, 0x44, 0xFE, 0x90, 0x28, 0xFA, // leave: ldh a, (LY); cp 144; jr z, leave
+    0xF0, 0x44, 0xFE, 0x90, 0x20, 0xFA, // enter: ldh a, (LY); cp 144; jr nz, enter
+];
+
+#[test]
+fn sram_watcher_reports_score_changes_once() {
+    let mut program = write_imm(0x0000, 0x0A);
+    program.extend(write_imm(0xA010, 0x12));
+    program.extend(write_imm(0xA011, 0x34));
+    for _ in 0..3 {
+        program.extend_from_slice(&WAIT_VBLANK);
+    }
+    program.extend(write_imm(0xA010, 0x56));
+    program.extend(write_imm(0xA011, 0x78));
+    program.extend_from_slice(&LOOP_FOREVER);
+
+    // MBC1 with 8 KiB of battery-backed RAM
+    let rom = RomBuilder::new("HISCORE")
+        .cartridge_type(0x03)
+        .ram_size(0x02)
+        .program(&program)
+        .build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+
+    let mut watcher = SramWatcher::new();
+    watcher.add(Watch::new("score", 0x10, 2, WatchEncoding::Bcd));
+    watcher.add(Watch::new("raw", 0x10, 2, WatchEncoding::LittleEndian));
+    watcher.add(Watch::new("outside", 0x2000, 1, WatchEncoding::BigEndian));
+
+    gameboy.execute_frame();
+    let changes = watcher.poll(&gameboy);
+    assert_eq!(
+        changes,
+        [
+            WatchChange {
+                name: "score".to_string(),
+                old: None,
+                new: 1234,
+            },
+            WatchChange {
+                name: "raw".to_string(),
+                old: None,
+                new: 0x3412,
+            },
+        ]
+    );
+
+    let mut later = Vec::new();
+    for _ in 0..6 {
+        gameboy.execute_frame();
+        later.extend(watcher.poll(&gameboy));
+    }
+    assert_eq!(later.len(), 2);
+    assert_eq!(
+        later[0],
+        WatchChange {
+            name: "score".to_string(),
+            old: Some(1234),
+            new: 5678,
+        }
+    );
+    assert_eq!(watcher.value("raw"), Some(0x7856));
+    assert_eq!(watcher.value("outside"), None);
+}
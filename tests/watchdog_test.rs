@@ -0,0 +1,64 @@
+mod common;
+
+use common::{RomBuilder, PROGRAM_START};
+use rust_gameboycolor::{
+    DeviceMode, EmulatorConfigBuilder, EmulatorEvent, GameBoyColor, StallReason,
+};
+
+fn run_frames(program: &[u8], frames: usize) -> Vec<EmulatorEvent> {
+    let rom = RomBuilder::new("WATCHDOG").program(program).build();
+    let mut gameboy = GameBoyColor::new(&rom, DeviceMode::GameBoy, None).unwrap();
+    let mut events = Vec::new();
+    for _ in 0..frames {
+        gameboy.execute_frame();
+        events.extend(gameboy.take_events());
+    }
+    events
+}
+
+#[test]
+fn invalid_opcode_is_reported_once() {
+    let events = run_frames(&[0x00, 0xD3], 5);
+    assert_eq!(
+        events,
+        [EmulatorEvent::EmulationStalled(
+            StallReason::InvalidOpcode {
+                opcode: 0xD3,
+                pc: PROGRAM_START as u16 + 1,
+            }
+        )]
+    );
+}
+
+#[test]
+fn halt_without_enabled_interrupts_is_reported() {
+    // di; xor a; ldh (IE), a; halt
+    let events = run_frames(&[0xF3, 0xAF, 0xE0, 0xFF, 0x76], 5);
+    assert_eq!(
+        events,
+        [EmulatorEvent::EmulationStalled(
+            StallReason::HaltWithoutWakeSource {
+                pc: PROGRAM_START as u16 + 5,
+            }
+        )]
+    );
+}
+
+#[test]
+fn frame_without_progress_returns_control() {
+    let rom = RomBuilder::new("WATCHDOG").program(&[0x18, 0xFE]).build();
+    let config = EmulatorConfigBuilder::default()
+        .device_mode(DeviceMode::GameBoy)
+        .watchdog_cycle_limit(1000)
+        .build()
+        .unwrap();
+    let mut gameboy = GameBoyColor::with_config(&rom, config).unwrap();
+
+    gameboy.execute_frame();
+    match gameboy.take_events().as_slice() {
+        [EmulatorEvent::EmulationStalled(StallReason::NoFrameProgress { cycles })] => {
+            assert!((1000..1010).contains(cycles))
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+}